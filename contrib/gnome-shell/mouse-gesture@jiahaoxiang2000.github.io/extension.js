@@ -0,0 +1,87 @@
+// Carries out the mouse-gesture daemon's `desktop:` actions in GNOME Shell.
+//
+// The daemon broadcasts org.mousegesture.Desktop.Request(command) on the
+// session bus; owning org.mousegesture.Bridge tells it someone is listening.
+
+import Gio from 'gi://Gio';
+import Meta from 'gi://Meta';
+
+import * as Main from 'resource:///org/gnome/shell/ui/main.js';
+import {Extension} from 'resource:///org/gnome/shell/extensions/extension.js';
+
+const BUS_NAME = 'org.mousegesture.Bridge';
+const OBJECT_PATH = '/org/mousegesture';
+const INTERFACE = 'org.mousegesture.Desktop';
+
+export default class MouseGestureExtension extends Extension {
+    enable() {
+        this._hiddenWindows = [];
+        this._subscription = Gio.DBus.session.signal_subscribe(
+            null, INTERFACE, 'Request', OBJECT_PATH, null,
+            Gio.DBusSignalFlags.NONE,
+            (_connection, _sender, _path, _interface, _signal, parameters) => {
+                const [command] = parameters.deepUnpack();
+                this._run(command);
+            });
+        this._nameId = Gio.bus_own_name(
+            Gio.BusType.SESSION, BUS_NAME, Gio.BusNameOwnerFlags.NONE,
+            null, null, null);
+    }
+
+    disable() {
+        Gio.bus_unown_name(this._nameId);
+        Gio.DBus.session.signal_unsubscribe(this._subscription);
+        this._nameId = null;
+        this._subscription = null;
+        this._hiddenWindows = null;
+    }
+
+    _run(command) {
+        switch (command) {
+        // The overview is GNOME's window picker as well
+        case 'overview':
+        case 'present_windows':
+            Main.overview.toggle();
+            break;
+        case 'app_grid':
+            if (Main.overview.dash.showAppsButton.checked)
+                Main.overview.hide();
+            else
+                Main.overview.showApps();
+            break;
+        case 'show_desktop':
+            this._toggleDesktop();
+            break;
+        case 'workspace_next':
+            this._switchWorkspace(Meta.MotionDirection.RIGHT);
+            break;
+        case 'workspace_previous':
+            this._switchWorkspace(Meta.MotionDirection.LEFT);
+            break;
+        default:
+            console.warn(`mouse-gesture: unknown desktop command ${command}`);
+        }
+    }
+
+    _switchWorkspace(direction) {
+        const workspace = global.workspace_manager.get_active_workspace();
+        workspace.get_neighbor(direction).activate(global.get_current_time());
+    }
+
+    // GNOME has no show-desktop of its own: minimize the workspace's windows,
+    // and restore them the next time
+    _toggleDesktop() {
+        const restore = this._hiddenWindows.filter(window => window.minimized);
+        this._hiddenWindows = [];
+        if (restore.length > 0) {
+            restore.forEach(window => window.unminimize());
+            return;
+        }
+
+        const workspace = global.workspace_manager.get_active_workspace();
+        this._hiddenWindows = workspace.list_windows().filter(window =>
+            !window.minimized && !window.skip_taskbar &&
+            window.window_type === Meta.WindowType.NORMAL);
+        this._hiddenWindows.forEach(window => window.minimize());
+    }
+}
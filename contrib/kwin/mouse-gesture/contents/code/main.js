@@ -0,0 +1,22 @@
+// Carries out the mouse-gesture daemon's `desktop:` actions in KWin.
+//
+// KWin scripts can't subscribe to D-Bus signals, so each command is a global
+// shortcut without a key, which the daemon invokes through kglobalaccel.
+
+function invokeKWinShortcut(name) {
+    callDBus("org.kde.kglobalaccel", "/component/kwin",
+             "org.kde.kglobalaccel.Component", "invokeShortcut", name);
+}
+
+const commands = {
+    overview: () => invokeKWinShortcut("Overview"),
+    app_grid: () => invokeKWinShortcut("Grid View"),
+    present_windows: () => invokeKWinShortcut("ExposeAll"),
+    show_desktop: () => workspace.slotToggleShowDesktop(),
+    workspace_next: () => workspace.slotSwitchDesktopNext(),
+    workspace_previous: () => workspace.slotSwitchDesktopPrevious(),
+};
+
+for (const [command, run] of Object.entries(commands)) {
+    registerShortcut("mouse_gesture_" + command, "Mouse Gesture: " + command, "", run);
+}
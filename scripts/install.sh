@@ -14,7 +14,6 @@ NC='\033[0m' # No Color
 BINARY_NAME="mouse-gesture-recognition"
 INSTALL_DIR="/usr/local/bin"
 CONFIG_DIR="$HOME/.config/mouse-gesture"
-SERVICE_DIR="$HOME/.config/systemd/user"
 UDEV_DIR="/etc/udev/rules.d"
 
 # Get the directory of this script
@@ -120,6 +119,8 @@ install_udev_rules() {
 # Allows members of 'input' group to access Magic Mouse devices
 SUBSYSTEM=="input", ATTRS{name}=="*Magic Mouse*", MODE="0664", GROUP="input"
 SUBSYSTEM=="input", ATTRS{name}=="*Magic Trackpad*", MODE="0664", GROUP="input"
+# Virtual output device for clicks and key combos
+KERNEL=="uinput", SUBSYSTEM=="misc", MODE="0660", GROUP="input", OPTIONS+="static_node=uinput"
 EOF
     
     # Reload udev rules
@@ -133,31 +134,8 @@ EOF
 install_service() {
     print_status "Installing systemd user service..."
     
-    # Create user systemd directory if it doesn't exist
-    mkdir -p "$SERVICE_DIR"
-    
-    cat << EOF > "$SERVICE_DIR/mouse-gesture.service"
-[Unit]
-Description=Magic Mouse Gesture Recognition
-Documentation=https://github.com/jiahaoxiang2000/mouse-gesture
-After=graphical-session.target
-Wants=graphical-session.target
-
-[Service]
-Type=simple
-ExecStart=$INSTALL_DIR/$BINARY_NAME --config $CONFIG_DIR/config.json
-Restart=always
-RestartSec=5
-Environment=DISPLAY=:0
-Environment=WAYLAND_DISPLAY=wayland-0
-
-# Security settings
-NoNewPrivileges=true
-PrivateTmp=true
-
-[Install]
-WantedBy=default.target
-EOF
+    # The binary generates the unit, running itself in daemon mode
+    "$INSTALL_DIR/$BINARY_NAME" --config "$CONFIG_DIR/config.json" install-service
     
     systemctl --user daemon-reload
     print_status "Systemd user service installed"
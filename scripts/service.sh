@@ -143,7 +143,8 @@ edit_config() {
     EDITOR=${EDITOR:-nano}
     "$EDITOR" "$CONFIG_FILE"
     
-    print_status "Configuration edited. Restart the service to apply changes:"
+    print_status "Configuration edited. Gestures and actions apply right away;"
+    print_status "restart the service to apply device settings:"
     echo "  $0 restart"
 }
 
@@ -0,0 +1,141 @@
+use anyhow::{bail, Result};
+use log::{trace, warn};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+use crate::device::describe_gesture;
+use crate::event_handler::EventHandler;
+use crate::latency::{self, Timing};
+use crate::metrics;
+use crate::multitouch::{GestureModifiers, MultiTouchEvent};
+
+/// Bounded queue handing recognized gestures to a worker task
+///
+/// The touch processing loop only enqueues, so a slow action can't hold up
+/// reading touches. Gestures run one at a time in the order they were
+/// recognized; when the queue is full, new gestures are dropped rather than
+/// stalling the loop. Each gesture carries the handler it was recognized
+/// under, so a configuration reload never splits one gesture across two.
+pub struct ActionQueue {
+    sender: mpsc::Sender<Queued>,
+    worker: JoinHandle<()>,
+}
+
+struct Queued {
+    event_handler: Arc<EventHandler>,
+    event: MultiTouchEvent,
+    modifiers: GestureModifiers,
+    timing: Timing,
+}
+
+impl ActionQueue {
+    /// Start the worker; it finishes the queued gestures and exits once the
+    /// queue is dropped
+    pub fn new(capacity: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Queued>(capacity.max(1));
+
+        let worker = tokio::spawn(async move {
+            while let Some(Queued {
+                event_handler,
+                event,
+                modifiers,
+                timing,
+            }) = receiver.recv().await
+            {
+                latency::dispatched(
+                    &event,
+                    timing,
+                    event_handler.config.execution.latency_budget(),
+                );
+                if let Err(e) = event_handler
+                    .handle_multitouch_event(event, modifiers)
+                    .await
+                {
+                    warn!("Failed to handle multi-touch event: {}", e);
+                }
+            }
+            trace!("Action queue closed");
+        });
+
+        Self { sender, worker }
+    }
+
+    /// Wait for the queued gestures to finish; dropping the queue instead
+    /// leaves them to finish in the background
+    pub async fn flush(self) {
+        drop(self.sender);
+        if let Err(e) = self.worker.await {
+            warn!("Action queue worker failed: {}", e);
+        }
+    }
+
+    /// Queue a gesture without waiting for earlier actions to finish; fails
+    /// only once the worker is gone, e.g. after a panic in an action
+    ///
+    /// `input_time` is when the input that completed the gesture happened.
+    pub fn push(
+        &self,
+        event_handler: &Arc<EventHandler>,
+        event: MultiTouchEvent,
+        modifiers: GestureModifiers,
+        input_time: SystemTime,
+    ) -> Result<()> {
+        match self.sender.try_send(Queued {
+            event_handler: Arc::clone(event_handler),
+            event,
+            modifiers,
+            timing: Timing::recognized_now(input_time),
+        }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(Queued { event, .. })) => {
+                metrics::queue_dropped();
+                warn!("Action queue full, dropping {}", describe_gesture(&event));
+            }
+            Err(TrySendError::Closed(_)) => bail!("Action queue worker stopped"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Action, Config};
+
+    #[tokio::test]
+    async fn test_flush_finishes_queued_actions() {
+        let dir = std::env::temp_dir().join(format!("mouse-gesture-queue-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("pinches");
+        let mut config = Config::default();
+        config.actions.insert(
+            "pinch_out".to_string(),
+            Action::from(format!("exec:sleep 0.05; echo >> {}", log.display()).as_str()),
+        );
+        let event_handler = Arc::new(EventHandler::new(config));
+
+        let queue = ActionQueue::new(8);
+        for _ in 0..3 {
+            let pinch = MultiTouchEvent::Pinch {
+                center_x: 0.0,
+                center_y: 0.0,
+                scale_factor: 1.5,
+            };
+            queue
+                .push(
+                    &event_handler,
+                    pinch,
+                    GestureModifiers::default(),
+                    SystemTime::now(),
+                )
+                .unwrap();
+        }
+        queue.flush().await;
+
+        let pinches = std::fs::read_to_string(&log).unwrap_or_default();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(pinches.lines().count(), 3);
+    }
+}
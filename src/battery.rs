@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::BatteryConfig;
+use crate::event_handler::EventHandler;
+
+const POWER_SUPPLY_CLASS: &str = "/sys/class/power_supply";
+
+/// Battery state as reported by the HID battery driver
+#[derive(Debug, Clone)]
+pub struct BatteryStatus {
+    /// Model name of the device the battery belongs to
+    pub model: String,
+    /// Charge level in percent
+    pub capacity: u8,
+    /// `Charging`, `Discharging`, `Full`, ...
+    pub status: String,
+}
+
+impl BatteryStatus {
+    pub fn is_charging(&self) -> bool {
+        self.status == "Charging" || self.status == "Full"
+    }
+}
+
+/// Find the power supply belonging to an input device
+///
+/// The HID battery is registered under the same HID device as the event node,
+/// so it is looked up through sysfs first. After a reconnect the event node may
+/// have changed, so devices are also matched by model name.
+pub fn find_battery(device_path: &Path, name_pattern: Option<&str>) -> Option<PathBuf> {
+    if let Some(supply) = find_battery_for_event_node(device_path) {
+        return Some(supply);
+    }
+
+    let pattern = name_pattern?;
+    let entries = std::fs::read_dir(POWER_SUPPLY_CLASS).ok()?;
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|supply| {
+            // Only peripherals; skip laptop batteries and AC adapters
+            read_attribute(supply, "scope").is_some_and(|scope| scope == "Device")
+                && read_attribute(supply, "model_name").is_some_and(|model| model.contains(pattern))
+        })
+}
+
+fn find_battery_for_event_node(device_path: &Path) -> Option<PathBuf> {
+    let device_path = std::fs::canonicalize(device_path).ok()?;
+    let event_node = device_path.file_name()?.to_str()?;
+
+    // eventN -> inputN -> HID device, which owns the power_supply directory
+    let supplies = Path::new("/sys/class/input")
+        .join(event_node)
+        .join("device/device/power_supply");
+    let mut entries = std::fs::read_dir(supplies).ok()?;
+    entries.find_map(|entry| entry.ok().map(|entry| entry.path()))
+}
+
+/// Read the current battery state of a power supply
+pub fn read_battery(supply: &Path) -> Result<BatteryStatus> {
+    let capacity = read_attribute(supply, "capacity")
+        .with_context(|| format!("No battery capacity reported by {:?}", supply))?
+        .parse()
+        .with_context(|| format!("Invalid battery capacity in {:?}", supply))?;
+
+    Ok(BatteryStatus {
+        model: read_attribute(supply, "model_name").unwrap_or_else(|| "Unknown".to_string()),
+        capacity,
+        status: read_attribute(supply, "status").unwrap_or_else(|| "Unknown".to_string()),
+    })
+}
+
+fn read_attribute(supply: &Path, attribute: &str) -> Option<String> {
+    std::fs::read_to_string(supply.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+/// Periodically check the battery and run `on_battery_low` once per discharge
+pub async fn monitor(
+    device_path: PathBuf,
+    name_pattern: Option<String>,
+    config: BatteryConfig,
+    event_handler: EventHandler,
+) {
+    let interval = Duration::from_secs(config.check_interval_secs.max(1));
+    let mut warned = false;
+
+    loop {
+        let status =
+            find_battery(&device_path, name_pattern.as_deref()).map(|supply| read_battery(&supply));
+
+        match status {
+            Some(Ok(status)) => {
+                debug!(
+                    "Battery of {}: {}% ({})",
+                    status.model, status.capacity, status.status
+                );
+                let low = status.capacity <= config.low_threshold && !status.is_charging();
+                if low && !warned {
+                    warn!("Battery of {} is low: {}%", status.model, status.capacity);
+                    event_handler.handle_battery_low(&status).await;
+                } else if !low && warned {
+                    info!(
+                        "Battery of {} recovered: {}%",
+                        status.model, status.capacity
+                    );
+                }
+                warned = low;
+            }
+            Some(Err(e)) => debug!("Failed to read battery: {}", e),
+            None => debug!("No battery found for {:?}", device_path),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
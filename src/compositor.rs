@@ -0,0 +1,102 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+const IPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// sway IPC message header magic
+pub const I3_IPC_MAGIC: &[u8; 6] = b"i3-ipc";
+/// sway IPC message type running a command
+const RUN_COMMAND: u32 = 0;
+
+/// Run a Hyprland dispatcher, e.g. `workspace e+1`, over Hyprland's request
+/// socket (what `hyprctl dispatch` does)
+pub async fn hyprland_dispatch(dispatch: &str) -> Result<()> {
+    let reply = with_timeout(async {
+        let mut stream = UnixStream::connect(hyprland_socket(".socket.sock")?)
+            .await
+            .context("Failed to connect to Hyprland")?;
+        stream
+            .write_all(format!("dispatch {}", dispatch).as_bytes())
+            .await?;
+
+        // Hyprland answers and closes the connection
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await?;
+        Ok(reply)
+    })
+    .await?;
+
+    debug!("Hyprland replied: {}", reply.trim());
+    if reply.trim() != "ok" {
+        bail!("Hyprland dispatch {} failed: {}", dispatch, reply.trim());
+    }
+    Ok(())
+}
+
+/// Run a sway command, e.g. `workspace next`, over `$SWAYSOCK` (what
+/// `swaymsg` does)
+pub async fn sway_command(command: &str) -> Result<()> {
+    let reply = with_timeout(async {
+        let socket = std::env::var_os("SWAYSOCK").context("SWAYSOCK is not set")?;
+        let mut stream = UnixStream::connect(&socket)
+            .await
+            .context("Failed to connect to sway")?;
+
+        let mut message = I3_IPC_MAGIC.to_vec();
+        message.extend_from_slice(&(command.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&RUN_COMMAND.to_ne_bytes());
+        message.extend_from_slice(command.as_bytes());
+        stream.write_all(&message).await?;
+
+        let mut header = [0u8; 14];
+        stream.read_exact(&mut header).await?;
+        if &header[..6] != I3_IPC_MAGIC {
+            bail!("Unexpected reply from sway");
+        }
+        let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+        let mut payload = vec![0u8; length as usize];
+        stream.read_exact(&mut payload).await?;
+        Ok(payload)
+    })
+    .await?;
+
+    // One result per command: [{"success": true}, ...]
+    let results: Vec<serde_json::Value> =
+        serde_json::from_slice(&reply).context("Invalid reply from sway")?;
+    for result in &results {
+        if result["success"] != true {
+            bail!(
+                "sway command {} failed: {}",
+                command,
+                result["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// One of Hyprland's sockets, `.socket.sock` for requests or `.socket2.sock`
+/// for events: under `$XDG_RUNTIME_DIR/hypr` since 0.40, under `/tmp/hypr`
+/// before
+pub fn hyprland_socket(name: &str) -> Result<PathBuf> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("HYPRLAND_INSTANCE_SIGNATURE is not set")?;
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+
+    [runtime_dir, Some(PathBuf::from("/tmp"))]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("hypr").join(&signature).join(name))
+        .find(|path| path.exists())
+        .context("Hyprland socket not found")
+}
+
+async fn with_timeout<T>(request: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(IPC_TIMEOUT, request)
+        .await
+        .context("Compositor didn't answer in time")?
+}
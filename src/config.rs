@@ -1,43 +1,981 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{migrate, privileges};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Schema version, bumped when fields are renamed or restructured;
+    /// older files are migrated on load
+    #[serde(default)]
+    pub version: u32,
     pub device: DeviceConfig,
     pub gesture: GestureConfig,
-    pub actions: HashMap<String, String>,
+    pub actions: HashMap<String, Action>,
+    /// Devices to listen on simultaneously; when empty, the single device
+    /// described by `device` is used
+    #[serde(default)]
+    pub devices: Vec<DeviceEntry>,
+    #[serde(default)]
+    pub battery: BatteryConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub plugins: PluginConfig,
+    #[serde(default)]
+    pub dbus: DbusConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub overlay: OverlayConfig,
+    #[serde(default)]
+    pub feedback: FeedbackConfig,
+    #[serde(default)]
+    pub execution: ExecutionConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub auto_pause: AutoPauseConfig,
+    /// Named sets of actions and gesture settings layered over the global
+    /// ones, switchable at runtime
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Profile active at startup; none uses the global settings as they are
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Log what gestures would run instead of running it (`--dry-run`)
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+/// How gesture actions are queued and bounded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionConfig {
+    /// Gestures waiting for their actions; further ones are dropped while full
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// Actions still running after this long (milliseconds) are cancelled;
+    /// `0` lets them run indefinitely
+    #[serde(default = "default_action_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Per-action override of `timeout_ms`, by action name
+    #[serde(default)]
+    pub timeouts: HashMap<String, u64>,
+    /// Run commands that aren't built-ins through `sh -c`; when off, only
+    /// `exec:` commands reach the shell
+    #[serde(default = "default_shell_fallback")]
+    pub shell_fallback: bool,
+    /// Warn about gestures whose action starts more than this long
+    /// (milliseconds) after their input; `0` disables the warning
+    #[serde(default = "default_latency_budget_ms")]
+    pub latency_budget_ms: u64,
+}
+
+impl ExecutionConfig {
+    /// Time limit of an action, `None` if it may run indefinitely
+    pub fn timeout(&self, action_name: &str) -> Option<Duration> {
+        let timeout_ms = self
+            .timeouts
+            .get(action_name)
+            .copied()
+            .unwrap_or(self.timeout_ms);
+        (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms))
+    }
+
+    /// Longest acceptable time from input to action, `None` if unlimited
+    pub fn latency_budget(&self) -> Option<Duration> {
+        (self.latency_budget_ms > 0).then(|| Duration::from_millis(self.latency_budget_ms))
+    }
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            queue_size: default_queue_size(),
+            timeout_ms: default_action_timeout_ms(),
+            timeouts: HashMap::new(),
+            shell_fallback: default_shell_fallback(),
+            latency_budget_ms: default_latency_budget_ms(),
+        }
+    }
+}
+
+/// Pausing gestures while certain windows have focus
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutoPauseConfig {
+    /// Pause while the focused window is fullscreen, unless a profile lists
+    /// its class in `windows`
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// Windows to pause for whether fullscreen or not
+    #[serde(default)]
+    pub windows: Vec<WindowRule>,
+    /// Also let go of the device grab while paused, so the desktop gets the
+    /// touches
+    #[serde(default)]
+    pub release_grab: bool,
+}
+
+/// Sounds or commands confirming touches, independent of the actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackConfig {
+    /// When fingers first touch the surface
+    #[serde(default)]
+    pub begin: FeedbackHook,
+    /// When a gesture is recognized
+    #[serde(default)]
+    pub end: FeedbackHook,
+    /// When a resting finger clicks (`gesture.dwell_click`), on top of `end`
+    #[serde(default)]
+    pub dwell: FeedbackHook,
+    /// A hook fires at most once per interval (milliseconds)
+    #[serde(default = "default_feedback_min_interval_ms")]
+    pub min_interval_ms: u64,
+}
+
+impl Default for FeedbackConfig {
+    fn default() -> Self {
+        Self {
+            begin: FeedbackHook::default(),
+            end: FeedbackHook::default(),
+            dwell: FeedbackHook::default(),
+            min_interval_ms: default_feedback_min_interval_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedbackHook {
+    /// Sound file played with paplay, pw-play or aplay
+    #[serde(default)]
+    pub sound: Option<PathBuf>,
+    /// Shell command run in the background
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+/// Desktop notifications naming the recognized gesture and the action it ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Notify for every gesture unless turned off in `gestures`
+    #[serde(default)]
+    pub enabled: bool,
+    /// Per-gesture override of `enabled`, by action name
+    #[serde(default)]
+    pub gestures: HashMap<String, bool>,
+    /// How long a notification stays up, in milliseconds
+    #[serde(default = "default_notification_timeout_ms")]
+    pub timeout_ms: i32,
+}
+
+impl NotificationConfig {
+    /// Whether recognizing `action_name` shows a notification
+    pub fn enabled_for(&self, action_name: &str) -> bool {
+        self.gestures
+            .get(action_name)
+            .copied()
+            .unwrap_or(self.enabled)
+    }
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gestures: HashMap::new(),
+            timeout_ms: default_notification_timeout_ms(),
+        }
+    }
+}
+
+/// On-screen flash naming the recognized gesture, for learning the gesture set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverlayConfig {
+    /// Show it; `toggle_overlay` switches it at runtime
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long it stays up, in milliseconds
+    #[serde(default = "default_overlay_duration_ms")]
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub position: OverlayPosition,
+    /// X11 core font (XLFD pattern); `fixed` when the server has no match
+    #[serde(default = "default_overlay_font")]
+    pub font: String,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            duration_ms: default_overlay_duration_ms(),
+            position: OverlayPosition::default(),
+            font: default_overlay_font(),
+        }
+    }
+}
+
+/// Where on the screen the overlay appears, horizontally centered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    Top,
+    Center,
+    #[default]
+    Bottom,
+}
+
+fn default_overlay_duration_ms() -> u64 {
+    800
+}
+
+fn default_overlay_font() -> String {
+    "-*-*-bold-r-normal--24-*-*-*-*-*-iso8859-1".to_string()
+}
+
+/// Session bus integration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DbusConfig {
+    /// Emit `org.mousegesture.Gesture` for every recognized gesture
+    #[serde(default)]
+    pub signals: bool,
+}
+
+/// Prometheus metrics endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Address serving `/metrics` over HTTP, e.g. `127.0.0.1:9750`; read at
+    /// startup, off by default
+    #[serde(default)]
+    pub listen: Option<String>,
+}
+
+/// Native plugins adding recognizers and actions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// Directory searched for `*.so` plugins; defaults to `plugins` next to the
+    /// configuration file. Only read at startup
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+}
+
+/// What a gesture runs: a single command, or a chain of steps run in order
+///
+/// A command is a built-in such as `click` or `key:ctrl+t`, or a shell command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Action {
+    Command(String),
+    Chain(Vec<ActionStep>),
+    /// Binding scaled to the gesture's magnitude: `increase` or `decrease` runs
+    /// once per `step` of change, e.g. one zoom step per 10% of pinch
+    Axis {
+        decrease: String,
+        increase: String,
+        #[serde(default = "default_axis_step")]
+        step: f64,
+    },
+    /// Program run with its own environment or working directory, e.g.
+    /// `{"argv": ["notify-send", "Swiped"]}`
+    Process(ProcessAction),
+    /// Swipe binding that runs `repeat` once per `every_mm` of finger travel
+    /// while the swipe is in progress, e.g. `{"repeat": "key:volumeup"}`
+    Repeat {
+        repeat: String,
+        #[serde(default = "default_repeat_every_mm")]
+        every_mm: f64,
+    },
+}
+
+impl Action {
+    /// The command of a single-command action
+    pub fn as_command(&self) -> Option<&str> {
+        match self {
+            Action::Command(command) => Some(command),
+            Action::Chain(_) | Action::Axis { .. } | Action::Process(_) | Action::Repeat { .. } => {
+                None
+            }
+        }
+    }
+}
+
+fn default_shell_fallback() -> bool {
+    true
+}
+
+fn default_queue_size() -> usize {
+    32
+}
+
+fn default_action_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_latency_budget_ms() -> u64 {
+    100
+}
+
+fn default_feedback_min_interval_ms() -> u64 {
+    100
+}
+
+fn default_notification_timeout_ms() -> i32 {
+    2000
+}
+
+fn default_axis_step() -> f64 {
+    0.1
+}
+
+fn default_repeat_every_mm() -> f64 {
+    5.0
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Command(command) => write!(f, "{}", command),
+            Action::Chain(steps) => {
+                for (i, step) in steps.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match step {
+                        ActionStep::Command(command) => write!(f, "{}", command)?,
+                        ActionStep::Process(process) => write!(f, "{}", process)?,
+                        ActionStep::Delay { delay_ms } => write!(f, "wait {}ms", delay_ms)?,
+                    }
+                }
+                Ok(())
+            }
+            Action::Axis {
+                decrease,
+                increase,
+                step,
+            } => write!(f, "{} / {} per {}", decrease, increase, step),
+            Action::Process(process) => write!(f, "{}", process),
+            Action::Repeat { repeat, every_mm } => {
+                write!(f, "{} every {}mm", repeat, every_mm)
+            }
+        }
+    }
+}
+
+impl From<&str> for Action {
+    fn from(command: &str) -> Self {
+        Action::Command(command.to_string())
+    }
+}
+
+/// One step of an action chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ActionStep {
+    Command(String),
+    Process(ProcessAction),
+    /// Pause before the next step, e.g. `{"delay_ms": 100}`
+    Delay {
+        delay_ms: u64,
+    },
+}
+
+/// A program to run, either directly (`argv`) or through `sh -c` (`shell`),
+/// with optional extra environment variables and working directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessAction {
+    /// Program and arguments, run without a shell
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub argv: Vec<String>,
+    /// Shell command, used when `argv` is empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+}
+
+impl fmt::Display for ProcessAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.shell {
+            Some(shell) if self.argv.is_empty() => write!(f, "{}", shell),
+            _ => write!(f, "{}", self.argv.join(" ")),
+        }
+    }
+}
+
+/// How clicks, key combos and scroll steps are injected
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutputConfig {
+    #[serde(default)]
+    pub backend: OutputBackend,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputBackend {
+    /// uinput when accessible, otherwise a tool matching the session:
+    /// ydotool or wtype on Wayland, XTEST or xdotool on X11
+    #[default]
+    Auto,
+    /// Virtual keyboard/mouse through /dev/uinput
+    Uinput,
+    /// In-process injection through the X11 XTEST extension
+    Xtest,
+    Xdotool,
+    /// ydotool (needs the ydotoold daemon)
+    Ydotool,
+    /// wtype, Wayland compositors with virtual-keyboard support; keys only
+    Wtype,
+}
+
+/// Battery monitoring of wireless devices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatteryConfig {
+    /// Interval between battery checks in seconds
+    pub check_interval_secs: u64,
+    /// Charge level in percent at or below which `on_battery_low` runs
+    pub low_threshold: u8,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 300,
+            low_threshold: 15,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceConfig {
     pub path: Option<String>,
+    /// Stable device ID: a `/dev/input/by-id` or `/dev/input/by-path` link name
+    #[serde(default)]
+    pub id: Option<String>,
     pub auto_detect: bool,
     pub name_pattern: String,
+    /// Additional name or `vendor:product` patterns for auto-detection, tried in
+    /// order before `name_pattern`
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Name or `vendor:product` patterns of devices auto-detection must skip
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Which calibration to use; `auto` picks it from the device name
+    #[serde(rename = "type", default)]
+    pub device_type: DeviceType,
+    /// Re-discover and reopen the device after it disconnects
+    #[serde(default = "default_reconnect")]
+    pub reconnect: bool,
+    /// Grab the device exclusively so the desktop doesn't also act on touches;
+    /// pointer motion and clicks are re-injected through uinput
+    #[serde(default)]
+    pub grab: bool,
+    /// Keep forwarding the driver's scroll wheel events while grabbed
+    #[serde(default = "default_passthrough_scroll")]
+    pub passthrough_scroll: bool,
+    /// Where touches are read from
+    #[serde(default)]
+    pub backend: Backend,
+    /// Input events buffered between the device and gesture recognition
+    #[serde(default = "default_event_buffer")]
+    pub event_buffer: usize,
+    /// What happens when recognition falls behind and the buffer fills up
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// Priority of the thread reading input events
+    #[serde(default)]
+    pub scheduling: SchedulingConfig,
+    /// Re-emit touches through a virtual touchpad for the desktop's own gestures
+    #[serde(default)]
+    pub touchpad: TouchpadConfig,
+}
+
+/// A uinput touchpad mirroring the touches, so libinput and the desktop
+/// apply their native scrolling and gestures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TouchpadConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Fewest fingers forwarded; fewer stay with the mouse so a resting finger
+    /// doesn't move the pointer
+    #[serde(default = "default_touchpad_min_fingers")]
+    pub min_fingers: usize,
+    /// Skip the configured actions of gestures with at least `min_fingers`
+    /// fingers, leaving them to the desktop
+    #[serde(default = "default_suppress_actions")]
+    pub suppress_actions: bool,
+}
+
+impl Default for TouchpadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_fingers: default_touchpad_min_fingers(),
+            suppress_actions: default_suppress_actions(),
+        }
+    }
+}
+
+/// Raised priority for reading input events, so touches keep being read
+/// while the system is busy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchedulingConfig {
+    /// Read with the `SCHED_FIFO` real-time policy at this priority (1-99)
+    #[serde(default)]
+    pub realtime_priority: Option<i32>,
+    /// Niceness of the reading thread (-20 to 19), used when real-time
+    /// scheduling isn't requested or isn't permitted
+    #[serde(default)]
+    pub nice: Option<i32>,
+}
+
+/// Handling of input events arriving while the event buffer is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drop events until there is room again, then re-read the touch state
+    /// from the device
+    #[default]
+    Resync,
+    /// Stop reading until there is room; the kernel drops events instead
+    Block,
+}
+
+/// Source of touch data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// Events decoded by the kernel driver (`/dev/input/event*`)
+    #[default]
+    Evdev,
+    /// Raw Magic Mouse HID reports (`/dev/hidraw*`), without the kernel driver
+    Hidraw,
+}
+
+/// One of several devices processed at the same time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceEntry {
+    /// Fixed device path; takes precedence over `id` and `name_pattern`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Stable device ID (`/dev/input/by-id` or `by-path` link name); takes
+    /// precedence over `name_pattern`
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Device name substring used for auto-detection
+    #[serde(default)]
+    pub name_pattern: Option<String>,
+    /// Name or `vendor:product` patterns tried in order before `name_pattern`
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(rename = "type", default)]
+    pub device_type: DeviceType,
+    /// Gesture settings overriding the global `gesture` section, e.g.
+    /// `{"swipe_threshold": 20.0}`
+    #[serde(default)]
+    pub gesture: serde_json::Map<String, serde_json::Value>,
+    /// Actions overriding the global `actions` for gestures on this device
+    #[serde(default)]
+    pub actions: HashMap<String, Action>,
+}
+
+/// Named overrides of the global actions and gesture settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Gesture settings overriding the global `gesture` section
+    #[serde(default)]
+    pub gesture: serde_json::Map<String, serde_json::Value>,
+    /// Actions overriding the global `actions`
+    #[serde(default)]
+    pub actions: HashMap<String, Action>,
+    /// Windows switching to this profile while they have focus
+    #[serde(default)]
+    pub windows: Vec<WindowRule>,
+}
+
+/// Windows matched by a profile or `auto_pause`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WindowRule {
+    /// Window class (X11) or app ID (Wayland), ignoring case
+    Class(String),
+    /// Every condition given must hold, e.g.
+    /// `{"class": "libreoffice-impress", "fullscreen": true}`
+    Match(WindowMatch),
+}
+
+impl WindowRule {
+    /// Conditions the rule has, so the most specific rule can win
+    pub fn conditions(&self) -> usize {
+        match self {
+            WindowRule::Class(_) => 1,
+            WindowRule::Match(rule) => {
+                rule.class.is_some() as usize
+                    + rule.title.is_some() as usize
+                    + rule.fullscreen.is_some() as usize
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowMatch {
+    /// Window class or app ID, ignoring case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Text the title contains, ignoring case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Whether the window is fullscreen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullscreen: Option<bool>,
+}
+
+/// Supported touch device families
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceType {
+    #[default]
+    Auto,
+    MagicMouse,
+    MagicTrackpad,
+    /// Any multi-touch (protocol type B) touchpad
+    Generic,
+}
+
+impl DeviceType {
+    /// Resolve `auto` from the device name reported by the kernel
+    pub fn resolve(self, device_name: &str) -> DeviceType {
+        match self {
+            DeviceType::Auto if device_name.contains("Magic Trackpad") => DeviceType::MagicTrackpad,
+            DeviceType::Auto if device_name.contains("Magic Mouse") => DeviceType::MagicMouse,
+            DeviceType::Auto => DeviceType::Generic,
+            other => other,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GestureConfig {
-    /// Minimum movement distance in millimeters for scroll gestures
-    pub scroll_threshold: f64,
-    /// Minimum movement distance in millimeters for swipe gestures
-    pub swipe_threshold: f64,
+    /// Minimum movement distance for scroll gestures
+    pub scroll_threshold: Distance,
+    /// Minimum movement distance for swipe gestures
+    pub swipe_threshold: Distance,
     /// Minimum scale change percentage for pinch gestures (0.1 = 10%)
     pub pinch_threshold: f64,
-    /// Maximum duration in milliseconds for tap gestures
-    pub tap_timeout_ms: u64,
-    /// Debounce time in milliseconds between gesture events
+    /// Maximum duration in milliseconds for tap gestures; unset (`null`), the
+    /// device's default applies (see `tap_timeout_ms()`)
+    #[serde(default)]
+    pub tap_timeout_ms: Option<u64>,
+    /// Finished gestures following the previous one within this many
+    /// milliseconds are dropped; their contacts are still tracked
     pub debounce_ms: u64,
     // Multi-touch specific settings
-    /// Maximum duration in milliseconds for two-finger tap
-    pub two_finger_tap_timeout_ms: u64,
+    /// Maximum duration in milliseconds for two-finger tap; unset (`null`),
+    /// the device's default applies
+    #[serde(default)]
+    pub two_finger_tap_timeout_ms: Option<u64>,
     /// Maximum distance in millimeters between fingers for two-finger tap
-    pub two_finger_tap_distance_threshold: f64,
+    pub two_finger_tap_distance_threshold: Distance,
     /// Minimum contact pressure percentage for valid touch (0-100)
     pub contact_pressure_threshold: f64,
     /// Maximum movement distance in millimeters for single-finger tap
-    pub single_finger_tap_movement_threshold: f64,
+    pub single_finger_tap_movement_threshold: Distance,
+    /// Finger-count phases shorter than this (in milliseconds) are treated as
+    /// transitions and don't decide which gesture a session is classified as
+    #[serde(default = "default_finger_count_grace_ms")]
+    pub finger_count_grace_ms: u64,
+    /// Lock two-finger scrolling to the dominant axis once it passes the scroll
+    /// threshold; disable for free (2D) scrolling
+    #[serde(default = "default_scroll_axis_lock")]
+    pub scroll_axis_lock: bool,
+    /// Mirror the touch surface horizontally and swap left/right click actions
+    /// for left-handed use
+    #[serde(default)]
+    pub left_handed: bool,
+    /// How far the device is turned clockwise from its usual orientation, in
+    /// degrees; positions are turned back before gestures are recognized
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Mirror the touch surface horizontally after rotating, without swapping
+    /// clicks like `left_handed`
+    #[serde(default)]
+    pub mirror: bool,
+    /// Regions of the touch surface whose contacts are ignored entirely
+    #[serde(default)]
+    pub dead_zones: Vec<DeadZone>,
+    /// Taps are ignored when the pointer moved faster than this (in pointer
+    /// counts per second) during the touch; 0 disables the check
+    #[serde(default = "default_tap_max_pointer_speed")]
+    pub tap_max_pointer_speed: f64,
+    /// High-resolution wheel units per millimeter of finger travel for the
+    /// `smooth_scroll` action; 120 units are one wheel detent
+    #[serde(default = "default_smooth_scroll_speed")]
+    pub smooth_scroll_speed: f64,
+    /// Content follows the fingers when smooth scrolling, as on macOS
+    #[serde(default)]
+    pub natural_scroll: bool,
+    /// Click by resting one finger still instead of tapping, bound as
+    /// `dwell_1finger`
+    #[serde(default)]
+    pub dwell_click: bool,
+    /// How long (milliseconds) the finger rests before the dwell click
+    #[serde(default = "default_dwell_ms")]
+    pub dwell_ms: u64,
+    /// How far the resting finger may drift without restarting the countdown
+    #[serde(default = "default_dwell_radius")]
+    pub dwell_radius: Distance,
+    /// Hold the left button with a long press (`long_press_1finger`, bound to
+    /// `drag_lock`) until the next tap, so dragging needs no held button
+    #[serde(default)]
+    pub sticky_drag: bool,
+    /// How long (milliseconds) a finger stays down for a long press
+    #[serde(default = "default_long_press_ms")]
+    pub long_press_ms: u64,
+    /// Contacts not updated for this many milliseconds are dropped as a missed
+    /// release, so they don't count as a finger forever; 0 (the default) keeps
+    /// them, as a finger resting perfectly still reports nothing either
+    #[serde(default)]
+    pub stale_contact_ms: u64,
+    /// Archive each contact's raw axis changes for this many milliseconds
+    /// after it lands, for scripts and `events --contacts`; 0 (the default)
+    /// archives nothing
+    #[serde(default)]
+    pub contact_archive_ms: u64,
+    /// Smooth contact positions and ignore small oscillations before gestures
+    /// are classified, for users with hand tremor
+    #[serde(default)]
+    pub tremor_filter: bool,
+    /// Weight of each new position report in the tremor filter (0-1); lower
+    /// smooths harder but lags more on slow movements
+    #[serde(default = "default_tremor_smoothing")]
+    pub tremor_smoothing: f64,
+    /// Movement the tremor filter swallows before a contact moves at all
+    #[serde(default = "default_tremor_deadband")]
+    pub tremor_deadband: Distance,
+}
+
+/// Rotation of a device from its usual orientation, written as 0, 90, 180 or
+/// 270 degrees clockwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum Rotation {
+    #[default]
+    Upright,
+    Clockwise,
+    UpsideDown,
+    Counterclockwise,
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = String;
+
+    fn try_from(degrees: u16) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Rotation::Upright),
+            90 => Ok(Rotation::Clockwise),
+            180 => Ok(Rotation::UpsideDown),
+            270 => Ok(Rotation::Counterclockwise),
+            _ => Err(format!(
+                "invalid rotation {}, expected 0, 90, 180 or 270",
+                degrees
+            )),
+        }
+    }
+}
+
+impl From<Rotation> for u16 {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Upright => 0,
+            Rotation::Clockwise => 90,
+            Rotation::UpsideDown => 180,
+            Rotation::Counterclockwise => 270,
+        }
+    }
+}
+
+/// Distance threshold with its unit
+///
+/// Written as a bare number (millimeters tuned for the USB-C Magic Mouse) or a
+/// string with a unit: `"12mm"`, `"300u"` (raw device units) or `"10%"` (of
+/// the shorter side of the touch surface).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "DistanceRepr", into = "DistanceRepr")]
+pub enum Distance {
+    /// Millimeters on the USB-C Magic Mouse, scaled to the size of other
+    /// surfaces
+    Scaled(f64),
+    /// Millimeters on any surface
+    Millimeters(f64),
+    /// Raw device units
+    Units(f64),
+    /// Percentage of the shorter side of the surface
+    Percent(f64),
+}
+
+impl Distance {
+    /// Adapt a bare millimeter value to a surface `factor` times the size of
+    /// the reference one; explicit units are kept as they are
+    pub fn scaled(self, factor: f64) -> Self {
+        match self {
+            Distance::Scaled(mm) => Distance::Scaled(mm * factor),
+            distance => distance,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DistanceRepr {
+    Number(f64),
+    Text(String),
+}
+
+impl TryFrom<DistanceRepr> for Distance {
+    type Error = String;
+
+    fn try_from(repr: DistanceRepr) -> Result<Self, Self::Error> {
+        let text = match repr {
+            DistanceRepr::Number(mm) => return Ok(Distance::Scaled(mm)),
+            DistanceRepr::Text(text) => text,
+        };
+
+        let text = text.trim();
+        let (number, unit): (&str, fn(f64) -> Distance) =
+            if let Some(number) = text.strip_suffix("mm") {
+                (number, Distance::Millimeters)
+            } else if let Some(number) = text.strip_suffix('%') {
+                (number, Distance::Percent)
+            } else if let Some(number) = text.strip_suffix('u') {
+                (number, Distance::Units)
+            } else {
+                (text, Distance::Scaled)
+            };
+        number
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .map(unit)
+            .ok_or_else(|| {
+                format!(
+                    "invalid distance {:?}, expected e.g. 12, \"12mm\", \"300u\" or \"10%\"",
+                    text
+                )
+            })
+    }
+}
+
+impl From<Distance> for DistanceRepr {
+    fn from(distance: Distance) -> Self {
+        match distance {
+            Distance::Scaled(mm) => DistanceRepr::Number(mm),
+            Distance::Millimeters(mm) => DistanceRepr::Text(format!("{}mm", mm)),
+            Distance::Units(units) => DistanceRepr::Text(format!("{}u", units)),
+            Distance::Percent(percent) => DistanceRepr::Text(format!("{}%", percent)),
+        }
+    }
+}
+
+/// Rectangular region of the touch surface in normalized coordinates
+///
+/// (0, 0) is the minimum corner of the surface as reported by the device and
+/// (1, 1) the maximum; on the Magic Mouse larger Y values are towards the palm.
+/// Zones are unaffected by `rotation`, `mirror` and `left_handed`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadZone {
+    pub x_min: f64,
+    pub y_min: f64,
+    pub x_max: f64,
+    pub y_max: f64,
+}
+
+impl DeadZone {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x_min && x <= self.x_max && y >= self.y_min && y <= self.y_max
+    }
+}
+
+/// Tap windows (milliseconds) of devices without a model-specific default
+const DEFAULT_TAP_TIMEOUT_MS: u64 = 300;
+const DEFAULT_TWO_FINGER_TAP_TIMEOUT_MS: u64 = 250;
+
+impl GestureConfig {
+    /// Longest one-finger tap in milliseconds, the default unless configured
+    pub fn tap_timeout_ms(&self) -> u64 {
+        self.tap_timeout_ms.unwrap_or(DEFAULT_TAP_TIMEOUT_MS)
+    }
+
+    /// Longest two-finger tap in milliseconds, the default unless configured
+    pub fn two_finger_tap_timeout_ms(&self) -> u64 {
+        self.two_finger_tap_timeout_ms
+            .unwrap_or(DEFAULT_TWO_FINGER_TAP_TIMEOUT_MS)
+    }
+}
+
+fn default_finger_count_grace_ms() -> u64 {
+    50
+}
+
+fn default_tap_max_pointer_speed() -> f64 {
+    400.0
+}
+
+fn default_smooth_scroll_speed() -> f64 {
+    30.0
+}
+
+fn default_dwell_ms() -> u64 {
+    800
+}
+
+fn default_dwell_radius() -> Distance {
+    Distance::Scaled(1.5)
+}
+
+fn default_long_press_ms() -> u64 {
+    600
+}
+
+fn default_tremor_smoothing() -> f64 {
+    0.25
+}
+
+fn default_tremor_deadband() -> Distance {
+    Distance::Scaled(1.0)
+}
+
+fn default_reconnect() -> bool {
+    true
+}
+
+fn default_passthrough_scroll() -> bool {
+    true
+}
+
+fn default_event_buffer() -> usize {
+    1000
+}
+
+fn default_touchpad_min_fingers() -> usize {
+    2
+}
+
+fn default_suppress_actions() -> bool {
+    true
+}
+
+fn default_scroll_axis_lock() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -45,76 +983,500 @@ impl Default for Config {
         let mut actions = HashMap::new();
 
         // Default action mappings
-        actions.insert(
-            "swipe_left_2finger".to_string(),
-            "xdotool key alt+Right".to_string(),
-        );
-        actions.insert(
-            "swipe_right_2finger".to_string(),
-            "xdotool key alt+Left".to_string(),
-        );
-        actions.insert(
-            "swipe_up_2finger".to_string(),
-            "xdotool key ctrl+t".to_string(),
-        );
-        actions.insert(
-            "swipe_down_2finger".to_string(),
-            "xdotool key ctrl+w".to_string(),
-        );
-        actions.insert("scroll_vertical".to_string(), "scroll_vertical".to_string());
-        actions.insert(
-            "scroll_horizontal".to_string(),
-            "scroll_horizontal".to_string(),
-        );
-        actions.insert("tap_1finger".to_string(), "click".to_string());
-        actions.insert("tap_2finger".to_string(), "right_click".to_string());
-        actions.insert("pinch_in".to_string(), "xdotool key ctrl+minus".to_string());
-        actions.insert("pinch_out".to_string(), "xdotool key ctrl+plus".to_string());
+        actions.insert("swipe_left_2finger".to_string(), "key:alt+Right".into());
+        actions.insert("swipe_right_2finger".to_string(), "key:alt+Left".into());
+        actions.insert("swipe_up_2finger".to_string(), "key:ctrl+t".into());
+        actions.insert("swipe_down_2finger".to_string(), "key:ctrl+w".into());
+        actions.insert("scroll_vertical".to_string(), "scroll_vertical".into());
+        actions.insert("scroll_horizontal".to_string(), "scroll_horizontal".into());
+        actions.insert("tap_1finger".to_string(), "click".into());
+        actions.insert("tap_2finger".to_string(), "right_click".into());
+        actions.insert("dwell_1finger".to_string(), "click".into());
+        actions.insert("long_press_1finger".to_string(), "drag_lock".into());
+        actions.insert("pinch_in".to_string(), "key:ctrl+minus".into());
+        actions.insert("pinch_out".to_string(), "key:ctrl+plus".into());
 
         Self {
+            version: migrate::CURRENT_VERSION,
             device: DeviceConfig {
                 path: None,
+                id: None,
                 auto_detect: true,
                 name_pattern: "Magic Mouse".to_string(),
+                include: Vec::new(),
+                exclude: Vec::new(),
+                device_type: DeviceType::Auto,
+                reconnect: default_reconnect(),
+                grab: false,
+                passthrough_scroll: default_passthrough_scroll(),
+                backend: Backend::Evdev,
+                event_buffer: default_event_buffer(),
+                overflow: OverflowPolicy::default(),
+                scheduling: SchedulingConfig::default(),
+                touchpad: TouchpadConfig::default(),
             },
             gesture: GestureConfig {
-                scroll_threshold: 2.0, // 2mm movement threshold for scroll
-                swipe_threshold: 12.0, // 12mm movement threshold for swipe
-                pinch_threshold: 0.1,  // 10% scale change threshold for pinch
-                tap_timeout_ms: 300,
+                scroll_threshold: Distance::Scaled(2.0), // 2mm movement threshold for scroll
+                swipe_threshold: Distance::Scaled(12.0), // 12mm movement threshold for swipe
+                pinch_threshold: 0.1,                    // 10% scale change threshold for pinch
+                tap_timeout_ms: None,
                 debounce_ms: 100,
-                two_finger_tap_timeout_ms: 250,
-                two_finger_tap_distance_threshold: 30.0, // 30mm max distance between fingers for tap
+                two_finger_tap_timeout_ms: None,
+                two_finger_tap_distance_threshold: Distance::Scaled(30.0), // 30mm max distance between fingers for tap
                 contact_pressure_threshold: 50.0, // Keep pressure threshold as-is (percentage)
-                single_finger_tap_movement_threshold: 2.0, // 2mm max movement for single tap
+                single_finger_tap_movement_threshold: Distance::Scaled(2.0), // 2mm max movement for single tap
+                finger_count_grace_ms: default_finger_count_grace_ms(),
+                scroll_axis_lock: default_scroll_axis_lock(),
+                left_handed: false,
+                rotation: Rotation::default(),
+                mirror: false,
+                dead_zones: Vec::new(),
+                tap_max_pointer_speed: default_tap_max_pointer_speed(),
+                smooth_scroll_speed: default_smooth_scroll_speed(),
+                natural_scroll: false,
+                dwell_click: false,
+                dwell_ms: default_dwell_ms(),
+                dwell_radius: default_dwell_radius(),
+                sticky_drag: false,
+                long_press_ms: default_long_press_ms(),
+                stale_contact_ms: 0,
+                contact_archive_ms: 0,
+                tremor_filter: false,
+                tremor_smoothing: default_tremor_smoothing(),
+                tremor_deadband: default_tremor_deadband(),
             },
             actions,
+            devices: Vec::new(),
+            battery: BatteryConfig::default(),
+            output: OutputConfig::default(),
+            plugins: PluginConfig::default(),
+            dbus: DbusConfig::default(),
+            notifications: NotificationConfig::default(),
+            overlay: OverlayConfig::default(),
+            auto_pause: AutoPauseConfig::default(),
+            feedback: FeedbackConfig::default(),
+            execution: ExecutionConfig::default(),
+            metrics: MetricsConfig::default(),
+            profiles: BTreeMap::new(),
+            profile: None,
+            dry_run: false,
         }
     }
 }
 
 impl Config {
-    pub fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
+    /// Device entries to open, falling back to the single `device` section
+    pub fn device_entries(&self) -> Vec<DeviceEntry> {
+        if !self.devices.is_empty() {
+            return self.devices.clone();
+        }
 
-        if path.exists() {
-            let content = std::fs::read_to_string(path)
-                .with_context(|| format!("Failed to read config file: {:?}", path))?;
+        // Generic touchpads are found by capability probing, not by name
+        let name_pattern = (self.device.device_type != DeviceType::Generic)
+            .then(|| self.device.name_pattern.clone());
+
+        vec![DeviceEntry {
+            path: self.device.path.clone(),
+            id: self.device.id.clone(),
+            name_pattern,
+            include: self.device.include.clone(),
+            device_type: self.device.device_type,
+            gesture: serde_json::Map::new(),
+            actions: HashMap::new(),
+        }]
+    }
+
+    /// Configuration used for one device, with its gesture and action
+    /// overrides applied
+    pub fn for_device(&self, entry: &DeviceEntry) -> Result<Config> {
+        let mut config = self.clone();
+        config.device.device_type = entry.device_type;
+        config
+            .actions
+            .extend(entry.actions.iter().map(|(k, v)| (k.clone(), v.clone())));
+        config.gesture = merge_gesture(&self.gesture, &entry.gesture)
+            .context("Invalid gesture override in device entry")?;
+
+        Ok(config)
+    }
+
+    /// Configuration with a profile's overrides applied; `None` leaves the
+    /// global settings as they are
+    pub fn with_profile(&self, name: Option<&str>) -> Result<Config> {
+        let Some(name) = name else {
+            return Ok(self.clone());
+        };
+        let profile = self
+            .profiles
+            .get(name)
+            .with_context(|| format!("Unknown profile: {}", name))?;
+
+        let mut config = self.clone();
+        config
+            .actions
+            .extend(profile.actions.iter().map(|(k, v)| (k.clone(), v.clone())));
+        config.gesture = merge_gesture(&self.gesture, &profile.gesture)
+            .with_context(|| format!("Invalid gesture override in profile {}", name))?;
+
+        Ok(config)
+    }
 
-            let config: Config = serde_json::from_str(&content)
+    /// Read and validate an existing configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {:?}", path))?;
+
+        let mut value: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+        let version = migrate::migrate(&mut value)
+            .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+        if version < migrate::CURRENT_VERSION {
+            // Only rewrite the file once the migrated version is known to work
+            Self::from_value(value.clone())
                 .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+            Self::upgrade_file(path, &content, &value, version)?;
+        }
+
+        Self::from_value_with_env(value)
+            .with_context(|| format!("Failed to parse config file: {:?}", path))
+    }
+
+    /// `from_value` with the `MOUSE_GESTURE__*` environment variables merged in
+    fn from_value_with_env(mut value: serde_json::Value) -> Result<Self> {
+        let overridden = apply_env_overrides(&mut value, std::env::vars())?;
+        let config = Self::from_value(value)?;
+
+        // Misspelled settings would otherwise be ignored without a word
+        let effective = serde_json::to_value(&config)?;
+        for (name, key) in overridden {
+            if lookup(&effective, &key).is_none() {
+                log::warn!("{} doesn't match any setting", name);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Deserialize and validate an up-to-date configuration
+    pub fn from_value(value: serde_json::Value) -> Result<Self> {
+        let config: Config = serde_json::from_value(value)?;
+
+        // Catch bad per-device and profile overrides at startup rather than
+        // on connect or on switching
+        for entry in &config.devices {
+            config.for_device(entry)?;
+        }
+        for name in config.profiles.keys().chain(&config.profile) {
+            config.with_profile(Some(name))?;
+        }
+        // An empty rule would match every window
+        let rules = config
+            .profiles
+            .values()
+            .flat_map(|profile| &profile.windows);
+        if rules
+            .chain(&config.auto_pause.windows)
+            .any(|rule| rule.conditions() == 0)
+        {
+            anyhow::bail!("Window rules need a class, title or fullscreen condition");
+        }
+
+        Ok(config)
+    }
+
+    /// Rewrite a migrated configuration in place, keeping the original
+    /// next to it as `<name>.v<version>.bak`
+    ///
+    /// Only the settings in the file are written, so defaults left out stay
+    /// defaults.
+    fn upgrade_file(
+        path: &Path,
+        original: &str,
+        migrated: &serde_json::Value,
+        version: u32,
+    ) -> Result<()> {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{}.bak", version));
+        std::fs::write(&backup, original)
+            .with_context(|| format!("Failed to back up config file to: {:?}", backup))?;
+        privileges::adopt(Path::new(&backup))?;
+
+        let content =
+            serde_json::to_string_pretty(migrated).context("Failed to serialize config")? + "\n";
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write migrated config to: {:?}", path))?;
+
+        log::info!(
+            "Migrated configuration from version {} to {}, original saved as {:?}",
+            version,
+            migrate::CURRENT_VERSION,
+            backup
+        );
+        Ok(())
+    }
 
-            Ok(config)
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            Self::load(path)
         } else {
             let default_config = Config::default();
             let content = serde_json::to_string_pretty(&default_config)
                 .context("Failed to serialize default config")?;
 
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                privileges::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create config directory: {:?}", dir))?;
+            }
             std::fs::write(path, content)
                 .with_context(|| format!("Failed to write default config to: {:?}", path))?;
+            privileges::adopt(path)?;
 
             log::info!("Created default configuration file: {:?}", path);
-            Ok(default_config)
+            let value = serde_json::to_value(default_config)
+                .context("Failed to serialize default config")?;
+            Self::from_value_with_env(value)
+        }
+    }
+}
+
+/// Prefix of environment variables overriding settings, e.g.
+/// `MOUSE_GESTURE__GESTURE__SWIPE_THRESHOLD=10` for `gesture.swipe_threshold`
+const ENV_PREFIX: &str = "MOUSE_GESTURE__";
+
+/// Merge `MOUSE_GESTURE__*` variables over a parsed configuration
+///
+/// Path segments are separated by double underscores and lowercased; values
+/// are taken as JSON if they parse as such and as strings otherwise.
+///
+/// Returns the variables applied with the setting each one names.
+pub fn apply_env_overrides(
+    config: &mut serde_json::Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<(String, String)>> {
+    let mut overridden = Vec::new();
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let key = key.to_lowercase().replace("__", ".");
+        let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+
+        log::info!("Overriding {} from {}", key, name);
+        *lookup_mut(config, &key).with_context(|| format!("Invalid override {}", name))? = value;
+        overridden.push((name, key));
+    }
+    Ok(overridden)
+}
+
+/// Value at a dotted path like `gesture.swipe_threshold` or `devices.0.path`
+pub fn lookup<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    use serde_json::Value;
+
+    key.split('.').try_fold(value, |value, part| match value {
+        Value::Object(fields) => fields.get(part),
+        Value::Array(items) => items.get(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Slot at a dotted path, creating missing objects along the way
+pub fn lookup_mut<'a>(
+    value: &'a mut serde_json::Value,
+    key: &str,
+) -> Result<&'a mut serde_json::Value> {
+    use serde_json::Value;
+
+    key.split('.').try_fold(value, |value, part| {
+        if value.is_null() {
+            *value = Value::Object(Default::default());
+        }
+        match value {
+            Value::Object(fields) => Ok(fields.entry(part).or_insert(Value::Null)),
+            Value::Array(items) => part
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| items.get_mut(index))
+                .with_context(|| format!("No element {} in {}", part, key)),
+            _ => anyhow::bail!("{} is not a section in {}", part, key),
         }
+    })
+}
+
+/// Gesture settings with some fields overridden
+fn merge_gesture(
+    gesture: &GestureConfig,
+    overrides: &serde_json::Map<String, serde_json::Value>,
+) -> Result<GestureConfig> {
+    if overrides.is_empty() {
+        return Ok(gesture.clone());
+    }
+
+    let mut merged = serde_json::to_value(gesture).context("Failed to serialize gesture config")?;
+    if let Some(fields) = merged.as_object_mut() {
+        fields.extend(overrides.clone());
+    }
+    Ok(serde_json::from_value(merged)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action_chain() {
+        let actions: HashMap<String, Action> = serde_json::from_str(
+            r#"{
+                "tap_1finger": "click",
+                "swipe_up_2finger": ["xdotool keyup super", {"delay_ms": 50}, "key:super+Tab"],
+                "swipe_right_2finger": {"repeat": "key:volumeup"},
+                "pinch_in": {"argv": ["notify-send", "Pinched in"]},
+                "pinch_out": {"shell": "./zoom.sh", "env": {"STEP": "2"}, "cwd": "/opt/zoom"}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(actions["tap_1finger"], Action::from("click"));
+        assert_eq!(
+            actions["swipe_up_2finger"],
+            Action::Chain(vec![
+                ActionStep::Command("xdotool keyup super".to_string()),
+                ActionStep::Delay { delay_ms: 50 },
+                ActionStep::Command("key:super+Tab".to_string()),
+            ])
+        );
+        assert_eq!(
+            actions["swipe_right_2finger"],
+            Action::Repeat {
+                repeat: "key:volumeup".to_string(),
+                every_mm: 5.0,
+            }
+        );
+        assert_eq!(
+            actions["pinch_in"],
+            Action::Process(ProcessAction {
+                argv: vec!["notify-send".to_string(), "Pinched in".to_string()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            actions["pinch_out"],
+            Action::Process(ProcessAction {
+                shell: Some("./zoom.sh".to_string()),
+                env: HashMap::from([("STEP".to_string(), "2".to_string())]),
+                cwd: Some(PathBuf::from("/opt/zoom")),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_device_action_overrides() {
+        // The default actions click on one-finger and right-click on two-finger taps
+        let config = Config::default();
+        let entry: DeviceEntry = serde_json::from_str(
+            r#"{"name_pattern": "Magic Trackpad", "actions": {"tap_1finger": "middle_click"}}"#,
+        )
+        .unwrap();
+
+        let device = config.for_device(&entry).unwrap();
+        assert_eq!(device.actions["tap_1finger"], Action::from("middle_click"));
+        assert_eq!(device.actions["tap_2finger"], Action::from("right_click"));
+        // Other devices keep the global actions
+        assert_eq!(config.actions["tap_1finger"], Action::from("click"));
+    }
+
+    #[test]
+    fn test_device_gesture_overrides() {
+        let config = Config::default();
+        let entry: DeviceEntry = serde_json::from_str(
+            r#"{"gesture": {"swipe_threshold": "20mm", "scroll_axis_lock": false}}"#,
+        )
+        .unwrap();
+
+        let device = config.for_device(&entry).unwrap();
+        assert_eq!(device.gesture.swipe_threshold, Distance::Millimeters(20.0));
+        assert!(!device.gesture.scroll_axis_lock);
+        // Settings the entry leaves out fall through to the global section
+        assert_eq!(
+            device.gesture.scroll_threshold,
+            config.gesture.scroll_threshold
+        );
+        assert_eq!(device.gesture.tap_timeout_ms, config.gesture.tap_timeout_ms);
+        assert!(config.gesture.scroll_axis_lock);
+
+        let entry: DeviceEntry =
+            serde_json::from_str(r#"{"gesture": {"swipe_threshold": "20cm"}}"#).unwrap();
+        assert!(config.for_device(&entry).is_err());
+    }
+
+    #[test]
+    fn test_resolve_device_type() {
+        let auto = |name| DeviceType::Auto.resolve(name);
+        assert_eq!(auto("Apple Inc. Magic Trackpad"), DeviceType::MagicTrackpad);
+        assert_eq!(auto("Magic Mouse 2"), DeviceType::MagicMouse);
+        assert_eq!(auto("SynPS/2 Synaptics TouchPad"), DeviceType::Generic);
+        // A configured type wins over the name
+        assert_eq!(
+            DeviceType::Generic.resolve("Magic Mouse"),
+            DeviceType::Generic
+        );
+    }
+
+    #[test]
+    fn test_parse_distance() {
+        let parse = |json: &str| serde_json::from_str::<Distance>(json);
+
+        assert_eq!(parse("12").unwrap(), Distance::Scaled(12.0));
+        assert_eq!(parse(r#""12mm""#).unwrap(), Distance::Millimeters(12.0));
+        assert_eq!(parse(r#""300 u""#).unwrap(), Distance::Units(300.0));
+        assert_eq!(parse(r#""10%""#).unwrap(), Distance::Percent(10.0));
+        assert!(parse(r#""12cm""#).is_err());
+        assert_eq!(
+            serde_json::to_string(&Distance::Percent(10.0)).unwrap(),
+            r#""10%""#
+        );
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = serde_json::to_value(Config::default()).unwrap();
+        let vars = [
+            ("MOUSE_GESTURE__GESTURE__SWIPE_THRESHOLD", "10%"),
+            ("MOUSE_GESTURE__DEVICE__GRAB", "true"),
+            ("MOUSE_GESTURE__ACTIONS__TAP_3FINGER", "key:ctrl+w"),
+            ("HOME", "/home/me"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        apply_env_overrides(&mut config, vars.into_iter()).unwrap();
+        let config = Config::from_value(config).unwrap();
+        assert_eq!(config.gesture.swipe_threshold, Distance::Percent(10.0));
+        assert!(config.device.grab);
+        assert!(config.actions.contains_key("tap_3finger"));
+    }
+
+    #[test]
+    fn test_lookup() {
+        use serde_json::Value;
+
+        let mut config = serde_json::json!({
+            "gesture": { "swipe_threshold": 10.0 },
+            "devices": [{ "path": "/dev/input/event3" }]
+        });
+
+        assert_eq!(
+            lookup(&config, "gesture.swipe_threshold"),
+            Some(&Value::from(10.0))
+        );
+        assert_eq!(
+            lookup(&config, "devices.0.path"),
+            Some(&Value::from("/dev/input/event3"))
+        );
+        assert_eq!(lookup(&config, "gesture.missing"), None);
+
+        *lookup_mut(&mut config, "actions.tap_1finger").unwrap() = Value::from("click");
+        assert_eq!(config["actions"]["tap_1finger"], "click");
+        assert!(lookup_mut(&mut config, "gesture.swipe_threshold.x").is_err());
     }
 }
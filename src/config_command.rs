@@ -0,0 +1,159 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::config::{self, lookup, lookup_mut, Config};
+use crate::migrate;
+
+/// Print a setting of the effective configuration, defaults included, e.g.
+/// `gesture.swipe_threshold`
+pub fn get(path: &Path, key: &str) -> Result<()> {
+    let config = serde_json::to_value(Config::load_or_create(path)?)?;
+    let value = lookup(&config, key).with_context(|| format!("Unknown setting: {}", key))?;
+
+    match value {
+        Value::String(text) => println!("{}", text),
+        value => println!("{}", serde_json::to_string_pretty(value)?),
+    }
+    Ok(())
+}
+
+/// Change a setting in the configuration file
+///
+/// `value` is taken as JSON if it parses as such and as a string otherwise.
+/// The file is only replaced once the result validates, and atomically, so a
+/// running daemon reloads either the old or the new version.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    let mut document = load_document(path)?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    *lookup_mut(&mut document, key)? = value;
+
+    // Misspelled fields would otherwise be ignored without a word
+    let config = Config::from_value(document.clone())
+        .with_context(|| format!("Invalid value for {}", key))?;
+    if lookup(&serde_json::to_value(config)?, key).is_none() {
+        bail!("Unknown setting: {}", key);
+    }
+    save_document(path, &document)
+}
+
+/// The configuration file as written, migrated to the current version;
+/// created with the defaults if it doesn't exist
+pub fn load_document(path: &Path) -> Result<Value> {
+    Config::load_or_create(path)?;
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let mut document: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+    migrate::migrate(&mut document)?;
+    Ok(document)
+}
+
+/// Replace the configuration file with `document` in one step
+pub fn save_document(path: &Path, document: &Value) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, serde_json::to_string_pretty(document)? + "\n")
+        .with_context(|| format!("Failed to write {:?}", temporary))?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Failed to replace config file: {:?}", path))?;
+    Ok(())
+}
+
+/// Where an effective setting comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Default,
+    File,
+    Env,
+    Profile,
+    Cli,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Source::Default => "default",
+            Source::File => "file",
+            Source::Env => "env",
+            Source::Profile => "profile",
+            Source::Cli => "cli",
+        })
+    }
+}
+
+/// Print every setting the daemon would run with, each with where its value
+/// comes from
+///
+/// The configuration is built in the same steps as at startup: file, then
+/// environment overrides, then command line flags (`apply_args`), then the
+/// active profile; a setting's source is the last step that changed it.
+pub fn explain(path: &Path, apply_args: &dyn Fn(&mut Config), profile: Option<&str>) -> Result<()> {
+    Config::load_or_create(path)?;
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let mut file: Value = serde_json::from_str(&content)?;
+    migrate::migrate(&mut file)?;
+
+    let mut merged = file.clone();
+    let overridden = config::apply_env_overrides(&mut merged, std::env::vars())?;
+    let mut config = Config::from_value(merged)?;
+    let from_env = serde_json::to_value(&config)?;
+
+    apply_args(&mut config);
+    let from_cli = serde_json::to_value(&config)?;
+
+    let profile = profile
+        .map(str::to_string)
+        .or_else(|| config.profile.clone());
+    let config = config.with_profile(profile.as_deref())?;
+    let effective = serde_json::to_value(&config)?;
+
+    println!("# {}", path.display());
+    if let Some(profile) = &profile {
+        println!("# profile: {}", profile);
+    }
+    for (key, value) in leaves(&effective) {
+        let source = if lookup(&from_cli, &key) != Some(value) {
+            Source::Profile
+        } else if lookup(&from_env, &key) != Some(value) {
+            Source::Cli
+        } else if overridden
+            .iter()
+            .any(|(_, env_key)| key == *env_key || key.starts_with(&format!("{}.", env_key)))
+        {
+            Source::Env
+        } else if lookup(&file, &key).is_some() {
+            Source::File
+        } else {
+            Source::Default
+        };
+        println!("{} = {} ({})", key, value, source);
+    }
+    Ok(())
+}
+
+/// Dotted paths and values of the scalars, empty collections and lists in a
+/// document, in key order
+fn leaves(value: &Value) -> Vec<(String, &Value)> {
+    fn collect<'a>(prefix: String, value: &'a Value, out: &mut Vec<(String, &'a Value)>) {
+        match value {
+            Value::Object(fields) if !fields.is_empty() => {
+                for (key, value) in fields {
+                    let path = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    collect(path, value, out);
+                }
+            }
+            value => out.push((prefix, value)),
+        }
+    }
+
+    let mut out = Vec::new();
+    collect(String::new(), value, &mut out);
+    out
+}
@@ -0,0 +1,386 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::latency;
+use crate::metrics;
+use crate::overlay;
+use crate::paths;
+use crate::profile;
+use crate::reload::ConfigSource;
+use crate::status::{self, StreamEvent};
+use crate::window;
+
+/// A command sent to the control socket, one JSON object per line, e.g.
+/// `{"command": "switch-profile", "profile": "media"}`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Version, uptime, active profile, pause state, devices and gesture
+    /// counts
+    Status,
+    /// Stop running gestures until resumed
+    Pause,
+    Resume,
+    /// Re-read the configuration file
+    ReloadConfig,
+    /// A profile name, `next` or `default`, as with the `profile:` action
+    SwitchProfile {
+        profile: String,
+    },
+    /// Fingers currently on each device
+    ListContacts,
+    /// Counters and histograms in the Prometheus text format
+    Metrics,
+    /// Show or hide the gesture overlay, as the `toggle_overlay` action
+    ToggleOverlay,
+    /// Turn the connection into a stream of recognized gestures, one JSON
+    /// object per line, and contact updates if `contacts` is set
+    Subscribe {
+        #[serde(default)]
+        contacts: bool,
+    },
+}
+
+/// `$XDG_RUNTIME_DIR/mouse-gesture.sock`
+pub fn socket_path() -> PathBuf {
+    paths::runtime_file("mouse-gesture.sock")
+}
+
+/// Control socket that's removed again when dropped
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Accept control connections in the background
+pub fn listen(path: &Path, configs: Arc<ConfigSource>) -> Result<ControlSocket> {
+    if path.exists() {
+        // A socket nobody accepts on is left over from a crash
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            bail!("Another instance is listening on {:?}", path);
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    // The runtime directory is private, but the temporary directory isn't
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    info!("Control socket listening on {:?}", path);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, Arc::clone(&configs)));
+                }
+                Err(e) => warn!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+
+    Ok(ControlSocket {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Answer each request line with a response line until the client hangs up
+async fn serve(stream: UnixStream, configs: Arc<ConfigSource>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = serde_json::from_str::<Request>(&line);
+        if let Ok(Request::Subscribe { contacts }) = request {
+            debug!("Control client subscribed");
+            stream_events(lines, writer, contacts).await;
+            return;
+        }
+
+        let response = match request {
+            Ok(request) => {
+                debug!("Control request: {:?}", request);
+                handle(request, &configs)
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        let response = match response {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert("ok".to_string(), true.into());
+                Value::Object(fields)
+            }
+            Ok(_) => json!({ "ok": true }),
+            Err(e) => json!({ "ok": false, "error": format!("{:#}", e) }),
+        };
+        if writer
+            .write_all(format!("{}\n", response).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Forward events until the client hangs up
+async fn stream_events(
+    mut lines: Lines<BufReader<OwnedReadHalf>>,
+    mut writer: OwnedWriteHalf,
+    contacts: bool,
+) {
+    let mut events = status::subscribe();
+    let _following = contacts.then(status::follow_contacts);
+    if writer.write_all(b"{\"ok\":true}\n").await.is_err() {
+        return;
+    }
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            // Nothing more is read, but EOF tells when to stop
+            line = lines.next_line() => match line {
+                Ok(Some(_)) => continue,
+                _ => return,
+            },
+        };
+        let event = match event {
+            Ok(StreamEvent::Contacts { .. }) if !contacts => continue,
+            Ok(StreamEvent::Gesture {
+                action,
+                archived: Some(archived),
+                ..
+            }) if contacts => StreamEvent::Gesture {
+                action,
+                gesture: archived,
+                archived: None,
+            },
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                debug!("Control client missed {} events", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let Ok(line) = serde_json::to_string(&event) else {
+            continue;
+        };
+        if writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn handle(request: Request, configs: &ConfigSource) -> Result<Value> {
+    Ok(match request {
+        Request::Status => {
+            let devices: Vec<Value> = status::devices()
+                .into_iter()
+                .map(|device| {
+                    json!({
+                        "path": device.path,
+                        "name": device.name,
+                        "contacts": device.contacts.len(),
+                    })
+                })
+                .collect();
+            json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "pid": std::process::id(),
+                "uptime_secs": status::uptime().as_secs(),
+                "paused": status::paused(),
+                "auto_paused": status::auto_paused(),
+                "profile": profile::active(),
+                "window": window::current(),
+                "config": configs.path(),
+                "devices": devices,
+                "gestures": status::gesture_counts(),
+                "latency": latency::percentiles().map(|latency| json!({
+                    "p50_ms": latency.p50.as_secs_f64() * 1000.0,
+                    "p90_ms": latency.p90.as_secs_f64() * 1000.0,
+                    "p99_ms": latency.p99.as_secs_f64() * 1000.0,
+                    "samples": latency.samples,
+                })),
+            })
+        }
+        Request::Pause | Request::Resume => {
+            status::set_paused(request == Request::Pause);
+            json!({ "paused": status::paused() })
+        }
+        Request::ReloadConfig => {
+            configs.reload()?;
+            json!({})
+        }
+        Request::SwitchProfile { profile } => {
+            profile::execute(&configs.current(), &profile)?;
+            json!({ "profile": profile::active() })
+        }
+        Request::ListContacts => json!({ "devices": status::devices() }),
+        Request::Metrics => json!({ "metrics": metrics::render() }),
+        Request::ToggleOverlay => json!({ "overlay": overlay::toggle(&configs.current().overlay) }),
+        Request::Subscribe { .. } => unreachable!("subscriptions are served by stream_events"),
+    })
+}
+
+/// Send one request to the running daemon and print the answer
+pub fn client(path: &Path, request: Request) -> Result<()> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("Daemon is not running (no control socket at {:?})", path))?;
+    writeln!(stream, "{}", serde_json::to_string(&request)?)?;
+
+    let mut reader = std::io::BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let response: Value = serde_json::from_str(&line).context("Invalid response from daemon")?;
+    if response["ok"] != true {
+        bail!("{}", response["error"].as_str().unwrap_or("Request failed"));
+    }
+
+    match request {
+        Request::Status => print_status(&response),
+        Request::Pause => println!("Gestures paused"),
+        Request::Resume => println!("Gestures resumed"),
+        Request::ReloadConfig => println!("Configuration reloaded"),
+        Request::SwitchProfile { .. } => println!(
+            "Profile: {}",
+            response["profile"].as_str().unwrap_or("default")
+        ),
+        Request::ListContacts => {
+            println!("{}", serde_json::to_string_pretty(&response["devices"])?)
+        }
+        Request::Metrics => print!("{}", response["metrics"].as_str().unwrap_or_default()),
+        Request::ToggleOverlay => println!(
+            "Overlay {}",
+            if response["overlay"] == true {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        ),
+        Request::Subscribe { .. } => {
+            for line in reader.lines() {
+                println!("{}", line?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_status(status: &Value) {
+    let uptime = status["uptime_secs"].as_u64().unwrap_or(0);
+    println!(
+        "mouse-gesture-recognition {} (PID {}), up {}:{:02}:{:02}",
+        status["version"].as_str().unwrap_or("?"),
+        status["pid"],
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60
+    );
+    println!(
+        "Gestures: {}",
+        if status["auto_paused"] == true {
+            "paused for the focused window"
+        } else if status["paused"] == true {
+            "paused"
+        } else {
+            "running"
+        }
+    );
+    println!(
+        "Profile:  {}",
+        status["profile"].as_str().unwrap_or("default")
+    );
+    let window = &status["window"];
+    if window.is_object() {
+        println!(
+            "Window:   {} ({}){}",
+            window["class"].as_str().unwrap_or("?"),
+            window["title"].as_str().unwrap_or_default(),
+            if window["fullscreen"] == true {
+                ", fullscreen"
+            } else {
+                ""
+            }
+        );
+    }
+    println!("Config:   {}", status["config"].as_str().unwrap_or("?"));
+
+    println!("Devices:");
+    for device in status["devices"].as_array().into_iter().flatten() {
+        println!(
+            "  {}  {} ({} contacts)",
+            device["path"].as_str().unwrap_or("?"),
+            device["name"].as_str().unwrap_or("?"),
+            device["contacts"]
+        );
+    }
+
+    let latency = &status["latency"];
+    if latency.is_object() {
+        println!(
+            "Latency:  p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms over the last {} gestures",
+            latency["p50_ms"].as_f64().unwrap_or(0.0),
+            latency["p90_ms"].as_f64().unwrap_or(0.0),
+            latency["p99_ms"].as_f64().unwrap_or(0.0),
+            latency["samples"]
+        );
+    }
+
+    let gestures = status["gestures"].as_object();
+    if gestures.is_some_and(|gestures| !gestures.is_empty()) {
+        println!("Recognized gestures:");
+        for (name, count) in gestures.into_iter().flatten() {
+            println!("  {:<24} {}", name, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let parse = |line| serde_json::from_str::<Request>(line);
+
+        assert_eq!(parse(r#"{"command": "pause"}"#).unwrap(), Request::Pause);
+        assert_eq!(
+            parse(r#"{"command": "reload-config"}"#).unwrap(),
+            Request::ReloadConfig
+        );
+        assert_eq!(
+            parse(r#"{"command": "switch-profile", "profile": "media"}"#).unwrap(),
+            Request::SwitchProfile {
+                profile: "media".to_string()
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command": "subscribe"}"#).unwrap(),
+            Request::Subscribe { contacts: false }
+        );
+        assert!(parse(r#"{"command": "switch-profile"}"#).is_err());
+        assert!(parse(r#"{"command": "shutdown"}"#).is_err());
+    }
+}
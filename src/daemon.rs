@@ -0,0 +1,278 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::paths;
+use crate::reload::ConfigSource;
+use crate::status;
+
+const SERVICE_FILE: &str = "mouse-gesture.service";
+
+/// Whether systemd started us as a `Type=notify` service
+pub fn supervised() -> bool {
+    std::env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+/// Move to the background when run from a terminal: fork, let the parent
+/// exit and start a new session
+///
+/// Must run before the tokio runtime exists, since only the forking thread
+/// survives in the child. Under systemd there is nothing to do, the service
+/// manager already runs us detached and tracks the main PID itself.
+pub fn detach() -> Result<()> {
+    // SAFETY: no other threads exist yet, see above
+    match unsafe { libc::fork() } {
+        -1 => bail!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => {}
+        _ => unsafe { libc::_exit(0) },
+    }
+
+    if unsafe { libc::setsid() } == -1 {
+        bail!("setsid failed: {}", std::io::Error::last_os_error());
+    }
+
+    // Logs keep going to stdout and stderr for the caller to redirect
+    let null = std::fs::File::open("/dev/null")?;
+    if unsafe { libc::dup2(std::os::fd::AsRawFd::as_raw_fd(&null), libc::STDIN_FILENO) } == -1 {
+        bail!(
+            "Failed to detach stdin: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+/// Send a state change like `READY=1` to systemd, if it's listening
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send_notification(Path::new(&socket), state) {
+        warn!("Failed to notify systemd ({}): {}", state, e);
+    }
+}
+
+fn send_notification(socket: &Path, state: &str) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::ffi::OsStrExt;
+
+    // A leading '@' stands for the abstract namespace
+    let address = match socket.as_os_str().as_bytes() {
+        [b'@', name @ ..] => SocketAddr::from_abstract_name(name)?,
+        _ => SocketAddr::from_pathname(socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// Tell systemd we're up and keep its watchdog fed, if `WatchdogSec` is set
+pub fn ready() {
+    notify(&format!("READY=1\nMAINPID={}", std::process::id()));
+
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    debug!("Pinging the systemd watchdog every {:?}", interval);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// Half the watchdog timeout systemd expects pings within
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // The timeout may be meant for another process of the service
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Re-read the configuration on every SIGHUP, which is what
+/// `systemctl --user reload` sends
+pub async fn reload_on_hangup(configs: Arc<ConfigSource>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading configuration");
+        notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+        if let Err(e) = configs.reload() {
+            warn!("Keeping previous configuration: {:#}", e);
+        }
+        notify("READY=1");
+    }
+}
+
+/// Pause gestures on SIGUSR1 and resume them on SIGUSR2
+pub async fn pause_on_signals() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (Ok(mut pause), Ok(mut resume)) = (
+        signal(SignalKind::user_defined1()),
+        signal(SignalKind::user_defined2()),
+    ) else {
+        warn!("Failed to listen for SIGUSR1 and SIGUSR2");
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            _ = pause.recv() => status::set_paused(true),
+            _ = resume.recv() => status::set_paused(false),
+        }
+    }
+}
+
+fn monotonic_usec() -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1_000
+}
+
+/// PID file that's removed again when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// `$XDG_RUNTIME_DIR/mouse-gesture.pid`
+    pub fn default_path() -> PathBuf {
+        paths::runtime_file("mouse-gesture.pid")
+    }
+
+    pub fn create(path: PathBuf) -> Result<Self> {
+        if let Some(pid) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| content.trim().parse::<libc::pid_t>().ok())
+        {
+            // Signal 0 only checks whether the process exists
+            if unsafe { libc::kill(pid, 0) } == 0 {
+                bail!("Already running with PID {} (see {:?})", pid, path);
+            }
+        }
+
+        std::fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write PID file: {:?}", path))?;
+        debug!("Wrote PID file {:?}", path);
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Write a systemd user unit running this binary in daemon mode, or print it
+pub fn install_service(config_path: &Path, print: bool) -> Result<()> {
+    let executable = std::env::current_exe().context("Failed to locate this executable")?;
+    let config_path = std::path::absolute(config_path)?;
+    let environment: Vec<(&str, String)> = ["DISPLAY", "WAYLAND_DISPLAY"]
+        .into_iter()
+        .filter_map(|name| Some((name, std::env::var(name).ok()?)))
+        .collect();
+    let unit = render_unit(&executable, &config_path, &environment);
+
+    if print {
+        print!("{}", unit);
+        return Ok(());
+    }
+
+    let directory = paths::systemd_user_dir().context("HOME is not set")?;
+    std::fs::create_dir_all(&directory)
+        .with_context(|| format!("Failed to create {:?}", directory))?;
+    let path = directory.join(SERVICE_FILE);
+    std::fs::write(&path, unit).with_context(|| format!("Failed to write {:?}", path))?;
+
+    println!("Installed {}", path.display());
+    println!("Enable and start it with:");
+    println!("  systemctl --user daemon-reload");
+    println!("  systemctl --user enable --now {}", SERVICE_FILE);
+    Ok(())
+}
+
+fn render_unit(executable: &Path, config_path: &Path, environment: &[(&str, String)]) -> String {
+    let mut unit = format!(
+        "[Unit]
+Description=Magic Mouse Gesture Recognition
+Documentation=https://github.com/jiahaoxiang2000/mouse-gesture
+After=graphical-session.target
+Wants=graphical-session.target
+
+[Service]
+Type=notify
+NotifyAccess=main
+ExecStart={} --daemon --config {}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=always
+RestartSec=5
+WatchdogSec=30
+",
+        quote(&executable.display().to_string()),
+        quote(&config_path.display().to_string())
+    );
+    for (name, value) in environment {
+        unit += &format!("Environment={}\n", quote(&format!("{}={}", name, value)));
+    }
+    unit += "
+# Security settings
+NoNewPrivileges=true
+PrivateTmp=true
+
+[Install]
+WantedBy=default.target
+";
+    unit
+}
+
+/// Quote a unit file argument if it has spaces in it
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_unit() {
+        let unit = render_unit(
+            Path::new("/usr/local/bin/mouse-gesture-recognition"),
+            Path::new("/home/me/My Config/config.json"),
+            &[("WAYLAND_DISPLAY", "wayland-1".to_string())],
+        );
+
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains(
+            "ExecStart=/usr/local/bin/mouse-gesture-recognition --daemon \
+             --config \"/home/me/My Config/config.json\"\n"
+        ));
+        assert!(unit.contains("Environment=WAYLAND_DISPLAY=wayland-1\n"));
+        assert!(unit.ends_with("WantedBy=default.target\n"));
+    }
+}
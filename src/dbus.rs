@@ -0,0 +1,242 @@
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use tokio::sync::OnceCell;
+use zbus::zvariant::{ObjectPath, StructureBuilder, Value};
+use zbus::Connection;
+
+/// Object path and interface of the signals this daemon emits
+pub const SIGNAL_PATH: &str = "/org/mousegesture";
+pub const SIGNAL_INTERFACE: &str = "org.mousegesture";
+
+/// Shared session bus connection, opened on first use
+static SESSION: OnceCell<Connection> = OnceCell::const_new();
+
+/// A method call to send
+#[derive(Debug, PartialEq)]
+pub struct MethodCall {
+    pub destination: String,
+    pub path: String,
+    pub interface: String,
+    pub member: String,
+    pub args: Vec<Value<'static>>,
+}
+
+impl MethodCall {
+    pub fn new(destination: &str, path: &str, interface: &str, member: &str) -> Self {
+        Self {
+            destination: destination.to_string(),
+            path: path.to_string(),
+            interface: interface.to_string(),
+            member: member.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn with_args(mut self, args: Vec<Value<'static>>) -> Self {
+        self.args = args;
+        self
+    }
+}
+
+/// The session bus, connecting first if needed
+pub async fn session() -> Result<Connection> {
+    let connection = SESSION
+        .get_or_try_init(|| async {
+            let connection = Connection::session()
+                .await
+                .context("Failed to connect to the session bus")?;
+            info!("Connected to the session bus");
+            anyhow::Ok(connection)
+        })
+        .await?;
+    Ok(connection.clone())
+}
+
+/// Call a method on the session bus and return its reply
+pub async fn call(call: MethodCall) -> Result<zbus::Message> {
+    let bus = session().await?;
+    let destination = Some(call.destination.as_str());
+    let interface = Some(call.interface.as_str());
+    let reply = if call.args.is_empty() {
+        bus.call_method(
+            destination,
+            call.path.as_str(),
+            interface,
+            call.member.as_str(),
+            &(),
+        )
+        .await
+    } else {
+        // The fields of a structure body are sent as the call's arguments
+        let args = call
+            .args
+            .into_iter()
+            .fold(StructureBuilder::new(), StructureBuilder::append_field)
+            .build()?;
+        bus.call_method(
+            destination,
+            call.path.as_str(),
+            interface,
+            call.member.as_str(),
+            &args,
+        )
+        .await
+    };
+    Ok(reply?)
+}
+
+/// Emit `org.mousegesture.Gesture(action, details)` for a recognized gesture
+pub async fn emit_gesture(action: String, details: String) {
+    let result = async {
+        session()
+            .await?
+            .emit_signal(
+                None::<&str>,
+                SIGNAL_PATH,
+                SIGNAL_INTERFACE,
+                "Gesture",
+                &(action, details),
+            )
+            .await?;
+        anyhow::Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        warn!("Failed to emit gesture signal: {:#}", e);
+    }
+}
+
+/// Parse a `dbus:` action: `dest/path/interface.Method(args)`
+///
+/// Arguments are comma separated: quoted strings, `true`/`false`, integers
+/// (int32, or int64 if they don't fit), decimals (double), or a type name
+/// followed by a value for other types: `uint32 5`, `int64 5`, `uint64 5`,
+/// `byte 5`, `objectpath '/org/example'`.
+pub fn parse_method_call(spec: &str) -> Result<MethodCall> {
+    let (target, args) = match spec.split_once('(') {
+        Some((target, args)) => (
+            target,
+            args.strip_suffix(')')
+                .with_context(|| format!("Missing ')' in D-Bus call: {}", spec))?,
+        ),
+        None => (spec, ""),
+    };
+
+    let (destination, object) = target
+        .split_once('/')
+        .with_context(|| format!("Expected dest/path/interface.Method: {}", spec))?;
+    let (path, method) = object.rsplit_once('/').unwrap_or(("", object));
+    let (interface, member) = method
+        .rsplit_once('.')
+        .with_context(|| format!("Expected interface.Method in: {}", spec))?;
+
+    Ok(
+        MethodCall::new(destination, &format!("/{}", path), interface, member)
+            .with_args(parse_args(args)?),
+    )
+}
+
+fn parse_args(args: &str) -> Result<Vec<Value<'static>>> {
+    let mut values = Vec::new();
+    let mut rest = args.trim();
+    while !rest.is_empty() {
+        let (value, remainder) = parse_arg(rest)?;
+        values.push(value);
+        rest = remainder.trim_start();
+        match rest.strip_prefix(',') {
+            Some(after) => rest = after.trim_start(),
+            None if rest.is_empty() => {}
+            None => bail!("Expected ',' before {}", rest),
+        }
+    }
+    Ok(values)
+}
+
+/// Parse one argument, returning it and the unparsed remainder
+fn parse_arg(input: &str) -> Result<(Value<'static>, &str)> {
+    if let Some(quote @ ('\'' | '"')) = input.chars().next() {
+        let mut text = String::new();
+        let mut chars = input[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => text.extend(chars.next().map(|(_, escaped)| escaped)),
+                c if c == quote => return Ok((Value::from(text), &input[i + 2..])),
+                c => text.push(c),
+            }
+        }
+        bail!("Unterminated string in D-Bus arguments: {}", input);
+    }
+
+    let end = input.find(',').unwrap_or(input.len());
+    let (token, rest) = (input[..end].trim(), &input[end..]);
+
+    if let Some((type_name, literal)) = token.split_once(' ') {
+        let literal = literal.trim();
+        let value = match type_name {
+            "uint32" => Value::U32(literal.parse()?),
+            "int64" => Value::I64(literal.parse()?),
+            "uint64" => Value::U64(literal.parse()?),
+            "byte" => Value::U8(literal.parse()?),
+            "objectpath" => {
+                let (Value::Str(path), _) = parse_arg(literal)? else {
+                    bail!("Object paths must be quoted: {}", literal);
+                };
+                Value::ObjectPath(ObjectPath::try_from(path.to_string())?)
+            }
+            other => bail!("Unknown D-Bus argument type: {}", other),
+        };
+        return Ok((value, rest));
+    }
+
+    let value = match token {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        number => {
+            if let Ok(int) = number.parse::<i32>() {
+                Value::I32(int)
+            } else if let Ok(int) = number.parse::<i64>() {
+                Value::I64(int)
+            } else if let Ok(float) = number.parse::<f64>() {
+                Value::F64(float)
+            } else {
+                bail!("Invalid D-Bus argument: {}", number);
+            }
+        }
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_method_call() {
+        let call = parse_method_call(
+            "org.gnome.Shell/org/gnome/Shell/org.gnome.Shell.Eval('a, \\'b\\'', 3, uint32 4, true)",
+        )
+        .unwrap();
+        assert_eq!(
+            call,
+            MethodCall::new(
+                "org.gnome.Shell",
+                "/org/gnome/Shell",
+                "org.gnome.Shell",
+                "Eval"
+            )
+            .with_args(vec![
+                Value::from("a, 'b'"),
+                Value::I32(3),
+                Value::U32(4),
+                Value::Bool(true),
+            ])
+        );
+
+        let call = parse_method_call("org.example/org.example.Ping").unwrap();
+        assert_eq!(
+            call,
+            MethodCall::new("org.example", "/", "org.example", "Ping")
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{bail, Result};
+use log::debug;
+use zbus::fdo::DBusProxy;
+
+use crate::dbus::{self, SIGNAL_PATH};
+
+/// Name the GNOME Shell extension owns while it listens for requests
+const BRIDGE_NAME: &str = "org.mousegesture.Bridge";
+/// Interface of the `Request(command)` signal the extension acts on
+const DESKTOP_INTERFACE: &str = "org.mousegesture.Desktop";
+
+const KWIN_NAME: &str = "org.kde.KWin";
+/// Prefix of the global shortcuts the KWin script registers per command
+const KWIN_SHORTCUT_PREFIX: &str = "mouse_gesture_";
+
+#[zbus::proxy(
+    interface = "org.kde.kglobalaccel.Component",
+    default_service = "org.kde.kglobalaccel",
+    default_path = "/component/kwin"
+)]
+trait Component {
+    #[zbus(name = "shortcutNames")]
+    fn shortcut_names(&self) -> zbus::Result<Vec<String>>;
+    #[zbus(name = "invokeShortcut")]
+    fn invoke_shortcut(&self, shortcut: &str) -> zbus::Result<()>;
+}
+
+/// Desktop operations the shell integrations carry out natively
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Overview,
+    AppGrid,
+    PresentWindows,
+    ShowDesktop,
+    WorkspaceNext,
+    WorkspacePrevious,
+}
+
+impl Command {
+    const ALL: [Command; 6] = [
+        Command::Overview,
+        Command::AppGrid,
+        Command::PresentWindows,
+        Command::ShowDesktop,
+        Command::WorkspaceNext,
+        Command::WorkspacePrevious,
+    ];
+
+    /// Name in `desktop:` actions and on the bus
+    fn name(self) -> &'static str {
+        match self {
+            Command::Overview => "overview",
+            Command::AppGrid => "app_grid",
+            Command::PresentWindows => "present_windows",
+            Command::ShowDesktop => "show_desktop",
+            Command::WorkspaceNext => "workspace_next",
+            Command::WorkspacePrevious => "workspace_previous",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self> {
+        match Command::ALL
+            .into_iter()
+            .find(|command| command.name() == name)
+        {
+            Some(command) => Ok(command),
+            None => bail!("Unknown desktop action: {}", name),
+        }
+    }
+}
+
+/// Run a `desktop:` action through the GNOME Shell extension or KWin script
+///
+/// The extension follows `org.mousegesture.Desktop.Request` signals. KWin
+/// scripts can't subscribe to D-Bus signals, so the KWin script registers a
+/// global shortcut per command instead, which is invoked over kglobalaccel.
+pub async fn execute(argument: &str) -> Result<()> {
+    let command = Command::parse(argument)?;
+    let bus = dbus::session().await?;
+    let names = DBusProxy::new(&bus).await?;
+
+    if names.name_has_owner(BRIDGE_NAME.try_into()?).await? {
+        debug!(
+            "Requesting {} from the GNOME Shell extension",
+            command.name()
+        );
+        bus.emit_signal(
+            None::<&str>,
+            SIGNAL_PATH,
+            DESKTOP_INTERFACE,
+            "Request",
+            &command.name(),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if names.name_has_owner(KWIN_NAME.try_into()?).await? {
+        let shortcut = format!("{}{}", KWIN_SHORTCUT_PREFIX, command.name());
+        let component = ComponentProxy::new(&bus).await?;
+        if !component.shortcut_names().await?.contains(&shortcut) {
+            bail!("The mouse-gesture KWin script isn't loaded (contrib/kwin)");
+        }
+        debug!("Invoking KWin shortcut {}", shortcut);
+        component.invoke_shortcut(&shortcut).await?;
+        return Ok(());
+    }
+
+    bail!(
+        "No desktop integration is running: enable the GNOME Shell extension \
+         (contrib/gnome-shell) or the KWin script (contrib/kwin)"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        for command in Command::ALL {
+            assert_eq!(Command::parse(command.name()).unwrap(), command);
+        }
+        assert!(Command::parse("workspace-next").is_err());
+    }
+}
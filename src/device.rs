@@ -1,25 +1,210 @@
 use anyhow::{Context, Result};
-use evdev::Device;
+use evdev::raw_stream::RawDevice;
+use evdev::{AbsoluteAxisType, Device};
 use log::{debug, error, info, warn};
+use std::io;
+use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::action_queue::ActionQueue;
+use crate::config::{Config, DeviceConfig, DeviceEntry, DeviceType, GestureConfig};
 use crate::event_handler::EventHandler;
-use crate::multitouch::MultiTouchProcessor;
+use crate::event_queue;
+use crate::lock;
+use crate::metrics;
+use crate::multitouch::{
+    self, AxisInfo, DeviceInfo, MtSlotState, MultiTouchEvent, MultiTouchProcessor, SurfaceBounds,
+};
+use crate::passthrough::Passthrough;
+use crate::reload::LiveConfig;
+use crate::scheduling;
+use crate::stats::SessionTracker;
+use crate::status;
+use crate::touchpad::VirtualTouchpad;
+use crate::visualize::{Screen, Visualizer};
 
 pub struct MagicMouseDevice {
     device: Device,
     path: PathBuf,
+    device_type: DeviceType,
+    model: Option<MagicMouseModel>,
+    surface: SurfaceBounds,
+    /// Opened ahead of time by `open_ahead`, used by the next recognition run
+    reader: Option<RawDevice>,
+    passthrough: Option<Passthrough>,
+    touchpad: Option<VirtualTouchpad>,
+}
+
+/// Magic Mouse hardware generations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MagicMouseModel {
+    MagicMouse1,
+    MagicMouse2,
+    /// Magic Mouse 2 with USB-C (2024), which the default thresholds are tuned on
+    MagicMouseUsbC,
+}
+
+impl MagicMouseModel {
+    /// Identify the model from its USB or Bluetooth vendor/product IDs
+    pub fn from_ids(vendor: u16, product: u16) -> Option<Self> {
+        const USB_VENDOR_ID_APPLE: u16 = 0x05ac;
+        const BT_VENDOR_ID_APPLE: u16 = 0x004c;
+        if vendor != USB_VENDOR_ID_APPLE && vendor != BT_VENDOR_ID_APPLE {
+            return None;
+        }
+
+        match product {
+            0x030d => Some(MagicMouseModel::MagicMouse1),
+            0x0269 => Some(MagicMouseModel::MagicMouse2),
+            0x0323 => Some(MagicMouseModel::MagicMouseUsbC),
+            _ => None,
+        }
+    }
+
+    /// Surface calibration of this model
+    ///
+    /// hid-magicmouse describes the same 90.56mm x 51.52mm surface for both
+    /// Bluetooth generations, at 26 (X) and 70 (Y) units per mm; evtest on the
+    /// USB-C model reports the same.
+    pub fn calibration(self) -> SurfaceBounds {
+        match self {
+            MagicMouseModel::MagicMouse1 | MagicMouseModel::MagicMouse2 => SurfaceBounds {
+                x: AxisInfo {
+                    min: -1100,
+                    max: 1258,
+                    resolution: 26,
+                },
+                y: AxisInfo {
+                    min: -1589,
+                    max: 2047,
+                    resolution: 70,
+                },
+            },
+            MagicMouseModel::MagicMouseUsbC => SurfaceBounds::default(),
+        }
+    }
+
+    /// `config` with the settings it leaves unset filled in with this
+    /// model's defaults
+    ///
+    /// The defaults are tuned on the USB-C model, whose sensor the Magic
+    /// Mouse 2 shares. The first generation reports touches less often over
+    /// classic Bluetooth, so a quick tap spans fewer reports and gets a longer
+    /// window.
+    pub fn default_thresholds(self, config: &GestureConfig) -> GestureConfig {
+        let mut adjusted = config.clone();
+        if self == MagicMouseModel::MagicMouse1 {
+            adjusted
+                .tap_timeout_ms
+                .get_or_insert(MAGIC_MOUSE_1_TAP_TIMEOUT_MS);
+            adjusted
+                .two_finger_tap_timeout_ms
+                .get_or_insert(MAGIC_MOUSE_1_TWO_FINGER_TAP_TIMEOUT_MS);
+        }
+        adjusted
+    }
+}
+
+/// Tap windows of the first generation Magic Mouse (milliseconds)
+const MAGIC_MOUSE_1_TAP_TIMEOUT_MS: u64 = 350;
+const MAGIC_MOUSE_1_TWO_FINGER_TAP_TIMEOUT_MS: u64 = 300;
+
+/// First delay between reconnection attempts, doubled after every failure
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+/// Upper bound for the reconnection delay
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Consecutive read errors after which the device is considered gone
+const MAX_CONSECUTIVE_READ_ERRORS: u32 = 10;
+
+/// Redraw interval of `visualize`
+const VISUALIZE_FRAME: Duration = Duration::from_millis(33);
+
+/// Device lifecycle events, bindable to the `on_device_connected` and
+/// `on_device_disconnected` actions
+#[derive(Debug, Clone)]
+pub enum DeviceEvent {
+    Connected { path: PathBuf, name: String },
+    Disconnected { path: PathBuf },
+}
+
+/// Surface calibration used when the device doesn't report ranges or resolution
+///
+/// Ranges are those set up by hid-magicmouse; resolutions are in units per mm.
+pub fn calibration(device_type: DeviceType, model: Option<MagicMouseModel>) -> SurfaceBounds {
+    match device_type {
+        DeviceType::Auto | DeviceType::MagicMouse if model.is_some() => {
+            model.map(MagicMouseModel::calibration).unwrap_or_default()
+        }
+        DeviceType::MagicTrackpad => SurfaceBounds {
+            x: AxisInfo {
+                min: -3678,
+                max: 3934,
+                resolution: 48,
+            },
+            y: AxisInfo {
+                min: -2478,
+                max: 2587,
+                resolution: 44,
+            },
+        },
+        // Generic touchpads have no fixed calibration; see `query_surface_bounds`
+        DeviceType::Auto | DeviceType::MagicMouse | DeviceType::Generic => SurfaceBounds::default(),
+    }
+}
+
+/// Size assumed for generic touchpads that don't report a resolution (mm)
+const GENERIC_TOUCHPAD_SIZE_MM: (f64, f64) = (100.0, 65.0);
+
+/// Diagonal of the Magic Mouse touch surface, which the default thresholds are
+/// tuned for (mm)
+fn reference_diagonal_mm() -> f64 {
+    let surface = SurfaceBounds::default();
+    surface.x.length_mm().hypot(surface.y.length_mm())
+}
+
+/// Adapt the bare millimeter thresholds to the touch surface size
+///
+/// The defaults are tuned for the USB-C Magic Mouse; on a laptop touchpad the
+/// same finger travel is a much smaller fraction of the surface, so distance
+/// thresholds grow with the surface diagonal.
+pub fn scale_thresholds(config: &GestureConfig, surface: &SurfaceBounds) -> GestureConfig {
+    let diagonal = surface.x.length_mm().hypot(surface.y.length_mm());
+    let factor = (diagonal / reference_diagonal_mm()).clamp(0.5, 3.0);
+    debug!(
+        "Scaling distance thresholds by {:.2} for surface size",
+        factor
+    );
+
+    let mut scaled = config.clone();
+    scaled.scroll_threshold = config.scroll_threshold.scaled(factor);
+    scaled.swipe_threshold = config.swipe_threshold.scaled(factor);
+    scaled.single_finger_tap_movement_threshold =
+        config.single_finger_tap_movement_threshold.scaled(factor);
+    scaled.two_finger_tap_distance_threshold =
+        config.two_finger_tap_distance_threshold.scaled(factor);
+    scaled.dwell_radius = config.dwell_radius.scaled(factor);
+    scaled.tremor_deadband = config.tremor_deadband.scaled(factor);
+    scaled
 }
 
 impl MagicMouseDevice {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub fn new<P: AsRef<Path>>(path: P, device_type: DeviceType) -> Result<Self> {
         let path = path.as_ref().to_path_buf();
         let device =
             Device::open(&path).with_context(|| format!("Failed to open device: {:?}", path))?;
 
-        info!("Opened Magic Mouse device: {:?}", path);
-        info!("Device name: {}", device.name().unwrap_or("Unknown"));
+        let name = device.name().unwrap_or("Unknown");
+        let device_type = device_type.resolve(name);
+        info!("Opened {:?} device: {:?}", device_type, path);
+        info!("Device name: {}", name);
+
+        let input_id = device.input_id();
+        let model = MagicMouseModel::from_ids(input_id.vendor(), input_id.product());
+        if let Some(model) = model {
+            info!("Detected model: {:?}", model);
+        }
 
         // Log device capabilities for debugging
         debug!("Device capabilities:");
@@ -31,26 +216,301 @@ impl MagicMouseDevice {
         );
         debug!(
             "  Device supports multi-touch: {}",
-            device.supported_absolute_axes().map_or(false, |axes| {
-                axes.contains(evdev::AbsoluteAxisType::ABS_MT_SLOT)
-            })
+            device
+                .supported_absolute_axes()
+                .is_some_and(|axes| axes.contains(AbsoluteAxisType::ABS_MT_SLOT))
+        );
+
+        debug!(
+            "  Device reports pressure: {}",
+            device
+                .supported_absolute_axes()
+                .is_some_and(|axes| axes.contains(AbsoluteAxisType::ABS_MT_PRESSURE))
         );
 
-        Ok(Self { device, path })
+        let surface = surface_bounds(query_position_axes(&device), device_type, model);
+        debug!(
+            "  Touch surface: {:.1}mm x {:.1}mm ({:?})",
+            surface.x.length_mm(),
+            surface.y.length_mm(),
+            surface
+        );
+
+        Ok(Self {
+            device,
+            path,
+            device_type,
+            model,
+            surface,
+            reader: None,
+            passthrough: None,
+            touchpad: None,
+        })
+    }
+
+    /// Open everything recognition needs now, before the process gives up
+    /// the privileges to open `/dev/input` and `/dev/uinput`
+    ///
+    /// Only the first recognition run uses these; reconnecting opens the
+    /// device again with whatever access the process has by then.
+    pub fn open_ahead(&mut self, device_config: &DeviceConfig) -> Result<()> {
+        self.reader = Some(
+            RawDevice::open(&self.path)
+                .with_context(|| format!("Failed to open device: {:?}", self.path))?,
+        );
+        if device_config.grab {
+            self.passthrough = Passthrough::new(&self.device, device_config.passthrough_scroll)
+                .map_err(|e| warn!("Passthrough unavailable: {:#}", e))
+                .ok();
+        }
+        self.touchpad = VirtualTouchpad::open(&self.surface, &device_config.touchpad);
+        Ok(())
+    }
+
+    /// Make sure no other daemon handles this device, see `lock::lock_device`
+    pub fn lock(&self) -> Result<()> {
+        lock::lock_device(&self.device, &self.path)
+    }
+
+    /// Run recognition, re-discovering and reopening the device whenever it
+    /// disappears (e.g. a Bluetooth mouse going to sleep)
+    pub async fn run<F>(self, mut live: LiveConfig, reconnect: bool, rediscover: F) -> Result<()>
+    where
+        F: Fn() -> Result<PathBuf>,
+    {
+        let mut event_handler = Arc::new(EventHandler::new(live.current()?));
+        let mut device = self;
+        loop {
+            let name = device.device.name().unwrap_or("Unknown").to_string();
+            let path = device.path.clone();
+            let device_type = device.device_type;
+
+            event_handler
+                .handle_device_event(DeviceEvent::Connected {
+                    path: path.clone(),
+                    name,
+                })
+                .await;
+            device
+                .start_recognition(&mut event_handler, &mut live)
+                .await?;
+            if status::shutting_down() {
+                return Ok(());
+            }
+
+            // Close the stale fd before trying to reopen the device
+            drop(device);
+            warn!("Device {:?} disconnected", path);
+            event_handler
+                .handle_device_event(DeviceEvent::Disconnected { path })
+                .await;
+
+            if !reconnect {
+                return Ok(());
+            }
+
+            let mut attempt = 0;
+            device = loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(reconnect_delay(attempt)) => {}
+                    _ = status::shutdown_requested() => return Ok(()),
+                }
+                let reopened = rediscover()
+                    .and_then(|path| MagicMouseDevice::new(path, device_type))
+                    .and_then(|device| device.lock().map(|()| device));
+                match reopened {
+                    Ok(device) => break device,
+                    Err(e) => {
+                        attempt += 1;
+                        debug!(
+                            "Reconnection failed, retrying in {:?}: {}",
+                            reconnect_delay(attempt),
+                            e
+                        );
+                    }
+                }
+            };
+            info!("Device {:?} reconnected", device.path);
+            metrics::reconnected();
+        }
+    }
+
+    /// Multi-touch processor set up for this device's surface
+    fn processor(&self, config: &GestureConfig) -> MultiTouchProcessor {
+        let info = DeviceInfo::new(self.device.name().unwrap_or("Unknown"), self.surface);
+        gesture_processor(config, self.device_type, self.model, info)
     }
 
-    pub async fn start_recognition(&mut self, event_handler: EventHandler) -> Result<()> {
-        let (tx, mut rx) = mpsc::channel(1000);
+    /// Print raw events, kernel slot state and the processor's contact table
+    /// live, so unrecognized gestures can be debugged without evtest
+    pub async fn monitor(&mut self, config: &GestureConfig) -> Result<()> {
+        let mut mt_processor = self.processor(config);
+
+        match read_mt_slots(&self.device) {
+            Ok((slots, current_slot)) => {
+                println!("Kernel slot state (current slot {}):", current_slot);
+                for slot in slots.iter().filter(|slot| slot.tracking_id >= 0) {
+                    println!(
+                        "  slot {:>2}  id {:>5}  x {:>6}  y {:>6}",
+                        slot.slot, slot.tracking_id, slot.x, slot.y
+                    );
+                }
+                mt_processor.seed(&slots, current_slot);
+            }
+            Err(e) => warn!("Could not read slot state: {}", e),
+        }
+
+        let mut events = RawDevice::open(&self.path)
+            .and_then(|device| device.into_event_stream())
+            .with_context(|| format!("Failed to open device: {:?}", self.path))?;
+        let mut touches_changed = false;
+
+        loop {
+            let event = events.next_event().await?;
+            println!("{}", describe_event(&event));
+            touches_changed |= event.event_type() == evdev::EventType::ABSOLUTE;
+
+            if let Some(mt_events) = mt_processor.process_event(event).await {
+                for mt_event in mt_events {
+                    println!("=> {}", describe_gesture(&mt_event));
+                }
+            }
+
+            if mt_processor.needs_resync() {
+                println!("-- SYN_DROPPED, resyncing slot state --");
+                let (slots, current_slot) = read_mt_slots(&self.device).unwrap_or_default();
+                mt_processor.resync(&slots, current_slot);
+            }
+
+            let is_report = event.event_type() == evdev::EventType::SYNCHRONIZATION
+                && event.code() == evdev::Synchronization::SYN_REPORT.0;
+            if is_report && touches_changed {
+                print_contact_table(&mt_processor);
+                touches_changed = false;
+            }
+        }
+    }
+
+    /// Draw the surface with live contacts and fired gestures until `q`,
+    /// Escape or Ctrl-C
+    pub async fn visualize(&mut self, config: &GestureConfig) -> Result<()> {
+        let mut mt_processor = self.processor(config);
+        if let Ok((slots, current_slot)) = read_mt_slots(&self.device) {
+            mt_processor.seed(&slots, current_slot);
+        }
+
+        let mut events = RawDevice::open(&self.path)
+            .and_then(|device| device.into_event_stream())
+            .with_context(|| format!("Failed to open device: {:?}", self.path))?;
+        // Drawn the way the device is held
+        let info = mt_processor.device_info();
+        let held = DeviceInfo::new(info.name.clone(), info.surface.rotated(config.rotation));
+        let mut view = Visualizer::new(&held);
+        view.update(&mt_processor.active_contacts(), Instant::now());
+
+        let mut screen = Screen::open()?;
+        let mut frames = tokio::time::interval(VISUALIZE_FRAME);
+        frames.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let interrupted = tokio::signal::ctrl_c();
+        tokio::pin!(interrupted);
+        let mut changed = true;
+        loop {
+            tokio::select! {
+                event = events.next_event() => {
+                    let event = event?;
+                    let now = Instant::now();
+                    if let Some(mt_events) = mt_processor.process_event(event).await {
+                        for mt_event in &mt_events {
+                            view.gesture(mt_event, now);
+                        }
+                    }
+                    if mt_processor.needs_resync() {
+                        let (slots, current_slot) = read_mt_slots(&self.device).unwrap_or_default();
+                        mt_processor.resync(&slots, current_slot);
+                    }
+                    if event.event_type() == evdev::EventType::SYNCHRONIZATION
+                        && event.code() == evdev::Synchronization::SYN_REPORT.0
+                    {
+                        view.update(&mt_processor.active_contacts(), now);
+                        changed = true;
+                    }
+                }
+                _ = frames.tick() => {
+                    if screen.quit_pressed()? {
+                        return Ok(());
+                    }
+                    let now = Instant::now();
+                    // Keep drawing while trails fade and the flash wears off
+                    if view.expire(now) || changed {
+                        screen.draw(&view, now)?;
+                        changed = false;
+                    }
+                }
+                _ = &mut interrupted => return Ok(()),
+            }
+        }
+    }
+
+    /// Process events until the device goes away
+    ///
+    /// Reloaded configurations replace `event_handler` as they arrive; new
+    /// gesture thresholds wait until no finger is down so a touch session
+    /// isn't cut short.
+    pub async fn start_recognition(
+        &mut self,
+        event_handler: &mut Arc<EventHandler>,
+        live: &mut LiveConfig,
+    ) -> Result<()> {
+        let device_config = &event_handler.config.device;
+        let (mut tx, mut rx) =
+            event_queue::channel(device_config.event_buffer, device_config.overflow);
+
+        info!(
+            "Listening for touches on {} ({:?})",
+            self.device.name().unwrap_or("Unknown"),
+            self.device_type
+        );
+        let registration = status::register(&self.path, self.device.name().unwrap_or("Unknown"));
 
         // Create multi-touch processor
-        let mut mt_processor = MultiTouchProcessor::new(event_handler.config.gesture.clone());
+        let mut mt_processor = self.processor(&event_handler.config.gesture);
+
+        // Pick up fingers that are already resting on the surface
+        match read_mt_slots(&self.device) {
+            Ok((slots, current_slot)) => mt_processor.seed(&slots, current_slot),
+            Err(e) => debug!("Could not read initial slot state: {}", e),
+        }
+
+        // With an exclusive grab, pointer motion and clicks have to be re-injected;
+        // without a passthrough device grabbing would freeze the pointer
+        let prepared = self.passthrough.take().map(Ok);
+        let mut passthrough = if device_config.grab {
+            match prepared
+                .unwrap_or_else(|| Passthrough::new(&self.device, device_config.passthrough_scroll))
+            {
+                Ok(passthrough) => Some(passthrough),
+                Err(e) => {
+                    warn!("Not grabbing device, passthrough unavailable: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut touchpad = match self.touchpad.take() {
+            Some(touchpad) => Some(touchpad),
+            None => VirtualTouchpad::open(&self.surface, &device_config.touchpad),
+        };
 
-        // Spawn event reader task
+        // Spawn event reader task; it owns the only sender, so the processing loop
+        // below ends once the device is gone
         let device_path = self.path.clone();
-        let tx_clone = tx.clone();
+        let opened = self.reader.take().map(Ok);
 
-        tokio::spawn(async move {
-            let mut device = match Device::open(&device_path) {
+        let reader = scheduling::spawn_reader(async move {
+            // The raw device passes SYN_DROPPED through instead of papering over it,
+            // so the multi-touch processor can resync its slot state
+            let mut device = match opened.unwrap_or_else(|| RawDevice::open(&device_path)) {
                 Ok(d) => d,
                 Err(e) => {
                     error!("Failed to open device in reader task: {}", e);
@@ -58,76 +518,895 @@ impl MagicMouseDevice {
                 }
             };
 
+            // The grab only applies to this fd, which is the one we read from
+            if passthrough.is_some() {
+                match device.grab() {
+                    Ok(()) => info!("Grabbed device exclusively"),
+                    Err(e) => {
+                        warn!("Failed to grab device: {}", e);
+                        passthrough = None;
+                    }
+                }
+            }
+
+            // Events are read as soon as the fd becomes readable instead of polling
+            let mut events = match device.into_event_stream() {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("Failed to register device for async reads: {}", e);
+                    return;
+                }
+            };
+
+            // Paused for the focused window, the desktop may get the touches
+            let mut grab_released = status::subscribe_grab_released();
+            grab_released.mark_changed();
+            let mut grabbed = passthrough.is_some();
+
+            let mut consecutive_errors = 0;
             loop {
-                match device.fetch_events() {
-                    Ok(events) => {
-                        for event in events {
-                            if let Err(e) = tx_clone.send(event).await {
-                                error!("Failed to send event: {}", e);
-                                break;
+                let event = tokio::select! {
+                    event = events.next_event() => event,
+                    Ok(()) = grab_released.changed(), if passthrough.is_some() => {
+                        let grab = !*grab_released.borrow_and_update();
+                        if grab != grabbed {
+                            let device = events.device_mut();
+                            match if grab { device.grab() } else { device.ungrab() } {
+                                Ok(()) => {
+                                    info!("{} device", if grab { "Grabbed" } else { "Released" });
+                                    grabbed = grab;
+                                }
+                                Err(e) => warn!("Failed to change the device grab: {}", e),
                             }
                         }
+                        continue;
+                    }
+                };
+                match event {
+                    Ok(event) => {
+                        consecutive_errors = 0;
+                        if let Some(passthrough) = passthrough.as_mut().filter(|_| grabbed) {
+                            if let Err(e) = passthrough.forward(event) {
+                                warn!("Failed to forward event: {}", e);
+                            }
+                        }
+                        if let Err(e) = tx.send(event).await {
+                            error!("Failed to send event: {}", e);
+                            break;
+                        }
+                    }
+                    Err(e) if e.raw_os_error() == Some(libc::ENODEV) => {
+                        debug!("Device removed: {}", e);
+                        break;
                     }
                     Err(e) => {
                         error!("Failed to fetch events: {}", e);
+                        consecutive_errors += 1;
+                        if consecutive_errors >= MAX_CONSECUTIVE_READ_ERRORS {
+                            break;
+                        }
                         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                     }
                 }
-
-                // Small delay to prevent busy waiting
-                tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
             }
         });
 
-        // Process events
-        while let Some(event) = rx.recv().await {
-            // Only process ABS_* events through multi-touch processor
-            if event.event_type() == evdev::EventType::ABSOLUTE {
+        // Process events; actions run on the queue's worker so they can't stall
+        // reading touches
+        let queue = ActionQueue::new(event_handler.config.execution.queue_size);
+        let mut was_touching = !mt_processor.active_contacts().is_empty();
+        let mut session = SessionTracker::default();
+        let mut gesture_changed = false;
+        let mut shutdown = std::pin::pin!(status::shutdown_requested());
+        let mut failure = None;
+        'events: loop {
+            let deadline = mt_processor.deadline();
+            let event = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = &mut shutdown => break,
+                // A resting finger sends nothing, but its dwell click is due, or
+                // a contact whose release was missed goes stale
+                _ = multitouch::wait_for_deadline(deadline) => {
+                    let mt_events = mt_processor.poll();
+                    if mt_events.is_some() {
+                        session.recognized();
+                    }
+                    publish_contacts(&mt_processor, &registration, touchpad.as_mut());
+                    if let Err(e) = dispatch(
+                        &queue,
+                        event_handler,
+                        &mt_processor,
+                        touchpad.as_ref(),
+                        mt_events.unwrap_or_default(),
+                    ) {
+                        failure = Some(e);
+                        break 'events;
+                    }
+                    continue;
+                }
+            };
+            if let Some(config) = live.changed() {
+                *event_handler = Arc::new(EventHandler::new(config));
+                gesture_changed = true;
+            }
+
+            // Touches (ABS_*), buttons and BTN_TOOL_* hints (KEY_*), pointer motion
+            // (REL_*) and SYN_* events are relevant to the multi-touch processor
+            if matches!(
+                event.event_type(),
+                evdev::EventType::ABSOLUTE
+                    | evdev::EventType::KEY
+                    | evdev::EventType::RELATIVE
+                    | evdev::EventType::SYNCHRONIZATION
+            ) {
                 debug!("Raw event: {:?}", event);
-                if let Some(mt_events) = mt_processor.process_event(event).await {
-                    for mt_event in mt_events {
-                        // Handle the multi-touch event
-                        if let Err(e) = event_handler.handle_multitouch_event(mt_event).await {
-                            warn!("Failed to handle multi-touch event: {}", e);
+                metrics::event_processed();
+                let mt_events = mt_processor.process_event(event).await;
+                let touching = !mt_processor.active_contacts().is_empty();
+                if touching && !was_touching {
+                    event_handler.handle_touch_begin();
+                }
+                was_touching = touching;
+                if mt_events.is_some() {
+                    session.recognized();
+                }
+                session.update(mt_processor.active_contacts().len());
+                if event.event_type() == evdev::EventType::SYNCHRONIZATION {
+                    publish_contacts(&mt_processor, &registration, touchpad.as_mut());
+                }
+
+                if let Err(e) = dispatch(
+                    &queue,
+                    event_handler,
+                    &mt_processor,
+                    touchpad.as_ref(),
+                    mt_events.unwrap_or_default(),
+                ) {
+                    failure = Some(e);
+                    break 'events;
+                }
+
+                if gesture_changed && !touching {
+                    mt_processor = self.processor(&event_handler.config.gesture);
+                    if let Ok((slots, current_slot)) = read_mt_slots(&self.device) {
+                        mt_processor.seed(&slots, current_slot);
+                    }
+                    gesture_changed = false;
+                }
+
+                if mt_processor.needs_resync() {
+                    match read_mt_slots(&self.device) {
+                        Ok((slots, current_slot)) => mt_processor.resync(&slots, current_slot),
+                        Err(e) => {
+                            warn!("Failed to read multi-touch slot state: {}", e);
+                            mt_processor.resync(&[], 0);
                         }
                     }
                 }
             }
         }
 
+        // Closing the device releases the grab and the passthrough device with it
+        reader.abort();
+        let reader = reader.await;
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        // A reader that panicked looks like a disconnect to the loop above
+        if reader.is_err_and(|e| e.is_panic()) {
+            anyhow::bail!("Device reader task panicked");
+        }
+        if status::shutting_down() {
+            queue.flush().await;
+        }
         Ok(())
     }
 }
 
-/// Find Magic Mouse device automatically
-pub fn find_magic_mouse_device(name_pattern: &str) -> Result<PathBuf> {
-    let input_dir = Path::new("/dev/input");
+/// Report the tracked contacts to control clients and mirror them on the
+/// virtual touchpad
+pub fn publish_contacts(
+    mt_processor: &MultiTouchProcessor,
+    registration: &status::Registration,
+    touchpad: Option<&mut VirtualTouchpad>,
+) {
+    let contacts = mt_processor.active_contacts();
+    registration.update_contacts(&contacts);
+    if let Some(touchpad) = touchpad {
+        if let Err(e) = touchpad.update(&contacts) {
+            warn!("Failed to forward touches to the virtual touchpad: {}", e);
+        }
+    }
+}
+
+/// Queue the actions of recognized gestures, except the ones the virtual
+/// touchpad leaves to the desktop
+pub fn dispatch(
+    queue: &ActionQueue,
+    event_handler: &Arc<EventHandler>,
+    mt_processor: &MultiTouchProcessor,
+    touchpad: Option<&VirtualTouchpad>,
+    mt_events: Vec<MultiTouchEvent>,
+) -> Result<()> {
+    let modifiers = mt_processor.modifiers();
+    let input_time = mt_processor.input_time();
+    for mt_event in mt_events {
+        metrics::gesture_recognized(mt_event.kind());
+        if touchpad.is_some_and(|touchpad| touchpad.handles(&mt_event)) {
+            debug!("{} left to the desktop", mt_event.kind());
+            continue;
+        }
+        queue.push(event_handler, mt_event, modifiers, input_time)?;
+    }
+    Ok(())
+}
 
-    if !input_dir.exists() {
-        return Err(anyhow::anyhow!("/dev/input directory not found"));
+/// Multi-touch processor for `device`, of `device_type` and `model`
+pub fn gesture_processor(
+    config: &GestureConfig,
+    device_type: DeviceType,
+    model: Option<MagicMouseModel>,
+    device: DeviceInfo,
+) -> MultiTouchProcessor {
+    // Defaults are tuned on the Magic Mouse, whose generations share a surface
+    // size; the trackpad uses them as-is
+    let gesture_config = match device_type {
+        DeviceType::Generic => scale_thresholds(config, &device.surface),
+        DeviceType::MagicTrackpad => config.clone(),
+        DeviceType::Auto | DeviceType::MagicMouse => match model {
+            Some(model) => model.default_thresholds(config),
+            None => config.clone(),
+        },
+    };
+    MultiTouchProcessor::new(gesture_config).with_device(device)
+}
+
+/// Read the multi-touch position ranges and resolution (EVIOCGABS) of the device
+fn query_position_axes(device: &Device) -> Option<SurfaceBounds> {
+    let axes = device.supported_absolute_axes()?;
+    if !axes.contains(AbsoluteAxisType::ABS_MT_POSITION_X)
+        || !axes.contains(AbsoluteAxisType::ABS_MT_POSITION_Y)
+    {
+        return None;
     }
 
-    for entry in std::fs::read_dir(input_dir)? {
-        let entry = entry?;
-        let path = entry.path();
+    let state = device.get_abs_state().ok()?;
+    let x = &state[AbsoluteAxisType::ABS_MT_POSITION_X.0 as usize];
+    let y = &state[AbsoluteAxisType::ABS_MT_POSITION_Y.0 as usize];
+    Some(SurfaceBounds {
+        x: AxisInfo {
+            min: x.minimum,
+            max: x.maximum,
+            resolution: x.resolution,
+        },
+        y: AxisInfo {
+            min: y.minimum,
+            max: y.maximum,
+            resolution: y.resolution,
+        },
+    })
+}
 
-        // Only check event devices
-        if let Some(filename) = path.file_name() {
-            if let Some(filename_str) = filename.to_str() {
-                if filename_str.starts_with("event") {
-                    // Try to open the device and check its name
-                    if let Ok(device) = Device::open(&path) {
-                        if let Some(device_name) = device.name() {
-                            if device_name.contains(name_pattern) {
-                                info!("Found Magic Mouse device: {} at {:?}", device_name, path);
-                                return Ok(path);
-                            }
-                        }
-                    }
-                }
+/// Touch surface of a device whose position axes report `axes`, filling in
+/// what they leave out from the built-in calibration
+pub fn surface_bounds(
+    axes: Option<SurfaceBounds>,
+    device_type: DeviceType,
+    model: Option<MagicMouseModel>,
+) -> SurfaceBounds {
+    let calibration = calibration(device_type, model);
+    let Some(mut surface) = axes else {
+        warn!(
+            "Could not read touch surface ranges, assuming {:?} defaults",
+            device_type
+        );
+        return calibration;
+    };
+
+    if surface.x.resolution <= 0 || surface.y.resolution <= 0 {
+        if device_type == DeviceType::Generic {
+            // Derive a resolution by assuming a typical laptop touchpad size
+            warn!(
+                "Device reports no touch resolution, assuming a {}x{}mm surface",
+                GENERIC_TOUCHPAD_SIZE_MM.0, GENERIC_TOUCHPAD_SIZE_MM.1
+            );
+            let (width_mm, height_mm) = GENERIC_TOUCHPAD_SIZE_MM;
+            surface.x.resolution =
+                ((surface.x.max - surface.x.min) as f64 / width_mm).round() as i32;
+            surface.y.resolution =
+                ((surface.y.max - surface.y.min) as f64 / height_mm).round() as i32;
+        } else {
+            warn!("Device reports no touch resolution, using built-in calibration");
+        }
+    }
+
+    surface.with_fallback_resolution(&calibration)
+}
+
+/// Whether a device speaks multi-touch protocol type B and behaves like a touchpad
+///
+/// Touchscreens and tablets (INPUT_PROP_DIRECT) are excluded since their touches
+/// are already handled as direct pointer input.
+pub fn is_multitouch_touchpad(device: &Device) -> bool {
+    let has_mt_axes = device.supported_absolute_axes().is_some_and(|axes| {
+        axes.contains(AbsoluteAxisType::ABS_MT_SLOT)
+            && axes.contains(AbsoluteAxisType::ABS_MT_TRACKING_ID)
+            && axes.contains(AbsoluteAxisType::ABS_MT_POSITION_X)
+            && axes.contains(AbsoluteAxisType::ABS_MT_POSITION_Y)
+    });
+
+    has_mt_axes && !device.properties().contains(evdev::PropType::DIRECT)
+}
+
+/// Delay before the reconnection attempt following `attempt` failed ones
+fn reconnect_delay(attempt: u32) -> Duration {
+    RECONNECT_INITIAL_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RECONNECT_MAX_DELAY)
+}
+
+/// Find the first multi-touch touchpad by probing device capabilities
+pub fn find_multitouch_device(exclude: &[String]) -> Result<PathBuf> {
+    for node in input_nodes()? {
+        if exclude.iter().any(|pattern| node.matches(pattern)) {
+            continue;
+        }
+
+        if let Ok(device) = Device::open(&node.path) {
+            if is_multitouch_touchpad(&device) {
+                info!(
+                    "Found multi-touch touchpad: {} at {:?}",
+                    node.name, node.path
+                );
+                return Ok(node.path);
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "No multi-touch touchpad found. Ensure your user can read /dev/input/event*."
+    ))
+}
+
+/// EVIOCGMTSLOTS(len): read one ABS_MT_* value for every slot
+fn eviocgmtslots(len: usize) -> libc::c_ulong {
+    const IOC_READ: libc::c_ulong = 2;
+    (IOC_READ << 30) | ((len as libc::c_ulong) << 16) | ((b'E' as libc::c_ulong) << 8) | 0x0a
+}
+
+/// Query the kernel's current multi-touch slot state (EVIOCGMTSLOTS)
+///
+/// Returns every slot with its tracking ID and position, plus the slot the
+/// kernel will send updates for next.
+pub fn read_mt_slots(device: &Device) -> io::Result<(Vec<MtSlotState>, i32)> {
+    let abs = device.get_abs_state()?;
+    let slot_info = abs[AbsoluteAxisType::ABS_MT_SLOT.0 as usize];
+    let slot_count = (slot_info.maximum + 1).max(0) as usize;
+    let mut slots: Vec<MtSlotState> = (0..slot_count as i32).map(MtSlotState::empty).collect();
+
+    let supported = device.supported_absolute_axes();
+    let codes = [
+        AbsoluteAxisType::ABS_MT_TRACKING_ID,
+        AbsoluteAxisType::ABS_MT_POSITION_X,
+        AbsoluteAxisType::ABS_MT_POSITION_Y,
+        AbsoluteAxisType::ABS_MT_TOUCH_MAJOR,
+        AbsoluteAxisType::ABS_MT_TOUCH_MINOR,
+        AbsoluteAxisType::ABS_MT_ORIENTATION,
+        AbsoluteAxisType::ABS_MT_PRESSURE,
+    ];
+
+    for code in codes {
+        if !supported.is_some_and(|axes| axes.contains(code)) {
+            continue;
+        }
+
+        // struct input_mt_request_layout { __u32 code; __s32 values[num_slots]; }
+        let mut request = vec![0i32; slot_count + 1];
+        request[0] = code.0 as i32;
+        let len = request.len() * std::mem::size_of::<i32>();
+        // SAFETY: the buffer holds the code followed by one value per slot, matching
+        // the length encoded in the request number
+        let ret = unsafe {
+            libc::ioctl(
+                device.as_raw_fd(),
+                eviocgmtslots(len) as _,
+                request.as_mut_ptr(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for (state, value) in slots.iter_mut().zip(&request[1..]) {
+            match code {
+                AbsoluteAxisType::ABS_MT_TRACKING_ID => state.tracking_id = *value,
+                AbsoluteAxisType::ABS_MT_POSITION_X => state.x = *value,
+                AbsoluteAxisType::ABS_MT_POSITION_Y => state.y = *value,
+                AbsoluteAxisType::ABS_MT_TOUCH_MAJOR => state.touch_major = *value,
+                AbsoluteAxisType::ABS_MT_TOUCH_MINOR => state.touch_minor = *value,
+                AbsoluteAxisType::ABS_MT_ORIENTATION => state.orientation = *value,
+                _ => state.pressure = *value,
             }
         }
     }
 
+    Ok((slots, slot_info.value))
+}
+
+/// One line per raw event, like evtest prints them
+fn describe_event(event: &evdev::InputEvent) -> String {
+    let timestamp = event
+        .timestamp()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    match event.kind() {
+        evdev::InputEventKind::Synchronization(sync) => {
+            format!("{:.6}  {:?} ------------", timestamp, sync)
+        }
+        evdev::InputEventKind::AbsAxis(axis) => {
+            format!("{:.6}  {:?} {}", timestamp, axis, event.value())
+        }
+        evdev::InputEventKind::RelAxis(axis) => {
+            format!("{:.6}  {:?} {}", timestamp, axis, event.value())
+        }
+        evdev::InputEventKind::Key(key) => format!("{:.6}  {:?} {}", timestamp, key, event.value()),
+        other => format!("{:.6}  {:?} {}", timestamp, other, event.value()),
+    }
+}
+
+/// Gesture name and its key values, without the full contact history
+pub fn describe_gesture(event: &MultiTouchEvent) -> String {
+    match event {
+        MultiTouchEvent::SingleFingerTap { duration_ms, .. } => {
+            format!("single-finger tap ({}ms)", duration_ms)
+        }
+        MultiTouchEvent::TwoFingerTap { duration_ms, .. } => {
+            format!("two-finger tap ({}ms)", duration_ms)
+        }
+        MultiTouchEvent::TwoFingerSwipe {
+            delta_x, delta_y, ..
+        } => format!("two-finger swipe ({:.1}, {:.1})mm", delta_x, delta_y),
+        MultiTouchEvent::Scroll { delta_x, delta_y } => {
+            format!("scroll ({:.2}, {:.2})mm", delta_x, delta_y)
+        }
+        MultiTouchEvent::Pinch { scale_factor, .. } => format!("pinch (scale {:.2})", scale_factor),
+        MultiTouchEvent::Dwell { duration_ms, .. } => format!("dwell ({}ms)", duration_ms),
+        MultiTouchEvent::LongPress { duration_ms, .. } => {
+            format!("long press ({}ms)", duration_ms)
+        }
+        MultiTouchEvent::Custom { name, fingers } => {
+            format!("{} ({} fingers, plugin)", name, fingers.len())
+        }
+    }
+}
+
+fn print_contact_table(processor: &MultiTouchProcessor) {
+    let contacts = processor.active_contacts();
+    if contacts.is_empty() {
+        println!("   (no active contacts)");
+        return;
+    }
+
+    println!(
+        "   {:>4} {:>6} {:>7} {:>7} {:>6} {:>6} {:>8} {:>7}",
+        "slot", "id", "x_mm", "y_mm", "major", "minor", "pressure", "age_ms"
+    );
+    for contact in contacts {
+        let (x_mm, y_mm) = contact.position_mm();
+        println!(
+            "   {:>4} {:>6} {:>7.1} {:>7.1} {:>6} {:>6} {:>8} {:>7}",
+            contact.slot,
+            contact.id,
+            x_mm,
+            y_mm,
+            contact.touch_major,
+            contact.touch_minor,
+            contact.pressure,
+            contact.contact_duration().as_millis()
+        );
+    }
+}
+
+/// Symlink directories udev maintains with names that survive reboots
+const STABLE_LINK_DIRS: [&str; 2] = ["/dev/input/by-id", "/dev/input/by-path"];
+
+/// Find the device node for a configured device entry
+pub fn resolve_device_path(entry: &DeviceEntry, config: &Config) -> Result<PathBuf> {
+    if let Some(path) = &entry.path {
+        return Ok(PathBuf::from(path));
+    }
+
+    if let Some(id) = &entry.id {
+        return resolve_stable_id(id);
+    }
+
+    if !config.device.auto_detect {
+        return Err(anyhow::anyhow!(
+            "No device path specified and auto-detection is disabled"
+        ));
+    }
+
+    // Candidate patterns in priority order
+    let mut patterns = entry.include.clone();
+    patterns.extend(entry.name_pattern.clone());
+    if patterns.is_empty() {
+        if entry.device_type == DeviceType::Generic {
+            return find_multitouch_device(&config.device.exclude);
+        }
+        patterns.push(config.device.name_pattern.clone());
+    }
+
+    find_magic_mouse_device(&patterns, &config.device.exclude)
+}
+
+/// Resolve a stable device ID (a `/dev/input/by-id` or `by-path` link name, or a
+/// full path) to its current event node
+pub fn resolve_stable_id(id: &str) -> Result<PathBuf> {
+    let candidates: Vec<PathBuf> = if id.contains('/') {
+        vec![PathBuf::from(id)]
+    } else {
+        STABLE_LINK_DIRS
+            .iter()
+            .map(|dir| Path::new(dir).join(id))
+            .collect()
+    };
+
+    candidates
+        .iter()
+        .find_map(|link| std::fs::canonicalize(link).ok())
+        .with_context(|| format!("No input device with ID {:?} is present", id))
+}
+
+/// Stable link names pointing at an event node, for use as a device `id`
+pub fn stable_ids(device_path: &Path) -> Vec<String> {
+    let Ok(target) = std::fs::canonicalize(device_path) else {
+        return Vec::new();
+    };
+
+    let mut ids = Vec::new();
+    for dir in STABLE_LINK_DIRS {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if std::fs::canonicalize(entry.path()).is_ok_and(|path| path == target) {
+                ids.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+    }
+    ids.sort();
+    ids
+}
+
+/// Identity of an input device as reported by sysfs
+struct InputNode {
+    path: PathBuf,
+    name: String,
+    vendor: u16,
+    product: u16,
+}
+
+impl InputNode {
+    /// Match a `vendor:product` ID pair in hex (e.g. `05ac:0269`), or otherwise a
+    /// device name substring
+    fn matches(&self, pattern: &str) -> bool {
+        match parse_usb_id(pattern) {
+            Some((vendor, product)) => self.vendor == vendor && self.product == product,
+            None => self.name.contains(pattern),
+        }
+    }
+}
+
+fn parse_usb_id(pattern: &str) -> Option<(u16, u16)> {
+    let (vendor, product) = pattern.split_once(':')?;
+    let parse = |id: &str| {
+        (id.len() == 4)
+            .then(|| u16::from_str_radix(id, 16).ok())
+            .flatten()
+    };
+    Some((parse(vendor)?, parse(product)?))
+}
+
+/// All event nodes in numeric order, identified through sysfs so no device has
+/// to be opened
+fn input_nodes() -> Result<Vec<InputNode>> {
+    let input_dir = Path::new("/dev/input");
+    let mut event_nodes: Vec<String> = std::fs::read_dir(input_dir)
+        .context("/dev/input directory not found")?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("event"))
+        .collect();
+    event_nodes.sort_by_key(|name| name["event".len()..].parse::<u32>().unwrap_or(u32::MAX));
+
+    let read_id = |sysfs: &Path, attribute: &str| {
+        std::fs::read_to_string(sysfs.join("id").join(attribute))
+            .ok()
+            .and_then(|id| u16::from_str_radix(id.trim(), 16).ok())
+            .unwrap_or(0)
+    };
+
+    Ok(event_nodes
+        .into_iter()
+        .filter_map(|event_node| {
+            let sysfs = Path::new("/sys/class/input")
+                .join(&event_node)
+                .join("device");
+            let name = std::fs::read_to_string(sysfs.join("name")).ok()?;
+            Some(InputNode {
+                path: input_dir.join(&event_node),
+                name: name.trim().to_string(),
+                vendor: read_id(&sysfs, "vendor"),
+                product: read_id(&sysfs, "product"),
+            })
+        })
+        .collect())
+}
+
+/// The node matching the earliest of `patterns`, never one matching `exclude`
+fn select_node<'a>(
+    nodes: &'a [InputNode],
+    patterns: &[String],
+    exclude: &[String],
+) -> Option<&'a InputNode> {
+    let allowed: Vec<&InputNode> = nodes
+        .iter()
+        .filter(|node| {
+            let excluded = exclude.iter().any(|pattern| node.matches(pattern));
+            if excluded {
+                debug!("Excluding {} at {:?}", node.name, node.path);
+            }
+            !excluded
+        })
+        .collect();
+
+    patterns
+        .iter()
+        .find_map(|pattern| allowed.iter().find(|node| node.matches(pattern)).copied())
+}
+
+/// Find an event node by name or `vendor:product` patterns
+///
+/// Patterns are tried in order, so earlier ones take priority; devices matching
+/// any `exclude` pattern are never picked. Devices are identified through sysfs,
+/// which avoids opening every input device on the system.
+pub fn find_magic_mouse_device(patterns: &[String], exclude: &[String]) -> Result<PathBuf> {
+    let nodes = input_nodes()?;
+    if let Some(node) = select_node(&nodes, patterns, exclude) {
+        info!(
+            "Found Magic Mouse device: {} ({:04x}:{:04x}) at {:?}",
+            node.name, node.vendor, node.product, node.path
+        );
+        if let Some(id) = stable_ids(&node.path).first() {
+            info!("Set \"id\": \"{}\" to always bind this device", id);
+        }
+        return Ok(node.path.clone());
+    }
+
     Err(anyhow::anyhow!("Magic Mouse device not found. Ensure it's connected and the hid-magicmouse module is loaded."))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Distance;
+
+    fn node(event: u32, name: &str, vendor: u16, product: u16) -> InputNode {
+        InputNode {
+            path: PathBuf::from(format!("/dev/input/event{}", event)),
+            name: name.to_string(),
+            vendor,
+            product,
+        }
+    }
+
+    #[test]
+    fn test_model_from_ids() {
+        assert_eq!(
+            MagicMouseModel::from_ids(0x05ac, 0x030d),
+            Some(MagicMouseModel::MagicMouse1)
+        );
+        // Over Bluetooth the Apple vendor ID differs
+        assert_eq!(
+            MagicMouseModel::from_ids(0x004c, 0x0269),
+            Some(MagicMouseModel::MagicMouse2)
+        );
+        assert_eq!(
+            MagicMouseModel::from_ids(0x05ac, 0x0323),
+            Some(MagicMouseModel::MagicMouseUsbC)
+        );
+        // A trackpad, and a mouse of another vendor with a colliding product ID
+        assert_eq!(MagicMouseModel::from_ids(0x05ac, 0x0265), None);
+        assert_eq!(MagicMouseModel::from_ids(0x046d, 0x0269), None);
+    }
+
+    #[test]
+    fn test_model_calibration() {
+        for model in [
+            MagicMouseModel::MagicMouse1,
+            MagicMouseModel::MagicMouse2,
+            MagicMouseModel::MagicMouseUsbC,
+        ] {
+            let surface = model.calibration();
+            assert_eq!(
+                (surface.x.resolution, surface.y.resolution),
+                (26, 70),
+                "{:?}",
+                model
+            );
+            let (width, height) = (surface.x.length_mm(), surface.y.length_mm());
+            assert!((width - 90.56).abs() < 0.5, "{:?}: {}mm", model, width);
+            assert!((height - 51.52).abs() < 0.5, "{:?}: {}mm", model, height);
+        }
+    }
+
+    #[test]
+    fn test_model_default_thresholds() {
+        let defaults = Config::default().gesture;
+        let first = MagicMouseModel::MagicMouse1.default_thresholds(&defaults);
+        assert_eq!(first.tap_timeout_ms(), MAGIC_MOUSE_1_TAP_TIMEOUT_MS);
+        assert_eq!(
+            first.two_finger_tap_timeout_ms(),
+            MAGIC_MOUSE_1_TWO_FINGER_TAP_TIMEOUT_MS
+        );
+        let second = MagicMouseModel::MagicMouse2.default_thresholds(&defaults);
+        assert_eq!(second.tap_timeout_ms(), defaults.tap_timeout_ms());
+
+        // Configured values are kept, even when they equal the default
+        let configured = GestureConfig {
+            tap_timeout_ms: Some(200),
+            two_finger_tap_timeout_ms: Some(defaults.two_finger_tap_timeout_ms()),
+            ..defaults
+        };
+        let first = MagicMouseModel::MagicMouse1.default_thresholds(&configured);
+        assert_eq!(first.tap_timeout_ms(), 200);
+        assert_eq!(
+            first.two_finger_tap_timeout_ms(),
+            configured.two_finger_tap_timeout_ms()
+        );
+    }
+
+    #[test]
+    fn test_scale_thresholds() {
+        let defaults = Config::default().gesture;
+        // The Magic Mouse surface with every range multiplied
+        let surface = |factor: i32| {
+            let mut surface = SurfaceBounds::default();
+            for axis in [&mut surface.x, &mut surface.y] {
+                axis.min *= factor;
+                axis.max *= factor;
+            }
+            surface
+        };
+        let swipe = |surface| scale_thresholds(&defaults, &surface).swipe_threshold;
+
+        assert_eq!(swipe(SurfaceBounds::default()), defaults.swipe_threshold);
+        assert_eq!(swipe(surface(2)), defaults.swipe_threshold.scaled(2.0));
+        // Clamped on surfaces far off the reference size
+        assert_eq!(swipe(surface(10)), defaults.swipe_threshold.scaled(3.0));
+        let tiny = SurfaceBounds {
+            x: AxisInfo {
+                min: 0,
+                max: 100,
+                resolution: 26,
+            },
+            y: AxisInfo {
+                min: 0,
+                max: 100,
+                resolution: 70,
+            },
+        };
+        assert_eq!(swipe(tiny), defaults.swipe_threshold.scaled(0.5));
+
+        // Distances with an explicit unit mean the same on every surface
+        let explicit = GestureConfig {
+            scroll_threshold: Distance::Millimeters(4.0),
+            swipe_threshold: Distance::Units(300.0),
+            dwell_radius: Distance::Percent(5.0),
+            ..defaults.clone()
+        };
+        let scaled = scale_thresholds(&explicit, &surface(2));
+        assert_eq!(scaled.scroll_threshold, Distance::Millimeters(4.0));
+        assert_eq!(scaled.swipe_threshold, Distance::Units(300.0));
+        assert_eq!(scaled.dwell_radius, Distance::Percent(5.0));
+        assert_eq!(
+            scaled.single_finger_tap_movement_threshold,
+            defaults.single_finger_tap_movement_threshold.scaled(2.0)
+        );
+    }
+
+    #[test]
+    fn test_surface_bounds() {
+        let axis = |max, resolution| AxisInfo {
+            min: 0,
+            max,
+            resolution,
+        };
+        let reported = SurfaceBounds {
+            x: axis(3000, 30),
+            y: axis(2000, 40),
+        };
+        assert_eq!(
+            surface_bounds(Some(reported), DeviceType::Generic, None),
+            reported
+        );
+        assert_eq!(
+            surface_bounds(None, DeviceType::MagicTrackpad, None),
+            calibration(DeviceType::MagicTrackpad, None)
+        );
+
+        // Without a resolution a generic touchpad is taken to be of typical size
+        let unresolved = SurfaceBounds {
+            x: axis(2000, 0),
+            y: axis(1300, 0),
+        };
+        let generic = surface_bounds(Some(unresolved), DeviceType::Generic, None);
+        assert_eq!((generic.x.resolution, generic.y.resolution), (20, 20));
+        assert_eq!(
+            (generic.x.length_mm(), generic.y.length_mm()),
+            GENERIC_TOUCHPAD_SIZE_MM
+        );
+        // while known devices use their calibration's
+        let mouse = surface_bounds(
+            Some(unresolved),
+            DeviceType::MagicMouse,
+            Some(MagicMouseModel::MagicMouse2),
+        );
+        assert_eq!((mouse.x.resolution, mouse.y.resolution), (26, 70));
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let delays: Vec<u64> = (0..7)
+            .map(|attempt| reconnect_delay(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+        // Still capped after the device has been gone for a long time
+        assert_eq!(reconnect_delay(u32::MAX), RECONNECT_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_select_node() {
+        let nodes = [
+            node(3, "Apple Inc. Magic Trackpad", 0x05ac, 0x0265),
+            node(5, "Magic Mouse", 0x05ac, 0x0269),
+            node(7, "Magic Mouse 2", 0x004c, 0x0269),
+        ];
+        let strings = |patterns: &[&str]| -> Vec<String> {
+            patterns.iter().map(|pattern| pattern.to_string()).collect()
+        };
+        let select = |patterns: &[&str], exclude: &[&str]| {
+            select_node(&nodes, &strings(patterns), &strings(exclude))
+                .map(|node| node.path.to_string_lossy().into_owned())
+        };
+
+        // Earlier patterns take priority over node order
+        assert_eq!(
+            select(&["Magic Mouse 2", "Trackpad"], &[]).as_deref(),
+            Some("/dev/input/event7")
+        );
+        // IDs are hex in either case and need both halves to match
+        assert_eq!(
+            select(&["05AC:0269"], &[]).as_deref(),
+            Some("/dev/input/event5")
+        );
+        assert_eq!(select(&["05ac:0270"], &[]), None);
+
+        // Exclusion wins over a matching include, by ID or by name
+        assert_eq!(
+            select(&["Magic Mouse"], &["05ac:0269"]).as_deref(),
+            Some("/dev/input/event7")
+        );
+        assert_eq!(select(&["05ac:0269"], &["Magic Mouse"]), None);
+
+        // Malformed IDs are taken as names, which nothing is called
+        for pattern in ["5ac:269", "05ac:0269:1", "05ac:zzzz", "05ac0269"] {
+            assert_eq!(parse_usb_id(pattern), None, "{}", pattern);
+            assert_eq!(select(&[pattern], &[]), None, "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use anyhow::{bail, Result};
+use evdev::raw_stream::RawDevice;
+use std::ffi::{CStr, CString};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Backend, Config, DeviceEntry, DeviceType};
+use crate::device;
+use crate::hidraw;
+use crate::lock;
+use crate::output;
+use crate::paths;
+use crate::session::SessionType;
+
+/// Group the udev rules from `scripts/install.sh` give device access to
+const INPUT_GROUP: &str = "input";
+const UDEV_RULES: &str = "/etc/udev/rules.d/99-magic-mouse.rules";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    /// Works, but something is missing or degraded
+    Warning,
+    /// Gestures won't work until it's fixed
+    Failed,
+}
+
+/// Outcome of one check, with what to do about it when it didn't pass
+#[derive(Debug)]
+struct Check {
+    name: String,
+    status: Status,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warning(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Warning,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn failed(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Failed,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Check everything the daemon needs and print how to fix what's missing,
+/// for the `doctor` subcommand and `--check-deps`
+///
+/// Fails when any check failed, so scripts can tell.
+pub async fn run(config_path: &Path, device: Option<&Path>) -> Result<()> {
+    let mut checks = Vec::new();
+
+    let config = match config_path.exists() {
+        true => {
+            match Config::load(config_path) {
+                Ok(config) => {
+                    checks.push(Check::ok(
+                        "Configuration",
+                        config_path.display().to_string(),
+                    ));
+                    config
+                }
+                Err(e) => {
+                    checks.push(Check::failed(
+                    "Configuration",
+                    format!("{:#}", e),
+                    format!("Fix the file, or move it away to start from the defaults: mv {0} {0}.bak", config_path.display()),
+                ));
+                    Config::default()
+                }
+            }
+        }
+        false => {
+            checks.push(Check::ok(
+                "Configuration",
+                format!(
+                    "{} doesn't exist yet; defaults apply",
+                    config_path.display()
+                ),
+            ));
+            Config::default()
+        }
+    };
+    if paths::state_dir().is_none() {
+        checks.push(Check::warning(
+            "State directory",
+            "HOME is not set",
+            "Run as a user with a home directory; profiles and statistics aren't saved otherwise",
+        ));
+    }
+
+    checks.push(input_group());
+    checks.push(kernel_module(&config));
+    checks.extend(devices(&config, device));
+    let uinput = uinput();
+    let uinput_writable = uinput.status == Status::Ok;
+    checks.push(uinput);
+    checks.push(session(uinput_writable).await);
+
+    print!("{}", render(&checks));
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Failed)
+        .count();
+    if failed > 0 {
+        bail!("{} check(s) failed", failed);
+    }
+    Ok(())
+}
+
+/// Checks one per line, with the fix indented below each that didn't pass
+fn render(checks: &[Check]) -> String {
+    let mut out = String::new();
+    for check in checks {
+        let mark = match check.status {
+            Status::Ok => "✓",
+            Status::Warning => "!",
+            Status::Failed => "✗",
+        };
+        let _ = writeln!(out, "{} {:<20} {}", mark, check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            for line in fix.lines() {
+                let _ = writeln!(out, "  {:<20} {}", "", line);
+            }
+        }
+    }
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let _ = writeln!(
+        out,
+        "\n{} passed, {} warning(s), {} failed",
+        count(Status::Ok),
+        count(Status::Warning),
+        count(Status::Failed)
+    );
+    out
+}
+
+/// Whether this process, rather than just the group database, is in the
+/// `input` group; a fresh `usermod -aG` only applies to new logins
+fn input_group() -> Check {
+    const NAME: &str = "Input group";
+    if unsafe { libc::geteuid() } == 0 {
+        return Check::ok(NAME, "running as root");
+    }
+    let Some((gid, members)) = lookup_group(INPUT_GROUP) else {
+        return Check::warning(
+            NAME,
+            "there is no `input` group",
+            "Create it, then install the udev rules and join it: ./scripts/install.sh",
+        );
+    };
+    if process_groups().contains(&gid) {
+        return Check::ok(NAME, "member of `input`");
+    }
+
+    let user = std::env::var("USER").unwrap_or_default();
+    if members.contains(&user) {
+        Check::warning(
+            NAME,
+            "added to `input`, but this session predates it",
+            "Log out and back in, or start a shell with it applied: newgrp input",
+        )
+    } else {
+        Check::warning(
+            NAME,
+            "not a member of `input`",
+            "sudo usermod -aG input $USER, then log out and back in",
+        )
+    }
+}
+
+/// GID and members of the group called `name`
+fn lookup_group(name: &str) -> Option<(libc::gid_t, Vec<String>)> {
+    let c_name = CString::new(name).ok()?;
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    loop {
+        let error = unsafe {
+            libc::getgrnam_r(
+                c_name.as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        match error {
+            0 => break,
+            libc::ERANGE => buffer.resize(buffer.len() * 2, 0),
+            _ => return None,
+        }
+    }
+    if result.is_null() {
+        return None;
+    }
+
+    // SAFETY: getgrnam_r succeeded, so gr_mem is a null-terminated array of
+    // strings in `buffer`
+    let mut members = Vec::new();
+    unsafe {
+        let mut member = entry.gr_mem;
+        while !(*member).is_null() {
+            members.push(CStr::from_ptr(*member).to_string_lossy().into_owned());
+            member = member.add(1);
+        }
+    }
+    Some((entry.gr_gid, members))
+}
+
+/// Effective and supplementary groups of this process
+fn process_groups() -> Vec<libc::gid_t> {
+    let mut groups = vec![0; 256];
+    let count = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    groups.push(unsafe { libc::getegid() });
+    groups
+}
+
+fn kernel_module(config: &Config) -> Check {
+    const NAME: &str = "hid-magicmouse";
+    if Path::new("/sys/module/hid_magicmouse").exists() {
+        return Check::ok(NAME, "driver loaded");
+    }
+    let generic = config
+        .device_entries()
+        .iter()
+        .all(|entry| entry.device_type == DeviceType::Generic);
+    if generic {
+        return Check::ok(NAME, "not loaded, not needed for generic touch devices");
+    }
+    Check::failed(
+        NAME,
+        "driver not loaded, so the mouse reports no touches",
+        "sudo modprobe hid-magicmouse\n\
+         To load it at boot: echo hid-magicmouse | sudo tee /etc/modules-load.d/hid-magicmouse.conf",
+    )
+}
+
+/// Find, open and test-grab each configured device
+fn devices(config: &Config, device: Option<&Path>) -> Vec<Check> {
+    let hidraw = config.device.backend == Backend::Hidraw;
+    let found: Vec<Result<PathBuf>> = match device {
+        Some(path) => vec![Ok(path.to_path_buf())],
+        None => config
+            .device_entries()
+            .iter()
+            .map(|entry| resolve(entry, config, hidraw))
+            .collect(),
+    };
+
+    let numbered = found.len() > 1;
+    found
+        .into_iter()
+        .enumerate()
+        .map(|(i, found)| {
+            let name = match numbered {
+                true => format!("Device {}", i + 1),
+                false => "Device".to_string(),
+            };
+            match found {
+                Ok(path) if hidraw => check_hidraw(name, &path),
+                Ok(path) => check_evdev(name, &path),
+                Err(e) => Check::failed(
+                    name,
+                    format!("{:#}", e),
+                    "Connect the mouse (e.g. bluetoothctl connect <address>) and look for it in `sudo evtest`\n\
+                     If it has an unusual name, set device.path or device.name_pattern in the configuration",
+                ),
+            }
+        })
+        .collect()
+}
+
+fn resolve(entry: &DeviceEntry, config: &Config, hidraw: bool) -> Result<PathBuf> {
+    if !hidraw {
+        return device::resolve_device_path(entry, config);
+    }
+    match &entry.path {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => hidraw::find_hidraw_device(
+            entry
+                .name_pattern
+                .as_deref()
+                .unwrap_or(&config.device.name_pattern),
+        ),
+    }
+}
+
+fn check_evdev(name: String, path: &Path) -> Check {
+    let mut device = match RawDevice::open(path) {
+        Ok(device) => device,
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            return Check::failed(
+                name,
+                format!("{} can't be opened: {}", path.display(), e),
+                format!(
+                    "Join the `input` group and install the udev rules: ./scripts/install.sh\n\
+                     Or grant access until the next reconnect: sudo setfacl -m u:$USER:rw {}",
+                    path.display()
+                ),
+            );
+        }
+        Err(e) => {
+            return Check::failed(
+                name,
+                format!("{} can't be opened: {}", path.display(), e),
+                "Check that the device is still connected: sudo evtest",
+            );
+        }
+    };
+    let found = format!(
+        "{} ({})",
+        path.display(),
+        device.name().unwrap_or("unnamed")
+    );
+
+    // A grab by anyone else starves every other reader, this daemon included
+    match device.grab() {
+        Ok(()) => {
+            let _ = device.ungrab();
+        }
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+            if let Some(pid) = lock::lock_holder(path) {
+                return Check::ok(
+                    name,
+                    format!(
+                        "{}, grabbed by the daemon, {}",
+                        found,
+                        lock::describe_process(pid)
+                    ),
+                );
+            }
+            let openers: Vec<String> = openers(path)
+                .into_iter()
+                .map(lock::describe_process)
+                .collect();
+            let fix = match openers.is_empty() {
+                true => format!(
+                    "Find the program holding it (run as root to see every process): sudo fuser -v {}",
+                    path.display()
+                ),
+                false => format!(
+                    "Stop or reconfigure the program holding it, one of:\n{}",
+                    openers.join("\n")
+                ),
+            };
+            return Check::failed(
+                name,
+                format!("{} is grabbed exclusively by another process", found),
+                fix,
+            );
+        }
+        Err(e) => {
+            return Check::warning(
+                name,
+                format!("{}, but grabbing it failed: {}", found, e),
+                "Leave device.grab off",
+            );
+        }
+    }
+
+    match lock::lock_holder(path) {
+        Some(pid) => Check::ok(
+            name,
+            format!("{}, handled by {}", found, lock::describe_process(pid)),
+        ),
+        None => Check::ok(name, found),
+    }
+}
+
+fn check_hidraw(name: String, path: &Path) -> Check {
+    match std::fs::OpenOptions::new().read(true).write(true).open(path) {
+        Ok(_) => Check::ok(name, path.display().to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Check::failed(
+            name,
+            format!("{} can't be opened: {}", path.display(), e),
+            format!(
+                "Add this line to {} and reconnect the mouse:\n\
+                 SUBSYSTEM==\"hidraw\", ATTRS{{name}}==\"*Magic Mouse*\", MODE=\"0660\", GROUP=\"input\"",
+                UDEV_RULES
+            ),
+        ),
+        Err(e) => Check::failed(
+            name,
+            format!("{} can't be opened: {}", path.display(), e),
+            "Check that the device is still connected",
+        ),
+    }
+}
+
+/// Other processes with `path` open; only our own user's unless run as root
+fn openers(path: &Path) -> Vec<u32> {
+    let Ok(target) = path.canonicalize() else {
+        return Vec::new();
+    };
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let own = std::process::id();
+    let mut pids: Vec<u32> = processes
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter(|&pid| pid != own)
+        .filter(|pid| {
+            std::fs::read_dir(format!("/proc/{}/fd", pid)).is_ok_and(|fds| {
+                fds.flatten()
+                    .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == target))
+            })
+        })
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+fn uinput() -> Check {
+    const NAME: &str = "/dev/uinput";
+    match std::fs::OpenOptions::new().write(true).open(NAME) {
+        Ok(_) => Check::ok(NAME, "writable, clicks and key combos are injected directly"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Check::warning(
+            NAME,
+            "missing, falling back to tools",
+            "sudo modprobe uinput\n\
+             To load it at boot: echo uinput | sudo tee /etc/modules-load.d/uinput.conf",
+        ),
+        Err(e) => Check::warning(
+            NAME,
+            format!("not writable ({}), falling back to tools", e),
+            format!(
+                "Add this line to {} and run `sudo udevadm trigger`:\n\
+                 KERNEL==\"uinput\", SUBSYSTEM==\"misc\", MODE=\"0660\", GROUP=\"input\", OPTIONS+=\"static_node=uinput\"",
+                UDEV_RULES
+            ),
+        ),
+    }
+}
+
+/// The session type and whether actions can reach it without `/dev/uinput`
+async fn session(uinput_writable: bool) -> Check {
+    const NAME: &str = "Session";
+    let (session, tools): (&str, &[&str]) = match crate::session::detect().await {
+        SessionType::Wayland => ("Wayland", &["ydotool", "wtype"]),
+        SessionType::X11 => ("X11", &["xdotool"]),
+        SessionType::Tty => ("TTY", &["ydotool"]),
+        SessionType::Unknown => {
+            return Check::warning(
+                NAME,
+                "no graphical session (none from logind, and XDG_SESSION_TYPE, \
+                 WAYLAND_DISPLAY and DISPLAY are unset)",
+                "Run inside the desktop session, or pass it on to the systemd user manager:\n\
+                 systemctl --user import-environment XDG_SESSION_TYPE WAYLAND_DISPLAY DISPLAY",
+            );
+        }
+    };
+
+    let found: Vec<&str> = tools
+        .iter()
+        .copied()
+        .filter(|tool| output::command_exists(tool))
+        .collect();
+    if !found.is_empty() {
+        Check::ok(NAME, format!("{}, with {}", session, found.join(", ")))
+    } else if uinput_writable {
+        Check::ok(NAME, format!("{}, using /dev/uinput", session))
+    } else if session == "X11" && crate::x11::is_available() {
+        Check::ok(NAME, "X11, using XTEST")
+    } else {
+        Check::failed(
+            NAME,
+            format!("{}, but clicks and key combos have no way in", session),
+            format!(
+                "Make /dev/uinput writable (see above) or install {}",
+                tools.join(" or ")
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let checks = [
+            Check::ok("Configuration", "config.json"),
+            Check::failed(
+                "hid-magicmouse",
+                "driver not loaded",
+                "sudo modprobe hid-magicmouse\nsecond line",
+            ),
+        ];
+        assert_eq!(
+            render(&checks),
+            "✓ Configuration        config.json\n\
+             ✗ hid-magicmouse       driver not loaded\n\
+             \x20                      sudo modprobe hid-magicmouse\n\
+             \x20                      second line\n\
+             \n1 passed, 0 warning(s), 1 failed\n"
+        );
+        assert!(lookup_group("root").is_some_and(|(gid, _)| gid == 0));
+    }
+}
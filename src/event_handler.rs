@@ -1,133 +1,1245 @@
-use anyhow::{Context, Result};
-use log::{debug, info, warn};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, trace, warn};
+use std::collections::HashMap;
 use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tokio::process::Command;
 
-use crate::config::Config;
-use crate::multitouch::MultiTouchEvent;
+use crate::battery::BatteryStatus;
+use crate::compositor;
+use crate::config::{Action, ActionStep, Config, ProcessAction};
+use crate::dbus;
+use crate::desktop;
+use crate::device::DeviceEvent;
+use crate::feedback::{Feedback, FeedbackEvent};
+use crate::keyboard;
+use crate::macros;
+use crate::metrics;
+use crate::mpris;
+use crate::multitouch::{GestureModifiers, MultiTouchEvent};
+use crate::notify;
+use crate::output::{self, Output};
+use crate::overlay;
+use crate::plugin;
+use crate::profile;
+use crate::script::{self, ScriptRunner};
+use crate::stats;
+use crate::status;
+use crate::workspace;
+use crate::x11;
 
 pub struct EventHandler {
     pub config: Config,
+    output: Output,
+    scripts: ScriptRunner,
+    /// Whether any binding depends on held keyboard modifiers, so keyboards
+    /// are only polled when needed
+    keyboard_bindings: bool,
+    /// Travel of the swipe in progress, for `repeat` bindings
+    swipe_repeat: Mutex<SwipeRepeat>,
+    /// When each command-backed scroll action last ran, for throttling
+    scroll_actions: Mutex<HashMap<String, Instant>>,
+    feedback: Feedback,
+}
+
+/// Scroll frames further apart than this belong to different touch sessions
+const SWIPE_SESSION_GAP: Duration = Duration::from_millis(250);
+
+/// Scroll actions bound to commands run at most this often, as scroll events
+/// arrive with every frame
+const SCROLL_ACTION_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress of `repeat` swipe bindings during one touch session
+#[derive(Debug, Default)]
+struct SwipeRepeat {
+    /// Finger travel (mm) along each axis not yet turned into repeats
+    pending: (f64, f64),
+    /// Whether any repeat ran in this session
+    repeated: bool,
+    /// Whether a scroll action ran in this session, which then doesn't also
+    /// end as a swipe
+    scrolled: bool,
+    last_frame: Option<Instant>,
 }
 
 impl EventHandler {
     pub fn new(config: Config) -> Self {
-        Self { config }
+        let output = Output::new(config.output.backend);
+        let feedback = Feedback::new(config.feedback.clone());
+        let keyboard_bindings = config
+            .actions
+            .keys()
+            .any(|name| keyboard::is_modifier_binding(name));
+        Self {
+            config,
+            output,
+            scripts: ScriptRunner::new(),
+            keyboard_bindings,
+            swipe_repeat: Mutex::new(SwipeRepeat::default()),
+            scroll_actions: Mutex::new(HashMap::new()),
+            feedback,
+        }
     }
 
-    pub async fn handle_multitouch_event(&self, event: MultiTouchEvent) -> Result<()> {
-        match event {
+    /// Fingers touched the surface, starting a touch session
+    pub fn handle_touch_begin(&self) {
+        if status::paused() {
+            return;
+        }
+        self.fire_feedback(FeedbackEvent::Begin);
+    }
+
+    fn fire_feedback(&self, event: FeedbackEvent) {
+        if !self.config.dry_run {
+            self.feedback.fire(event);
+        }
+    }
+
+    pub async fn handle_multitouch_event(
+        &self,
+        event: MultiTouchEvent,
+        modifiers: GestureModifiers,
+    ) -> Result<()> {
+        let scroll = matches!(event, MultiTouchEvent::Scroll { .. });
+        if status::paused() {
+            // Only a gesture bound to toggle_gestures may still run, and
+            // scrolling can't be
+            if scroll {
+                return Ok(());
+            }
+        } else if !scroll {
+            self.fire_feedback(FeedbackEvent::End);
+        }
+
+        let gesture = Some(&event);
+        match &event {
             MultiTouchEvent::TwoFingerTap {
-                finger1: _,
-                finger2: _,
+                finger1,
+                finger2,
                 duration_ms,
             } => {
-                info!("Two-finger tap detected ({}ms)", duration_ms);
-                self.execute_action("tap_2finger").await?;
+                info!(
+                    gesture = event.kind(), fingers = 2, duration_ms = *duration_ms;
+                    "Two-finger tap detected ({}ms)", duration_ms
+                );
+                debug!(
+                    "Tap contacts in slots {} and {}",
+                    finger1.slot, finger2.slot
+                );
+                if !self.release_drag_lock().await? {
+                    self.execute_gesture_action("tap_2finger", modifiers, gesture)
+                        .await?;
+                }
             }
             MultiTouchEvent::SingleFingerTap {
-                finger: _,
+                finger,
                 duration_ms,
             } => {
-                info!("Single-finger tap detected ({}ms)", duration_ms);
-                self.execute_action("tap_1finger").await?;
+                info!(
+                    gesture = event.kind(), fingers = 1, duration_ms = *duration_ms;
+                    "Single-finger tap detected ({}ms)", duration_ms
+                );
+                debug!("Tap contact in slot {}", finger.slot);
+                if !self.release_drag_lock().await? {
+                    self.execute_gesture_action("tap_1finger", modifiers, gesture)
+                        .await?;
+                }
+            }
+            MultiTouchEvent::Dwell {
+                finger,
+                duration_ms,
+            } => {
+                info!(
+                    gesture = event.kind(), fingers = 1, duration_ms = *duration_ms;
+                    "Dwell detected ({}ms)", duration_ms
+                );
+                debug!("Dwell contact in slot {}", finger.slot);
+                self.fire_feedback(FeedbackEvent::Dwell);
+                self.execute_gesture_action("dwell_1finger", modifiers, gesture)
+                    .await?;
+            }
+            MultiTouchEvent::LongPress {
+                finger,
+                duration_ms,
+            } => {
+                info!(
+                    gesture = event.kind(), fingers = 1, duration_ms = *duration_ms;
+                    "Long press detected ({}ms)", duration_ms
+                );
+                debug!("Long press contact in slot {}", finger.slot);
+                self.execute_gesture_action("long_press_1finger", modifiers, gesture)
+                    .await?;
             }
             MultiTouchEvent::TwoFingerSwipe {
-                finger1: _,
-                finger2: _,
+                finger1,
+                finger2,
                 delta_x,
                 delta_y,
             } => {
-                let direction = self.determine_swipe_direction(delta_x, delta_y);
-                info!("Two-finger swipe detected: {}", direction);
-                self.execute_action(&format!("swipe_{}_2finger", direction))
+                let direction = swipe_direction(*delta_x, *delta_y);
+                info!(
+                    gesture = event.kind(), fingers = 2, direction,
+                    delta_x = *delta_x, delta_y = *delta_y;
+                    "Two-finger swipe detected: {}", direction
+                );
+                debug!(
+                    "Swipe contacts in slots {} and {}, delta=({:.1}, {:.1})mm",
+                    finger1.slot, finger2.slot, delta_x, delta_y
+                );
+                let action_name = format!("swipe_{}_2finger", direction);
+                if let Some(reason) = self.finish_swipe_session(&action_name, modifiers) {
+                    debug!("Skipping {}: {}", action_name, reason);
+                } else {
+                    self.execute_gesture_action(&action_name, modifiers, gesture)
+                        .await?;
+                }
+            }
+            MultiTouchEvent::Scroll { delta_x, delta_y } => {
+                debug!(
+                    gesture = event.kind(), delta_x = *delta_x, delta_y = *delta_y;
+                    "Scroll: delta=({:.2}, {:.2})mm", delta_x, delta_y
+                );
+                self.repeat_swipe(*delta_x, *delta_y, modifiers, gesture)
+                    .await?;
+                if *delta_y != 0.0 {
+                    self.execute_scroll_action(
+                        "scroll_vertical",
+                        0.0,
+                        *delta_y,
+                        modifiers,
+                        gesture,
+                    )
+                    .await?;
+                }
+                if *delta_x != 0.0 {
+                    self.execute_scroll_action(
+                        "scroll_horizontal",
+                        *delta_x,
+                        0.0,
+                        modifiers,
+                        gesture,
+                    )
                     .await?;
+                }
             }
             MultiTouchEvent::Pinch {
-                center_x: _,
-                center_y: _,
+                center_x,
+                center_y,
                 scale_factor,
             } => {
-                let action = if scale_factor > 1.0 {
+                // A `pinch` axis binding takes the magnitude into account,
+                // replacing the separate in/out bindings
+                let axis_name = self.gesture_action_name("pinch", modifiers);
+                let action = if matches!(
+                    self.config.actions.get(&axis_name),
+                    Some(Action::Axis { .. })
+                ) {
+                    "pinch"
+                } else if *scale_factor > 1.0 {
                     "pinch_out"
                 } else {
                     "pinch_in"
                 };
-                info!("Pinch gesture detected: scale={:.2}", scale_factor);
-                self.execute_action(action).await?;
+                info!(
+                    gesture = event.kind(), fingers = 2, scale = *scale_factor,
+                    center_x = *center_x, center_y = *center_y;
+                    "Pinch gesture detected: scale={:.2}", scale_factor
+                );
+                debug!("Pinch center at ({:.1}, {:.1})mm", center_x, center_y);
+                self.execute_gesture_action(action, modifiers, gesture)
+                    .await?;
+            }
+            MultiTouchEvent::Custom { name, fingers } => {
+                info!(
+                    gesture = event.kind(), name = name.as_str(), fingers = fingers.len();
+                    "Plugin gesture detected: {}", name
+                );
+                debug!("{} contacts in plugin gesture", fingers.len());
+                self.execute_gesture_action(name, modifiers, gesture)
+                    .await?;
             }
         }
 
         Ok(())
     }
 
-    async fn execute_action(&self, action_name: &str) -> Result<()> {
-        if let Some(command) = self.config.actions.get(action_name) {
-            match command.as_str() {
-                "click" => self.simulate_click(1).await?,
-                "right_click" => self.simulate_click(3).await?,
-                "middle_click" => self.simulate_click(2).await?,
-                _ => self.execute_shell_command(command).await?,
+    /// Run the optional lifecycle action bound to a device event
+    pub async fn handle_device_event(&self, event: DeviceEvent) {
+        let action = match &event {
+            DeviceEvent::Connected { path, name } => {
+                info!("Device connected: {} ({:?})", name, path);
+                "on_device_connected"
+            }
+            DeviceEvent::Disconnected { path } => {
+                info!("Device disconnected: {:?}", path);
+                "on_device_disconnected"
+            }
+        };
+
+        self.execute_lifecycle_action(action).await;
+    }
+
+    /// Run the optional `on_start` action once the devices are set up
+    pub async fn handle_start(&self) {
+        self.execute_lifecycle_action("on_start").await;
+    }
+
+    /// Run the optional `on_stop` action before the daemon exits
+    pub async fn handle_stop(&self) {
+        self.execute_lifecycle_action("on_stop").await;
+        // A button left down by drag_lock would outlive the daemon
+        if let Err(e) = self.release_drag_lock().await {
+            warn!("Failed to release held mouse buttons: {:#}", e);
+        }
+    }
+
+    /// Release the buttons `drag_lock` holds down; the tap that does so runs
+    /// no action of its own. Returns whether any button was held.
+    async fn release_drag_lock(&self) -> Result<bool> {
+        let held = output::held_buttons();
+        for &button in &held {
+            info!("Releasing mouse button {} held by drag_lock", button);
+            self.output.set_button(button, false).await?;
+        }
+        Ok(!held.is_empty())
+    }
+
+    /// Run the optional `on_battery_low` action
+    pub async fn handle_battery_low(&self, status: &BatteryStatus) {
+        debug!("Battery low: {:?}", status);
+        self.execute_lifecycle_action("on_battery_low").await;
+    }
+
+    async fn execute_lifecycle_action(&self, action: &str) {
+        // Lifecycle actions are opt-in, so a missing binding isn't worth a warning
+        if !self.config.actions.contains_key(action) {
+            return;
+        }
+        if let Err(e) = self.execute_action(action, None).await {
+            warn!("Failed to run {}: {}", action, e);
+        }
+    }
+
+    /// Run a gesture's action, preferring its keyboard-modifier and `click+`
+    /// variants while modifiers or a physical button are held
+    async fn execute_gesture_action(
+        &self,
+        action_name: &str,
+        modifiers: GestureModifiers,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<()> {
+        let action_name = self.gesture_action_name(action_name, modifiers);
+        let action = self.config.actions.get(&action_name);
+        if status::paused() && action.and_then(Action::as_command) != Some("toggle_gestures") {
+            trace!("Gestures paused, not running {}", action_name);
+            return Ok(());
+        }
+        status::count_gesture(&action_name);
+        // The JSON is only built when someone is there to receive it
+        let subscribed = status::subscribed();
+        if subscribed || self.config.dbus.signals {
+            let details = gesture.map(script::gesture_json).unwrap_or_default();
+            if self.config.dbus.signals {
+                dbus::emit_gesture(action_name.clone(), details.to_string()).await;
+            }
+            if subscribed {
+                let archived = gesture
+                    .filter(|_| status::contacts_followed())
+                    .map(script::gesture_json_with_archive);
+                status::publish_gesture(&action_name, details, archived);
+            }
+        }
+        if let Some(gesture) = gesture {
+            stats::recognized(&action_name, gesture);
+        }
+
+        let started = Instant::now();
+        let result = self.execute_action(&action_name, gesture).await;
+        let elapsed = started.elapsed();
+        metrics::action_finished(elapsed, result.is_ok());
+        if result.is_err() {
+            stats::action_failed(&action_name);
+        }
+        info!(
+            action = action_name.as_str(),
+            action_ms = elapsed.as_secs_f64() * 1000.0,
+            result = if result.is_ok() { "ok" } else { "error" };
+            "{} finished in {:?}", action_name, elapsed
+        );
+        if self.config.notifications.enabled_for(&action_name) {
+            self.notify_gesture(&action_name, &result);
+        }
+        if overlay::enabled(&self.config.overlay) {
+            overlay::show(
+                &self.config.overlay,
+                overlay::text(&action_name, self.config.actions.get(&action_name)),
+            );
+        }
+        result
+    }
+
+    /// Show which gesture was recognized and what it ran
+    fn notify_gesture(&self, action_name: &str, result: &Result<()>) {
+        let body = match (self.config.actions.get(action_name), result) {
+            (None, _) => "No action configured".to_string(),
+            (Some(action), Ok(())) => action.to_string(),
+            (Some(action), Err(e)) => format!("{} failed: {}", action, e),
+        };
+        notify::show(
+            format!("Gesture: {}", action_name),
+            body,
+            self.config.notifications.timeout_ms,
+        );
+    }
+
+    /// The most specific configured binding for a gesture, e.g.
+    /// `ctrl+click+swipe_up_2finger` over `click+swipe_up_2finger` over
+    /// `swipe_up_2finger`
+    fn gesture_action_name(&self, action_name: &str, modifiers: GestureModifiers) -> String {
+        let held = if self.keyboard_bindings {
+            keyboard::held_modifiers()
+        } else {
+            Vec::new()
+        };
+
+        candidate_action_names(action_name, &held, modifiers.clicked)
+            .into_iter()
+            .find(|name| self.config.actions.contains_key(name))
+            .unwrap_or_else(|| action_name.to_string())
+    }
+
+    /// Run `repeat` swipe bindings for the finger travel of one scroll frame
+    ///
+    /// Travel is tracked per axis, so reversing direction mid-swipe repeats the
+    /// opposite binding.
+    async fn repeat_swipe(
+        &self,
+        delta_x: f64,
+        delta_y: f64,
+        modifiers: GestureModifiers,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<()> {
+        let repeats = {
+            let mut state = self.swipe_repeat.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            if state
+                .last_frame
+                .is_none_or(|last| now.duration_since(last) > SWIPE_SESSION_GAP)
+            {
+                *state = SwipeRepeat::default();
             }
+            state.last_frame = Some(now);
+            state.pending.0 += delta_x;
+            state.pending.1 += delta_y;
+
+            let mut repeats = Vec::new();
+            let (pending_x, pending_y) = state.pending;
+            for (axis, travel) in [(0, pending_x), (1, pending_y)] {
+                let direction = match (axis, travel > 0.0) {
+                    (0, true) => "right",
+                    (0, false) => "left",
+                    (_, true) => "down",
+                    (_, false) => "up",
+                };
+                let action_name =
+                    self.gesture_action_name(&format!("swipe_{}_2finger", direction), modifiers);
+                let Some(Action::Repeat { repeat, every_mm }) =
+                    self.config.actions.get(&action_name)
+                else {
+                    continue;
+                };
+                if *every_mm <= 0.0 {
+                    continue;
+                }
+
+                let count = (travel.abs() / every_mm).floor();
+                if count >= 1.0 {
+                    let consumed = count * every_mm * travel.signum();
+                    if axis == 0 {
+                        state.pending.0 -= consumed;
+                    } else {
+                        state.pending.1 -= consumed;
+                    }
+                    state.repeated = true;
+                    repeats.push((action_name, repeat.clone(), count as usize));
+                }
+            }
+            repeats
+        };
+
+        for (action_name, command, count) in repeats {
+            if self.config.dry_run {
+                info!(
+                    "Dry run: {} would repeat {} {} times",
+                    action_name, command, count
+                );
+                continue;
+            }
+            trace!("Repeating {} {} times", action_name, count);
+            let env = script::gesture_env(&action_name, gesture);
+            let run = async {
+                for _ in 0..count {
+                    self.execute_command(&action_name, &command, &env).await?;
+                }
+                Ok(())
+            };
+            self.within_timeout(&action_name, run).await?;
+        }
+        Ok(())
+    }
+
+    /// End the swipe's touch session; why the swipe's binding mustn't run, if
+    /// scrolling or its `repeat` already handled the finger travel
+    fn finish_swipe_session(
+        &self,
+        action_name: &str,
+        modifiers: GestureModifiers,
+    ) -> Option<&'static str> {
+        let state =
+            std::mem::take(&mut *self.swipe_repeat.lock().unwrap_or_else(|e| e.into_inner()));
+        let action_name = self.gesture_action_name(action_name, modifiers);
+        if state.scrolled {
+            Some("scrolled during the swipe")
+        } else if state.repeated
+            && matches!(
+                self.config.actions.get(&action_name),
+                Some(Action::Repeat { .. })
+            )
+        {
+            Some("already repeated during the swipe")
         } else {
+            None
+        }
+    }
+
+    /// Run a scroll action; `smooth_scroll` turns the finger travel (mm) into
+    /// high-resolution wheel motion, anything else runs once per scroll frame
+    async fn execute_scroll_action(
+        &self,
+        action_name: &str,
+        delta_x: f64,
+        delta_y: f64,
+        modifiers: GestureModifiers,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<()> {
+        let action_name = self.gesture_action_name(action_name, modifiers);
+        // Scroll events arrive with every frame, so an unbound or native scroll
+        // action is skipped without logging each one
+        let Some(action) = self.config.actions.get(&action_name) else {
+            trace!("No action configured for: {}", action_name);
+            return Ok(());
+        };
+        if let Some("scroll_vertical" | "scroll_horizontal") = action.as_command() {
+            trace!("Native scrolling for {}", action_name);
+            return Ok(());
+        }
+        // The finger travel went into scrolling, not into a swipe
+        self.swipe_repeat
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .scrolled = true;
+        if action.as_command() != Some("smooth_scroll") {
+            let due = scroll_action_due(
+                &mut self
+                    .scroll_actions
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner()),
+                &action_name,
+                Instant::now(),
+            );
+            if !due {
+                trace!("Throttling {}", action_name);
+                return Ok(());
+            }
+            return self.execute_action(&action_name, gesture).await;
+        }
+
+        // Fingers moving towards the palm (+Y) scroll down like a wheel rolled
+        // back, unless natural scrolling is enabled
+        let settings = &self.config.gesture;
+        let direction = if settings.natural_scroll { -1.0 } else { 1.0 };
+        let horizontal = delta_x * settings.smooth_scroll_speed * direction;
+        let vertical = -delta_y * settings.smooth_scroll_speed * direction;
+        if self.config.dry_run {
+            debug!(
+                "Dry run: {} would scroll ({:.1}, {:.1})",
+                action_name, horizontal, vertical
+            );
+            return Ok(());
+        }
+        self.output.smooth_scroll(horizontal, vertical).await
+    }
+
+    /// Run an action, cancelling it once it exceeds its timeout
+    async fn execute_action(
+        &self,
+        action_name: &str,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<()> {
+        self.within_timeout(action_name, self.run_action(action_name, gesture))
+            .await
+    }
+
+    /// Run `action_name`'s work, cancelling it once it exceeds the action's
+    /// timeout
+    async fn within_timeout(
+        &self,
+        action_name: &str,
+        run: impl std::future::Future<Output = Result<()>>,
+    ) -> Result<()> {
+        match self.config.execution.timeout(action_name) {
+            Some(timeout) => tokio::time::timeout(timeout, run)
+                .await
+                .map_err(|_| anyhow!("{} timed out after {:?}", action_name, timeout))?,
+            None => run.await,
+        }
+    }
+
+    async fn run_action(&self, action_name: &str, gesture: Option<&MultiTouchEvent>) -> Result<()> {
+        let Some(action) = self.config.actions.get(action_name) else {
             warn!("No action configured for: {}", action_name);
+            return Ok(());
+        };
+
+        if self.config.dry_run {
+            info!("Dry run: {} would run {}", action_name, action);
+            return Ok(());
+        }
+
+        let env = script::gesture_env(action_name, gesture);
+        if let Action::Axis {
+            decrease,
+            increase,
+            step,
+        } = action
+        {
+            let magnitude = gesture.and_then(axis_magnitude).with_context(|| {
+                format!(
+                    "{} is an axis binding, but only pinches have a magnitude",
+                    action_name
+                )
+            })?;
+            let (direction, count) = axis_steps(magnitude, *step)
+                .with_context(|| format!("{}: axis step must be positive", action_name))?;
+            let command = if direction > 0.0 { increase } else { decrease };
+            trace!("Running {} {} times for {}", command, count, action_name);
+            for _ in 0..count {
+                self.execute_command(action_name, command, &env).await?;
+            }
+            return Ok(());
+        }
+
+        for step in action_steps(action) {
+            match step {
+                ActionStep::Command(command) => match command.strip_prefix("script:") {
+                    Some(script) => self.execute_script(action_name, script, gesture).await?,
+                    None => self.execute_command(action_name, &command, &env).await?,
+                },
+                ActionStep::Process(process) => self.execute_process(&process, &env).await?,
+                ActionStep::Delay { delay_ms } => {
+                    tokio::time::sleep(Duration::from_millis(delay_ms)).await
+                }
+            }
         }
 
         Ok(())
     }
 
-    async fn simulate_click(&self, button: u8) -> Result<()> {
-        debug!("Simulating mouse click: button {}", button);
+    /// Let a script decide what to run for a gesture
+    async fn execute_script(
+        &self,
+        action_name: &str,
+        script: &str,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<()> {
+        let Some(action) = self.scripts.run(script, action_name, gesture)? else {
+            return Ok(());
+        };
+
+        let env = script::gesture_env(action_name, gesture);
+        for step in action_steps(&action) {
+            match step {
+                ActionStep::Command(command) => {
+                    self.execute_command(action_name, &command, &env).await?
+                }
+                ActionStep::Process(process) => self.execute_process(&process, &env).await?,
+                ActionStep::Delay { delay_ms } => {
+                    tokio::time::sleep(Duration::from_millis(delay_ms)).await
+                }
+            }
+        }
 
-        // // here we close the mouse click function
-        // let output = Command::new("xdotool")
-        //     .args(&["click", &button.to_string()])
-        //     .stdout(Stdio::null())
-        //     .stderr(Stdio::piped())
-        //     .output()
-        //     .await
-        //     .context("Failed to execute xdotool click")?;
+        Ok(())
+    }
 
-        // if !output.status.success() {
-        //     let stderr = String::from_utf8_lossy(&output.stderr);
-        //     warn!("xdotool click failed: {}", stderr);
-        // }
+    /// Run a built-in or shell command; `env` is passed to spawned processes
+    async fn execute_command(
+        &self,
+        action_name: &str,
+        command: &str,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        match self.handed_command(command) {
+            "click" => self.output.click(1).await?,
+            "right_click" => self.output.click(3).await?,
+            "middle_click" => self.output.click(2).await?,
+            "toggle_gestures" => status::set_paused(!status::paused()),
+            // Held until the next tap; left-handed mode holds the right button
+            "drag_lock" => {
+                let button = if self.config.gesture.left_handed {
+                    3
+                } else {
+                    1
+                };
+                if output::held_buttons().contains(&button) {
+                    debug!("Mouse button {} is already held", button);
+                } else {
+                    self.output.set_button(button, true).await?;
+                }
+            }
+            "toggle_overlay" => {
+                overlay::toggle(&self.config.overlay);
+            }
+            // Scrolling itself is left to the kernel driver's native wheel emulation
+            "scroll_vertical" | "scroll_horizontal" => {
+                trace!("Native scrolling for {}", action_name);
+            }
+            // Only meaningful for scroll gestures, which carry the travel
+            "smooth_scroll" => {
+                warn!("smooth_scroll is only supported for scroll actions");
+            }
+            "scroll_up" => self.output.scroll(0, 1).await?,
+            "scroll_down" => self.output.scroll(0, -1).await?,
+            "scroll_left" => self.output.scroll(-1, 0).await?,
+            "scroll_right" => self.output.scroll(1, 0).await?,
+            command => {
+                let (kind, argument) = command.split_once(':').unwrap_or_default();
+                match kind {
+                    "key" => self.output.key(argument).await?,
+                    "button" => {
+                        let button = output::parse_button(argument)
+                            .with_context(|| format!("Unknown mouse button: {}", argument))?;
+                        self.output.click(button).await?
+                    }
+                    "scroll" => {
+                        let (horizontal, vertical) = output::parse_scroll(argument)
+                            .with_context(|| format!("Invalid scroll action: {}", command))?;
+                        self.output.scroll(horizontal, vertical).await?
+                    }
+                    "exec" => self.execute_shell_command(argument, env).await?,
+                    "media" => mpris::execute(argument).await?,
+                    "macro" => macros::execute(&self.output, argument).await?,
+                    "workspace" => workspace::execute(argument).await?,
+                    "desktop" => desktop::execute(argument).await?,
+                    "profile" => profile::execute(&self.config, argument)?,
+                    "hyprland" => compositor::hyprland_dispatch(argument).await?,
+                    "sway" => compositor::sway_command(argument).await?,
+                    "dbus" => {
+                        dbus::call(dbus::parse_method_call(argument)?).await?;
+                    }
+                    _ => match plugin::execute(kind, argument).await {
+                        Some(result) => result?,
+                        None if self.config.execution.shell_fallback => {
+                            self.execute_shell_command(command, env).await?
+                        }
+                        None => bail!(
+                            "{} is not a built-in action; prefix shell commands with exec:",
+                            command
+                        ),
+                    },
+                }
+            }
+        }
 
         Ok(())
     }
 
-    async fn execute_shell_command(&self, command: &str) -> Result<()> {
-        debug!("Executing shell command: {}", command);
+    /// Swap left and right click semantics in left-handed mode
+    fn handed_command<'a>(&self, command: &'a str) -> &'a str {
+        if !self.config.gesture.left_handed {
+            return command;
+        }
 
-        let output = Command::new("sh")
-            .args(&["-c", command])
-            .stdout(Stdio::null())
-            .stderr(Stdio::piped())
-            .output()
-            .await
-            .context("Failed to execute shell command")?;
+        match command {
+            "click" => "right_click",
+            "right_click" => "click",
+            "button:left" => "button:right",
+            "button:right" => "button:left",
+            other => other,
+        }
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            warn!("Shell command failed: {} - Error: {}", command, stderr);
+    /// Fill in `{pointer_x}`, `{pointer_y}` and `{window_id}` in each of
+    /// `commands` with the pointer location and the window under it, queried
+    /// from the X server once
+    ///
+    /// Without an X server (e.g. on Wayland) the placeholders expand to empty
+    /// strings.
+    async fn expand_placeholders(&self, commands: &[&str]) -> Vec<String> {
+        const PLACEHOLDERS: [&str; 3] = ["{pointer_x}", "{pointer_y}", "{window_id}"];
+        if !commands.iter().any(|command| {
+            PLACEHOLDERS
+                .iter()
+                .any(|placeholder| command.contains(placeholder))
+        }) {
+            return commands.iter().map(|command| command.to_string()).collect();
         }
 
-        Ok(())
+        let pointer = x11::with_connection_async(|connection| connection.query_pointer()).await;
+        let values = match pointer {
+            Ok(pointer) => [
+                pointer.x.to_string(),
+                pointer.y.to_string(),
+                pointer.window.to_string(),
+            ],
+            Err(e) => {
+                warn!("Can't query the pointer for action placeholders: {:#}", e);
+                Default::default()
+            }
+        };
+
+        commands
+            .iter()
+            .map(|command| {
+                PLACEHOLDERS
+                    .iter()
+                    .zip(&values)
+                    .fold(command.to_string(), |command, (placeholder, value)| {
+                        command.replace(placeholder, value)
+                    })
+            })
+            .collect()
     }
 
-    fn determine_swipe_direction(&self, delta_x: f64, delta_y: f64) -> &'static str {
-        if delta_x.abs() > delta_y.abs() {
-            if delta_x > 0.0 {
-                "right"
-            } else {
-                "left"
+    async fn execute_shell_command(&self, command: &str, env: &[(String, String)]) -> Result<()> {
+        let command = &self.expand_placeholders(&[command]).await.remove(0);
+        debug!("Executing shell command: {}", command);
+
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]).envs(env.iter().cloned());
+        run_process(shell, command).await
+    }
+
+    /// Run a process action; `argv` arguments are expanded one by one and
+    /// never interpreted by a shell
+    async fn execute_process(
+        &self,
+        process: &ProcessAction,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let (mut command, description) = match process.argv.split_first() {
+            Some((program, args)) => {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                let args = self.expand_placeholders(&args).await;
+                debug!("Executing {} {:?}", program, args);
+                let mut command = Command::new(program);
+                command.args(&args);
+                (command, program.clone())
             }
-        } else {
-            if delta_y > 0.0 {
-                "down"
-            } else {
-                "up"
+            None => {
+                let shell = process
+                    .shell
+                    .as_deref()
+                    .context("Process action needs argv or shell")?;
+                let shell = self.expand_placeholders(&[shell]).await.remove(0);
+                debug!("Executing shell command: {}", shell);
+                let mut command = Command::new("sh");
+                command.args(["-c", &shell]);
+                (command, shell)
             }
+        };
+
+        // The action's own variables win over the gesture's
+        command.envs(env.iter().cloned()).envs(&process.env);
+        if let Some(cwd) = &process.cwd {
+            command.current_dir(cwd);
+        }
+        run_process(command, &description).await
+    }
+}
+
+/// Direction of a swipe as used in its action name, by the dominant axis
+pub fn swipe_direction(delta_x: f64, delta_y: f64) -> &'static str {
+    if delta_x.abs() > delta_y.abs() {
+        if delta_x > 0.0 {
+            "right"
+        } else {
+            "left"
+        }
+    } else {
+        if delta_y > 0.0 {
+            "down"
+        } else {
+            "up"
+        }
+    }
+}
+
+/// Run a process to completion, logging its stderr if it fails
+async fn run_process(mut command: Command, description: &str) -> Result<()> {
+    // Killed if the action times out
+    let output = command
+        .kill_on_drop(true)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .with_context(|| format!("Failed to execute {}", description))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!("Command failed: {} - Error: {}", description, stderr);
+    }
+
+    Ok(())
+}
+
+/// Signed magnitude of a gesture for axis bindings: the log of the pinch
+/// scale, so zooming in and out by the same factor is symmetric
+fn axis_magnitude(gesture: &MultiTouchEvent) -> Option<f64> {
+    match gesture {
+        MultiTouchEvent::Pinch { scale_factor, .. } if *scale_factor > 0.0 => {
+            Some(scale_factor.ln())
+        }
+        _ => None,
+    }
+}
+
+/// Whether a command-backed scroll action may run again, recording the run
+fn scroll_action_due(
+    last_run: &mut HashMap<String, Instant>,
+    action_name: &str,
+    now: Instant,
+) -> bool {
+    if last_run
+        .get(action_name)
+        .is_some_and(|last| now.duration_since(*last) < SCROLL_ACTION_INTERVAL)
+    {
+        return false;
+    }
+    last_run.insert(action_name.to_string(), now);
+    true
+}
+
+/// Direction (sign) and number of commands for an axis binding: one per
+/// `step` of relative change, at least one
+fn axis_steps(magnitude: f64, step: f64) -> Option<(f64, usize)> {
+    if step <= 0.0 {
+        return None;
+    }
+    let count = (magnitude.abs() / step.ln_1p()).round().max(1.0);
+    Some((magnitude.signum(), count as usize))
+}
+
+/// Binding names for a gesture from most to least specific
+fn candidate_action_names(action_name: &str, held: &[&str], clicked: bool) -> Vec<String> {
+    let mut names = Vec::new();
+    if !held.is_empty() {
+        let prefix = held.join("+");
+        if clicked {
+            names.push(format!("{}+click+{}", prefix, action_name));
         }
+        names.push(format!("{}+{}", prefix, action_name));
+    }
+    if clicked {
+        names.push(format!("click+{}", action_name));
+    }
+    names.push(action_name.to_string());
+    names
+}
+
+/// Steps of an action; a single command is a one-step chain
+///
+/// Steps run strictly in order and a failing step ends the chain.
+fn action_steps(action: &Action) -> Vec<ActionStep> {
+    match action {
+        Action::Command(command) => vec![ActionStep::Command(command.clone())],
+        Action::Chain(steps) => steps.clone(),
+        Action::Process(process) => vec![ActionStep::Process(process.clone())],
+        // Run by magnitude in `run_action`
+        Action::Axis { .. } => Vec::new(),
+        Action::Repeat { repeat, .. } => vec![ActionStep::Command(repeat.clone())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macros::MacroStep;
+    use crate::multitouch::TouchContact;
+    use std::sync::Arc;
+
+    fn finger() -> Arc<TouchContact> {
+        Arc::new(TouchContact {
+            normalized_x: 0.5,
+            normalized_y: 0.5,
+            is_active: false,
+            ..TouchContact::at(1, 0, 0, 0, Instant::now())
+        })
+    }
+
+    fn tap() -> MultiTouchEvent {
+        MultiTouchEvent::SingleFingerTap {
+            finger: finger(),
+            duration_ms: 80,
+        }
+    }
+
+    /// Held buttons are process-wide, so no other test may press any
+    #[tokio::test]
+    async fn test_drag_lock() {
+        let mut config = Config::default();
+        config.actions.insert(
+            "tap_1finger".to_string(),
+            Action::Command("drag_lock".to_string()),
+        );
+        let mut handler = EventHandler::new(config.clone());
+        handler.output = Output::discarding();
+        let modifiers = GestureModifiers::default();
+        let press = |button: u8, down: bool| MacroStep::Button { button, down };
+
+        // Locking twice presses the button only once
+        handler
+            .handle_multitouch_event(tap(), modifiers)
+            .await
+            .unwrap();
+        handler
+            .execute_command("tap_1finger", "drag_lock", &[])
+            .await
+            .unwrap();
+        assert_eq!(output::held_buttons(), vec![1]);
+        assert_eq!(handler.output.discarded(), vec![press(1, true)]);
+
+        // The next tap only releases it; running its action would lock again
+        handler
+            .handle_multitouch_event(tap(), modifiers)
+            .await
+            .unwrap();
+        assert!(output::held_buttons().is_empty());
+        assert_eq!(
+            handler.output.discarded(),
+            vec![press(1, true), press(1, false)]
+        );
+
+        // Stopping doesn't leave it held
+        handler
+            .handle_multitouch_event(tap(), modifiers)
+            .await
+            .unwrap();
+        handler.handle_stop().await;
+        assert!(output::held_buttons().is_empty());
+
+        // Left-handed, the right button is held
+        config.gesture.left_handed = true;
+        let mut handler = EventHandler::new(config);
+        handler.output = Output::discarding();
+        handler
+            .handle_multitouch_event(tap(), modifiers)
+            .await
+            .unwrap();
+        assert_eq!(output::held_buttons(), vec![3]);
+        handler.handle_stop().await;
+        assert_eq!(
+            handler.output.discarded(),
+            vec![press(3, true), press(3, false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_swipe_after_scrolling_is_skipped() {
+        let mut config = Config::default();
+        config.actions.insert(
+            "scroll_vertical".to_string(),
+            Action::Command("smooth_scroll".to_string()),
+        );
+        config.actions.insert(
+            "swipe_down_2finger".to_string(),
+            Action::Command("key:a".to_string()),
+        );
+        let mut handler = EventHandler::new(config);
+        handler.output = Output::discarding();
+        let modifiers = GestureModifiers::default();
+        let swipe = || MultiTouchEvent::TwoFingerSwipe {
+            finger1: finger(),
+            finger2: finger(),
+            delta_x: 0.0,
+            delta_y: 15.0,
+        };
+        let keys = |handler: &EventHandler| {
+            handler
+                .output
+                .discarded()
+                .iter()
+                .filter(|step| matches!(step, MacroStep::Key { .. }))
+                .count()
+        };
+
+        // The travel scrolled, so the swipe's key isn't pressed as well
+        for _ in 0..3 {
+            let scroll = MultiTouchEvent::Scroll {
+                delta_x: 0.0,
+                delta_y: 5.0,
+            };
+            handler
+                .handle_multitouch_event(scroll, modifiers)
+                .await
+                .unwrap();
+        }
+        handler
+            .handle_multitouch_event(swipe(), modifiers)
+            .await
+            .unwrap();
+        assert_eq!(keys(&handler), 0);
+
+        // A swipe of the next session that didn't scroll runs
+        handler
+            .handle_multitouch_event(swipe(), modifiers)
+            .await
+            .unwrap();
+        assert_eq!(keys(&handler), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeat_swipe() {
+        let mut config = Config::default();
+        for (direction, key) in [("right", "Right"), ("left", "Left"), ("down", "Down")] {
+            config.actions.insert(
+                format!("swipe_{}_2finger", direction),
+                Action::Repeat {
+                    repeat: format!("key:{}", key),
+                    every_mm: 5.0,
+                },
+            );
+        }
+        let mut handler = EventHandler::new(config);
+        handler.output = Output::discarding();
+        let modifiers = GestureModifiers::default();
+        let scroll = |delta_x, delta_y| MultiTouchEvent::Scroll { delta_x, delta_y };
+        let keys = |handler: &EventHandler| -> Vec<String> {
+            handler
+                .output
+                .discarded()
+                .into_iter()
+                .filter_map(|step| match step {
+                    MacroStep::Key { combo } => Some(combo),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // One repeat per 5mm, with the rest carried into the next frame
+        for (delta_x, delta_y) in [(12.0, 0.0), (4.0, 4.0), (0.0, 2.0)] {
+            handler
+                .handle_multitouch_event(scroll(delta_x, delta_y), modifiers)
+                .await
+                .unwrap();
+        }
+        assert_eq!(keys(&handler), ["Right", "Right", "Right", "Down"]);
+
+        // Reversing counts the travel back from what was left over
+        handler
+            .handle_multitouch_event(scroll(-12.0, 0.0), modifiers)
+            .await
+            .unwrap();
+        assert_eq!(keys(&handler)[4..], ["Left", "Left"]);
+
+        // The swipe ending the session doesn't repeat once more
+        let swipe = MultiTouchEvent::TwoFingerSwipe {
+            finger1: finger(),
+            finger2: finger(),
+            delta_x: -20.0,
+            delta_y: 0.0,
+        };
+        handler
+            .handle_multitouch_event(swipe, modifiers)
+            .await
+            .unwrap();
+        assert_eq!(keys(&handler).len(), 6);
+    }
+
+    #[test]
+    fn test_axis_steps() {
+        // 1.1^3 ≈ 1.33, and pinching in by the same factor mirrors it
+        assert_eq!(axis_steps(1.331f64.ln(), 0.1), Some((1.0, 3)));
+        assert_eq!(axis_steps((1.0f64 / 1.331).ln(), 0.1), Some((-1.0, 3)));
+        assert_eq!(axis_steps(1.01f64.ln(), 0.1), Some((1.0, 1)));
+        assert_eq!(axis_steps(1.5f64.ln(), 0.0), None);
+    }
+
+    #[tokio::test]
+    async fn test_process_action_runs_without_shell() {
+        // A string is a shell command, argv a program with its arguments
+        let action: Action = serde_json::from_str(r#""touch a""#).unwrap();
+        assert_eq!(action.as_command(), Some("touch a"));
+        let name = "a b;touch c";
+        let action: Action =
+            serde_json::from_value(serde_json::json!({"argv": ["touch", name]})).unwrap();
+        let Action::Process(mut process) = action else {
+            panic!("expected a process action, got {:?}", action);
+        };
+
+        let dir = std::env::temp_dir().join(format!("mouse-gesture-argv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        process.cwd = Some(dir.clone());
+        let handler = EventHandler::new(Config::default());
+        handler.execute_process(&process, &[]).await.unwrap();
+        // The argument reaches touch verbatim, spaces and semicolon included
+        let created: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(created, [name]);
+
+        // Nothing to run is an error, not a panic
+        let empty: ProcessAction = serde_json::from_str(r#"{"argv": []}"#).unwrap();
+        let error = handler.execute_process(&empty, &[]).await.unwrap_err();
+        assert!(error.to_string().contains("argv or shell"), "{}", error);
+    }
+
+    #[test]
+    fn test_scroll_action_due() {
+        let mut last_run = HashMap::new();
+        let start = Instant::now();
+        assert!(scroll_action_due(&mut last_run, "scroll_vertical", start));
+        // Frames in between are dropped, other actions have their own interval
+        let frame = start + Duration::from_millis(10);
+        assert!(!scroll_action_due(&mut last_run, "scroll_vertical", frame));
+        assert!(scroll_action_due(&mut last_run, "scroll_horizontal", frame));
+        let later = start + SCROLL_ACTION_INTERVAL;
+        assert!(scroll_action_due(&mut last_run, "scroll_vertical", later));
+    }
+
+    #[test]
+    fn test_candidate_action_names() {
+        assert_eq!(
+            candidate_action_names("swipe_up_2finger", &["ctrl", "shift"], true),
+            vec![
+                "ctrl+shift+click+swipe_up_2finger",
+                "ctrl+shift+swipe_up_2finger",
+                "click+swipe_up_2finger",
+                "swipe_up_2finger",
+            ]
+        );
+        assert_eq!(
+            candidate_action_names("tap_1finger", &[], false),
+            vec!["tap_1finger"]
+        );
+    }
+
+    #[test]
+    fn test_handed_command() {
+        let mut config = Config::default();
+        let handler = EventHandler::new(config.clone());
+        assert_eq!(handler.handed_command("click"), "click");
+
+        config.gesture.left_handed = true;
+        let handler = EventHandler::new(config);
+        assert_eq!(handler.handed_command("click"), "right_click");
+        assert_eq!(handler.handed_command("right_click"), "click");
+        assert_eq!(handler.handed_command("button:left"), "button:right");
+        assert_eq!(handler.handed_command("button:right"), "button:left");
+        assert_eq!(handler.handed_command("middle_click"), "middle_click");
     }
 }
@@ -0,0 +1,121 @@
+use anyhow::{bail, Result};
+use evdev::{EventType, InputEvent, Synchronization};
+use log::warn;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::config::OverflowPolicy;
+use crate::metrics;
+
+/// Sending half of the buffer between a device's reader task and gesture
+/// recognition
+///
+/// With `OverflowPolicy::Resync` the reader never waits on a slow
+/// recognition loop, so the kernel's own event buffer keeps draining. Events
+/// arriving while the buffer is full are dropped and counted; once there is
+/// room again a synthetic `SYN_DROPPED` goes ahead of the next event, which
+/// makes the multi-touch processor discard the torn frame and re-read the slot
+/// state from the device, just as after a kernel overflow.
+pub struct EventSender {
+    sender: mpsc::Sender<InputEvent>,
+    policy: OverflowPolicy,
+    overflowed: bool,
+}
+
+/// Create the buffer; `capacity` events fit before the overflow policy applies
+pub fn channel(
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (EventSender, mpsc::Receiver<InputEvent>) {
+    let (sender, receiver) = mpsc::channel(capacity.max(2));
+    let sender = EventSender {
+        sender,
+        policy,
+        overflowed: false,
+    };
+    (sender, receiver)
+}
+
+impl EventSender {
+    /// Hand `event` to recognition; fails only once the receiver is gone
+    pub async fn send(&mut self, event: InputEvent) -> Result<()> {
+        if event.event_type() == EventType::SYNCHRONIZATION
+            && event.code() == Synchronization::SYN_DROPPED.0
+        {
+            metrics::kernel_dropped();
+        }
+
+        if self.policy == OverflowPolicy::Block {
+            if self.sender.send(event).await.is_err() {
+                bail!("Event receiver closed");
+            }
+            return Ok(());
+        }
+
+        if self.overflowed {
+            if self.sender.capacity() == 0 {
+                metrics::reader_dropped();
+                return Ok(());
+            }
+            let dropped = InputEvent::new(
+                EventType::SYNCHRONIZATION,
+                Synchronization::SYN_DROPPED.0,
+                0,
+            );
+            self.try_send(dropped)?;
+            self.overflowed = false;
+        }
+        self.try_send(event)
+    }
+
+    fn try_send(&mut self, event: InputEvent) -> Result<()> {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                if !self.overflowed {
+                    warn!("Event buffer full, dropping input until recognition catches up");
+                }
+                metrics::reader_dropped();
+                self.overflowed = true;
+            }
+            Err(TrySendError::Closed(_)) => bail!("Event receiver closed"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::AbsoluteAxisType;
+
+    fn position(x: i32) -> InputEvent {
+        InputEvent::new(
+            EventType::ABSOLUTE,
+            AbsoluteAxisType::ABS_MT_POSITION_X.0,
+            x,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_overflow_resyncs() {
+        let (mut sender, mut receiver) = channel(2, OverflowPolicy::Resync);
+        for x in 0..5 {
+            sender.send(position(x)).await.unwrap();
+        }
+
+        // The first events fit, the rest were dropped without blocking
+        assert_eq!(receiver.recv().await.unwrap().value(), 0);
+        assert_eq!(receiver.recv().await.unwrap().value(), 1);
+        assert!(receiver.try_recv().is_err());
+
+        // The gap is announced ahead of the next event
+        sender.send(position(5)).await.unwrap();
+        let dropped = receiver.recv().await.unwrap();
+        assert_eq!(dropped.event_type(), EventType::SYNCHRONIZATION);
+        assert_eq!(dropped.code(), Synchronization::SYN_DROPPED.0);
+        assert_eq!(receiver.recv().await.unwrap().value(), 5);
+
+        drop(receiver);
+        assert!(sender.send(position(6)).await.is_err());
+    }
+}
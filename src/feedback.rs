@@ -0,0 +1,112 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+use crate::config::{FeedbackConfig, FeedbackHook};
+use crate::output;
+
+/// Sound players tried in order
+const SOUND_PLAYERS: [&str; 3] = ["paplay", "pw-play", "aplay"];
+
+/// Moment a feedback hook fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedbackEvent {
+    /// Fingers touched the surface
+    Begin,
+    /// A gesture was recognized
+    End,
+    /// A resting finger dwell-clicked
+    Dwell,
+}
+
+/// Runs the configured feedback hooks, at most once per interval each
+pub struct Feedback {
+    config: FeedbackConfig,
+    last_fired: Mutex<HashMap<FeedbackEvent, Instant>>,
+}
+
+impl Feedback {
+    pub fn new(config: FeedbackConfig) -> Self {
+        Self {
+            config,
+            last_fired: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start the hook for `event` in the background
+    pub fn fire(&self, event: FeedbackEvent) {
+        let hook = match event {
+            FeedbackEvent::Begin => &self.config.begin,
+            FeedbackEvent::End => &self.config.end,
+            FeedbackEvent::Dwell => &self.config.dwell,
+        };
+        if hook.sound.is_none() && hook.command.is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        let min_interval = Duration::from_millis(self.config.min_interval_ms);
+        {
+            let mut last_fired = self.last_fired.lock().unwrap_or_else(|e| e.into_inner());
+            if last_fired
+                .get(&event)
+                .is_some_and(|last| now.duration_since(*last) < min_interval)
+            {
+                debug!("Skipping {:?} feedback, fired too recently", event);
+                return;
+            }
+            last_fired.insert(event, now);
+        }
+
+        run_hook(hook);
+    }
+}
+
+fn run_hook(hook: &FeedbackHook) {
+    if let Some(sound) = &hook.sound {
+        play_sound(sound);
+    }
+    if let Some(command) = &hook.command {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        spawn_detached(shell);
+    }
+}
+
+fn play_sound(sound: &Path) {
+    static PLAYER: OnceLock<Option<&'static str>> = OnceLock::new();
+    let player = PLAYER.get_or_init(|| {
+        SOUND_PLAYERS
+            .into_iter()
+            .find(|player| output::command_exists(player))
+    });
+
+    match player {
+        Some(player) => {
+            let mut command = Command::new(player);
+            command.arg(sound);
+            spawn_detached(command);
+        }
+        None => warn!("No sound player found (tried {})", SOUND_PLAYERS.join(", ")),
+    }
+}
+
+/// Start a feedback process without waiting for it, reaping it once it exits
+fn spawn_detached(mut command: Command) {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    match command.spawn() {
+        Ok(mut child) => {
+            tokio::spawn(async move {
+                let _ = child.wait().await;
+            });
+        }
+        Err(e) => warn!("Failed to run feedback hook: {}", e),
+    }
+}
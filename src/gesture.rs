@@ -1,34 +1,270 @@
 use log::{debug, trace};
 
 use crate::multitouch::{MultiTouchEvent, TouchContact};
+use crate::timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-// Magic Mouse 2 USB-C 2024 hardware specifications
-// Based on evtest output showing resolution values
-const MAGIC_MOUSE_X_RESOLUTION: f64 = 26.0; // units per mm
-const MAGIC_MOUSE_Y_RESOLUTION: f64 = 70.0; // units per mm
+/// Tracks how the number of fingers on the surface changes over one touch session
+///
+/// A session starts when the first finger lands and ends when the last one lifts.
+/// Finger-count phases shorter than the grace window are treated as transitions
+/// (e.g. the second finger of a two-finger tap landing a few ms late) and do not
+/// count towards the dominant finger count.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GestureSession {
+    /// Time spent at each finger count
+    count_durations: BTreeMap<usize, Duration>,
+    /// Finger count of the phase currently in progress
+    current_count: usize,
+    /// When the current phase started
+    #[serde(with = "timestamp")]
+    phase_start: Instant,
+    /// Highest finger count seen during the session
+    max_count: usize,
+    /// Phases shorter than this are ignored when picking the dominant count
+    grace_window: Duration,
+}
+
+impl GestureSession {
+    pub fn new(initial_count: usize, now: Instant, grace_window_ms: u64) -> Self {
+        Self {
+            count_durations: BTreeMap::new(),
+            current_count: initial_count,
+            phase_start: now,
+            max_count: initial_count,
+            grace_window: Duration::from_millis(grace_window_ms),
+        }
+    }
+
+    /// Record a finger-count change, closing the current phase
+    pub fn update_count(&mut self, count: usize, now: Instant) {
+        if count == self.current_count {
+            return;
+        }
+
+        debug!(
+            "Finger count transition {} -> {}",
+            self.current_count, count
+        );
+        self.close_phase(now);
+        self.current_count = count;
+        self.max_count = self.max_count.max(count);
+    }
+
+    fn close_phase(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.phase_start);
+        if self.current_count > 0 {
+            *self
+                .count_durations
+                .entry(self.current_count)
+                .or_insert(Duration::ZERO) += elapsed;
+        }
+        self.phase_start = now;
+    }
+
+    /// Finger count that dominated the session
+    ///
+    /// Only phases at least as long as the grace window are considered. If every
+    /// phase was shorter (a very quick tap), the longest phase wins instead. Ties
+    /// go to the higher finger count.
+    pub fn dominant_count(&self, now: Instant) -> usize {
+        let mut durations = self.count_durations.clone();
+        if self.current_count > 0 {
+            *durations
+                .entry(self.current_count)
+                .or_insert(Duration::ZERO) += now.saturating_duration_since(self.phase_start);
+        }
+
+        let pick = |min: Duration| {
+            durations
+                .iter()
+                .filter(|(_, duration)| **duration >= min)
+                .max_by_key(|(count, duration)| (**duration, **count))
+                .map(|(count, _)| *count)
+        };
+
+        pick(self.grace_window)
+            .or_else(|| pick(Duration::ZERO))
+            .unwrap_or(self.max_count)
+    }
+
+    /// Finish the session and select the contacts that belong to the dominant gesture
+    ///
+    /// Contacts that were only on the surface briefly (a resting third finger that
+    /// lifted quickly, for example) are dropped in favour of the longest-lived ones.
+    pub fn finish<'a>(
+        &mut self,
+        contacts: &'a [TouchContact],
+        now: Instant,
+    ) -> Vec<&'a TouchContact> {
+        self.close_phase(now);
+        self.current_count = 0;
+
+        let dominant = self.dominant_count(now);
+        if dominant >= contacts.len() {
+            return contacts.iter().collect();
+        }
+
+        debug!(
+            "Dominant finger count {} over {} contacts in session",
+            dominant,
+            contacts.len()
+        );
+
+        let mut selected: Vec<&TouchContact> = contacts.iter().collect();
+        selected.sort_by_key(|contact| std::cmp::Reverse(contact.contact_duration()));
+        selected.truncate(dominant);
+        selected.sort_by_key(|contact| contact.first_contact_time);
+        selected
+    }
+}
+
+/// Resting finger detection behind the dwell click and the long press: a
+/// single finger staying within a small radius for long enough fires the
+/// tracker's gesture
+///
+/// Each rest fires once; the finger (or, on a mouse, the pointer) has to move
+/// before the next rest counts.
+pub struct DwellTracker {
+    duration: Duration,
+    radius: f64,
+    /// Builds the gesture from the resting contact and how long it rested
+    gesture: fn(Arc<TouchContact>, u64) -> MultiTouchEvent,
+    rest: Option<Rest>,
+}
+
+/// Where and since when the watched finger rests
+struct Rest {
+    id: i32,
+    anchor: (f64, f64),
+    since: Instant,
+    clicked: bool,
+}
+
+impl DwellTracker {
+    pub fn new(
+        duration_ms: u64,
+        radius: f64,
+        gesture: fn(Arc<TouchContact>, u64) -> MultiTouchEvent,
+    ) -> Self {
+        Self {
+            duration: Duration::from_millis(duration_ms),
+            radius,
+            gesture,
+            rest: None,
+        }
+    }
+
+    /// Follow the contacts after a sync; `moved` is pointer motion or a held
+    /// button, which restarts the countdown
+    pub fn update(&mut self, contacts: &[&TouchContact], moved: bool, now: Instant) {
+        let [contact] = contacts else {
+            self.rest = None;
+            return;
+        };
+        let position = contact.position_mm();
+        if let Some(rest) = &self.rest {
+            let (dx, dy) = (position.0 - rest.anchor.0, position.1 - rest.anchor.1);
+            if !moved && rest.id == contact.id && dx.hypot(dy) <= self.radius {
+                return;
+            }
+        }
+        self.rest = Some(Rest {
+            id: contact.id,
+            anchor: position,
+            since: now,
+            clicked: false,
+        });
+    }
+
+    /// When the resting finger fires, `None` if no countdown is running
+    pub fn deadline(&self) -> Option<Instant> {
+        self.rest
+            .as_ref()
+            .filter(|rest| !rest.clicked)
+            .map(|rest| rest.since + self.duration)
+    }
+
+    /// The gesture, once the finger has rested long enough by `now`
+    pub fn expire(&mut self, contacts: &[&TouchContact], now: Instant) -> Option<MultiTouchEvent> {
+        let rest = self.rest.as_mut()?;
+        if rest.clicked || now < rest.since + self.duration {
+            return None;
+        }
+        let contact = match contacts {
+            [contact] if contact.id == rest.id => contact,
+            _ => {
+                self.rest = None;
+                return None;
+            }
+        };
+        rest.clicked = true;
+        let duration_ms = now.duration_since(rest.since).as_millis() as u64;
+        Some((self.gesture)(Arc::new((*contact).clone()), duration_ms))
+    }
+
+    /// Treat the current rest as used up, so it doesn't fire after another
+    /// tracker's gesture
+    fn consume(&mut self) {
+        if let Some(rest) = self.rest.as_mut() {
+            rest.clicked = true;
+        }
+    }
+}
 
-/// Convert Magic Mouse X coordinate units to millimeters
-fn units_to_mm_x(units: i32) -> f64 {
-    units as f64 / MAGIC_MOUSE_X_RESOLUTION
+/// Gestures of the resting-finger trackers due by `now`; whichever fires
+/// first takes the rest, so the dwell click and long press never both fire
+pub fn expire_rests(
+    trackers: &mut [DwellTracker],
+    contacts: &[&TouchContact],
+    now: Instant,
+) -> Vec<MultiTouchEvent> {
+    let mut events = Vec::new();
+    for index in 0..trackers.len() {
+        if let Some(event) = trackers[index].expire(contacts, now) {
+            events.push(event);
+            trackers.iter_mut().for_each(DwellTracker::consume);
+        }
+    }
+    events
 }
 
-/// Convert Magic Mouse Y coordinate units to millimeters
-fn units_to_mm_y(units: i32) -> f64 {
-    units as f64 / MAGIC_MOUSE_Y_RESOLUTION
+/// A single gesture classifier plugged into the recognition pipeline
+///
+/// `feed` receives the contacts of a touch session and returns the gestures it
+/// recognized, or an empty list if the contacts don't match its pattern.
+pub trait Recognizer: Send {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Inspect the contacts and report any recognized gestures
+    fn feed(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent>;
+
+    /// Inspect the active contacts after each sync while the session is in
+    /// progress. Recognizers that only classify finished sessions keep the
+    /// default, which reports nothing.
+    fn feed_frame(&mut self, _contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        Vec::new()
+    }
+
+    /// Drop any per-session state once the session has ended
+    fn reset(&mut self) {}
 }
 
-/// Gesture recognizer focused on multi-touch tap and swipe detection
+/// Gesture recognition pipeline
+///
+/// Recognizers are consulted in registration order and the first one that
+/// reports a gesture wins, so earlier recognizers take priority (taps before
+/// swipes before pinches for the built-in set).
 pub struct GestureRecognizer {
-    swipe_threshold: f64,
-    pinch_threshold: f64,
-    tap_timeout_ms: u64,
-    single_finger_tap_movement_threshold: f64,
-    two_finger_tap_timeout_ms: u64,
-    two_finger_tap_distance_threshold: f64,
+    recognizers: Vec<Box<dyn Recognizer>>,
 }
 
 impl GestureRecognizer {
+    /// Build the pipeline with the built-in tap, swipe and pinch recognizers
     pub fn new(
         swipe_threshold: f64,
         pinch_threshold: f64,
@@ -38,30 +274,91 @@ impl GestureRecognizer {
         two_finger_tap_timeout_ms: u64,
         two_finger_tap_distance_threshold: f64,
     ) -> Self {
-        Self {
-            swipe_threshold,
-            pinch_threshold,
+        let mut pipeline = Self::empty();
+        pipeline.register(Box::new(TapRecognizer {
             tap_timeout_ms,
             single_finger_tap_movement_threshold,
             two_finger_tap_timeout_ms,
             two_finger_tap_distance_threshold,
+        }));
+        pipeline.register(Box::new(SwipeRecognizer { swipe_threshold }));
+        pipeline.register(Box::new(PinchRecognizer { pinch_threshold }));
+        pipeline
+    }
+
+    /// Create a pipeline without any recognizers
+    pub fn empty() -> Self {
+        Self {
+            recognizers: Vec::new(),
         }
     }
 
-    /// Analyze contacts and detect gestures
-    pub fn analyze_gesture(&mut self, contacts: &[TouchContact]) -> Option<MultiTouchEvent> {
+    /// Append a recognizer to the end of the pipeline
+    pub fn register(&mut self, recognizer: Box<dyn Recognizer>) {
+        debug!("Registered gesture recognizer: {}", recognizer.name());
+        self.recognizers.push(recognizer);
+    }
+
+    /// Analyze the contacts of a finished session and detect gestures
+    pub fn analyze_gesture(&mut self, contacts: &[&TouchContact]) -> Option<MultiTouchEvent> {
         debug!("Analyzing {} contacts for gestures", contacts.len());
-        match contacts.len() {
-            1 => self.analyze_single_finger(contacts),
-            2 => self.analyze_two_finger(contacts),
-            _ => None,
+        let mut result = None;
+        for recognizer in self.recognizers.iter_mut() {
+            if let Some(event) = recognizer.feed(contacts).into_iter().next() {
+                trace!("Recognizer {} matched", recognizer.name());
+                result = Some(event);
+                break;
+            }
+        }
+
+        self.reset();
+        result
+    }
+
+    /// Drop the per-session state of every recognizer
+    pub fn reset(&mut self) {
+        for recognizer in self.recognizers.iter_mut() {
+            recognizer.reset();
+        }
+    }
+
+    /// Feed the active contacts of an in-progress session to every recognizer
+    pub fn analyze_frame(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        self.recognizers
+            .iter_mut()
+            .flat_map(|recognizer| recognizer.feed_frame(contacts))
+            .collect()
+    }
+}
+
+/// Single and two-finger tap detection
+pub struct TapRecognizer {
+    tap_timeout_ms: u64,
+    single_finger_tap_movement_threshold: f64,
+    two_finger_tap_timeout_ms: u64,
+    two_finger_tap_distance_threshold: f64,
+}
+
+impl Recognizer for TapRecognizer {
+    fn name(&self) -> &'static str {
+        "tap"
+    }
+
+    fn feed(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        match contacts {
+            [contact] => self.single_finger_tap(contact).into_iter().collect(),
+            [contact1, contact2] => self
+                .two_finger_tap(contact1, contact2)
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
         }
     }
+}
 
-    /// Detect single finger gestures (primarily taps)
-    fn analyze_single_finger(&self, contacts: &[TouchContact]) -> Option<MultiTouchEvent> {
-        let contact = &contacts[0];
-        // Check for single tap - short duration and contact is no longer active
+impl TapRecognizer {
+    /// Detect a single tap - short duration and contact is no longer active
+    fn single_finger_tap(&self, contact: &TouchContact) -> Option<MultiTouchEvent> {
         if !contact.is_active
             && contact.is_tap(
                 self.tap_timeout_ms,
@@ -69,7 +366,7 @@ impl GestureRecognizer {
             )
         {
             return Some(MultiTouchEvent::SingleFingerTap {
-                finger: contact.clone(),
+                finger: Arc::new(contact.clone()),
                 duration_ms: contact.contact_duration().as_millis() as u64,
             });
         }
@@ -77,58 +374,25 @@ impl GestureRecognizer {
         None
     }
 
-    /// Detect two finger gestures (taps, swipes, pinch)
-    fn analyze_two_finger(&self, contacts: &[TouchContact]) -> Option<MultiTouchEvent> {
-        let contact1 = &contacts[0];
-        let contact2 = &contacts[1];
-
-        // Check for two-finger tap first (highest priority)
-        if self.is_two_finger_tap(contact1, contact2) {
-            let max_duration = contact1.contact_duration().max(contact2.contact_duration());
-            trace!(
-                "Detected two-finger tap: duration_ms = {}",
-                max_duration.as_millis()
-            );
-            return Some(MultiTouchEvent::TwoFingerTap {
-                finger1: contact1.clone(),
-                finger2: contact2.clone(),
-                duration_ms: max_duration.as_millis() as u64,
-            });
-        }
-
-        // Check for swipe gesture
-        if let Some((delta_x, delta_y)) = self.detect_swipe(contact1, contact2) {
-            trace!(
-                "Detected two-finger swipe: delta_x = {}, delta_y = {}",
-                delta_x,
-                delta_y
-            );
-            return Some(MultiTouchEvent::TwoFingerSwipe {
-                finger1: contact1.clone(),
-                finger2: contact2.clone(),
-                delta_x,
-                delta_y,
-            });
-        }
-
-        // Check for pinch gesture
-        if let Some(scale_factor) = self.detect_pinch(contact1, contact2) {
-            let center_x = (units_to_mm_x(contact1.x) + units_to_mm_x(contact2.x)) / 2.0;
-            let center_y = (units_to_mm_y(contact1.y) + units_to_mm_y(contact2.y)) / 2.0;
-            trace!(
-                "Detected pinch gesture: center_x = {}, center_y = {}, scale_factor = {}",
-                center_x,
-                center_y,
-                scale_factor
-            );
-            return Some(MultiTouchEvent::Pinch {
-                center_x,
-                center_y,
-                scale_factor,
-            });
+    fn two_finger_tap(
+        &self,
+        contact1: &TouchContact,
+        contact2: &TouchContact,
+    ) -> Option<MultiTouchEvent> {
+        if !self.is_two_finger_tap(contact1, contact2) {
+            return None;
         }
 
-        None
+        let max_duration = contact1.contact_duration().max(contact2.contact_duration());
+        trace!(
+            "Detected two-finger tap: duration_ms = {}",
+            max_duration.as_millis()
+        );
+        Some(MultiTouchEvent::TwoFingerTap {
+            finger1: Arc::new(contact1.clone()),
+            finger2: Arc::new(contact2.clone()),
+            duration_ms: max_duration.as_millis() as u64,
+        })
     }
 
     /// Detect two-finger tap based on Linux Multi-Touch Protocol requirements
@@ -160,7 +424,42 @@ impl GestureRecognizer {
 
         time_diff < Duration::from_millis(100)
     }
+}
+
+/// Two-finger swipe detection based on average movement
+pub struct SwipeRecognizer {
+    swipe_threshold: f64,
+}
+
+impl Recognizer for SwipeRecognizer {
+    fn name(&self) -> &'static str {
+        "swipe"
+    }
 
+    fn feed(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        let [contact1, contact2] = contacts else {
+            return Vec::new();
+        };
+
+        let Some((delta_x, delta_y)) = self.detect_swipe(contact1, contact2) else {
+            return Vec::new();
+        };
+
+        trace!(
+            "Detected two-finger swipe: delta_x = {}, delta_y = {}",
+            delta_x,
+            delta_y
+        );
+        vec![MultiTouchEvent::TwoFingerSwipe {
+            finger1: Arc::new((*contact1).clone()),
+            finger2: Arc::new((*contact2).clone()),
+            delta_x,
+            delta_y,
+        }]
+    }
+}
+
+impl SwipeRecognizer {
     /// Detect swipe gestures based on movement delta
     fn detect_swipe(&self, contact1: &TouchContact, contact2: &TouchContact) -> Option<(f64, f64)> {
         let (dx1, dy1) = contact1.movement_delta();
@@ -178,30 +477,172 @@ impl GestureRecognizer {
             None
         }
     }
+}
 
-    /// Detect pinch gestures based on distance changes between two contacts over time
-    fn detect_pinch(&self, contact1: &TouchContact, contact2: &TouchContact) -> Option<f64> {
-        // Need at least 3 position samples to calculate meaningful distance changes
-        if contact1.position_history.len() < 3 || contact2.position_history.len() < 3 {
-            return None;
+/// Axis a scroll session is locked to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// Continuous two-finger scrolling
+///
+/// Emits a scroll event for every frame once the fingers have travelled past the
+/// scroll threshold. With axis locking enabled, the dominant direction of that
+/// initial travel fixes the axis for the rest of the session, so diagonal drift
+/// doesn't leak into the other axis.
+pub struct ScrollRecognizer {
+    scroll_threshold: f64,
+    axis_lock: bool,
+    /// Finger centroid (mm) when the session started, or when the pair last
+    /// changed before scrolling began
+    anchor: Option<(f64, f64)>,
+    /// Finger centroid (mm) at the last emitted scroll event
+    last_position: (f64, f64),
+    /// Tracking IDs of the pair in the previous frame, `None` after a frame
+    /// without exactly two fingers
+    pair: Option<(i32, i32)>,
+    /// Whether the initial threshold has been crossed
+    scrolling: bool,
+    /// Axis chosen after crossing the threshold (only with axis locking)
+    locked_axis: Option<ScrollAxis>,
+}
+
+impl ScrollRecognizer {
+    pub fn new(scroll_threshold: f64, axis_lock: bool) -> Self {
+        Self {
+            scroll_threshold,
+            axis_lock,
+            anchor: None,
+            last_position: (0.0, 0.0),
+            pair: None,
+            scrolling: false,
+            locked_axis: None,
         }
+    }
+}
 
-        // Calculate initial distance (using early positions, skipping the (0,0) initialization)
-        let initial_pos1 = if contact1.position_history.len() >= 3 {
-            contact1.position_history[2] // Skip the (0,0) initialization
-        } else {
-            contact1.position_history[1]
+impl Recognizer for ScrollRecognizer {
+    fn name(&self) -> &'static str {
+        "scroll"
+    }
+
+    fn feed(&mut self, _contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        Vec::new()
+    }
+
+    fn feed_frame(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        let [contact1, contact2] = contacts else {
+            self.pair = None;
+            return Vec::new();
         };
 
-        let initial_pos2 = if contact2.position_history.len() >= 3 {
-            contact2.position_history[2] // Skip the (0,0) initialization
-        } else {
-            contact2.position_history[1]
+        let (x1, y1) = contact1.position_mm();
+        let (x2, y2) = contact2.position_mm();
+        let position = ((x1 + x2) / 2.0, (y1 + y2) / 2.0);
+
+        // Another pair has its centroid elsewhere; measure from there instead
+        // of scrolling by the jump
+        let pair = (contact1.id.min(contact2.id), contact1.id.max(contact2.id));
+        if self.pair != Some(pair) {
+            self.pair = Some(pair);
+            if self.scrolling {
+                self.last_position = position;
+            } else {
+                self.anchor = Some(position);
+            }
+        }
+        let anchor = *self.anchor.get_or_insert(position);
+
+        if !self.scrolling {
+            let (total_x, total_y) = (position.0 - anchor.0, position.1 - anchor.1);
+            if (total_x * total_x + total_y * total_y).sqrt() < self.scroll_threshold {
+                return Vec::new();
+            }
+
+            self.scrolling = true;
+            self.last_position = anchor;
+            if self.axis_lock {
+                let axis = if total_x.abs() > total_y.abs() {
+                    ScrollAxis::Horizontal
+                } else {
+                    ScrollAxis::Vertical
+                };
+                debug!("Scroll locked to {:?} axis", axis);
+                self.locked_axis = Some(axis);
+            }
+        }
+
+        let mut delta_x = position.0 - self.last_position.0;
+        let mut delta_y = position.1 - self.last_position.1;
+        self.last_position = position;
+
+        match self.locked_axis {
+            Some(ScrollAxis::Vertical) => delta_x = 0.0,
+            Some(ScrollAxis::Horizontal) => delta_y = 0.0,
+            None => {}
+        }
+
+        if delta_x == 0.0 && delta_y == 0.0 {
+            return Vec::new();
+        }
+
+        vec![MultiTouchEvent::Scroll { delta_x, delta_y }]
+    }
+
+    fn reset(&mut self) {
+        self.anchor = None;
+        self.pair = None;
+        self.scrolling = false;
+        self.locked_axis = None;
+    }
+}
+
+/// Two-finger pinch detection based on the change in finger distance
+pub struct PinchRecognizer {
+    pinch_threshold: f64,
+}
+
+impl Recognizer for PinchRecognizer {
+    fn name(&self) -> &'static str {
+        "pinch"
+    }
+
+    fn feed(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        let [contact1, contact2] = contacts else {
+            return Vec::new();
         };
 
+        let Some(scale_factor) = self.detect_pinch(contact1, contact2) else {
+            return Vec::new();
+        };
+
+        let (x1, y1) = contact1.position_mm();
+        let (x2, y2) = contact2.position_mm();
+        let center_x = (x1 + x2) / 2.0;
+        let center_y = (y1 + y2) / 2.0;
+        trace!(
+            "Detected pinch gesture: center_x = {}, center_y = {}, scale_factor = {}",
+            center_x,
+            center_y,
+            scale_factor
+        );
+        vec![MultiTouchEvent::Pinch {
+            center_x,
+            center_y,
+            scale_factor,
+        }]
+    }
+}
+
+impl PinchRecognizer {
+    /// Detect pinch gestures based on distance changes between two contacts over time
+    fn detect_pinch(&self, contact1: &TouchContact, contact2: &TouchContact) -> Option<f64> {
+        // Measured from where both fingers first reported a complete position
+        let ((x1, y1), (x2, y2)) = (contact1.origin_mm()?, contact2.origin_mm()?);
         let initial_distance = {
-            let dx_mm = units_to_mm_x(initial_pos1.0) - units_to_mm_x(initial_pos2.0);
-            let dy_mm = units_to_mm_y(initial_pos1.1) - units_to_mm_y(initial_pos2.1);
+            let (dx_mm, dy_mm) = (x1 - x2, y1 - y2);
             (dx_mm * dx_mm + dy_mm * dy_mm).sqrt()
         };
 
@@ -233,6 +674,19 @@ impl GestureRecognizer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::multitouch::Motion;
+    use std::collections::VecDeque;
+
+    /// A contact that landed at `start` and is now at `(x, y)`
+    fn moved_contact(id: i32, start: (i32, i32), x: i32, y: i32, now: Instant) -> TouchContact {
+        let mut motion = Motion::default();
+        motion.record(start.0, start.1, now);
+        motion.record(start.0, start.1, now);
+        TouchContact {
+            motion,
+            ..TouchContact::at(id, id, x, y, now)
+        }
+    }
 
     #[test]
     fn test_two_finger_tap_detection() {
@@ -247,35 +701,22 @@ mod tests {
         );
 
         // Create two close contacts with short duration
+        let now = Instant::now();
         let contact1 = TouchContact {
-            id: 1,
-            slot: 0,
-            x: 100,
-            y: 100,
             touch_major: 100,
             touch_minor: 100,
-            orientation: 0,
-            first_contact_time: Instant::now(),
-            last_update_time: Instant::now(),
             is_active: false,
-            position_history: vec![(100, 100, Instant::now())],
+            ..TouchContact::at(1, 0, 100, 100, now)
         };
 
         let contact2 = TouchContact {
-            id: 2,
-            slot: 1,
-            x: 120,
-            y: 110,
             touch_major: 90,
             touch_minor: 90,
-            orientation: 0,
-            first_contact_time: Instant::now(),
-            last_update_time: Instant::now(),
             is_active: false,
-            position_history: vec![(120, 110, Instant::now())],
+            ..TouchContact::at(2, 1, 120, 110, now)
         };
 
-        let contacts = vec![contact1, contact2];
+        let contacts = vec![&contact1, &contact2];
 
         if let Some(MultiTouchEvent::TwoFingerTap { .. }) = recognizer.analyze_gesture(&contacts) {
             // Test passed
@@ -284,6 +725,15 @@ mod tests {
         }
     }
 
+    /// Motion of a contact that went through `history`, after the placeholder
+    fn motion_of(history: &VecDeque<(i32, i32, Instant)>) -> Motion {
+        let mut motion = Motion::default();
+        for &(x, y, time) in history.iter().skip(1) {
+            motion.record(x, y, time);
+        }
+        motion
+    }
+
     #[test]
     fn test_pinch_detection() {
         // Initialize debug logging for the test
@@ -306,45 +756,35 @@ mod tests {
         let time4 = now + Duration::from_millis(750);
 
         // Create two contacts that start close and move apart (pinch out)
-        let contact1 = TouchContact {
-            id: 1,
-            slot: 0,
-            x: 150, // Moved further apart
-            y: 150,
+        let mut contact1 = TouchContact {
             touch_major: 100,
             touch_minor: 100,
-            orientation: 0,
-            first_contact_time: time1,
             last_update_time: time4,
-            is_active: true,
-            position_history: vec![
+            position_history: VecDeque::from([
                 (0, 0, time1),     // Initial (0,0) position
                 (100, 100, time2), // First real position
                 (110, 110, time3), // Early position
                 (150, 150, time4), // Final position (moved apart)
-            ],
+            ]),
+            ..TouchContact::at(1, 0, 150, 150, time1) // Moved further apart
         };
 
-        let contact2 = TouchContact {
-            id: 2,
-            slot: 1,
-            x: 50, // Moved in opposite direction
-            y: 50,
+        let mut contact2 = TouchContact {
             touch_major: 90,
             touch_minor: 90,
-            orientation: 0,
-            first_contact_time: time1,
             last_update_time: time4,
-            is_active: true,
-            position_history: vec![
+            position_history: VecDeque::from([
                 (0, 0, time1),     // Initial (0,0) position
                 (100, 100, time2), // First real position (same as contact1)
                 (90, 90, time3),   // Early position
                 (50, 50, time4),   // Final position (moved apart)
-            ],
+            ]),
+            ..TouchContact::at(2, 1, 50, 50, time1) // Moved in opposite direction
         };
+        contact1.motion = motion_of(&contact1.position_history);
+        contact2.motion = motion_of(&contact2.position_history);
 
-        let contacts = vec![contact1.clone(), contact2.clone()];
+        let contacts = vec![&contact1, &contact2];
 
         if let Some(MultiTouchEvent::Pinch { scale_factor, .. }) =
             recognizer.analyze_gesture(&contacts)
@@ -359,15 +799,147 @@ mod tests {
             // Debug: let's see what the actual distances are
             let initial_pos1 = contact1.position_history[2];
             let initial_pos2 = contact2.position_history[2];
-            let initial_dx = units_to_mm_x(initial_pos1.0) - units_to_mm_x(initial_pos2.0);
-            let initial_dy = units_to_mm_y(initial_pos1.1) - units_to_mm_y(initial_pos2.1);
+            let (x1, y1) = contact1.surface.units_to_mm(initial_pos1.0, initial_pos1.1);
+            let (x2, y2) = contact2.surface.units_to_mm(initial_pos2.0, initial_pos2.1);
+            let (initial_dx, initial_dy) = (x1 - x2, y1 - y2);
             let initial_distance = (initial_dx * initial_dx + initial_dy * initial_dy).sqrt();
             let current_distance = contact1.distance_to(&contact2);
             let scale_factor = current_distance / initial_distance;
             let scale_change = (scale_factor - 1.0).abs();
 
             panic!("Expected pinch detection. Initial distance: {:.3}mm, Current distance: {:.3}mm, Scale factor: {:.3}, Scale change: {:.3}, Threshold: {:.3}", 
-                   initial_distance, current_distance, scale_factor, scale_change, recognizer.pinch_threshold);
+                   initial_distance, current_distance, scale_factor, scale_change, 0.2);
+        }
+    }
+
+    #[test]
+    fn test_session_ignores_brief_third_finger() {
+        let start = Instant::now();
+        let mut session = GestureSession::new(0, start, 50);
+        session.update_count(1, start);
+        session.update_count(2, start + Duration::from_millis(10));
+        // A third finger brushes the surface for 20ms mid-swipe
+        session.update_count(3, start + Duration::from_millis(200));
+        session.update_count(2, start + Duration::from_millis(220));
+        session.update_count(0, start + Duration::from_millis(400));
+
+        let contact = |id: i32, first: u64, last: u64| TouchContact {
+            last_update_time: start + Duration::from_millis(last),
+            is_active: false,
+            position_history: VecDeque::new(),
+            ..TouchContact::at(id, id, 0, 0, start + Duration::from_millis(first))
+        };
+        let contacts = vec![
+            contact(1, 0, 400),
+            contact(2, 10, 400),
+            contact(3, 200, 220),
+        ];
+
+        let selected = session.finish(&contacts, start + Duration::from_millis(400));
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].id, 1);
+        assert_eq!(selected[1].id, 2);
+    }
+
+    #[test]
+    fn test_custom_recognizer_runs_after_builtins() {
+        struct ThreeFingerTap;
+
+        impl Recognizer for ThreeFingerTap {
+            fn name(&self) -> &'static str {
+                "three_finger_tap"
+            }
+
+            fn feed(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+                if contacts.len() != 3 {
+                    return Vec::new();
+                }
+                vec![MultiTouchEvent::SingleFingerTap {
+                    finger: Arc::new(contacts[0].clone()),
+                    duration_ms: 0,
+                }]
+            }
         }
+
+        let mut recognizer = GestureRecognizer::new(12.0, 0.1, 2.0, 300, 2.0, 250, 30.0);
+        recognizer.register(Box::new(ThreeFingerTap));
+
+        let now = Instant::now();
+        let contact = |id: i32| TouchContact {
+            is_active: false,
+            ..TouchContact::at(id, id, 100, 100, now)
+        };
+
+        assert!(recognizer
+            .analyze_gesture(&[&contact(1), &contact(2), &contact(3)])
+            .is_some());
+    }
+
+    #[test]
+    fn test_scroll_axis_lock_ignores_drift() {
+        let now = Instant::now();
+        let contact = |id: i32, x: i32, y: i32| moved_contact(id, (x, y), x, y, now);
+        let frame = |x: i32, y: i32| [contact(1, x, y), contact(2, x + 260, y)];
+
+        let mut scroll = ScrollRecognizer::new(2.0, true);
+        assert!(scroll.feed_frame(&frame(0, 0).each_ref()).is_empty());
+        // 3mm down with 0.5mm sideways drift locks to the vertical axis
+        let events = scroll.feed_frame(&frame(13, 210).each_ref());
+        let Some(MultiTouchEvent::Scroll { delta_x, delta_y }) = events.first() else {
+            panic!("Expected scroll event, got {:?}", events);
+        };
+        assert_eq!(*delta_x, 0.0);
+        assert!(*delta_y > 2.9);
+
+        // Further diagonal drift never produces horizontal scroll
+        let events = scroll.feed_frame(&frame(130, 280).each_ref());
+        let Some(MultiTouchEvent::Scroll { delta_x, .. }) = events.first() else {
+            panic!("Expected scroll event, got {:?}", events);
+        };
+        assert_eq!(*delta_x, 0.0);
+
+        let mut free = ScrollRecognizer::new(2.0, false);
+        free.feed_frame(&frame(0, 0).each_ref());
+        let events = free.feed_frame(&frame(13, 210).each_ref());
+        let Some(MultiTouchEvent::Scroll { delta_x, .. }) = events.first() else {
+            panic!("Expected scroll event, got {:?}", events);
+        };
+        assert!(*delta_x > 0.0);
+    }
+
+    #[test]
+    fn test_scroll_ignores_finger_swap() {
+        let now = Instant::now();
+        let contact = |id: i32, x: i32, y: i32| moved_contact(id, (x, y), x, y, now);
+        let deltas = |events: Vec<MultiTouchEvent>| -> Vec<f64> {
+            events
+                .iter()
+                .filter_map(|event| match event {
+                    MultiTouchEvent::Scroll { delta_y, .. } => Some(*delta_y),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut scroll = ScrollRecognizer::new(2.0, true);
+        // Scrolling down 1mm (70 units) per frame
+        for step in 0..5 {
+            scroll.feed_frame(&[&contact(1, 0, step * 70), &contact(2, 260, step * 70)]);
+        }
+
+        // The second finger is replaced by one 20mm further down
+        let swapped = scroll.feed_frame(&[&contact(1, 0, 350), &contact(3, 260, 350 + 1400)]);
+        assert!(deltas(swapped).is_empty());
+        let next = deltas(scroll.feed_frame(&[&contact(1, 0, 420), &contact(3, 260, 1820)]));
+        assert!(
+            next.iter().all(|delta| (delta - 1.0).abs() < 0.01),
+            "{:?}",
+            next
+        );
+
+        // Lifting to one finger and putting another down re-anchors too
+        scroll.feed_frame(&[&contact(1, 0, 490)]);
+        let returned = scroll.feed_frame(&[&contact(1, 0, 490), &contact(4, 260, 3000)]);
+        assert!(deltas(returned).is_empty());
     }
 }
@@ -0,0 +1,510 @@
+use anyhow::{Context, Result};
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, RelativeAxisType, Synchronization};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::unix::AsyncFd;
+
+use crate::action_queue::ActionQueue;
+use crate::config::{Config, DeviceConfig, DeviceType};
+use crate::device::{self, MagicMouseModel};
+use crate::event_handler::EventHandler;
+use crate::lock;
+use crate::metrics;
+use crate::multitouch::{self, DeviceInfo, SurfaceBounds};
+use crate::reload::LiveConfig;
+use crate::stats::SessionTracker;
+use crate::status;
+use crate::touchpad::VirtualTouchpad;
+
+// Report IDs and layouts as handled by the kernel's hid-magicmouse driver
+const MOUSE_REPORT_ID: u8 = 0x29;
+const MOUSE2_REPORT_ID: u8 = 0x12;
+const MOUSE_HEADER_LEN: usize = 6;
+const MOUSE2_HEADER_LEN: usize = 14;
+const TOUCH_LEN: usize = 8;
+
+const TOUCH_STATE_MASK: u8 = 0xf0;
+const TOUCH_STATE_NONE: u8 = 0x00;
+
+const APPLE_VENDOR_ID: u32 = 0x05ac;
+const MAGIC_MOUSE_PRODUCT_ID: u32 = 0x030d;
+
+// Feature reports switching the mouse into multi-touch reporting mode
+const FEATURE_MT_MOUSE: [u8; 2] = [0xd7, 0x01];
+const FEATURE_MT_MOUSE2: [u8; 3] = [0xf1, 0x02, 0x01];
+
+/// One finger in a raw multi-touch report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawTouch {
+    /// Finger identifier assigned by the mouse (0-15)
+    pub id: u8,
+    pub x: i32,
+    pub y: i32,
+    pub touch_major: i32,
+    pub touch_minor: i32,
+    /// Contact size, which the kernel driver discards
+    pub size: i32,
+    pub orientation: i32,
+    pub down: bool,
+}
+
+/// Decoded multi-touch report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawReport {
+    pub touches: Vec<RawTouch>,
+    /// Physical button bits (bit 0 left, bit 1 right)
+    pub buttons: u8,
+    pub delta_x: i32,
+    pub delta_y: i32,
+}
+
+/// Decode a raw Magic Mouse report; `None` for reports that carry no touches
+pub fn parse_report(data: &[u8]) -> Option<RawReport> {
+    let (header_len, buttons, delta_x, delta_y) = match *data.first()? {
+        MOUSE_REPORT_ID if data.len() >= MOUSE_HEADER_LEN => (
+            MOUSE_HEADER_LEN,
+            data[3],
+            ((((data[3] & 0x0c) as u32) << 28) | ((data[1] as u32) << 22)) as i32 >> 22,
+            ((((data[3] & 0x30) as u32) << 26) | ((data[2] as u32) << 22)) as i32 >> 22,
+        ),
+        MOUSE2_REPORT_ID if data.len() >= MOUSE2_HEADER_LEN => (
+            MOUSE2_HEADER_LEN,
+            data[1],
+            (((data[3] as u32) << 24) | ((data[2] as u32) << 16)) as i32 >> 16,
+            (((data[5] as u32) << 24) | ((data[4] as u32) << 16)) as i32 >> 16,
+        ),
+        _ => return None,
+    };
+
+    if !(data.len() - header_len).is_multiple_of(TOUCH_LEN) {
+        return None;
+    }
+
+    let touches = data[header_len..]
+        .chunks_exact(TOUCH_LEN)
+        .map(parse_touch)
+        .collect();
+
+    Some(RawReport {
+        touches,
+        buttons: buttons & 0x03,
+        delta_x,
+        delta_y,
+    })
+}
+
+fn parse_touch(t: &[u8]) -> RawTouch {
+    let byte = |i: usize| t[i] as u32;
+    RawTouch {
+        id: (((byte(6) << 2) | (byte(5) >> 6)) & 0x0f) as u8,
+        // 12-bit signed coordinates; Y grows towards the palm like in the driver
+        x: (((byte(1) << 28) | (byte(0) << 20)) as i32) >> 20,
+        y: -((((byte(2) << 24) | (byte(1) << 16)) as i32) >> 20),
+        touch_major: (t[3] as i32) << 2,
+        touch_minor: (t[4] as i32) << 2,
+        size: (t[5] & 0x3f) as i32,
+        orientation: (t[6] >> 2) as i32 - 32,
+        down: t[7] & TOUCH_STATE_MASK != TOUCH_STATE_NONE,
+    }
+}
+
+/// Turns raw reports into the evdev events the kernel driver would have sent
+#[derive(Default)]
+pub struct ReportTranslator {
+    /// Tracking IDs of fingers currently down, by finger ID (used as slot)
+    tracking_ids: HashMap<u8, i32>,
+    next_tracking_id: i32,
+    buttons: u8,
+}
+
+impl ReportTranslator {
+    pub fn translate(&mut self, report: &RawReport) -> Vec<InputEvent> {
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let mut events = Vec::new();
+
+        // Fingers that are no longer reported, or reported as lifted, end
+        let down: Vec<u8> = report
+            .touches
+            .iter()
+            .filter(|touch| touch.down)
+            .map(|touch| touch.id)
+            .collect();
+        let mut ended: Vec<u8> = self
+            .tracking_ids
+            .keys()
+            .filter(|id| !down.contains(id))
+            .copied()
+            .collect();
+        ended.sort();
+        for id in ended {
+            self.tracking_ids.remove(&id);
+            events.push(abs(AbsoluteAxisType::ABS_MT_SLOT, id as i32));
+            events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1));
+        }
+
+        for touch in report.touches.iter().filter(|touch| touch.down) {
+            events.push(abs(AbsoluteAxisType::ABS_MT_SLOT, touch.id as i32));
+            if !self.tracking_ids.contains_key(&touch.id) {
+                let tracking_id = self.next_tracking_id;
+                self.next_tracking_id = (self.next_tracking_id + 1) & 0xffff;
+                self.tracking_ids.insert(touch.id, tracking_id);
+                events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, tracking_id));
+            }
+            events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, touch.x));
+            events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, touch.y));
+            events.push(abs(AbsoluteAxisType::ABS_MT_TOUCH_MAJOR, touch.touch_major));
+            events.push(abs(AbsoluteAxisType::ABS_MT_TOUCH_MINOR, touch.touch_minor));
+            events.push(abs(AbsoluteAxisType::ABS_MT_ORIENTATION, touch.orientation));
+            events.push(abs(AbsoluteAxisType::ABS_MT_PRESSURE, touch.size));
+        }
+
+        for (bit, key) in [(0x01, Key::BTN_LEFT), (0x02, Key::BTN_RIGHT)] {
+            if (report.buttons ^ self.buttons) & bit != 0 {
+                let pressed = report.buttons & bit != 0;
+                events.push(InputEvent::new(EventType::KEY, key.code(), pressed as i32));
+            }
+        }
+        self.buttons = report.buttons;
+
+        if report.delta_x != 0 {
+            events.push(InputEvent::new(
+                EventType::RELATIVE,
+                RelativeAxisType::REL_X.0,
+                report.delta_x,
+            ));
+        }
+        if report.delta_y != 0 {
+            events.push(InputEvent::new(
+                EventType::RELATIVE,
+                RelativeAxisType::REL_Y.0,
+                report.delta_y,
+            ));
+        }
+
+        events.push(InputEvent::new(
+            EventType::SYNCHRONIZATION,
+            Synchronization::SYN_REPORT.0,
+            0,
+        ));
+        events
+    }
+}
+
+/// Find a hidraw node whose HID device name contains `name_pattern`
+pub fn find_hidraw_device(name_pattern: &str) -> Result<PathBuf> {
+    let mut nodes: Vec<String> = std::fs::read_dir("/sys/class/hidraw")
+        .context("No hidraw devices available")?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    nodes.sort();
+
+    for node in nodes {
+        let Some(uevent) = read_uevent(&node) else {
+            continue;
+        };
+        if uevent
+            .get("HID_NAME")
+            .is_some_and(|name| name.contains(name_pattern))
+        {
+            let path = Path::new("/dev").join(&node);
+            info!("Found hidraw device: {} at {:?}", uevent["HID_NAME"], path);
+            return Ok(path);
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "No hidraw device matching {:?} found",
+        name_pattern
+    ))
+}
+
+fn read_uevent(node: &str) -> Option<HashMap<String, String>> {
+    let uevent = std::fs::read_to_string(
+        Path::new("/sys/class/hidraw")
+            .join(node)
+            .join("device/uevent"),
+    )
+    .ok()?;
+    Some(
+        uevent
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    )
+}
+
+/// `HID_ID=bus:vendor:product` of a hidraw node
+fn hid_id(path: &Path) -> Option<(u32, u32)> {
+    let node = std::fs::canonicalize(path).ok()?;
+    let uevent = read_uevent(node.file_name()?.to_str()?)?;
+    let mut parts = uevent.get("HID_ID")?.split(':').skip(1);
+    let vendor = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let product = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some((vendor, product))
+}
+
+/// `HID_NAME` of a hidraw node
+fn hid_name(path: &Path) -> Option<String> {
+    let node = std::fs::canonicalize(path).ok()?;
+    let mut uevent = read_uevent(node.file_name()?.to_str()?)?;
+    uevent.remove("HID_NAME")
+}
+
+/// HIDIOCSFEATURE(len)
+fn hidiocsfeature(len: usize) -> libc::c_ulong {
+    const IOC_READ_WRITE: libc::c_ulong = 3;
+    (IOC_READ_WRITE << 30) | ((len as libc::c_ulong) << 16) | ((b'H' as libc::c_ulong) << 8) | 0x06
+}
+
+/// Switch the mouse into multi-touch reporting, which the kernel driver would
+/// otherwise do
+fn enable_multitouch(file: &File, path: &Path) -> std::io::Result<()> {
+    let feature: &[u8] = match hid_id(path) {
+        Some((APPLE_VENDOR_ID, MAGIC_MOUSE_PRODUCT_ID)) => &FEATURE_MT_MOUSE,
+        _ => &FEATURE_MT_MOUSE2,
+    };
+
+    // SAFETY: the buffer is valid for `feature.len()` bytes, which is the size
+    // encoded in the request
+    let result = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            hidiocsfeature(feature.len()),
+            feature.as_ptr(),
+        )
+    };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Open and lock a hidraw node for `run`
+pub fn open(path: &Path) -> Result<File> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .with_context(|| format!("Failed to open hidraw device: {:?}", path))?;
+
+    lock::lock_device(&file, path)?;
+    Ok(file)
+}
+
+/// Open the virtual touchpad for a hidraw device, if enabled
+///
+/// `run` opens it itself unless given one opened before the process gave up
+/// access to `/dev/uinput`.
+pub fn open_touchpad(config: &DeviceConfig) -> Option<VirtualTouchpad> {
+    VirtualTouchpad::open(&SurfaceBounds::default(), &config.touchpad)
+}
+
+/// Read raw reports from a hidraw node opened by `open` and run them through
+/// gesture recognition
+pub async fn run(
+    path: PathBuf,
+    file: File,
+    touchpad: Option<VirtualTouchpad>,
+    mut live: LiveConfig,
+) -> Result<()> {
+    if let Err(e) = enable_multitouch(&file, &path) {
+        warn!("Failed to enable multi-touch reports: {}", e);
+    }
+
+    info!("Listening for raw reports on {:?}", path);
+    let registration = status::register(&path, "hidraw");
+    // Waiting for readiness rather than blocking a thread in read() lets
+    // shutdown interrupt the wait for the next report
+    let file = AsyncFd::new(file)?;
+    let mut event_handler = Arc::new(EventHandler::new(live.current()?));
+    let info = DeviceInfo::new(
+        hid_name(&path).unwrap_or_else(|| "Unknown".to_string()),
+        SurfaceBounds::default(),
+    );
+    let model = hid_id(&path).and_then(|(vendor, product)| {
+        MagicMouseModel::from_ids(u16::try_from(vendor).ok()?, u16::try_from(product).ok()?)
+    });
+    let new_processor = |config: &Config| {
+        device::gesture_processor(&config.gesture, DeviceType::MagicMouse, model, info.clone())
+    };
+    let mut mt_processor = new_processor(&event_handler.config);
+    let mut gesture_changed = false;
+    let mut translator = ReportTranslator::default();
+    let mut buffer = [0u8; 256];
+    let mut was_touching = false;
+    let mut session = SessionTracker::default();
+    let queue = ActionQueue::new(event_handler.config.execution.queue_size);
+    let mut touchpad = touchpad.or_else(|| open_touchpad(&event_handler.config.device));
+
+    let mut shutdown = std::pin::pin!(status::shutdown_requested());
+    loop {
+        // hidraw returns exactly one report per read
+        let deadline = mt_processor.deadline();
+        let len = tokio::select! {
+            len = read_report(&file, &mut buffer) => {
+                len.with_context(|| format!("Failed to read from {:?}", path))?
+            }
+            _ = &mut shutdown => break,
+            _ = multitouch::wait_for_deadline(deadline) => {
+                let mt_events = mt_processor.poll();
+                if mt_events.is_some() {
+                    session.recognized();
+                }
+                device::publish_contacts(&mt_processor, &registration, touchpad.as_mut());
+                device::dispatch(
+                    &queue,
+                    &event_handler,
+                    &mt_processor,
+                    touchpad.as_ref(),
+                    mt_events.unwrap_or_default(),
+                )?;
+                continue;
+            }
+        };
+        if len == 0 {
+            break;
+        }
+
+        let Some(report) = parse_report(&buffer[..len]) else {
+            continue;
+        };
+        debug!("Raw report: {:?}", report);
+
+        // New actions apply to the next gesture; thresholds wait until no
+        // finger is down so a touch session isn't cut short
+        if let Some(config) = live.changed() {
+            event_handler = Arc::new(EventHandler::new(config));
+            gesture_changed = true;
+        }
+
+        for event in translator.translate(&report) {
+            metrics::event_processed();
+            let mt_events = mt_processor.process_event(event).await;
+            let touching = !mt_processor.active_contacts().is_empty();
+            if touching && !was_touching {
+                event_handler.handle_touch_begin();
+            }
+            was_touching = touching;
+            if mt_events.is_some() {
+                session.recognized();
+            }
+            session.update(mt_processor.active_contacts().len());
+            device::dispatch(
+                &queue,
+                &event_handler,
+                &mt_processor,
+                touchpad.as_ref(),
+                mt_events.unwrap_or_default(),
+            )?;
+        }
+        device::publish_contacts(&mt_processor, &registration, touchpad.as_mut());
+
+        if gesture_changed && !was_touching {
+            mt_processor = new_processor(&event_handler.config);
+            gesture_changed = false;
+        }
+    }
+
+    if status::shutting_down() {
+        queue.flush().await;
+    }
+    Ok(())
+}
+
+async fn read_report(file: &AsyncFd<File>, buffer: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+        let mut guard = file.readable().await?;
+        if let Ok(result) = guard.try_io(|file| file.get_ref().read(buffer)) {
+            return result;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a touch the way the mouse does
+    fn encode_touch(id: u8, x: i32, y: i32, state: u8) -> [u8; 8] {
+        let raw_y = -y;
+        [
+            (x & 0xff) as u8,
+            (((x >> 8) & 0x0f) | ((raw_y & 0x0f) << 4)) as u8,
+            ((raw_y >> 4) & 0xff) as u8,
+            10,
+            8,
+            ((id & 0x03) << 6) | 5,
+            ((id >> 2) & 0x03) | (32 << 2),
+            state,
+        ]
+    }
+
+    #[test]
+    fn test_parse_mouse2_report() {
+        let mut data = vec![MOUSE2_REPORT_ID, 0x01, 0x05, 0x00, 0xfd, 0xff];
+        data.resize(MOUSE2_HEADER_LEN, 0);
+        data.extend(encode_touch(3, -250, 1200, 0x40));
+        data.extend(encode_touch(6, 900, -1500, 0x00));
+
+        let report = parse_report(&data).expect("valid report");
+        assert_eq!(report.buttons, 0x01);
+        assert_eq!((report.delta_x, report.delta_y), (5, -3));
+        assert_eq!(report.touches.len(), 2);
+
+        let touch = report.touches[0];
+        assert_eq!((touch.id, touch.x, touch.y), (3, -250, 1200));
+        assert_eq!((touch.touch_major, touch.touch_minor), (40, 32));
+        assert_eq!((touch.size, touch.orientation), (5, 0));
+        assert!(touch.down);
+
+        let lifted = report.touches[1];
+        assert_eq!((lifted.id, lifted.x, lifted.y), (6, 900, -1500));
+        assert!(!lifted.down);
+    }
+
+    #[test]
+    fn test_translator_ends_missing_fingers() {
+        let touch = |down| RawTouch {
+            id: 2,
+            x: 0,
+            y: 0,
+            touch_major: 0,
+            touch_minor: 0,
+            size: 0,
+            orientation: 0,
+            down,
+        };
+        let mut translator = ReportTranslator::default();
+        let tracking_ids = |events: &[InputEvent]| -> Vec<i32> {
+            events
+                .iter()
+                .filter(|event| event.code() == AbsoluteAxisType::ABS_MT_TRACKING_ID.0)
+                .map(|event| event.value())
+                .collect()
+        };
+
+        let report = RawReport {
+            touches: vec![touch(true)],
+            ..Default::default()
+        };
+        assert_eq!(tracking_ids(&translator.translate(&report)), vec![0]);
+        // Still down: no new tracking ID
+        assert!(tracking_ids(&translator.translate(&report)).is_empty());
+        // Finger gone from the report
+        assert_eq!(
+            tracking_ids(&translator.translate(&RawReport::default())),
+            vec![-1]
+        );
+    }
+}
@@ -0,0 +1,644 @@
+use anyhow::{bail, Context, Result};
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use serde_yaml::{Mapping, Value as YamlValue};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::config_command;
+
+/// Configuration formats of other gesture tools that can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Touchegg's `touchegg.conf` (XML)
+    Touchegg,
+    /// libinput-gestures' `libinput-gestures.conf`
+    LibinputGestures,
+    /// Fusuma's `config.yml`
+    Fusuma,
+}
+
+impl Format {
+    /// Guess the format from the file's content
+    fn detect(content: &str) -> Format {
+        let fusuma_keys = ["---", "swipe:", "pinch:", "rotate:", "hold:", "context:"];
+        if content.trim_start().starts_with('<') {
+            Format::Touchegg
+        } else if content
+            .lines()
+            .any(|line| fusuma_keys.iter().any(|key| line.starts_with(key)))
+        {
+            Format::Fusuma
+        } else {
+            Format::LibinputGestures
+        }
+    }
+}
+
+/// Bindings converted from another tool's configuration
+#[derive(Debug, Default)]
+pub struct Imported {
+    /// Global actions by action name
+    pub actions: BTreeMap<String, String>,
+    /// Actions of application-specific bindings, by application
+    pub profiles: BTreeMap<String, BTreeMap<String, String>>,
+    /// Bindings that couldn't be converted, with the reason
+    pub skipped: Vec<String>,
+    /// Where each imported action came from, by scope and action name
+    origins: BTreeMap<(Option<String>, String), String>,
+}
+
+impl Imported {
+    /// Bind `name` to `action` globally or for `application`, unless an
+    /// earlier binding of the file already took it
+    fn bind(&mut self, application: Option<&str>, name: &str, action: String, origin: String) {
+        let key = (application.map(str::to_string), name.to_string());
+        if let Some(first) = self.origins.get(&key) {
+            self.skipped.push(format!(
+                "{}: {} is already bound by the {}",
+                origin, name, first
+            ));
+            return;
+        }
+        self.origins.insert(key, origin);
+        match application {
+            Some(application) => self
+                .profiles
+                .entry(application.to_string())
+                .or_default()
+                .insert(name.to_string(), action),
+            None => self.actions.insert(name.to_string(), action),
+        };
+    }
+
+    /// The imported bindings as a configuration fragment
+    fn to_value(&self) -> Value {
+        let profiles: Map<String, Value> = self
+            .profiles
+            .iter()
+            .map(|(application, actions)| {
+                let profile = json!({ "windows": [application], "actions": actions });
+                (application.clone(), profile)
+            })
+            .collect();
+        json!({ "actions": self.actions, "profiles": profiles })
+    }
+}
+
+/// Convert the gesture bindings in `file` and merge them into the
+/// configuration at `config_path`, or only print them with `print`
+pub fn run(config_path: &Path, file: &Path, format: Option<Format>, print: bool) -> Result<()> {
+    let content =
+        std::fs::read_to_string(file).with_context(|| format!("Failed to read {:?}", file))?;
+    let imported = match format.unwrap_or_else(|| Format::detect(&content)) {
+        Format::Touchegg => touchegg(&content)?,
+        Format::LibinputGestures => libinput_gestures(&content),
+        Format::Fusuma => fusuma(&content)?,
+    };
+
+    for skipped in &imported.skipped {
+        eprintln!("Skipped {}", skipped);
+    }
+    if print {
+        println!("{}", serde_json::to_string_pretty(&imported.to_value())?);
+        return Ok(());
+    }
+    if imported.actions.is_empty() && imported.profiles.is_empty() {
+        bail!("No gesture bindings found in {:?}", file);
+    }
+
+    let mut document = config_command::load_document(config_path)?;
+    merge(&mut document, &imported)?;
+    Config::from_value(document.clone()).context("Imported bindings are invalid")?;
+    config_command::save_document(config_path, &document)?;
+
+    for (name, action) in &imported.actions {
+        println!("{:<24} {}", name, action);
+    }
+    for (application, actions) in &imported.profiles {
+        for (name, action) in actions {
+            println!("{:<24} {}  (profile {})", name, action, application);
+        }
+    }
+    println!("Imported into {:?}", config_path);
+    Ok(())
+}
+
+/// Add the imported actions to `document`, replacing existing bindings of the
+/// same gestures
+fn merge(document: &mut Value, imported: &Imported) -> Result<()> {
+    let Some(root) = document.as_object_mut() else {
+        bail!("Configuration is not a JSON object");
+    };
+    let actions = root.entry("actions").or_insert_with(|| json!({}));
+    for (name, action) in &imported.actions {
+        actions[name] = Value::String(action.clone());
+    }
+    let profiles = root.entry("profiles").or_insert_with(|| json!({}));
+    for (application, bindings) in &imported.profiles {
+        let profile = profiles
+            .as_object_mut()
+            .context("`profiles` is not a JSON object")?
+            .entry(application.clone())
+            .or_insert_with(|| json!({}));
+        let profile = profile
+            .as_object_mut()
+            .context("Profile is not a JSON object")?;
+        // Switch to the profile while the application has focus
+        let windows = profile.entry("windows").or_insert_with(|| json!([]));
+        if let Some(windows) = windows.as_array_mut() {
+            let listed = windows.iter().any(|window| {
+                window
+                    .as_str()
+                    .is_some_and(|window| window.eq_ignore_ascii_case(application))
+            });
+            if !listed {
+                windows.push(Value::String(application.clone()));
+            }
+        }
+        let actions = profile.entry("actions").or_insert_with(|| json!({}));
+        for (name, action) in bindings {
+            actions[name] = Value::String(action.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Action name of a gesture from another tool: every swipe becomes the
+/// two-finger swipe, as that's the only one the Magic Mouse can make
+fn action_name(kind: &str, direction: &str, fingers: Option<u32>) -> Result<String, String> {
+    let direction = direction.to_ascii_lowercase();
+    match kind.to_ascii_lowercase().as_str() {
+        "swipe" => match direction.as_str() {
+            "up" | "down" | "left" | "right" => Ok(format!("swipe_{}_2finger", direction)),
+            _ => Err(format!("no {} swipes", direction)),
+        },
+        "pinch" => match direction.as_str() {
+            "in" | "out" => Ok(format!("pinch_{}", direction)),
+            _ => Err(format!("no {} pinches", direction)),
+        },
+        "tap" => match fingers {
+            Some(fingers @ 1..=2) => Ok(format!("tap_{}finger", fingers)),
+            _ => Err("only one- and two-finger taps".to_string()),
+        },
+        kind => Err(format!("no {} gestures", kind)),
+    }
+}
+
+/// Describe a gesture of the imported file, e.g. `3-finger swipe up`
+fn describe(kind: &str, direction: &str, fingers: Option<u32>) -> String {
+    let gesture = [kind, direction]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    match fingers {
+        Some(fingers) => format!("{}-finger {}", fingers, gesture),
+        None => gesture,
+    }
+}
+
+/// `xdotool key <keys>` as a `key:` action; other commands stay shell commands
+fn shell_action(command: &str) -> String {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match words[..] {
+        ["xdotool", "key", keys] if !keys.starts_with('-') => format!("key:{}", keys),
+        _ => command.trim().to_string(),
+    }
+}
+
+/// Convert libinput-gestures' `gesture <type> <direction> [fingers] <command>`
+/// lines; other settings don't apply
+pub fn libinput_gestures(content: &str) -> Imported {
+    let mut imported = Imported::default();
+    for line in content.lines() {
+        let line = line.trim();
+        let Some(rest) = line.strip_prefix("gesture") else {
+            continue;
+        };
+        let Some((kind, rest)) = rest.trim_start().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let (direction, mut command) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .unwrap_or((rest.trim(), ""));
+        // Without a finger count the binding applies to any count
+        let mut fingers = None;
+        if let Some((count, after)) = command.trim_start().split_once(char::is_whitespace) {
+            if let Ok(count) = count.parse() {
+                fingers = Some(count);
+                command = after;
+            }
+        }
+
+        let origin = describe(kind, direction, fingers);
+        let command = command.trim();
+        let name = match action_name(kind, direction, fingers) {
+            Ok(name) => name,
+            Err(reason) => {
+                imported.skipped.push(format!("{}: {}", origin, reason));
+                continue;
+            }
+        };
+        if command.is_empty() || command.starts_with("_internal") {
+            imported.skipped.push(format!(
+                "{}: libinput-gestures' internal commands have no equivalent",
+                origin
+            ));
+            continue;
+        }
+        imported.bind(None, &name, shell_action(command), origin);
+    }
+    imported
+}
+
+/// Convert the `<gesture>` bindings of each `<application>` in Touchegg's
+/// configuration; applications other than `All` become profiles
+pub fn touchegg(content: &str) -> Result<Imported> {
+    let document = Document::parse(content).context("Invalid Touchegg configuration")?;
+    let mut imported = Imported::default();
+    for application in children_named(document.root_element(), "application") {
+        let names = application.attribute("name").unwrap_or("All");
+        let applications: Vec<Option<&str>> = names
+            .split(',')
+            .map(str::trim)
+            .map(|name| (name != "All").then_some(name))
+            .collect();
+
+        for gesture in children_named(application, "gesture") {
+            let kind = gesture.attribute("type").unwrap_or_default();
+            let direction = gesture.attribute("direction").unwrap_or_default();
+            let fingers = gesture
+                .attribute("fingers")
+                .and_then(|fingers| fingers.parse().ok());
+            let origin = describe(kind, direction, fingers);
+
+            let converted = action_name(kind, direction, fingers).and_then(|name| {
+                let action = children_named(gesture, "action")
+                    .next()
+                    .ok_or_else(|| "no action".to_string())?;
+                Ok((name, touchegg_action(action)?))
+            });
+            match converted {
+                Ok((name, action)) => {
+                    for application in &applications {
+                        imported.bind(*application, &name, action.clone(), origin.clone());
+                    }
+                }
+                Err(reason) => imported.skipped.push(format!("{}: {}", origin, reason)),
+            }
+        }
+    }
+    Ok(imported)
+}
+
+fn touchegg_action(action: Node) -> Result<String, String> {
+    match action.attribute("type").unwrap_or_default() {
+        "RUN_COMMAND" => child_text(action, "command")
+            .map(|command| shell_action(&command))
+            .ok_or_else(|| "no command".to_string()),
+        "SEND_KEYS" => {
+            let modifiers = child_text(action, "modifiers").unwrap_or_default();
+            let keys = child_text(action, "keys").unwrap_or_default();
+            let combination: Vec<&str> = modifiers
+                .split('+')
+                .map(modifier_name)
+                .chain(keys.split('+'))
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .collect();
+            if combination.is_empty() {
+                return Err("no keys".to_string());
+            }
+            Ok(format!("key:{}", combination.join("+")))
+        }
+        other => Err(format!("Touchegg's {} action has no equivalent", other)),
+    }
+}
+
+/// Touchegg modifiers are X keysyms such as `Control_L`
+fn modifier_name(keysym: &str) -> &str {
+    let keysym = keysym.trim();
+    let base = keysym
+        .strip_suffix("_L")
+        .or_else(|| keysym.strip_suffix("_R"))
+        .unwrap_or(keysym);
+    match base {
+        "Control" => "ctrl",
+        "Shift" => "shift",
+        "Alt" => "alt",
+        "Super" | "Hyper" => "super",
+        "Meta" => "meta",
+        _ => keysym,
+    }
+}
+
+/// One document of Fusuma's configuration
+#[derive(Debug, Deserialize)]
+struct FusumaDocument {
+    context: Option<FusumaContext>,
+    /// Bindings by gesture and then finger count or direction, next to
+    /// settings such as `threshold`
+    #[serde(flatten)]
+    gestures: Mapping,
+}
+
+/// When a document's bindings apply
+#[derive(Debug, Deserialize)]
+struct FusumaContext {
+    application: Option<FusumaApplications>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FusumaApplications {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// What a gesture is bound to
+#[derive(Debug, Deserialize)]
+struct FusumaBinding {
+    command: Option<String>,
+    /// Fusuma's other actions, and per-binding settings such as `threshold`
+    #[serde(flatten)]
+    other: Mapping,
+}
+
+/// Convert the bindings of each document in Fusuma's configuration; documents
+/// with an application `context` become profiles
+pub fn fusuma(content: &str) -> Result<Imported> {
+    let mut imported = Imported::default();
+    for document in serde_yaml::Deserializer::from_str(content) {
+        let Some(document) = Option::<FusumaDocument>::deserialize(document)
+            .context("Invalid Fusuma configuration")?
+        else {
+            continue;
+        };
+        let applications: Vec<Option<String>> = match document.context {
+            None => vec![None],
+            Some(FusumaContext { application }) => match application {
+                Some(FusumaApplications::One(name)) => vec![Some(name)],
+                Some(FusumaApplications::Many(names)) => names.into_iter().map(Some).collect(),
+                None => {
+                    imported.skipped.push(
+                        "bindings of a context other than an application: no equivalent"
+                            .to_string(),
+                    );
+                    continue;
+                }
+            },
+        };
+
+        for (kind, gestures) in &document.gestures {
+            let kind = yaml_text(kind);
+            // The other top-level keys are settings such as `threshold`
+            if !["swipe", "pinch", "rotate", "hold", "tap"].contains(&kind.as_str()) {
+                continue;
+            }
+            for (key, value) in gestures.as_mapping().into_iter().flatten() {
+                // Directions right under the gesture apply to any finger count,
+                // gestures without a direction bind right under the count
+                let key = yaml_text(key);
+                let bindings: Vec<(Option<u32>, String, &YamlValue)> = match key.parse() {
+                    Ok(fingers) if value.get("command").is_some() => {
+                        vec![(Some(fingers), String::new(), value)]
+                    }
+                    Ok(fingers) => value
+                        .as_mapping()
+                        .into_iter()
+                        .flatten()
+                        .map(|(direction, binding)| (Some(fingers), yaml_text(direction), binding))
+                        .collect(),
+                    Err(_) => vec![(None, key, value)],
+                };
+                for (fingers, direction, binding) in bindings {
+                    let origin = describe(&kind, &direction, fingers);
+                    let converted = action_name(&kind, &direction, fingers)
+                        .and_then(|name| Ok((name, fusuma_action(binding)?)));
+                    match converted {
+                        Ok((name, action)) => {
+                            for application in &applications {
+                                imported.bind(
+                                    application.as_deref(),
+                                    &name,
+                                    action.clone(),
+                                    origin.clone(),
+                                );
+                            }
+                        }
+                        Err(reason) => imported.skipped.push(format!("{}: {}", origin, reason)),
+                    }
+                }
+            }
+        }
+    }
+    Ok(imported)
+}
+
+fn fusuma_action(binding: &YamlValue) -> Result<String, String> {
+    let binding: FusumaBinding =
+        serde_yaml::from_value(binding.clone()).map_err(|e| format!("invalid binding: {}", e))?;
+    if let Some(command) = binding.command {
+        return Ok(shell_action(&command));
+    }
+    // Per-binding settings aren't actions
+    let action = binding
+        .other
+        .keys()
+        .map(yaml_text)
+        .find(|key| !matches!(key.as_str(), "threshold" | "interval"));
+    match action {
+        Some(action) => Err(format!("Fusuma's {} action has no equivalent", action)),
+        None => Err("no command".to_string()),
+    }
+}
+
+/// A YAML key as text: finger counts are numbers, directions strings
+fn yaml_text(value: &YamlValue) -> String {
+    match value {
+        YamlValue::String(text) => text.clone(),
+        YamlValue::Number(number) => number.to_string(),
+        YamlValue::Bool(value) => value.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Child elements of `node` called `name`
+fn children_named<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+/// Trimmed text of the first child element called `name`, if it has any
+fn child_text(node: Node, name: &str) -> Option<String> {
+    let child = children_named(node, name).next()?;
+    let text: String = child
+        .descendants()
+        .filter(|descendant| descendant.is_text())
+        .filter_map(|descendant| descendant.text())
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_touchegg_and_libinput_gestures() {
+        let touchegg = touchegg(
+            r#"<?xml version="1.0"?>
+<touchégg>
+  <settings><property name="animation_delay">150</property></settings>
+  <!-- Global gestures -->
+  <application name="All">
+    <gesture type="SWIPE" fingers="3" direction="UP">
+      <action type="SEND_KEYS">
+        <modifiers>Super_L+Control_L</modifiers>
+        <keys>Up</keys>
+      </action>
+    </gesture>
+    <gesture type="SWIPE" fingers="4" direction="UP">
+      <action type="RUN_COMMAND"><command>notify-send &quot;up&quot;</command></action>
+    </gesture>
+    <gesture type="PINCH" fingers="2" direction="IN">
+      <action type="MAXIMIZE_RESTORE_WINDOW"/>
+    </gesture>
+  </application>
+  <application name="Google-chrome, Firefox">
+    <gesture type="PINCH" fingers="2" direction="OUT">
+      <action type="RUN_COMMAND"><command><![CDATA[xdotool key ctrl+plus]]></command></action>
+    </gesture>
+  </application>
+</touchégg>"#,
+        )
+        .unwrap();
+        assert_eq!(touchegg.actions["swipe_up_2finger"], "key:super+ctrl+Up");
+        assert_eq!(touchegg.actions.len(), 1);
+        assert_eq!(touchegg.profiles["Firefox"]["pinch_out"], "key:ctrl+plus");
+        assert!(touchegg.profiles.contains_key("Google-chrome"));
+        // Application profiles switch in with the application's windows
+        let mut document = json!({ "profiles": { "Firefox": { "windows": ["firefox"] } } });
+        merge(&mut document, &touchegg).unwrap();
+        assert_eq!(
+            document["profiles"]["Firefox"]["windows"],
+            json!(["firefox"])
+        );
+        assert_eq!(
+            document["profiles"]["Google-chrome"]["windows"],
+            json!(["Google-chrome"])
+        );
+        assert_eq!(
+            touchegg.to_value()["profiles"]["Google-chrome"]["windows"],
+            json!(["Google-chrome"])
+        );
+        assert_eq!(touchegg.skipped.len(), 2, "{:?}", touchegg.skipped);
+        assert!(touchegg.skipped[0].starts_with("4-finger swipe up: swipe_up_2finger"));
+
+        let gestures = libinput_gestures(
+            "# Workspaces\n\
+             gesture swipe left 3 xdotool key super+Right\n\
+             gesture swipe right\txdotool key --delay 50 super+Left\n\
+             gesture swipe up _internal ws_up\n\
+             gesture pinch clockwise xdotool key ctrl+r\n\
+             gesture pinch in 2 notify-send pinched\n\
+             swipe_threshold 0\n",
+        );
+        assert_eq!(gestures.actions["swipe_left_2finger"], "key:super+Right");
+        assert_eq!(
+            gestures.actions["swipe_right_2finger"],
+            "xdotool key --delay 50 super+Left"
+        );
+        assert_eq!(gestures.actions["pinch_in"], "notify-send pinched");
+        assert_eq!(gestures.skipped.len(), 2, "{:?}", gestures.skipped);
+
+        assert!(super::touchegg("<a><b></a>").is_err());
+    }
+
+    #[test]
+    fn test_import_fusuma() {
+        let content = r#"# Fusuma
+swipe:
+  3:
+    left:
+      command: 'xdotool key alt+Right' # back
+    right:
+      sendkey: "LEFTALT+LEFT"
+    up:
+      command: "notify-send \"it's up\" # not a comment"
+  4:
+    left:
+      command: 'xdotool key super+Left'
+pinch:
+  in:
+    command: xdotool key ctrl+minus
+  out:
+    command: xdotool key ctrl+plus
+    threshold: 0.5
+hold:
+  3:
+    command: notify-send held
+threshold:
+  swipe: 0.4
+---
+context:
+  application:
+    - Google-chrome
+    - Firefox
+swipe:
+  3:
+    left:
+      command: xdotool key ctrl+Tab
+---
+context:
+  application: [Alacritty, "Kitty, Nightly"]
+pinch:
+  2:
+    in:
+      command: >
+        xdotool key
+        ctrl+minus
+plugin:
+  inputs:
+    libinput_command_input:
+      - enable-tap: true
+      - verbose: true
+"#;
+        assert_eq!(Format::detect(content), Format::Fusuma);
+        let fusuma = fusuma(content).unwrap();
+        assert_eq!(fusuma.actions["swipe_left_2finger"], "key:alt+Right");
+        assert_eq!(
+            fusuma.actions["swipe_up_2finger"],
+            r#"notify-send "it's up" # not a comment"#
+        );
+        assert_eq!(fusuma.actions["pinch_in"], "key:ctrl+minus");
+        assert_eq!(fusuma.actions["pinch_out"], "key:ctrl+plus");
+        assert_eq!(fusuma.actions.len(), 4);
+        assert_eq!(
+            fusuma.profiles["Firefox"]["swipe_left_2finger"],
+            "key:ctrl+Tab"
+        );
+        assert!(fusuma.profiles.contains_key("Google-chrome"));
+        assert_eq!(
+            fusuma.profiles["Kitty, Nightly"]["pinch_in"],
+            "key:ctrl+minus"
+        );
+        assert!(fusuma.profiles.contains_key("Alacritty"));
+        assert_eq!(fusuma.skipped.len(), 3, "{:?}", fusuma.skipped);
+        assert_eq!(
+            fusuma.skipped[0],
+            "3-finger swipe right: Fusuma's sendkey action has no equivalent"
+        );
+
+        assert!(super::fusuma("a:\n    b: 1\n  c: 2\n").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use evdev::{Device, Key};
+use log::debug;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Modifier names as used in bindings, in the order they are written
+const MODIFIERS: [(&str, [Key; 2]); 4] = [
+    ("ctrl", [Key::KEY_LEFTCTRL, Key::KEY_RIGHTCTRL]),
+    ("shift", [Key::KEY_LEFTSHIFT, Key::KEY_RIGHTSHIFT]),
+    ("alt", [Key::KEY_LEFTALT, Key::KEY_RIGHTALT]),
+    ("super", [Key::KEY_LEFTMETA, Key::KEY_RIGHTMETA]),
+];
+
+/// How often the keyboard list is refreshed to pick up hotplugged keyboards
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Open keyboards and when they were enumerated
+static KEYBOARDS: Mutex<Option<(Instant, Vec<Device>)>> = Mutex::new(None);
+
+/// Whether an action name is bound to held keyboard modifiers, e.g.
+/// `ctrl+swipe_up_2finger`
+pub fn is_modifier_binding(action_name: &str) -> bool {
+    MODIFIERS.iter().any(|(name, _)| {
+        action_name
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('+'))
+    })
+}
+
+/// Keyboard modifiers currently held on any keyboard, in binding order
+///
+/// The key state is read from the keyboards' evdev nodes, so this works on
+/// X11 and Wayland alike, but needs read access to `/dev/input`.
+pub fn held_modifiers() -> Vec<&'static str> {
+    let mut keyboards = KEYBOARDS.lock().unwrap_or_else(|e| e.into_inner());
+    let stale = keyboards
+        .as_ref()
+        .is_none_or(|(scanned, _)| scanned.elapsed() >= RESCAN_INTERVAL);
+    if stale {
+        *keyboards = Some((Instant::now(), scan_keyboards()));
+    }
+    let (_, devices) = keyboards.as_mut().expect("keyboards were just scanned");
+
+    let mut held = Vec::new();
+    devices.retain(|device| match device.get_key_state() {
+        Ok(keys) => {
+            for (name, codes) in MODIFIERS {
+                if codes.iter().any(|key| keys.contains(*key)) && !held.contains(&name) {
+                    held.push(name);
+                }
+            }
+            true
+        }
+        // Unplugged; dropped until the next rescan finds it again
+        Err(_) => false,
+    });
+
+    // Keep the canonical order regardless of which keyboard reported what
+    MODIFIERS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| held.contains(name))
+        .collect()
+}
+
+fn scan_keyboards() -> Vec<Device> {
+    let keyboards: Vec<Device> = evdev::enumerate()
+        .map(|(_, device)| device)
+        .filter(|device| {
+            // Skip our own virtual devices, whose keys are injected by us
+            let ours = device
+                .name()
+                .is_some_and(|name| name.starts_with("Mouse Gesture"));
+            let is_keyboard = device
+                .supported_keys()
+                .is_some_and(|keys| keys.contains(Key::KEY_A) && keys.contains(Key::KEY_LEFTCTRL));
+            is_keyboard && !ours
+        })
+        .collect();
+    debug!("Found {} keyboards for modifier bindings", keyboards.len());
+    keyboards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modifier_binding_names() {
+        assert!(is_modifier_binding("ctrl+swipe_up_2finger"));
+        assert!(is_modifier_binding("super+click+tap_1finger"));
+        assert!(!is_modifier_binding("click+tap_1finger"));
+        assert!(!is_modifier_binding("shifty_gesture"));
+    }
+}
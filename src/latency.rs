@@ -0,0 +1,156 @@
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::device::describe_gesture;
+use crate::multitouch::MultiTouchEvent;
+
+/// Latencies kept for the percentiles
+const WINDOW: usize = 1000;
+
+lazy_static! {
+    static ref LATENCIES: Mutex<Latencies> = Mutex::new(Latencies::default());
+}
+
+/// When the input behind a gesture happened and when the gesture was recognized
+///
+/// Kernel event timestamps use the wall clock, so both are `SystemTime`s.
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    pub input: SystemTime,
+    pub recognized: SystemTime,
+}
+
+impl Timing {
+    /// A gesture recognized just now from input that happened at `input`
+    pub fn recognized_now(input: SystemTime) -> Self {
+        Self {
+            input,
+            recognized: SystemTime::now(),
+        }
+    }
+}
+
+/// Percentiles of the most recent input-to-dispatch latencies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    /// Latencies the percentiles are taken over
+    pub samples: usize,
+}
+
+#[derive(Debug, Default)]
+struct Latencies {
+    recent: VecDeque<Duration>,
+    count: u64,
+    sum: Duration,
+}
+
+impl Latencies {
+    fn observe(&mut self, latency: Duration) {
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(latency);
+        self.count += 1;
+        self.sum += latency;
+    }
+
+    fn percentiles(&self) -> Option<Percentiles> {
+        let mut sorted: Vec<Duration> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        // Nearest rank
+        let rank = |quantile: f64| {
+            let index = (quantile * sorted.len() as f64).ceil() as usize;
+            sorted.get(index.saturating_sub(1)).copied()
+        };
+        Some(Percentiles {
+            p50: rank(0.5)?,
+            p90: rank(0.9)?,
+            p99: rank(0.99)?,
+            samples: sorted.len(),
+        })
+    }
+}
+
+/// Record `event`'s action being started now, warning if the time since its
+/// input exceeds `budget`
+pub fn dispatched(event: &MultiTouchEvent, timing: Timing, budget: Option<Duration>) {
+    let now = SystemTime::now();
+    let total = now.duration_since(timing.input).unwrap_or_default();
+    let recognizing = timing
+        .recognized
+        .duration_since(timing.input)
+        .unwrap_or_default();
+    let queued = now.duration_since(timing.recognized).unwrap_or_default();
+    LATENCIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .observe(total);
+
+    match budget {
+        Some(budget) if total > budget => warn!(
+            "{} dispatched {:.1?} after its input, over the {:?} budget ({:.1?} recognizing, {:.1?} queued)",
+            describe_gesture(event),
+            total,
+            budget,
+            recognizing,
+            queued
+        ),
+        _ => debug!(
+            "{} dispatched {:.1?} after its input ({:.1?} recognizing, {:.1?} queued)",
+            event.kind(),
+            total,
+            recognizing,
+            queued
+        ),
+    }
+}
+
+/// Percentiles of the last dispatched gestures, `None` before the first one
+pub fn percentiles() -> Option<Percentiles> {
+    LATENCIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .percentiles()
+}
+
+/// Number and sum of all latencies since the start
+pub fn totals() -> (u64, Duration) {
+    let latencies = LATENCIES.lock().unwrap_or_else(|e| e.into_inner());
+    (latencies.count, latencies.sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.percentiles(), None);
+
+        for ms in (1..=100).rev() {
+            latencies.observe(Duration::from_millis(ms));
+        }
+        let percentiles = latencies.percentiles().unwrap();
+        assert_eq!(percentiles.p50, Duration::from_millis(50));
+        assert_eq!(percentiles.p90, Duration::from_millis(90));
+        assert_eq!(percentiles.p99, Duration::from_millis(99));
+        assert_eq!(percentiles.samples, 100);
+
+        // Only the most recent latencies count towards the percentiles
+        for _ in 0..WINDOW {
+            latencies.observe(Duration::from_millis(5));
+        }
+        assert_eq!(
+            latencies.percentiles().unwrap().p99,
+            Duration::from_millis(5)
+        );
+        assert_eq!(latencies.count, 100 + WINDOW as u64);
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{bail, Result};
+use log::debug;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Take an exclusive advisory lock on an open device node, so two daemons
+/// never handle the same device and run every action twice
+///
+/// The lock is on the node itself, whatever path it was opened by, and is
+/// released when `file` is closed. Other programs reading the device don't
+/// take it and aren't affected.
+pub fn lock_device(file: &impl AsRawFd, path: &Path) -> Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        debug!("Locked {:?}", path);
+        return Ok(());
+    }
+
+    let error = std::io::Error::last_os_error();
+    if error.raw_os_error() != Some(libc::EWOULDBLOCK) {
+        bail!("Failed to lock {:?}: {}", path, error);
+    }
+    let holder = match lock_holder(path) {
+        Some(pid) if pid == std::process::id() => {
+            "this daemon already (is it configured twice?)".to_string()
+        }
+        Some(pid) => describe_process(pid),
+        None => "another process".to_string(),
+    };
+    bail!("{:?} is already handled by {}", path, holder)
+}
+
+/// PID holding the lock on `path`, from `/proc/locks`
+pub fn lock_holder(path: &Path) -> Option<u32> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let locks = std::fs::read_to_string("/proc/locks").ok()?;
+    let key = (
+        libc::major(metadata.dev()),
+        libc::minor(metadata.dev()),
+        metadata.ino(),
+    );
+    locks
+        .lines()
+        .filter_map(parse_lock)
+        .find(|&(_, file)| file == key)
+        .map(|(pid, _)| pid)
+}
+
+/// PID and (major, minor, inode) of a flock in a `/proc/locks` line like
+/// `1: FLOCK  ADVISORY  WRITE 1234 00:05:567 0 EOF`
+fn parse_lock(line: &str) -> Option<(u32, (u32, u32, u64))> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    // Waiters are listed as `1: -> FLOCK ...`
+    let [_, "FLOCK", _, _, pid, file, ..] = fields[..] else {
+        return None;
+    };
+    let mut file = file.split(':');
+    let major = u32::from_str_radix(file.next()?, 16).ok()?;
+    let minor = u32::from_str_radix(file.next()?, 16).ok()?;
+    let inode = file.next()?.parse().ok()?;
+    Some((pid.parse().ok()?, (major, minor, inode)))
+}
+
+/// `PID 1234 (mouse-gesture-recognition --daemon)`
+pub fn describe_process(pid: u32) -> String {
+    match std::fs::read(format!("/proc/{}/cmdline", pid)) {
+        Ok(cmdline) if !cmdline.is_empty() => {
+            let args: Vec<String> = cmdline
+                .split(|&byte| byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            format!("PID {} ({})", pid, args.join(" "))
+        }
+        // Another user's process, or gone by now
+        _ => format!("PID {}", pid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lock() {
+        assert_eq!(
+            parse_lock("1: FLOCK  ADVISORY  WRITE 21670 fe:00:16171022 0 EOF"),
+            Some((21670, (254, 0, 16171022)))
+        );
+        assert_eq!(
+            parse_lock("2: POSIX  ADVISORY  WRITE 812 00:1a:3 0 EOF"),
+            None
+        );
+        assert_eq!(
+            parse_lock("3: -> FLOCK  ADVISORY  WRITE 900 00:05:9 0 EOF"),
+            None
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use log::kv::{self, Key, Value, VisitSource};
+use log::Record;
+use serde_json::{json, Map};
+use std::io::Write;
+
+/// How log records are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// env_logger's human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with the record's structured fields
+    Json,
+}
+
+/// Set up logging at `info`, or `debug` when verbose; `RUST_LOG` overrides both
+pub fn init(verbose: bool, format: LogFormat) {
+    let log_level = if verbose { "debug" } else { "info" };
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level));
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = record_json(record, &buf.timestamp_micros().to_string());
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+/// A record as a flat JSON object: the fields passed with `key = value;` in
+/// the logging macros next to `timestamp`, `level`, `target` and `message`
+fn record_json(record: &Record, timestamp: &str) -> serde_json::Value {
+    let mut fields = Fields(Map::new());
+    let _ = record.key_values().visit(&mut fields);
+    let mut object = fields.0;
+    // The record's own keys win over a field of the same name
+    object.insert("timestamp".to_string(), json!(timestamp));
+    object.insert("level".to_string(), json!(record.level().as_str()));
+    object.insert("target".to_string(), json!(record.target()));
+    object.insert("message".to_string(), json!(record.args().to_string()));
+    serde_json::Value::Object(object)
+}
+
+struct Fields(Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.as_str().to_string(), field_json(&value));
+        Ok(())
+    }
+}
+
+/// Numbers and booleans stay typed, everything else becomes a string
+fn field_json(value: &Value) -> serde_json::Value {
+    if let Some(value) = value.to_u64() {
+        json!(value)
+    } else if let Some(value) = value.to_i64() {
+        json!(value)
+    } else if let Some(value) = value.to_f64() {
+        json!(value)
+    } else if let Some(value) = value.to_bool() {
+        json!(value)
+    } else if let Some(value) = value.to_borrowed_str() {
+        json!(value)
+    } else {
+        json!(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_json() {
+        let fields = [
+            ("gesture", Value::from("two_finger_swipe")),
+            ("fingers", Value::from(2u64)),
+            ("delta_x", Value::from(-14.5)),
+            ("message", Value::from("shadowed")),
+        ];
+        let record = Record::builder()
+            .args(format_args!("Two-finger swipe detected: left"))
+            .level(log::Level::Info)
+            .target("mouse_gesture_recognition::event_handler")
+            .key_values(&fields)
+            .build();
+
+        let line = record_json(&record, "2026-01-01T00:00:00.000000Z");
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "Two-finger swipe detected: left");
+        assert_eq!(line["gesture"], "two_finger_swipe");
+        assert_eq!(line["fingers"], 2);
+        assert_eq!(line["delta_x"], -14.5);
+        assert_eq!(line["timestamp"], "2026-01-01T00:00:00.000000Z");
+    }
+}
@@ -0,0 +1,216 @@
+//! Gesture macros: the clicks, keys and scroll steps injected by actions are
+//! recorded between two `macro:record:<name>` gestures and replayed through the
+//! output backend by `macro:play:<name>`
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::output::Output;
+use crate::paths;
+
+/// Longest pause kept between recorded steps, so the time spent deciding on
+/// the next gesture isn't replayed
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Macro being recorded, if any
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+/// One piece of injected input, or a pause between two
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Mouse button click (X11 button number)
+    Click { button: u8 },
+    /// Mouse button pressed or released and left that way
+    Button { button: u8, down: bool },
+    /// Key combination in xdotool syntax
+    Key { combo: String },
+    /// Scroll wheel steps
+    Scroll { horizontal: i32, vertical: i32 },
+    /// Pause before the next step
+    Delay { delay_ms: u64 },
+}
+
+struct Recording {
+    name: String,
+    steps: Vec<MacroStep>,
+    /// When the previous step was injected, or recording started
+    last: Instant,
+}
+
+impl Recording {
+    fn new(name: &str, now: Instant) -> Self {
+        Self {
+            name: name.to_string(),
+            steps: Vec::new(),
+            last: now,
+        }
+    }
+
+    /// Add a step injected at `now`, after the pause since the previous one
+    fn push(&mut self, step: MacroStep, now: Instant) {
+        let delay = now.saturating_duration_since(self.last).min(MAX_DELAY);
+        if !self.steps.is_empty() && delay.as_millis() > 0 {
+            self.steps.push(MacroStep::Delay {
+                delay_ms: delay.as_millis() as u64,
+            });
+        }
+        self.steps.push(step);
+        self.last = now;
+    }
+}
+
+/// Account for injected input; does nothing unless a macro is being recorded
+pub fn record(step: MacroStep) {
+    if let Some(recording) = RECORDING.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        debug!("Recording into macro {}: {:?}", recording.name, step);
+        recording.push(step, Instant::now());
+    }
+}
+
+/// Run a `macro:` action: `record:<name>` or `play:<name>`
+pub async fn execute(output: &Output, argument: &str) -> Result<()> {
+    match argument.split_once(':') {
+        Some(("record", name)) if !name.is_empty() => toggle_recording(name),
+        Some(("play", name)) if !name.is_empty() => play(output, name).await,
+        _ => bail!(
+            "Unknown macro action: {} (expected record:<name> or play:<name>)",
+            argument
+        ),
+    }
+}
+
+/// Start recording `name`, or stop the recording in progress and save it
+fn toggle_recording(name: &str) -> Result<()> {
+    let finished = {
+        let mut recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+        match recording.take() {
+            Some(finished) => finished,
+            None => {
+                info!("Recording macro {}", name);
+                *recording = Some(Recording::new(name, Instant::now()));
+                return Ok(());
+            }
+        }
+    };
+
+    if finished.steps.is_empty() {
+        info!(
+            "Stopped recording macro {}: nothing was injected, keeping the saved one",
+            finished.name
+        );
+        return Ok(());
+    }
+    info!(
+        "Recorded macro {} ({} steps)",
+        finished.name,
+        finished.steps.len()
+    );
+    let mut macros = read()?;
+    macros.insert(finished.name, finished.steps);
+    save(&macros)
+}
+
+/// Replay a saved macro through `output`
+async fn play(output: &Output, name: &str) -> Result<()> {
+    let macros = read()?;
+    let steps = macros
+        .get(name)
+        .with_context(|| format!("No macro named {} was recorded", name))?;
+    info!("Playing macro {} ({} steps)", name, steps.len());
+
+    for step in steps {
+        match step {
+            MacroStep::Click { button } => output.click(*button).await?,
+            MacroStep::Button { button, down } => output.set_button(*button, *down).await?,
+            MacroStep::Key { combo } => output.key(combo).await?,
+            MacroStep::Scroll {
+                horizontal,
+                vertical,
+            } => output.scroll(*horizontal, *vertical).await?,
+            MacroStep::Delay { delay_ms } => {
+                tokio::time::sleep(Duration::from_millis(*delay_ms)).await
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `$XDG_STATE_HOME/mouse-gesture/macros.json`
+fn macros_file() -> Option<PathBuf> {
+    Some(paths::state_dir()?.join("macros.json"))
+}
+
+/// Macros saved so far, by name
+fn read() -> Result<BTreeMap<String, Vec<MacroStep>>> {
+    let path = macros_file().context("HOME is not set")?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => {
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    }
+}
+
+fn save(macros: &BTreeMap<String, Vec<MacroStep>>) -> Result<()> {
+    let path = macros_file().context("HOME is not set")?;
+    std::fs::create_dir_all(path.parent().unwrap_or(&path))?;
+    std::fs::write(&path, serde_json::to_string_pretty(macros)? + "\n")
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    debug!("Saved macros to {:?}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_keeps_pauses_between_steps() {
+        let start = Instant::now();
+        let mut recording = Recording::new("copy", start);
+        // Waiting before the first step doesn't count
+        let first = start + Duration::from_secs(1);
+        recording.push(MacroStep::Click { button: 1 }, first);
+        recording.push(
+            MacroStep::Key {
+                combo: "ctrl+c".to_string(),
+            },
+            first + Duration::from_millis(250),
+        );
+        // Steps injected together stay together, long pauses are shortened
+        recording.push(
+            MacroStep::Scroll {
+                horizontal: 0,
+                vertical: -1,
+            },
+            first + Duration::from_millis(250),
+        );
+        recording.push(
+            MacroStep::Button {
+                button: 1,
+                down: true,
+            },
+            first + Duration::from_secs(60),
+        );
+
+        let json = serde_json::to_value(&recording.steps).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "type": "click", "button": 1 },
+                { "type": "delay", "delay_ms": 250 },
+                { "type": "key", "combo": "ctrl+c" },
+                { "type": "scroll", "horizontal": 0, "vertical": -1 },
+                { "type": "delay", "delay_ms": 2000 },
+                { "type": "button", "button": 1, "down": true },
+            ])
+        );
+    }
+}
@@ -1,17 +1,74 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::{error, info, warn};
+use std::future::Future;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::task::JoinSet;
 
+mod action_queue;
+mod battery;
+mod clock;
+mod compositor;
 mod config;
+mod config_command;
+mod control;
+mod daemon;
+mod dbus;
+mod desktop;
 mod device;
+mod doctor;
 mod event_handler;
+mod event_queue;
+mod feedback;
 mod gesture;
+mod hidraw;
+mod import;
+mod keyboard;
+mod latency;
+mod lock;
+mod logging;
+mod macros;
+mod metrics;
+mod migrate;
+mod mpris;
 mod multitouch;
+mod notify;
+mod output;
+mod overlay;
+mod passthrough;
+mod paths;
+mod plugin;
+mod privileges;
+mod profile;
+mod reload;
+mod replay;
+mod scheduling;
+mod script;
+mod session;
+mod stats;
+mod status;
+mod supervisor;
+mod timestamp;
+mod touchpad;
+mod tremor;
+mod visualize;
+mod window;
+mod workspace;
+mod x11;
 
-use config::Config;
+use config::{Backend, Config, DeviceEntry};
 use device::MagicMouseDevice;
 use event_handler::EventHandler;
+use reload::LiveConfig;
+use supervisor::RestartPolicy;
+
+/// How long devices get to finish queued actions when shutting down
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A device's recognition, waiting to be spawned
+type Recognizer = Pin<Box<dyn Future<Output = (PathBuf, Result<()>)> + Send>>;
 
 #[derive(Parser)]
 #[command(name = "mouse-gesture-recognition")]
@@ -25,93 +82,542 @@ struct Args {
     #[arg(short, long)]
     verbose: bool,
 
-    /// Check system dependencies
+    /// Log as human-readable lines or as one JSON object per line, with
+    /// gesture details as fields
+    #[arg(long, value_enum, default_value_t = logging::LogFormat::Text)]
+    log_format: logging::LogFormat,
+
+    /// Check system dependencies; same as the `doctor` subcommand
     #[arg(long)]
     check_deps: bool,
 
-    /// Configuration file path
-    #[arg(short, long, default_value = "config.json")]
-    config: PathBuf,
+    /// Log the actions recognized gestures would run without running them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Profile to start with, overriding `profile` in the configuration
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Configuration file path [default: $XDG_CONFIG_HOME/mouse-gesture/config.json,
+    /// or ./config.json if only that exists]
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Run as a background service: notify systemd when started by it, fork
+    /// into the background otherwise, and write a PID file
+    #[arg(long)]
+    daemon: bool,
+
+    /// PID file written in daemon mode [default: $XDG_RUNTIME_DIR/mouse-gesture.pid]
+    #[arg(long, requires = "daemon")]
+    pid_file: Option<PathBuf>,
+
+    /// When started as root, switch to this user once the devices are open,
+    /// running actions in their session
+    #[arg(long)]
+    user: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check permissions, drivers, the session and the devices, and print
+    /// how to fix what's missing
+    Doctor,
+    /// Show the battery level of the configured devices
+    Battery,
+    /// Print raw touch events, slot state and tracked contacts live
+    Monitor,
+    /// Draw the touch surface in the terminal with live contacts, their
+    /// trails and the gestures they trigger
+    Visualize,
+    /// Run an evemu recording through gesture recognition and print the
+    /// recognized gestures
+    Replay {
+        /// Recording made with `evemu-record`
+        file: PathBuf,
+        /// Replay speed relative to the recording; 0 replays without pauses
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Also run the configured actions
+        #[arg(long)]
+        execute: bool,
+    },
+    /// Check that every recording in a directory is still recognized as its
+    /// `# expect:` comments say, with the built-in gesture settings
+    Verify {
+        #[arg(default_value = "tests/gestures")]
+        dir: PathBuf,
+    },
+    /// Show how often each gesture was recognized, its average size, failed
+    /// actions and touches that didn't become a gesture
+    Stats,
+    /// Read or change configuration settings
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Show the running daemon's devices, uptime, profile and gesture counts
+    Status,
+    /// Stop the running daemon from running gestures
+    Pause,
+    /// Let the running daemon run gestures again
+    Resume,
+    /// Make the running daemon re-read its configuration
+    Reload,
+    /// Print the running daemon's metrics in the Prometheus text format
+    Metrics,
+    /// Show or hide the running daemon's on-screen gesture overlay
+    ToggleOverlay,
+    /// Print gestures recognized by the running daemon as JSON lines
+    Events {
+        /// Include finger positions as they change
+        #[arg(long)]
+        contacts: bool,
+    },
+    /// Convert the gesture bindings of Touchegg, libinput-gestures or Fusuma
+    /// into actions of this configuration
+    Import {
+        /// `touchegg.conf`, `libinput-gestures.conf` or Fusuma's `config.yml`
+        file: PathBuf,
+        /// Format of the file, detected from its content if not given
+        #[arg(long, value_enum)]
+        format: Option<import::Format>,
+        /// Print the converted actions instead of adding them
+        #[arg(long)]
+        print: bool,
+    },
+    /// Install a systemd user service running the daemon with this configuration
+    InstallService {
+        /// Print the unit instead of writing it
+        #[arg(long)]
+        print: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print a setting, e.g. `gesture.swipe_threshold`
+    Get { key: String },
+    /// Change a setting in the configuration file; a running daemon picks it up
+    Set { key: String, value: String },
+    /// Print every setting in effect and whether it comes from the defaults,
+    /// the file, the environment, the command line or the active profile
+    Explain,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Initialize logging
-    let log_level = if args.verbose { "debug" } else { "info" };
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
+    // Only the forking thread survives, so this has to happen before the
+    // runtime starts its workers
+    if args.daemon && !daemon::supervised() {
+        daemon::detach()?;
+    }
+
+    // Setting variables is only safe while this is the only thread
+    let user = args.user.as_deref().map(privileges::lookup).transpose()?;
+    if let Some(user) = &user {
+        privileges::set_environment(user);
+        privileges::hand_files_to(user);
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(run(args, user));
+    // Don't let a hung action keep the process from exiting
+    runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+    result
+}
+
+async fn run(args: Args, user: Option<privileges::User>) -> Result<()> {
+    status::init();
+    logging::init(args.verbose, args.log_format);
 
     info!(
         "Magic Mouse Gesture Recognition v{}",
         env!("CARGO_PKG_VERSION")
     );
 
-    if args.check_deps {
-        return check_dependencies().await;
+    let dry_run = args.dry_run;
+    let apply_args = move |config: &mut Config| {
+        if dry_run {
+            config.dry_run = true;
+            // Grabbing would need the passthrough device to inject pointer motion,
+            // and the virtual touchpad would have the desktop act on touches
+            config.device.grab = false;
+            config.device.touchpad.enabled = false;
+        }
+    };
+
+    let request = match args.command {
+        Some(Command::Status) => Some(control::Request::Status),
+        Some(Command::Pause) => Some(control::Request::Pause),
+        Some(Command::Resume) => Some(control::Request::Resume),
+        Some(Command::Reload) => Some(control::Request::ReloadConfig),
+        Some(Command::Metrics) => Some(control::Request::Metrics),
+        Some(Command::ToggleOverlay) => Some(control::Request::ToggleOverlay),
+        Some(Command::Events { contacts }) => Some(control::Request::Subscribe { contacts }),
+        _ => None,
+    };
+    if let Some(request) = request {
+        return control::client(&control::socket_path(), request);
     }
 
     // Load configuration
-    let config = Config::load_or_create(&args.config)?;
-    info!("Configuration loaded from: {:?}", args.config);
-
-    // Initialize device
-    let device_path = if let Some(path) = args.device {
-        path
-    } else if config.device.auto_detect {
-        device::find_magic_mouse_device(&config.device.name_pattern)?
-    } else {
-        return Err(anyhow::anyhow!(
-            "No device path specified and auto-detection is disabled"
-        ));
-    };
+    let config_path = args.config.unwrap_or_else(paths::default_config_path);
+    if args.check_deps || matches!(args.command, Some(Command::Doctor)) {
+        return doctor::run(&config_path, args.device.as_deref()).await;
+    }
+    if let Some(Command::Config { command }) = &args.command {
+        return match command {
+            ConfigCommand::Get { key } => config_command::get(&config_path, key),
+            ConfigCommand::Set { key, value } => config_command::set(&config_path, key, value),
+            ConfigCommand::Explain => {
+                config_command::explain(&config_path, &apply_args, args.profile.as_deref())
+            }
+        };
+    }
+    if let Some(Command::Import {
+        file,
+        format,
+        print,
+    }) = &args.command
+    {
+        return import::run(&config_path, file, *format, *print);
+    }
+    if let Some(Command::InstallService { print }) = args.command {
+        return daemon::install_service(&config_path, print);
+    }
+    if let Some(Command::Verify { dir }) = &args.command {
+        return replay::verify(dir).await;
+    }
+    if let Some(Command::Stats) = &args.command {
+        return stats::show();
+    }
+    let mut config = Config::load_or_create(&config_path)?;
+    info!("Configuration loaded from: {:?}", config_path);
 
-    info!("Using device: {:?}", device_path);
+    if dry_run {
+        info!("Dry run: actions are logged, not run");
+    }
+    apply_args(&mut config);
 
-    // Initialize Magic Mouse device
-    let mut device = MagicMouseDevice::new(device_path)?;
+    if let Some(name) = args.profile.clone().or_else(|| profile::restore(&config)) {
+        config.with_profile(Some(&name))?;
+        profile::activate(Some(name));
+    }
 
-    // Initialize event handler
-    let event_handler = EventHandler::new(config.clone());
+    let plugin_dir = config.plugins.directory.clone().unwrap_or_else(|| {
+        config_path
+            .parent()
+            .unwrap_or(std::path::Path::new("."))
+            .join("plugins")
+    });
+    // The subcommands below never switch users, so they can load plugins now
+    if matches!(
+        args.command,
+        Some(Command::Monitor | Command::Visualize | Command::Replay { .. })
+    ) {
+        plugin::load_all(&plugin_dir);
+    }
 
-    // Start gesture recognition
-    info!("Starting gesture recognition...");
-    device.start_recognition(event_handler).await?;
+    // Resolve the devices to listen on; --device overrides the configuration
+    let entries = match args.device {
+        Some(path) => vec![DeviceEntry {
+            path: Some(path.to_string_lossy().into_owned()),
+            id: None,
+            name_pattern: None,
+            include: Vec::new(),
+            device_type: config.device.device_type,
+            gesture: Default::default(),
+            actions: Default::default(),
+        }],
+        None => config.device_entries(),
+    };
 
-    Ok(())
-}
+    match args.command {
+        Some(Command::Battery) => return show_battery(&entries, &config),
+        Some(Command::Monitor) => {
+            let entry = &entries[0];
+            let device_path = device::resolve_device_path(entry, &config)?;
+            let mut device = MagicMouseDevice::new(&device_path, entry.device_type)?;
+            let config = config.with_profile(profile::active().as_deref())?;
+            return device.monitor(&config.for_device(entry)?.gesture).await;
+        }
+        Some(Command::Visualize) => {
+            let entry = &entries[0];
+            let device_path = device::resolve_device_path(entry, &config)?;
+            let mut device = MagicMouseDevice::new(&device_path, entry.device_type)?;
+            let config = config.with_profile(profile::active().as_deref())?;
+            return device.visualize(&config.for_device(entry)?.gesture).await;
+        }
+        Some(Command::Replay {
+            file,
+            speed,
+            execute,
+        }) => {
+            let entry = &entries[0];
+            let config = config.with_profile(profile::active().as_deref())?;
+            let config = config.for_device(entry)?;
+            return replay::run(&file, &config, entry.device_type, speed, execute).await;
+        }
+        // The other subcommands returned before loading the configuration
+        _ => {}
+    }
 
-async fn check_dependencies() -> Result<()> {
-    info!("Checking system dependencies...");
+    // Devices pick up edits to the configuration file as they're saved
+    let configs = reload::watch(config_path.clone(), config.clone(), apply_args);
 
-    // Check for xdotool
-    match tokio::process::Command::new("which")
-        .arg("xdotool")
-        .output()
-        .await
-    {
-        Ok(output) if output.status.success() => {
-            info!("✓ xdotool found");
+    // Raising the reader thread's priority may need root, so it starts first
+    if config.device.backend == Backend::Evdev {
+        scheduling::init(&config.device.scheduling);
+    }
+
+    // Nothing runs before privileges are dropped, so no action or plugin ever
+    // runs as root
+    let mut recognizers: Vec<Recognizer> = Vec::new();
+    let mut battery_monitors = Vec::new();
+    for entry in &entries {
+        if config.device.backend == Backend::Hidraw {
+            let path = match &entry.path {
+                Some(path) => PathBuf::from(path),
+                None => hidraw::find_hidraw_device(
+                    entry
+                        .name_pattern
+                        .as_deref()
+                        .unwrap_or(&config.device.name_pattern),
+                )?,
+            };
+            // /dev/uinput is out of reach once privileges are dropped
+            let touchpad = user
+                .as_ref()
+                .and_then(|_| hidraw::open_touchpad(&config.device));
+            let mut opened = Some((hidraw::open(&path)?, touchpad));
+            let (configs, entry) = (configs.clone(), entry.clone());
+            let name = format!("Device {:?}", path);
+            recognizers.push(Box::pin(async move {
+                let result = supervisor::supervise(&name, RestartPolicy::default(), || {
+                    let (path, opened) = (path.clone(), opened.take());
+                    let live = LiveConfig::new(configs.subscribe(), entry.clone());
+                    async move {
+                        let (file, touchpad) = match opened {
+                            Some(opened) => opened,
+                            None => (hidraw::open(&path)?, None),
+                        };
+                        hidraw::run(path, file, touchpad, live).await
+                    }
+                })
+                .await;
+                (path, result)
+            }));
+            continue;
         }
-        _ => {
-            warn!("✗ xdotool not found - install with: sudo pacman -S xdotool");
+
+        let device_path = match device::resolve_device_path(entry, &config) {
+            Ok(path) => path,
+            Err(e) if entries.len() > 1 => {
+                warn!("Skipping device: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        info!("Using device: {:?}", device_path);
+
+        // Initialize Magic Mouse device
+        let mut device = MagicMouseDevice::new(&device_path, entry.device_type)?;
+        let opened = device.lock().and_then(|()| match user {
+            Some(_) => device.open_ahead(&config.device),
+            None => Ok(()),
+        });
+        match opened {
+            Ok(()) => {}
+            Err(e) if entries.len() > 1 => {
+                warn!("Skipping device: {:#}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
         }
+
+        // Watch the battery alongside recognition
+        battery_monitors.push(battery::monitor(
+            device_path.clone(),
+            entry.name_pattern.clone(),
+            config.battery.clone(),
+            EventHandler::new(config.for_device(entry)?),
+        ));
+
+        // A failed or panicked device is reopened from scratch, up to the
+        // restart policy's limit
+        let reconnect = config.device.reconnect;
+        let device_type = entry.device_type;
+        let (configs, entry, base_config) = (configs.clone(), entry.clone(), config.clone());
+        let mut opened = Some(device);
+        let name = format!("Device {:?}", device_path);
+        recognizers.push(Box::pin(async move {
+            let result = supervisor::supervise(&name, RestartPolicy::default(), || {
+                let opened = opened.take();
+                // Recognition follows this device's action overrides across reloads
+                let live = LiveConfig::new(configs.subscribe(), entry.clone());
+                let (entry, base_config) = (entry.clone(), base_config.clone());
+                async move {
+                    let rediscover = move || device::resolve_device_path(&entry, &base_config);
+                    let device = match opened {
+                        Some(device) => device,
+                        None => {
+                            let device = MagicMouseDevice::new(rediscover()?, device_type)?;
+                            device.lock()?;
+                            device
+                        }
+                    };
+                    device.run(live, reconnect, rediscover).await
+                }
+            })
+            .await;
+            (device_path, result)
+        }));
     }
 
-    // Check for evdev access
-    if std::path::Path::new("/dev/input").exists() {
-        info!("✓ /dev/input directory accessible");
-    } else {
-        error!("✗ /dev/input directory not accessible");
+    if recognizers.is_empty() {
+        return Err(anyhow::anyhow!("None of the configured devices was found"));
+    }
+
+    if let Some(user) = &user {
+        output::open_ahead(config.output.backend);
+        privileges::drop_to(user)?;
+    }
+    // Loading a library runs its initializers, so this waits for the switch
+    plugin::load_all(&plugin_dir);
+
+    let _pid_file = match args.daemon {
+        true => Some(daemon::PidFile::create(
+            args.pid_file.unwrap_or_else(daemon::PidFile::default_path),
+        )?),
+        false => None,
+    };
+
+    for monitor in battery_monitors {
+        tokio::spawn(monitor);
+    }
+    stats::start();
+    session::init(&config).await;
+    tokio::spawn(session::monitor(configs.clone()));
+    window::start();
+    tokio::spawn(window::follow(configs.clone()));
+    let mut recognizers: JoinSet<_> = recognizers.into_iter().collect();
+
+    // Start gesture recognition
+    info!(
+        "Starting gesture recognition on {} device(s)...",
+        recognizers.len()
+    );
+    let lifecycle = EventHandler::new(config.with_profile(profile::active().as_deref())?);
+    lifecycle.handle_start().await;
+
+    tokio::spawn(daemon::reload_on_hangup(configs.clone()));
+    tokio::spawn(daemon::pause_on_signals());
+    let _control = control::listen(&control::socket_path(), configs.clone())
+        .map_err(|e| warn!("Control socket unavailable: {:#}", e))
+        .ok();
+    if let Some(address) = &config.metrics.listen {
+        if let Err(e) = metrics::listen(address).await {
+            warn!("Metrics endpoint unavailable on {}: {:#}", address, e);
+        }
+    }
+    if args.daemon {
+        daemon::ready();
     }
 
-    // Check for Magic Mouse devices
-    match device::find_magic_mouse_device("Magic Mouse") {
-        Ok(path) => {
-            info!("✓ Magic Mouse device found at: {:?}", path);
+    // Counts the devices that gave up after too many restarts
+    let recognition = async {
+        let mut failed = 0;
+        while let Some(joined) = recognizers.join_next().await {
+            match joined {
+                Ok((path, Ok(()))) => info!("Device {:?} stopped", path),
+                Ok((path, Err(e))) => {
+                    error!("Device {:?} failed: {:#}", path, e);
+                    failed += 1;
+                }
+                Err(e) => {
+                    error!("Recognition task panicked: {}", e);
+                    failed += 1;
+                }
+            }
         }
-        Err(_) => {
-            warn!("✗ Magic Mouse device not found - ensure it's connected and paired");
+        failed
+    };
+    tokio::pin!(recognition);
+    let failed = tokio::select! {
+        failed = &mut recognition => failed,
+        signal = shutdown_signal() => {
+            info!("Received {}, shutting down", signal);
+            daemon::notify("STOPPING=1");
+            // Devices finish their queued actions and let go of the hardware
+            status::request_shutdown();
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut recognition).await.is_err() {
+                warn!("Devices didn't stop within {:?}", SHUTDOWN_TIMEOUT);
+            }
+            0
+        }
+    };
+
+    // on_stop may have been edited since startup
+    let config = Config::clone(&configs.current());
+    if let Err(e) = profile::save(&config) {
+        warn!("Failed to save the active profile: {:#}", e);
+    }
+    if let Err(e) = stats::save() {
+        warn!("Failed to save gesture statistics: {:#}", e);
+    }
+    let lifecycle = EventHandler::new(
+        config
+            .with_profile(profile::active().as_deref())
+            .unwrap_or(config),
+    );
+    lifecycle.handle_stop().await;
+
+    // A non-zero exit lets the service manager restart the daemon instead of
+    // it quietly handling no gestures
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "Gesture recognition failed on {} device(s)",
+            failed
+        ));
+    }
+    Ok(())
+}
+
+/// Wait for SIGINT or SIGTERM, returning its name
+async fn shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (Ok(mut interrupt), Ok(mut terminate)) = (
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::terminate()),
+    ) else {
+        warn!("Failed to listen for shutdown signals");
+        return std::future::pending().await;
+    };
+
+    tokio::select! {
+        _ = interrupt.recv() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+/// Print the battery level of every configured device
+fn show_battery(entries: &[DeviceEntry], config: &Config) -> Result<()> {
+    for entry in entries {
+        let device_path = device::resolve_device_path(entry, config)?;
+        match battery::find_battery(&device_path, entry.name_pattern.as_deref()) {
+            Some(supply) => {
+                let status = battery::read_battery(&supply)?;
+                println!("{}: {}% ({})", status.model, status.capacity, status.status);
+            }
+            None => println!("{}: no battery reported", device_path.display()),
         }
     }
 
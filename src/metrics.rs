@@ -0,0 +1,301 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::latency;
+use crate::status;
+
+/// Upper bounds of the action duration buckets, in seconds
+const ACTION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Longest request head read from a scraper
+const MAX_REQUEST_SIZE: usize = 8192;
+
+static EVENTS: AtomicU64 = AtomicU64::new(0);
+static KERNEL_DROPS: AtomicU64 = AtomicU64::new(0);
+static QUEUE_DROPS: AtomicU64 = AtomicU64::new(0);
+static READER_DROPS: AtomicU64 = AtomicU64::new(0);
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+static RESTARTS: AtomicU64 = AtomicU64::new(0);
+static ACTION_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    /// Recognized gestures by `MultiTouchEvent::kind`
+    static ref GESTURES: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+    static ref ACTION_DURATIONS: Mutex<Histogram> = Mutex::new(Histogram::default());
+}
+
+/// Cumulative histogram in the Prometheus sense: every observation counts in
+/// each bucket whose bound it doesn't exceed
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; ACTION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(ACTION_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// An input event reached gesture recognition
+pub fn event_processed() {
+    EVENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn gesture_recognized(kind: &'static str) {
+    *GESTURES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(kind)
+        .or_default() += 1;
+}
+
+/// The kernel dropped input events (SYN_DROPPED)
+pub fn kernel_dropped() {
+    KERNEL_DROPS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// An input event was dropped because the event buffer was full
+pub fn reader_dropped() {
+    READER_DROPS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A gesture was dropped because the action queue was full
+pub fn queue_dropped() {
+    QUEUE_DROPS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn reconnected() {
+    RECONNECTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A device's recognition was restarted after failing
+pub fn restarted() {
+    RESTARTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A gesture's action finished after `duration`
+pub fn action_finished(duration: Duration, succeeded: bool) {
+    if !succeeded {
+        ACTION_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+    ACTION_DURATIONS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .observe(duration.as_secs_f64());
+}
+
+/// All metrics in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+    let mut counter = |name: &str, help: &str, value: u64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    };
+    counter(
+        "mouse_gesture_events_total",
+        "Input events processed by gesture recognition.",
+        EVENTS.load(Ordering::Relaxed),
+    );
+    counter(
+        "mouse_gesture_device_reconnects_total",
+        "Devices reopened after disappearing.",
+        RECONNECTS.load(Ordering::Relaxed),
+    );
+    counter(
+        "mouse_gesture_device_restarts_total",
+        "Device recognition restarted after an error or a panic.",
+        RESTARTS.load(Ordering::Relaxed),
+    );
+    counter(
+        "mouse_gesture_action_failures_total",
+        "Gesture actions that failed or timed out.",
+        ACTION_FAILURES.load(Ordering::Relaxed),
+    );
+
+    out.push_str("# HELP mouse_gesture_dropped_total Input lost by the kernel or a full event buffer, or gestures dropped by a full action queue.\n");
+    out.push_str("# TYPE mouse_gesture_dropped_total counter\n");
+    let _ = writeln!(
+        out,
+        "mouse_gesture_dropped_total{{reason=\"kernel\"}} {}",
+        KERNEL_DROPS.load(Ordering::Relaxed)
+    );
+    let _ = writeln!(
+        out,
+        "mouse_gesture_dropped_total{{reason=\"buffer\"}} {}",
+        READER_DROPS.load(Ordering::Relaxed)
+    );
+    let _ = writeln!(
+        out,
+        "mouse_gesture_dropped_total{{reason=\"queue\"}} {}",
+        QUEUE_DROPS.load(Ordering::Relaxed)
+    );
+
+    out.push_str("# HELP mouse_gesture_gestures_total Recognized gestures by type.\n");
+    out.push_str("# TYPE mouse_gesture_gestures_total counter\n");
+    for (kind, count) in GESTURES.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        let _ = writeln!(
+            out,
+            "mouse_gesture_gestures_total{{gesture=\"{}\"}} {}",
+            kind, count
+        );
+    }
+
+    let durations = ACTION_DURATIONS.lock().unwrap_or_else(|e| e.into_inner());
+    out.push_str(
+        "# HELP mouse_gesture_action_duration_seconds Time gesture actions took to run.\n",
+    );
+    out.push_str("# TYPE mouse_gesture_action_duration_seconds histogram\n");
+    for (count, bound) in durations.buckets.iter().zip(ACTION_BUCKETS) {
+        let _ = writeln!(
+            out,
+            "mouse_gesture_action_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, count
+        );
+    }
+    let _ = writeln!(
+        out,
+        "mouse_gesture_action_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        durations.count
+    );
+    let _ = writeln!(
+        out,
+        "mouse_gesture_action_duration_seconds_sum {}",
+        durations.sum
+    );
+    let _ = writeln!(
+        out,
+        "mouse_gesture_action_duration_seconds_count {}",
+        durations.count
+    );
+
+    out.push_str("# HELP mouse_gesture_latency_seconds Time from a gesture's input to its action starting.\n");
+    out.push_str("# TYPE mouse_gesture_latency_seconds summary\n");
+    if let Some(percentiles) = latency::percentiles() {
+        for (quantile, latency) in [
+            ("0.5", percentiles.p50),
+            ("0.9", percentiles.p90),
+            ("0.99", percentiles.p99),
+        ] {
+            let _ = writeln!(
+                out,
+                "mouse_gesture_latency_seconds{{quantile=\"{}\"}} {}",
+                quantile,
+                latency.as_secs_f64()
+            );
+        }
+    }
+    let (count, sum) = latency::totals();
+    let _ = writeln!(
+        out,
+        "mouse_gesture_latency_seconds_sum {}",
+        sum.as_secs_f64()
+    );
+    let _ = writeln!(out, "mouse_gesture_latency_seconds_count {}", count);
+
+    out.push_str("# HELP mouse_gesture_paused Whether gestures are paused.\n");
+    out.push_str("# TYPE mouse_gesture_paused gauge\n");
+    let _ = writeln!(out, "mouse_gesture_paused {}", status::paused() as u8);
+    out.push_str("# HELP mouse_gesture_uptime_seconds Time since the daemon started.\n");
+    out.push_str("# TYPE mouse_gesture_uptime_seconds gauge\n");
+    let _ = writeln!(
+        out,
+        "mouse_gesture_uptime_seconds {}",
+        status::uptime().as_secs()
+    );
+    out
+}
+
+/// Serve `GET /metrics` over HTTP on `address` (`host:port`) in the background
+pub async fn listen(address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(stream).await {
+                            debug!("Metrics request from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept metrics connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answer one request and close the connection
+async fn serve(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let (method, target) = (request_line.next(), request_line.next());
+    let (status, body) = match (method, target) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        (Some("GET"), _) => ("404 Not Found", "Not found, try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.003);
+        histogram.observe(0.2);
+        histogram.observe(30.0);
+
+        assert_eq!(histogram.buckets[0], 1); // <= 5ms
+        assert_eq!(histogram.buckets[4], 1); // <= 100ms
+        assert_eq!(histogram.buckets[5], 2); // <= 250ms
+        assert_eq!(histogram.buckets[10], 2); // <= 10s
+        assert_eq!(histogram.count, 3);
+        assert!((histogram.sum - 30.203).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// Schema version written by this build
+pub const CURRENT_VERSION: u32 = 1;
+
+/// Upgrade from version `i` to `i + 1` at index `i`
+const MIGRATIONS: &[fn(&mut Value) -> Result<()>] = &[to_v1];
+
+/// Schema version of a parsed configuration; files from before versioning
+/// have none and count as version 0
+pub fn version(config: &Value) -> Result<u32> {
+    match config.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("version must be a non-negative integer"),
+    }
+}
+
+/// Upgrade a parsed configuration to `CURRENT_VERSION` in place, returning
+/// the version it was at
+pub fn migrate(config: &mut Value) -> Result<u32> {
+    if !config.is_object() {
+        bail!("Configuration must be a JSON object");
+    }
+
+    let from = version(config)?;
+    if from > CURRENT_VERSION {
+        bail!(
+            "Configuration is version {}, but this build only understands up to {}; \
+             it was written by a newer mouse-gesture-recognition",
+            from,
+            CURRENT_VERSION
+        );
+    }
+
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(config)
+            .with_context(|| format!("Failed to migrate configuration to version {}", step + 1))?;
+        config["version"] = Value::from(step as u32 + 1);
+    }
+    Ok(from)
+}
+
+/// Tap windows older configurations were written with: the example
+/// `config.json` and the built-in defaults
+const V0_TAP_TIMEOUTS_MS: &[(&str, u64)] = &[
+    ("tap_timeout_ms", 150),
+    ("tap_timeout_ms", 300),
+    ("two_finger_tap_timeout_ms", 150),
+    ("two_finger_tap_timeout_ms", 250),
+];
+
+fn to_v1(config: &mut Value) -> Result<()> {
+    fill_required_fields(config)?;
+    unset_tap_timeouts(config);
+    Ok(())
+}
+
+/// The tap windows used to be required, so every older file pins one; unset
+/// the ones it was written with, so the device's own defaults apply
+fn unset_tap_timeouts(config: &mut Value) {
+    let Some(gesture) = config["gesture"].as_object_mut() else {
+        return;
+    };
+    for &(key, old_default) in V0_TAP_TIMEOUTS_MS {
+        if gesture.get(key).and_then(Value::as_u64) == Some(old_default) {
+            gesture[key] = Value::Null;
+        }
+    }
+}
+
+/// Fields of `device` and `gesture` that were required before versioning
+const V0_DEVICE_FIELDS: &[&str] = &["path", "auto_detect", "name_pattern"];
+const V0_GESTURE_FIELDS: &[&str] = &[
+    "scroll_threshold",
+    "swipe_threshold",
+    "pinch_threshold",
+    "tap_timeout_ms",
+    "debounce_ms",
+    "two_finger_tap_timeout_ms",
+    "two_finger_tap_distance_threshold",
+    "contact_pressure_threshold",
+    "single_finger_tap_movement_threshold",
+];
+
+/// Version 1: before versioning, `device`, `gesture` and `actions` and every
+/// field in the first two were required; fill in what's missing with the
+/// defaults instead of refusing the file
+///
+/// Only those fields are filled in, settings added since keep following
+/// their defaults.
+fn fill_required_fields(config: &mut Value) -> Result<()> {
+    let defaults = serde_json::to_value(Config::default())?;
+
+    for (section, required) in [("device", V0_DEVICE_FIELDS), ("gesture", V0_GESTURE_FIELDS)] {
+        if matches!(config.get(section), None | Some(Value::Null)) {
+            config[section] = Value::Object(Default::default());
+        }
+        let Some(fields) = config[section].as_object_mut() else {
+            continue;
+        };
+        for &key in required {
+            if let Some(value) = defaults[section].get(key) {
+                fields.entry(key).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    if matches!(config.get("actions"), None | Some(Value::Null)) {
+        config["actions"] = defaults["actions"].clone();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_migrate_unversioned_config() {
+        // The example configuration from the README before versioning
+        let mut config = serde_json::json!({
+            "device": { "path": null, "auto_detect": true, "name_pattern": "Magic Mouse" },
+            "gesture": { "scroll_threshold": 2.0, "swipe_threshold": 12.0 },
+            "actions": { "tap_1finger": "click" }
+        });
+
+        assert_eq!(migrate(&mut config).unwrap(), 0);
+        assert_eq!(version(&config).unwrap(), CURRENT_VERSION);
+
+        let parsed: Config = serde_json::from_value(config).unwrap();
+        assert_eq!(
+            parsed.gesture.swipe_threshold,
+            crate::config::Distance::Scaled(12.0)
+        );
+        assert_eq!(parsed.actions.len(), 1);
+
+        // Tap windows as older files shipped them make way for per-model ones
+        let mut shipped = serde_json::json!({
+            "gesture": { "tap_timeout_ms": 150, "two_finger_tap_timeout_ms": 200 }
+        });
+        migrate(&mut shipped).unwrap();
+        assert_eq!(shipped["gesture"]["tap_timeout_ms"], Value::Null);
+        assert_eq!(shipped["gesture"]["two_finger_tap_timeout_ms"], 200);
+
+        // Nothing beyond the fields that used to be required is pinned
+        let mut empty = serde_json::json!({});
+        migrate(&mut empty).unwrap();
+        let keys = |section: &str| -> BTreeSet<&str> {
+            empty[section]
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect()
+        };
+        assert_eq!(keys("device"), V0_DEVICE_FIELDS.iter().copied().collect());
+        assert_eq!(keys("gesture"), V0_GESTURE_FIELDS.iter().copied().collect());
+        assert!(serde_json::from_value::<Config>(empty).is_ok());
+
+        let mut newer = serde_json::json!({ "version": CURRENT_VERSION + 1 });
+        assert!(migrate(&mut newer).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use zbus::fdo::DBusProxy;
+use zbus::proxy::CacheProperties;
+
+use crate::dbus;
+
+const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// Volume change of `volume_up` / `volume_down` (MPRIS volume is 0.0-1.0)
+const VOLUME_STEP: f64 = 0.05;
+
+#[zbus::proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    fn play_pause(&self) -> zbus::Result<()>;
+    fn play(&self) -> zbus::Result<()>;
+    fn pause(&self) -> zbus::Result<()>;
+    fn stop(&self) -> zbus::Result<()>;
+    fn next(&self) -> zbus::Result<()>;
+    fn previous(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn volume(&self) -> zbus::Result<f64>;
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) -> zbus::Result<()>;
+}
+
+/// Run a `media:` action on the active MPRIS player
+pub async fn execute(command: &str) -> Result<()> {
+    let player = active_player().await?;
+    debug!(
+        "Media command {} for {}",
+        command,
+        player.inner().destination()
+    );
+
+    match command {
+        "play_pause" => player.play_pause().await?,
+        "play" => player.play().await?,
+        "pause" => player.pause().await?,
+        "stop" => player.stop().await?,
+        "next" => player.next().await?,
+        "previous" => player.previous().await?,
+        "volume_up" => change_volume(&player, VOLUME_STEP).await?,
+        "volume_down" => change_volume(&player, -VOLUME_STEP).await?,
+        other => bail!("Unknown media action: {}", other),
+    }
+    Ok(())
+}
+
+/// The player to control: the first one that is playing, else the first one
+/// found
+async fn active_player() -> Result<PlayerProxy<'static>> {
+    let bus = dbus::session().await?;
+    let mut players = Vec::new();
+    for name in DBusProxy::new(&bus).await?.list_names().await? {
+        if !name.starts_with(BUS_PREFIX) {
+            continue;
+        }
+        // Properties are read once per action, so don't follow their changes
+        let player = PlayerProxy::builder(&bus)
+            .destination(name)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        if player
+            .playback_status()
+            .await
+            .is_ok_and(|status| status == "Playing")
+        {
+            return Ok(player);
+        }
+        players.push(player);
+    }
+
+    players
+        .into_iter()
+        .next()
+        .context("No MPRIS media player is running")
+}
+
+async fn change_volume(player: &PlayerProxy<'_>, delta: f64) -> Result<()> {
+    let volume = (player.volume().await? + delta).clamp(0.0, 1.0);
+    player.set_volume(volume).await?;
+    Ok(())
+}
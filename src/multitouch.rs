@@ -1,37 +1,383 @@
-use evdev::{AbsoluteAxisType, EventType, InputEvent, Synchronization};
-use log::{debug, trace};
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, RelativeAxisType, Synchronization};
+use log::{debug, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::config::GestureConfig;
-use crate::gesture::GestureRecognizer;
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Distance, GestureConfig, Rotation};
+use crate::gesture::{
+    expire_rests, DwellTracker, GestureRecognizer, GestureSession, ScrollRecognizer,
+};
+use crate::plugin;
+use crate::timestamp;
+use crate::tremor::TremorFilter;
 
 // Magic Mouse 2 USB-C 2024 hardware specifications
 // Based on evtest output showing resolution values:
 // Event code 53 (ABS_MT_POSITION_X): Resolution 26 units/mm
 // Event code 54 (ABS_MT_POSITION_Y): Resolution 70 units/mm
-// This allows us to convert raw touchpad coordinate units to real-world millimeters
-const MAGIC_MOUSE_X_RESOLUTION: f64 = 26.0; // units per mm
-const MAGIC_MOUSE_Y_RESOLUTION: f64 = 70.0; // units per mm
+// Used only when the device doesn't report a resolution of its own
+const MAGIC_MOUSE_X_RESOLUTION: i32 = 26; // units per mm
+const MAGIC_MOUSE_Y_RESOLUTION: i32 = 70; // units per mm
 
-/// Convert Magic Mouse X coordinate units to millimeters
-fn units_to_mm_x(units: i32) -> f64 {
-    units as f64 / MAGIC_MOUSE_X_RESOLUTION
+// Bounds for the interval pointer speed is measured over, so a single report
+// after the mouse was at rest doesn't count as a burst of speed
+const MIN_POINTER_INTERVAL: Duration = Duration::from_millis(4);
+const MAX_POINTER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Slots tracked per device; the Magic Mouse reports at most 16
+pub const MAX_SLOTS: usize = 16;
+
+/// Highest finger count a BTN_TOOL_* key reports (BTN_TOOL_QUINTTAP)
+const MAX_TOOL_FINGERS: usize = 5;
+/// How long the slots may hold more contacts than the BTN_TOOL_* hint before
+/// the extra ones are taken for missed releases
+const TOOL_HINT_DRIFT: Duration = Duration::from_millis(100);
+
+/// Most positions kept per contact, origin included
+const HISTORY_CAPACITY: usize = 100;
+/// Positions older than this, relative to the latest, are dropped
+const HISTORY_MAX_AGE: Duration = Duration::from_secs(2);
+/// Leading history entries that are never dropped: the (0,0) placeholder and
+/// the first position reports, which gestures measure movement from
+const HISTORY_ORIGIN: usize = 3;
+/// Position reports a contact's velocity is measured over
+const VELOCITY_SAMPLES: usize = 5;
+
+/// Reported range and resolution of an absolute axis (EVIOCGABS)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisInfo {
+    pub min: i32,
+    pub max: i32,
+    /// Units per millimeter
+    pub resolution: i32,
+}
+
+impl AxisInfo {
+    /// Map a raw axis value onto 0..1
+    pub fn normalize(&self, value: i32) -> f64 {
+        let span = (self.max - self.min) as f64;
+        if span <= 0.0 {
+            return 0.0;
+        }
+        ((value - self.min) as f64 / span).clamp(0.0, 1.0)
+    }
+
+    /// Reflect a value around the center of the range
+    pub fn mirror(&self, value: i32) -> i32 {
+        self.min + self.max - value
+    }
+
+    /// Convert raw units to millimeters
+    pub fn units_to_mm(&self, value: i32) -> f64 {
+        value as f64 / self.resolution.max(1) as f64
+    }
+
+    /// Length of the axis in millimeters
+    pub fn length_mm(&self) -> f64 {
+        self.units_to_mm(self.max - self.min)
+    }
+}
+
+/// Geometry of the touch surface in raw device units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceBounds {
+    pub x: AxisInfo,
+    pub y: AxisInfo,
+}
+
+impl Default for SurfaceBounds {
+    /// Ranges reported by hid-magicmouse for the Magic Mouse
+    fn default() -> Self {
+        Self {
+            x: AxisInfo {
+                min: -1100,
+                max: 1258,
+                resolution: MAGIC_MOUSE_X_RESOLUTION,
+            },
+            y: AxisInfo {
+                min: -1589,
+                max: 2047,
+                resolution: MAGIC_MOUSE_Y_RESOLUTION,
+            },
+        }
+    }
+}
+
+impl SurfaceBounds {
+    /// Normalized (0..1) position of a raw coordinate pair
+    pub fn normalize(&self, x: i32, y: i32) -> (f64, f64) {
+        (self.x.normalize(x), self.y.normalize(y))
+    }
+
+    /// Position of a raw coordinate pair in millimeters
+    pub fn units_to_mm(&self, x: i32, y: i32) -> (f64, f64) {
+        (self.x.units_to_mm(x), self.y.units_to_mm(y))
+    }
+
+    /// A distance threshold in millimeters on this surface
+    pub fn distance_mm(&self, distance: Distance) -> f64 {
+        match distance {
+            Distance::Scaled(mm) | Distance::Millimeters(mm) => mm,
+            Distance::Units(units) => {
+                let resolution = (self.x.resolution.max(1) + self.y.resolution.max(1)) as f64 / 2.0;
+                units / resolution
+            }
+            Distance::Percent(percent) => {
+                percent / 100.0 * self.x.length_mm().min(self.y.length_mm())
+            }
+        }
+    }
+
+    /// The surface turned by `rotation`, its axes swapped by a quarter turn
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Clockwise | Rotation::Counterclockwise => Self {
+                x: self.y,
+                y: self.x,
+            },
+            Rotation::Upright | Rotation::UpsideDown => *self,
+        }
+    }
+
+    /// Fill in the calibrated resolution for axes that report none
+    pub fn with_fallback_resolution(mut self, calibration: &SurfaceBounds) -> Self {
+        if self.x.resolution <= 0 {
+            self.x.resolution = calibration.x.resolution;
+        }
+        if self.y.resolution <= 0 {
+            self.y.resolution = calibration.y.resolution;
+        }
+        self
+    }
+}
+
+/// Turns device positions into positions on the surface as it is held
+#[derive(Debug, Clone, Copy)]
+struct Orientation {
+    rotation: Rotation,
+    mirror: bool,
+    /// Surface as the device reports it
+    device: SurfaceBounds,
+    /// Surface as it is held, the one contacts are measured on
+    surface: SurfaceBounds,
+}
+
+impl Orientation {
+    fn new(rotation: Rotation, mirror: bool, device: SurfaceBounds) -> Self {
+        Self {
+            rotation,
+            mirror,
+            device,
+            surface: device.rotated(rotation),
+        }
+    }
+
+    /// Which axis of the held surface a position `value` on the device's X or
+    /// Y `axis` lies on, and where
+    fn map(&self, axis: ContactAxis, value: i32) -> (ContactAxis, i32) {
+        use ContactAxis::{X, Y};
+        let vertical = axis != X;
+        let (axis, value) = match (self.rotation, vertical) {
+            (Rotation::Upright, _) => (axis, value),
+            // Turned clockwise, the device's right points down and its bottom left
+            (Rotation::Clockwise, false) => (Y, value),
+            (Rotation::Clockwise, true) => (X, self.device.y.mirror(value)),
+            (Rotation::UpsideDown, false) => (X, self.device.x.mirror(value)),
+            (Rotation::UpsideDown, true) => (Y, self.device.y.mirror(value)),
+            (Rotation::Counterclockwise, false) => (Y, self.device.x.mirror(value)),
+            (Rotation::Counterclockwise, true) => (X, value),
+        };
+        if self.mirror && axis == X {
+            (X, self.surface.x.mirror(value))
+        } else {
+            (axis, value)
+        }
+    }
+
+    /// A device position on the held surface
+    fn position(&self, x: i32, y: i32) -> (i32, i32) {
+        let (axis, x) = self.map(ContactAxis::X, x);
+        let (_, y) = self.map(ContactAxis::Y, y);
+        if axis == ContactAxis::X {
+            (x, y)
+        } else {
+            (y, x)
+        }
+    }
+
+    /// Where a position on the held surface lies on the device, undoing
+    /// [`Orientation::position`]
+    fn device_position(&self, x: i32, y: i32) -> (i32, i32) {
+        let x = if self.mirror {
+            self.surface.x.mirror(x)
+        } else {
+            x
+        };
+        match self.rotation {
+            Rotation::Upright => (x, y),
+            Rotation::Clockwise => (y, self.device.y.mirror(x)),
+            Rotation::UpsideDown => (self.device.x.mirror(x), self.device.y.mirror(y)),
+            Rotation::Counterclockwise => (self.device.x.mirror(y), x),
+        }
+    }
+}
+
+/// The device a processor reads from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// Name the device reports
+    pub name: String,
+    /// Bounding box of the touch surface
+    pub surface: SurfaceBounds,
+}
+
+impl Default for DeviceInfo {
+    /// An unnamed Magic Mouse
+    fn default() -> Self {
+        Self::new("", SurfaceBounds::default())
+    }
+}
+
+impl DeviceInfo {
+    pub fn new(name: impl Into<String>, surface: SurfaceBounds) -> Self {
+        Self {
+            name: name.into(),
+            surface,
+        }
+    }
+
+    /// Width and height of the touch surface in millimeters
+    pub fn size_mm(&self) -> (f64, f64) {
+        (self.surface.x.length_mm(), self.surface.y.length_mm())
+    }
+}
+
+/// Maps kernel event timestamps onto the monotonic `Instant` timeline
+///
+/// Contact timing uses the time the kernel recorded each event rather than the
+/// time it was processed, so a busy system doesn't stretch tap durations. The
+/// first event anchors kernel time to the processor's clock; the anchor is
+/// reset if the kernel clock jumps (CLOCK_REALTIME adjustments).
+#[derive(Debug, Default)]
+struct EventClock {
+    anchor: Option<(Instant, SystemTime)>,
+}
+
+impl EventClock {
+    /// How far mapped times may drift from the processing time before re-anchoring
+    const MAX_DRIFT: Duration = Duration::from_secs(5);
+
+    fn instant_for(&mut self, timestamp: SystemTime, now: Instant) -> Instant {
+        // Synthetic events (tests, injected state) carry no timestamp
+        if timestamp == UNIX_EPOCH {
+            return now;
+        }
+
+        let (anchor_instant, anchor_time) = *self.anchor.get_or_insert((now, timestamp));
+        let mapped = match timestamp.duration_since(anchor_time) {
+            Ok(elapsed) => anchor_instant + elapsed,
+            Err(err) => anchor_instant
+                .checked_sub(err.duration())
+                .unwrap_or(anchor_instant),
+        };
+
+        if mapped > now + Self::MAX_DRIFT || now.saturating_duration_since(mapped) > Self::MAX_DRIFT
+        {
+            debug!("Kernel event clock jumped, re-anchoring");
+            self.anchor = Some((now, timestamp));
+            return now;
+        }
+
+        mapped
+    }
+}
+
+/// Kernel-side state of one multi-touch slot (EVIOCGMTSLOTS)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MtSlotState {
+    pub slot: i32,
+    /// -1 if the slot is unused
+    pub tracking_id: i32,
+    pub x: i32,
+    pub y: i32,
+    pub touch_major: i32,
+    pub touch_minor: i32,
+    pub orientation: i32,
+    pub pressure: i32,
 }
 
-/// Convert Magic Mouse Y coordinate units to millimeters  
-fn units_to_mm_y(units: i32) -> f64 {
-    units as f64 / MAGIC_MOUSE_Y_RESOLUTION
+impl MtSlotState {
+    pub fn empty(slot: i32) -> Self {
+        Self {
+            slot,
+            tracking_id: -1,
+            x: 0,
+            y: 0,
+            touch_major: 0,
+            touch_minor: 0,
+            orientation: 0,
+            pressure: 0,
+        }
+    }
 }
 
-/// Convert millimeters to Magic Mouse X coordinate units
-fn mm_to_units_x(mm: f64) -> i32 {
-    (mm * MAGIC_MOUSE_X_RESOLUTION) as i32
+/// Per-slot state indexed by slot number, without hashing or allocation
+#[derive(Debug)]
+struct SlotMap<T> {
+    entries: [Option<T>; MAX_SLOTS],
 }
 
-/// Convert millimeters to Magic Mouse Y coordinate units
-fn mm_to_units_y(mm: f64) -> i32 {
-    (mm * MAGIC_MOUSE_Y_RESOLUTION) as i32
+impl<T> SlotMap<T> {
+    fn new() -> Self {
+        Self {
+            entries: std::array::from_fn(|_| None),
+        }
+    }
+
+    fn index(slot: i32) -> Option<usize> {
+        usize::try_from(slot)
+            .ok()
+            .filter(|&index| index < MAX_SLOTS)
+    }
+
+    fn get(&self, slot: i32) -> Option<&T> {
+        self.entries[Self::index(slot)?].as_ref()
+    }
+
+    fn get_mut(&mut self, slot: i32) -> Option<&mut T> {
+        self.entries[Self::index(slot)?].as_mut()
+    }
+
+    /// Store `value` in `slot`; false if the slot is out of range
+    fn insert(&mut self, slot: i32, value: T) -> bool {
+        match Self::index(slot) {
+            Some(index) => {
+                self.entries[index] = Some(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, slot: i32) -> Option<T> {
+        self.entries[Self::index(slot)?].take()
+    }
+
+    fn clear(&mut self) {
+        self.entries = std::array::from_fn(|_| None);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.iter().all(Option::is_none)
+    }
+
+    /// Occupied slots' values, in slot order
+    fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().flatten()
+    }
 }
 
 /// Multi-touch processor that follows the Linux Multi-Touch Protocol Type B
@@ -39,8 +385,8 @@ fn mm_to_units_y(mm: f64) -> i32 {
 /// This processor manages touch contacts using slots and tracking IDs as described in:
 /// https://www.kernel.org/doc/Documentation/input/multi-touch-protocol.txt
 pub struct MultiTouchProcessor {
-    /// Pending contact updates during current sync cycle
-    pending_contacts: HashMap<i32, TouchContact>,
+    /// Contacts on the surface, by slot
+    pending_contacts: SlotMap<TouchContact>,
     /// Completed contacts waiting for gesture recognition
     completed_contacts: Vec<TouchContact>,
     /// Number of currently active contacts
@@ -49,14 +395,71 @@ pub struct MultiTouchProcessor {
     current_slot: i32,
     /// Gesture recognizer
     gesture_recognizer: GestureRecognizer,
+    /// Finger-count tracking for the touch session in progress
+    session: Option<GestureSession>,
     /// Configuration
     config: GestureConfig,
-    /// Last sync time for debouncing
-    last_sync_time: Instant,
+    /// Device name and touch surface geometry, as the device reports them
+    device: DeviceInfo,
+    /// Rotation and mirroring of positions; contacts are on its surface, used
+    /// for unit conversion and normalized coordinates
+    orientation: Orientation,
+    /// Contacts started since the last sync whose position hasn't been checked yet
+    fresh_slots: Vec<i32>,
+    /// Slots holding a contact that started in a dead zone, with its tracking ID
+    ignored_slots: SlotMap<i32>,
+    /// Contacts dropped as a missed release, kept until their slot reports
+    /// again (the finger was only resting) or lifts
+    lost_contacts: SlotMap<TouchContact>,
+    /// When the last finished gesture was emitted, for debouncing
+    last_gesture_time: Option<Instant>,
+    /// Time source for events without a kernel timestamp and for mapping
+    /// kernel timestamps onto `Instant`s
+    clock: Arc<dyn Clock>,
+    /// Kernel timestamp mapping
+    event_clock: EventClock,
+    /// Timestamp of the event currently being processed
+    event_time: Instant,
+    /// Wall-clock time of the event currently being processed, for latency
+    input_time: SystemTime,
+    /// Finger count reported by the device through BTN_TOOL_* keys, if any.
+    /// Trackpads send these alongside the slots.
+    tool_finger_count: Option<usize>,
+    /// Since when the slots have held more contacts than `tool_finger_count`
+    tool_drift_since: Option<Instant>,
+    /// Set by SYN_DROPPED; events are discarded until the next SYN_REPORT
+    dropping_events: bool,
+    /// Slot state must be re-read from the device before processing continues
+    resync_pending: bool,
+    /// Physical mouse buttons currently held down
+    held_buttons: HashSet<Key>,
+    /// A physical button was pressed during the current (or last) session
+    session_clicked: bool,
+    /// The pointer moved faster than the tap speed limit during the session
+    session_fast_pointer: bool,
+    /// The pointer moved with the left button held during the session: the
+    /// fingers are dragging, not gesturing
+    session_dragged: bool,
+    /// Relative pointer motion accumulated since the last sync
+    pointer_motion: (i32, i32),
+    /// Time of the last sync that carried pointer motion
+    last_pointer_time: Option<Instant>,
+    /// Resting-finger gestures: dwell clicks with `dwell_click`, long presses
+    /// with `sticky_drag`
+    rests: Vec<DwellTracker>,
+    /// Position smoothing for hand tremor, with `tremor_filter`
+    tremor: Option<TremorFilter>,
+}
+
+/// Physical input state accompanying a gesture
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GestureModifiers {
+    /// A physical mouse button was held during the gesture
+    pub clicked: bool,
 }
 
 /// Represents a single touch contact with full lifecycle tracking
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TouchContact {
     /// Unique tracking ID assigned by kernel (-1 means contact ended)
     pub id: i32,
@@ -66,94 +469,347 @@ pub struct TouchContact {
     pub x: i32,
     /// Y position (ABS_MT_POSITION_Y)
     pub y: i32,
+    /// X position normalized onto the surface, 0 at its left edge and 1 at its right
+    #[serde(default)]
+    pub normalized_x: f64,
+    /// Y position normalized onto the surface, 0 at its top edge and 1 at its bottom
+    #[serde(default)]
+    pub normalized_y: f64,
     /// Major axis of contact area (ABS_MT_TOUCH_MAJOR)
     pub touch_major: i32,
     /// Minor axis of contact area (ABS_MT_TOUCH_MINOR)
     pub touch_minor: i32,
     /// Contact orientation (ABS_MT_ORIENTATION)
     pub orientation: i32,
+    /// Contact pressure (ABS_MT_PRESSURE, 0 on devices without a pressure axis)
+    pub pressure: i32,
     /// When this contact was first established
+    #[serde(with = "timestamp")]
     pub first_contact_time: Instant,
     /// Last time this contact was updated
+    #[serde(with = "timestamp")]
     pub last_update_time: Instant,
     /// Whether this contact is currently active
     pub is_active: bool,
-    /// Complete history of position changes for this contact
-    pub position_history: Vec<(i32, i32, Instant)>,
+    /// Where the contact started followed by its recent positions, bounded
+    /// by `HISTORY_CAPACITY` and `HISTORY_MAX_AGE`
+    #[serde(with = "timestamp::history")]
+    pub position_history: VecDeque<(i32, i32, Instant)>,
+    /// Movement summary gestures are measured from
+    #[serde(default)]
+    pub motion: Motion,
+    /// Every axis change the device reported for the contact in its first
+    /// `contact_archive_ms`, oldest first and unlike `position_history` never
+    /// trimmed; empty unless archiving is turned on
+    #[serde(default)]
+    pub archive: ContactArchive,
+    /// Geometry of the surface the contact is on, for unit conversion
+    pub surface: SurfaceBounds,
+}
+
+/// Per-contact axes kept in the event archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactAxis {
+    X,
+    Y,
+    TouchMajor,
+    TouchMinor,
+    Orientation,
+    Pressure,
+}
+
+impl ContactAxis {
+    fn from_abs(axis: AbsoluteAxisType) -> Option<Self> {
+        Some(match axis {
+            AbsoluteAxisType::ABS_MT_POSITION_X => ContactAxis::X,
+            AbsoluteAxisType::ABS_MT_POSITION_Y => ContactAxis::Y,
+            AbsoluteAxisType::ABS_MT_TOUCH_MAJOR => ContactAxis::TouchMajor,
+            AbsoluteAxisType::ABS_MT_TOUCH_MINOR => ContactAxis::TouchMinor,
+            AbsoluteAxisType::ABS_MT_ORIENTATION => ContactAxis::Orientation,
+            AbsoluteAxisType::ABS_MT_PRESSURE => ContactAxis::Pressure,
+            _ => return None,
+        })
+    }
+}
+
+/// One axis change of a contact with its event time, the value as the device
+/// reported it (before left-handed mirroring or tremor filtering)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisChange {
+    pub axis: ContactAxis,
+    pub value: i32,
+    #[serde(with = "timestamp")]
+    pub time: Instant,
+}
+
+/// Axis changes archived for a contact, shared by its copies
+///
+/// The log is only appended to, and each copy sees the changes up to when it
+/// was made, so neither handing a contact to a gesture nor archiving more
+/// changes afterwards copies it.
+#[derive(Debug, Clone, Default)]
+pub struct ContactArchive {
+    changes: Arc<Mutex<Vec<AxisChange>>>,
+    /// Changes this copy sees
+    len: usize,
+}
+
+impl ContactArchive {
+    fn push(&mut self, change: AxisChange) {
+        let mut changes = self.changes.lock().unwrap_or_else(|e| e.into_inner());
+        if changes.len() == self.len {
+            changes.push(change);
+        } else {
+            // Another copy archived since this one was made: go on with a log
+            // of its own
+            let mut own = changes[..self.len].to_vec();
+            own.push(change);
+            drop(changes);
+            self.changes = Arc::new(Mutex::new(own));
+        }
+        self.len += 1;
+    }
+
+    /// The changes this copy sees, oldest first
+    #[cfg(test)]
+    pub fn to_vec(&self) -> Vec<AxisChange> {
+        let changes = self.changes.lock().unwrap_or_else(|e| e.into_inner());
+        changes[..self.len].to_vec()
+    }
+}
+
+impl Serialize for ContactArchive {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let changes = self.changes.lock().unwrap_or_else(|e| e.into_inner());
+        serializer.collect_seq(&changes[..self.len])
+    }
+}
+
+impl<'de> Deserialize<'de> for ContactArchive {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let changes = Vec::<AxisChange>::deserialize(deserializer)?;
+        Ok(Self {
+            len: changes.len(),
+            changes: Arc::new(Mutex::new(changes)),
+        })
+    }
+}
+
+/// Running summary of a contact's movement, kept up to date with every
+/// position report so recognition never has to walk the position history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Motion {
+    /// Position reports received so far
+    updates: u32,
+    /// First complete position: the very first report changes only one axis
+    origin: Option<(i32, i32)>,
+    /// The latest `VELOCITY_SAMPLES` reports, oldest first
+    #[serde(skip)]
+    recent: VecDeque<(i32, i32, Instant)>,
+}
+
+impl Motion {
+    /// Account for a position report
+    pub fn record(&mut self, x: i32, y: i32, time: Instant) {
+        self.updates = self.updates.saturating_add(1);
+        if self.updates == 2 {
+            self.origin = Some((x, y));
+        }
+        // The half-built first report would skew the velocity
+        if self.origin.is_none() {
+            return;
+        }
+        if self.recent.len() == VELOCITY_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((x, y, time));
+    }
+
+    /// Where the contact started, once it has reported a complete position
+    pub fn origin(&self) -> Option<(i32, i32)> {
+        self.origin
+    }
+
+    /// Movement in raw units per second over the latest reports
+    fn velocity(&self) -> Option<(f64, f64)> {
+        let (&(x1, y1, t1), &(x2, y2, t2)) = (self.recent.front()?, self.recent.back()?);
+        let elapsed = t2.saturating_duration_since(t1).as_secs_f64();
+        (elapsed > 0.0).then(|| ((x2 - x1) as f64 / elapsed, (y2 - y1) as f64 / elapsed))
+    }
 }
 
 /// Multi-touch events generated from raw input events
-#[derive(Debug, Clone)]
+///
+/// Serialized with a snake_case `type` tag, e.g.
+/// `{"type": "scroll", "delta_x": 0.0, "delta_y": 1.5}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum MultiTouchEvent {
     /// Single finger tap gesture
     SingleFingerTap {
-        finger: TouchContact,
+        finger: Arc<TouchContact>,
         duration_ms: u64,
     },
     /// Two finger tap gesture
     TwoFingerTap {
-        finger1: TouchContact,
-        finger2: TouchContact,
+        finger1: Arc<TouchContact>,
+        finger2: Arc<TouchContact>,
         duration_ms: u64,
     },
     /// Two finger swipe gesture
     TwoFingerSwipe {
-        finger1: TouchContact,
-        finger2: TouchContact,
+        finger1: Arc<TouchContact>,
+        finger2: Arc<TouchContact>,
         delta_x: f64,
         delta_y: f64,
     },
+    /// Continuous two-finger scroll, deltas in millimeters since the previous event
+    Scroll { delta_x: f64, delta_y: f64 },
     /// Pinch gesture
     Pinch {
         center_x: f64,
         center_y: f64,
         scale_factor: f64,
     },
+    /// One finger rested still for the dwell time (`dwell_click`)
+    Dwell {
+        finger: Arc<TouchContact>,
+        duration_ms: u64,
+    },
+    /// One finger held still for the long-press time (`sticky_drag`)
+    LongPress {
+        finger: Arc<TouchContact>,
+        duration_ms: u64,
+    },
+    /// Gesture reported by a plugin recognizer; `name` is its action name
+    Custom {
+        name: String,
+        fingers: Vec<Arc<TouchContact>>,
+    },
+}
+
+impl MultiTouchEvent {
+    /// The `type` it's serialized with, e.g. `two_finger_swipe`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MultiTouchEvent::SingleFingerTap { .. } => "single_finger_tap",
+            MultiTouchEvent::TwoFingerTap { .. } => "two_finger_tap",
+            MultiTouchEvent::TwoFingerSwipe { .. } => "two_finger_swipe",
+            MultiTouchEvent::Scroll { .. } => "scroll",
+            MultiTouchEvent::Pinch { .. } => "pinch",
+            MultiTouchEvent::Dwell { .. } => "dwell",
+            MultiTouchEvent::LongPress { .. } => "long_press",
+            MultiTouchEvent::Custom { .. } => "custom",
+        }
+    }
+
+    /// Number of fingers that made the gesture
+    pub fn fingers(&self) -> usize {
+        match self {
+            MultiTouchEvent::SingleFingerTap { .. }
+            | MultiTouchEvent::Dwell { .. }
+            | MultiTouchEvent::LongPress { .. } => 1,
+            MultiTouchEvent::TwoFingerTap { .. }
+            | MultiTouchEvent::TwoFingerSwipe { .. }
+            | MultiTouchEvent::Scroll { .. }
+            | MultiTouchEvent::Pinch { .. } => 2,
+            MultiTouchEvent::Custom { fingers, .. } => fingers.len(),
+        }
+    }
 }
 
 impl TouchContact {
     /// Create a new touch contact
-    fn new(id: i32, slot: i32) -> Self {
-        let now = Instant::now();
+    fn new(id: i32, slot: i32, surface: SurfaceBounds, now: Instant) -> Self {
         Self {
             id,
             slot,
             x: 0,
             y: 0,
+            normalized_x: 0.0,
+            normalized_y: 0.0,
             touch_major: 0,
             touch_minor: 0,
             orientation: 0,
+            pressure: 0,
             first_contact_time: now,
             last_update_time: now,
             is_active: true,
-            position_history: vec![(0, 0, now)], // Start with initial position
+            position_history: {
+                // Allocated once; the history never grows past its capacity
+                let mut history = VecDeque::with_capacity(HISTORY_CAPACITY);
+                history.push_back((0, 0, now)); // Start with initial position
+                history
+            },
+            motion: Motion {
+                recent: VecDeque::with_capacity(VELOCITY_SAMPLES),
+                ..Motion::default()
+            },
+            archive: ContactArchive::default(),
+            surface,
+        }
+    }
+
+    /// Test contact resting at `(x, y)` since `now`
+    #[cfg(test)]
+    pub fn at(id: i32, slot: i32, x: i32, y: i32, now: Instant) -> Self {
+        Self {
+            x,
+            y,
+            position_history: VecDeque::from([(x, y, now)]),
+            ..Self::new(id, slot, SurfaceBounds::default(), now)
         }
     }
 
     /// Update contact position and add to history
-    fn update_position(&mut self, x: i32, y: i32) {
+    fn update_position(&mut self, x: i32, y: i32, time: Instant) {
         self.x = x;
         self.y = y;
-        self.last_update_time = Instant::now();
-        self.position_history.push((x, y, self.last_update_time));
+        (self.normalized_x, self.normalized_y) = self.surface.normalize(x, y);
+        self.last_update_time = time;
+        if self.position_history.len() == HISTORY_CAPACITY {
+            self.position_history.remove(HISTORY_ORIGIN);
+        }
+        self.position_history.push_back((x, y, time));
+        self.motion.record(x, y, time);
+
+        // Removing right behind the origin only shifts the origin's entries
+        while self
+            .position_history
+            .get(HISTORY_ORIGIN)
+            .is_some_and(|&(_, _, at)| time.saturating_duration_since(at) > HISTORY_MAX_AGE)
+        {
+            self.position_history.remove(HISTORY_ORIGIN);
+        }
+    }
 
-        // Keep position history manageable (last 100 updates)
-        if self.position_history.len() > 100 {
-            self.position_history.remove(0);
+    /// Add an axis change to the archive if it came within `window_ms` of the
+    /// contact landing; 0 archives nothing
+    fn archive_change(&mut self, axis: ContactAxis, value: i32, time: Instant, window_ms: u64) {
+        let window = Duration::from_millis(window_ms);
+        if time.saturating_duration_since(self.first_contact_time) < window {
+            self.archive.push(AxisChange { axis, value, time });
         }
     }
 
     /// Update touch area
-    fn update_touch_area(&mut self, major: i32, minor: i32) {
+    fn update_touch_area(&mut self, major: i32, minor: i32, time: Instant) {
         self.touch_major = major;
         self.touch_minor = minor;
-        self.last_update_time = Instant::now();
+        self.last_update_time = time;
+    }
+
+    /// Update pressure
+    fn update_pressure(&mut self, pressure: i32, time: Instant) {
+        self.pressure = pressure;
+        self.last_update_time = time;
     }
 
     /// Update orientation
-    fn update_orientation(&mut self, orientation: i32) {
+    fn update_orientation(&mut self, orientation: i32, time: Instant) {
         self.orientation = orientation;
-        self.last_update_time = Instant::now();
+        self.last_update_time = time;
     }
 
     /// Get duration of this contact
@@ -164,28 +820,42 @@ impl TouchContact {
 
     /// Calculate distance to another contact in millimeters
     pub fn distance_to(&self, other: &TouchContact) -> f64 {
-        let dx_mm = units_to_mm_x(self.x) - units_to_mm_x(other.x);
-        let dy_mm = units_to_mm_y(self.y) - units_to_mm_y(other.y);
+        let dx_mm = self.surface.x.units_to_mm(self.x) - other.surface.x.units_to_mm(other.x);
+        let dy_mm = self.surface.y.units_to_mm(self.y) - other.surface.y.units_to_mm(other.y);
         (dx_mm * dx_mm + dy_mm * dy_mm).sqrt()
     }
 
     /// Get movement delta from start to current position in millimeters
     pub fn movement_delta(&self) -> (f64, f64) {
-        // The first entry is always (0,0) from initialization, so we want the third entry if it exists
-        if self.position_history.len() >= 3 {
-            if let Some((start_x, start_y, _)) = self.position_history.get(2) {
-                let dx_mm = units_to_mm_x(self.x) - units_to_mm_x(*start_x);
-                let dy_mm = units_to_mm_y(self.y) - units_to_mm_y(*start_y);
-                (dx_mm, dy_mm)
-            } else {
-                (0.0, 0.0)
+        match self.motion.origin() {
+            Some((start_x, start_y)) => {
+                self.surface.units_to_mm(self.x - start_x, self.y - start_y)
             }
-        } else {
-            // If we only have the initial (0,0) position, no movement yet
-            (0.0, 0.0)
+            // Not a complete position yet, so no movement either
+            None => (0.0, 0.0),
         }
     }
 
+    /// Where the contact started in millimeters, once it reported a position
+    pub fn origin_mm(&self) -> Option<(f64, f64)> {
+        let (x, y) = self.motion.origin()?;
+        Some(self.surface.units_to_mm(x, y))
+    }
+
+    /// Current velocity in millimeters per second
+    pub fn velocity_mm(&self) -> (f64, f64) {
+        let (vx, vy) = self.motion.velocity().unwrap_or_default();
+        (
+            vx / self.surface.x.resolution.max(1) as f64,
+            vy / self.surface.y.resolution.max(1) as f64,
+        )
+    }
+
+    /// Current position in millimeters
+    pub fn position_mm(&self) -> (f64, f64) {
+        self.surface.units_to_mm(self.x, self.y)
+    }
+
     /// Check if this contact represents a tap (short duration, minimal movement)
     pub fn is_tap(&self, max_duration_ms: u64, max_movement: f64) -> bool {
         let duration = self.contact_duration();
@@ -199,45 +869,320 @@ impl TouchContact {
     }
 }
 
+/// Wait until a processor's `deadline`, forever if it has none
+pub async fn wait_for_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
 impl MultiTouchProcessor {
     pub fn new(config: GestureConfig) -> Self {
-        let gesture_recognizer = GestureRecognizer::new(
-            config.swipe_threshold,
+        Self::on_device(config, DeviceInfo::default(), Arc::new(SystemClock))
+    }
+
+    fn on_device(config: GestureConfig, device: DeviceInfo, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        // Left-handed mode mirrors the surface around its vertical center line, so
+        // swipe directions and anything positional (left/right zones) flip with it
+        let orientation = Orientation::new(
+            config.rotation,
+            config.mirror || config.left_handed,
+            device.surface,
+        );
+        let surface = &orientation.surface;
+        let scroll_threshold = surface.distance_mm(config.scroll_threshold);
+        let mut gesture_recognizer = GestureRecognizer::new(
+            surface.distance_mm(config.swipe_threshold),
             config.pinch_threshold,
-            config.scroll_threshold,
-            config.tap_timeout_ms,
-            config.single_finger_tap_movement_threshold,
-            config.two_finger_tap_timeout_ms,
-            config.two_finger_tap_distance_threshold,
+            scroll_threshold,
+            config.tap_timeout_ms(),
+            surface.distance_mm(config.single_finger_tap_movement_threshold),
+            config.two_finger_tap_timeout_ms(),
+            surface.distance_mm(config.two_finger_tap_distance_threshold),
         );
+        gesture_recognizer.register(Box::new(ScrollRecognizer::new(
+            scroll_threshold,
+            config.scroll_axis_lock,
+        )));
+        for recognizer in plugin::recognizers() {
+            gesture_recognizer.register(recognizer);
+        }
+        let mut rests = Vec::new();
+        if config.dwell_click {
+            rests.push(DwellTracker::new(
+                config.dwell_ms,
+                surface.distance_mm(config.dwell_radius),
+                |finger, duration_ms| MultiTouchEvent::Dwell {
+                    finger,
+                    duration_ms,
+                },
+            ));
+        }
+        if config.sticky_drag {
+            // A long press is a tap that stays down
+            rests.push(DwellTracker::new(
+                config.long_press_ms,
+                surface.distance_mm(config.single_finger_tap_movement_threshold),
+                |finger, duration_ms| MultiTouchEvent::LongPress {
+                    finger,
+                    duration_ms,
+                },
+            ));
+        }
+        let tremor = config.tremor_filter.then(|| {
+            TremorFilter::new(
+                config.tremor_smoothing,
+                surface.distance_mm(config.tremor_deadband),
+                surface,
+            )
+        });
 
         Self {
-            pending_contacts: HashMap::new(),
+            pending_contacts: SlotMap::new(),
             completed_contacts: Vec::new(),
             active_contact_count: 0,
             current_slot: 0,
             gesture_recognizer,
+            session: None,
             config,
-            last_sync_time: Instant::now(),
+            device,
+            orientation,
+            fresh_slots: Vec::new(),
+            ignored_slots: SlotMap::new(),
+            lost_contacts: SlotMap::new(),
+            last_gesture_time: None,
+            clock,
+            event_clock: EventClock::default(),
+            event_time: now,
+            input_time: SystemTime::now(),
+            tool_finger_count: None,
+            tool_drift_since: None,
+            dropping_events: false,
+            resync_pending: false,
+            held_buttons: HashSet::new(),
+            session_clicked: false,
+            session_fast_pointer: false,
+            session_dragged: false,
+            pointer_motion: (0, 0),
+            last_pointer_time: None,
+            rests,
+            tremor,
         }
     }
 
+    /// Use the name and touch surface geometry reported by the device
+    pub fn with_device(self, device: DeviceInfo) -> Self {
+        // Thresholds in device units or relative to the surface depend on it
+        Self::on_device(self.config, device, self.clock)
+    }
+
+    /// Take the time from `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self::on_device(self.config, self.device, clock)
+    }
+
+    /// The device the processor reads from, its surface as reported rather
+    /// than rotated
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.device
+    }
+
     /// Process a single evdev input event according to MT Protocol Type B
     pub async fn process_event(&mut self, event: InputEvent) -> Option<Vec<MultiTouchEvent>> {
         trace!("Processing event: {:?}", event);
+        self.event_time = self
+            .event_clock
+            .instant_for(event.timestamp(), self.clock.now());
+        self.input_time = match event.timestamp() {
+            UNIX_EPOCH => SystemTime::now(),
+            timestamp => timestamp,
+        };
+
+        if event.event_type() == EventType::SYNCHRONIZATION
+            && event.code() == Synchronization::SYN_DROPPED.0
+        {
+            warn!("Input events were dropped (SYN_DROPPED), touch state will be resynced");
+            self.dropping_events = true;
+            return None;
+        }
+
+        if self.dropping_events {
+            // Everything up to and including the next SYN_REPORT is incomplete
+            if event.event_type() == EventType::SYNCHRONIZATION
+                && event.code() == Synchronization::SYN_REPORT.0
+            {
+                self.dropping_events = false;
+                self.resync_pending = true;
+            }
+            return None;
+        }
 
         match event.event_type() {
             EventType::ABSOLUTE => self.handle_absolute_event(event),
+            EventType::KEY => self.handle_key_event(event),
+            EventType::RELATIVE => self.handle_relative_event(event),
             EventType::SYNCHRONIZATION => self.handle_sync_event(event).await,
             _ => None,
         }
     }
 
+    /// When a resting finger dwell-clicks or long-presses unless it moves, the
+    /// least recently updated contact goes stale, or contacts the BTN_TOOL_*
+    /// hint no longer counts are dropped; the device loop calls `poll` then, as
+    /// a still finger may not produce any events
+    pub fn deadline(&self) -> Option<Instant> {
+        let stale = self.stale_timeout().and_then(|timeout| {
+            let oldest = self
+                .pending_contacts
+                .values()
+                .map(|contact| contact.last_update_time)
+                .min()?;
+            Some(oldest + timeout)
+        });
+        let drift = self.tool_drift_since.map(|since| since + TOOL_HINT_DRIFT);
+        self.rests
+            .iter()
+            .filter_map(DwellTracker::deadline)
+            .chain(stale)
+            .chain(drift)
+            .min()
+    }
+
+    /// Gestures that are due without new input, once `deadline` passed
+    pub fn poll(&mut self) -> Option<Vec<MultiTouchEvent>> {
+        let now = self.clock.now();
+        self.expire_stale_contacts(now);
+        self.check_tool_hint(now);
+        let contacts: Vec<&TouchContact> = self.pending_contacts.values().collect();
+        let events = expire_rests(&mut self.rests, &contacts, now);
+        if events.is_empty() {
+            return None;
+        }
+        self.input_time = SystemTime::now();
+        debug!("Gestures recognized: {:?}", events);
+        Some(events)
+    }
+
+    /// When the event processed last happened: its kernel timestamp, or when it
+    /// was processed if it has none
+    pub fn input_time(&self) -> SystemTime {
+        self.input_time
+    }
+
+    /// Contacts currently on the surface, ordered by slot
+    pub fn active_contacts(&self) -> Vec<&TouchContact> {
+        self.pending_contacts.values().collect()
+    }
+
+    /// Modifiers of the session in progress, or of the last finished one
+    pub fn modifiers(&self) -> GestureModifiers {
+        GestureModifiers {
+            clicked: self.session_clicked || !self.held_buttons.is_empty(),
+        }
+    }
+
+    /// Whether the slot state needs to be re-read from the device (after SYN_DROPPED)
+    pub fn needs_resync(&self) -> bool {
+        self.resync_pending
+    }
+
+    /// Rebuild contact state from the kernel's current slot state
+    ///
+    /// Whatever session was in progress is abandoned: contacts may have ended or
+    /// started while events were dropped, so nothing recorded before the drop can
+    /// be trusted for recognition. Fingers still on the surface become fresh
+    /// contacts of a new session.
+    pub fn resync(&mut self, slots: &[MtSlotState], current_slot: i32) {
+        self.rebuild_from_slots(slots, current_slot);
+        info!(
+            "Resynced touch state: {} active contacts",
+            self.active_contact_count
+        );
+    }
+
+    /// Seed contact state from the slots already in use when the device is opened
+    ///
+    /// Fingers resting on the surface at startup never send a tracking ID, so
+    /// without this they would be invisible until lifted and would break finger
+    /// counting for the first gesture.
+    pub fn seed(&mut self, slots: &[MtSlotState], current_slot: i32) {
+        self.rebuild_from_slots(slots, current_slot);
+        if self.active_contact_count > 0 {
+            info!(
+                "{} contacts already on the surface at startup",
+                self.active_contact_count
+            );
+        }
+    }
+
+    fn rebuild_from_slots(&mut self, slots: &[MtSlotState], current_slot: i32) {
+        let now = self.clock.now();
+        self.resync_pending = false;
+        self.dropping_events = false;
+
+        self.pending_contacts.clear();
+        self.completed_contacts.clear();
+        self.ignored_slots.clear();
+        self.lost_contacts.clear();
+        self.fresh_slots.clear();
+        self.session = None;
+        self.active_contact_count = 0;
+        self.gesture_recognizer.reset();
+        self.current_slot = current_slot;
+        // Key events were dropped along with the rest; wait for the next hint
+        self.tool_finger_count = None;
+        self.tool_drift_since = None;
+
+        for state in slots.iter().filter(|state| state.tracking_id >= 0) {
+            let mut contact =
+                TouchContact::new(state.tracking_id, state.slot, self.orientation.surface, now);
+            let (x, y) = self.orientation.position(state.x, state.y);
+            contact.update_position(x, y, now);
+            contact.update_touch_area(state.touch_major, state.touch_minor, now);
+            contact.update_orientation(state.orientation, now);
+            contact.update_pressure(state.pressure, now);
+            for (axis, value) in [
+                (ContactAxis::X, state.x),
+                (ContactAxis::Y, state.y),
+                (ContactAxis::TouchMajor, state.touch_major),
+                (ContactAxis::TouchMinor, state.touch_minor),
+                (ContactAxis::Orientation, state.orientation),
+                (ContactAxis::Pressure, state.pressure),
+            ] {
+                contact.archive_change(axis, value, now, self.config.contact_archive_ms);
+            }
+            if !self.pending_contacts.insert(state.slot, contact) {
+                continue;
+            }
+            self.fresh_slots.push(state.slot);
+            self.active_contact_count += 1;
+        }
+
+        if self.active_contact_count > 0 {
+            let grace_ms = self.config.finger_count_grace_ms;
+            let mut session = GestureSession::new(0, now, grace_ms);
+            session.update_count(self.active_contact_count, now);
+            self.session = Some(session);
+            self.filter_dead_zone_contacts(now);
+            self.update_pointer_speed(now);
+        }
+    }
+
     /// Handle absolute axis events (ABS_MT_*)
     fn handle_absolute_event(&mut self, event: InputEvent) -> Option<Vec<MultiTouchEvent>> {
         let axis = AbsoluteAxisType(event.code());
         let value = event.value();
 
+        if let Some(contact_axis) = ContactAxis::from_abs(axis) {
+            self.restore_lost_contact();
+            let window_ms = self.config.contact_archive_ms;
+            if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
+                contact.archive_change(contact_axis, value, self.event_time, window_ms);
+            }
+        }
+
         match axis {
             AbsoluteAxisType::ABS_MT_SLOT => {
                 // Switch to a different slot for subsequent updates
@@ -248,10 +1193,10 @@ impl MultiTouchProcessor {
                 return self.handle_tracking_id(value);
             }
             AbsoluteAxisType::ABS_MT_POSITION_X => {
-                self.update_contact_x(value);
+                self.update_contact_position(ContactAxis::X, value);
             }
             AbsoluteAxisType::ABS_MT_POSITION_Y => {
-                self.update_contact_y(value);
+                self.update_contact_position(ContactAxis::Y, value);
             }
             AbsoluteAxisType::ABS_MT_TOUCH_MAJOR => {
                 self.update_contact_touch_major(value);
@@ -262,6 +1207,11 @@ impl MultiTouchProcessor {
             AbsoluteAxisType::ABS_MT_ORIENTATION => {
                 self.update_contact_orientation(value);
             }
+            AbsoluteAxisType::ABS_MT_PRESSURE => {
+                if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
+                    contact.update_pressure(value, self.event_time);
+                }
+            }
             _ => {
                 // Other absolute events we don't handle
             }
@@ -270,136 +1220,574 @@ impl MultiTouchProcessor {
         None // No events generated until sync
     }
 
-    /// Handle tracking ID updates (contact creation/destruction)
-    fn handle_tracking_id(&mut self, tracking_id: i32) -> Option<Vec<MultiTouchEvent>> {
-        if tracking_id == -1 {
-            // Contact ended - immediately trigger gesture recognition
-            if let Some(mut contact) = self.pending_contacts.remove(&self.current_slot) {
-                contact.is_active = false;
-                contact.last_update_time = Instant::now();
-                self.completed_contacts.push(contact);
-                self.active_contact_count = self.active_contact_count.saturating_sub(1);
+    /// Handle key events: physical buttons and the BTN_TOOL_* finger-count hints
+    fn handle_key_event(&mut self, event: InputEvent) -> Option<Vec<MultiTouchEvent>> {
+        let key = Key::new(event.code());
+        if matches!(key, Key::BTN_LEFT | Key::BTN_RIGHT | Key::BTN_MIDDLE) {
+            if event.value() != 0 {
+                self.held_buttons.insert(key);
+                self.session_clicked |= self.session.is_some();
+            } else {
+                self.held_buttons.remove(&key);
+            }
+            return None;
+        }
 
-                debug!(
-                    "Contact ended in slot {}, active contacts: {}",
-                    self.current_slot, self.active_contact_count
-                );
+        let fingers = match key {
+            Key::BTN_TOOL_FINGER => 1,
+            Key::BTN_TOOL_DOUBLETAP => 2,
+            Key::BTN_TOOL_TRIPLETAP => 3,
+            Key::BTN_TOOL_QUADTAP => 4,
+            Key::BTN_TOOL_QUINTTAP => 5,
+            _ => return None,
+        };
 
-                // Trigger gesture recognition immediately if no more active contacts
-                if self.active_contact_count == 0 && !self.completed_contacts.is_empty() {
-                    debug!(
-                        "All contacts ended, running gesture recognition on {} contacts",
-                        self.completed_contacts.len()
-                    );
-
-                    // Analyze gesture and return exactly one event
-                    let gesture_result = self
-                        .gesture_recognizer
-                        .analyze_gesture(&self.completed_contacts);
-
-                    // Always clear completed contacts after gesture analysis to prevent duplicates
-                    self.completed_contacts.clear();
-
-                    // Return the gesture event if one was recognized
-                    if let Some(gesture_event) = gesture_result {
-                        debug!("Gesture recognized: {:?}", gesture_event);
-                        return Some(vec![gesture_event]);
-                    }
-                }
+        if event.value() != 0 {
+            self.tool_finger_count = Some(fingers);
+        } else if self.tool_finger_count == Some(fingers) {
+            // Released without another one pressed: the last finger lifted
+            self.tool_finger_count = Some(0);
+        }
+
+        None
+    }
+
+    /// Accumulate relative pointer motion until the next sync
+    fn handle_relative_event(&mut self, event: InputEvent) -> Option<Vec<MultiTouchEvent>> {
+        match RelativeAxisType(event.code()) {
+            RelativeAxisType::REL_X => self.pointer_motion.0 += event.value(),
+            RelativeAxisType::REL_Y => self.pointer_motion.1 += event.value(),
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Flag the session if the pointer moved too fast for a deliberate tap
+    fn update_pointer_speed(&mut self, now: Instant) {
+        if self.pointer_motion == (0, 0) {
+            return;
+        }
+
+        let (dx, dy) = std::mem::take(&mut self.pointer_motion);
+        let elapsed = self
+            .last_pointer_time
+            .map_or(MAX_POINTER_INTERVAL, |last| now.duration_since(last))
+            .clamp(MIN_POINTER_INTERVAL, MAX_POINTER_INTERVAL);
+        self.last_pointer_time = Some(now);
+
+        let speed = (dx as f64).hypot(dy as f64) / elapsed.as_secs_f64();
+        let limit = self.config.tap_max_pointer_speed;
+        if limit > 0.0 && speed > limit && self.session.is_some() && !self.session_fast_pointer {
+            debug!(
+                "Pointer moving at {:.0} counts/s, suppressing taps for this session",
+                speed
+            );
+            self.session_fast_pointer = true;
+        }
+    }
+
+    /// Whether a recognized gesture should be dropped given the session's
+    /// physical input: taps during a click or fast pointer motion are accidental,
+    /// and so are swipes and scrolls while the mouse drags with the left button
+    fn is_suppressed(&self, event: &MultiTouchEvent) -> bool {
+        match event {
+            MultiTouchEvent::SingleFingerTap { .. } | MultiTouchEvent::TwoFingerTap { .. } => {
+                self.session_clicked || self.session_fast_pointer
             }
-        } else {
-            // New contact or update
-            let is_new_contact = !self.pending_contacts.contains_key(&self.current_slot);
-            let contact = self
-                .pending_contacts
-                .entry(self.current_slot)
-                .or_insert_with(|| {
-                    debug!("New contact {} in slot {}", tracking_id, self.current_slot);
-                    TouchContact::new(tracking_id, self.current_slot)
-                });
+            MultiTouchEvent::TwoFingerSwipe { .. } | MultiTouchEvent::Scroll { .. } => {
+                self.session_dragged
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a finished gesture follows the previous one too closely to be
+    /// emitted; the contacts it came from have been accounted for regardless
+    fn is_debounced(&self) -> bool {
+        let window = Duration::from_millis(self.config.debounce_ms);
+        self.last_gesture_time
+            .is_some_and(|last| self.event_time.saturating_duration_since(last) < window)
+    }
+
+    /// Handle tracking ID updates (contact creation/destruction)
+    fn handle_tracking_id(&mut self, tracking_id: i32) -> Option<Vec<MultiTouchEvent>> {
+        if let Some(ignored_id) = self.ignored_slots.get(self.current_slot) {
+            if *ignored_id == tracking_id {
+                return None;
+            }
+            // The dead-zone contact ended (or was replaced); the slot is live again
+            debug!("Dead-zone contact in slot {} ended", self.current_slot);
+            self.ignored_slots.remove(self.current_slot);
+            if tracking_id == -1 {
+                return None;
+            }
+        }
+
+        if let Some(lost) = self.lost_contacts.get(self.current_slot) {
+            if lost.id == tracking_id {
+                self.restore_lost_contact();
+                return None;
+            }
+            // The release wasn't missed after all, or a new finger took the slot
+            debug!("Dropped contact in slot {} ended", self.current_slot);
+            self.lost_contacts.remove(self.current_slot);
+        }
 
-            contact.id = tracking_id;
-            contact.is_active = true;
+        if tracking_id == -1 {
+            return self.end_contact();
+        }
 
-            if is_new_contact {
-                self.active_contact_count += 1;
+        // A new tracking ID in a slot that still holds a contact means the old
+        // one ended and a new one started within the same frame, without a -1
+        // in between (the kernel only reports the slot's latest state)
+        match self.pending_contacts.get(self.current_slot) {
+            Some(contact) if contact.id != tracking_id => {
                 debug!(
-                    "New contact started, active contacts: {}",
-                    self.active_contact_count
+                    "Contact {} in slot {} replaced by {}",
+                    contact.id, self.current_slot, tracking_id
                 );
+                let ended = self.end_contact();
+                self.start_contact(tracking_id);
+                ended
+            }
+            Some(_) => None,
+            None => {
+                self.start_contact(tracking_id);
+                None
+            }
+        }
+    }
+
+    /// End the contact in the current slot, running recognition once it was
+    /// the last one on the surface
+    fn end_contact(&mut self) -> Option<Vec<MultiTouchEvent>> {
+        // Contact ended - immediately trigger gesture recognition
+        if let Some(mut contact) = self.pending_contacts.remove(self.current_slot) {
+            contact.is_active = false;
+            contact.last_update_time = self.event_time;
+            self.completed_contacts.push(contact);
+            self.active_contact_count = self.active_contact_count.saturating_sub(1);
+            if let Some(session) = self.session.as_mut() {
+                session.update_count(self.active_contact_count, self.event_time);
+            }
+
+            debug!(
+                "Contact ended in slot {}, active contacts: {}",
+                self.current_slot, self.active_contact_count
+            );
+
+            // Trigger gesture recognition immediately if no more active contacts
+            if self.active_contact_count == 0 && !self.completed_contacts.is_empty() {
+                debug!(
+                    "All contacts ended, running gesture recognition on {} contacts",
+                    self.completed_contacts.len()
+                );
+
+                // Let the dominant finger count of the session decide which
+                // contacts take part in recognition
+                let contacts = match self.session.take() {
+                    Some(mut session) => session.finish(&self.completed_contacts, self.event_time),
+                    None => self.completed_contacts.iter().collect(),
+                };
+
+                // Analyze gesture and return exactly one event
+                let gesture_result = self.gesture_recognizer.analyze_gesture(&contacts);
+
+                // Always clear completed contacts after gesture analysis to prevent duplicates
+                self.completed_contacts.clear();
+
+                // Return the gesture event if one was recognized
+                if let Some(gesture_event) = gesture_result {
+                    if self.is_suppressed(&gesture_event) {
+                        debug!("Suppressed gesture: {:?}", gesture_event);
+                        return None;
+                    }
+                    if self.is_debounced() {
+                        debug!("Debounced gesture: {:?}", gesture_event);
+                        return None;
+                    }
+                    self.last_gesture_time = Some(self.event_time);
+                    debug!("Gesture recognized: {:?}", gesture_event);
+                    return Some(vec![gesture_event]);
+                }
             }
         }
 
         None
     }
 
+    /// Start a contact with `tracking_id` in the current, empty slot
+    fn start_contact(&mut self, tracking_id: i32) {
+        debug!("New contact {} in slot {}", tracking_id, self.current_slot);
+        let contact = TouchContact::new(
+            tracking_id,
+            self.current_slot,
+            self.orientation.surface,
+            self.event_time,
+        );
+        if !self.pending_contacts.insert(self.current_slot, contact) {
+            warn!(
+                "Ignoring contact in slot {}, only {} slots are tracked",
+                self.current_slot, MAX_SLOTS
+            );
+            return;
+        }
+
+        self.fresh_slots.push(self.current_slot);
+        self.active_contact_count += 1;
+        debug!(
+            "New contact started, active contacts: {}",
+            self.active_contact_count
+        );
+
+        let now = self.event_time;
+        let grace_ms = self.config.finger_count_grace_ms;
+        if self.session.is_none() {
+            self.session_clicked = !self.held_buttons.is_empty();
+            self.session_fast_pointer = false;
+            self.session_dragged = false;
+        }
+        self.session
+            .get_or_insert_with(|| GestureSession::new(0, now, grace_ms))
+            .update_count(self.active_contact_count, now);
+    }
+
+    /// Update the current slot's position on the device's X or Y `axis`,
+    /// rotated and mirrored onto the surface as it is held
+    fn update_contact_position(&mut self, axis: ContactAxis, value: i32) {
+        match self.orientation.map(axis, value) {
+            (ContactAxis::X, x) => self.update_contact_x(x),
+            (_, y) => self.update_contact_y(y),
+        }
+    }
+
     /// Update X position for current slot
     fn update_contact_x(&mut self, x: i32) {
-        if let Some(contact) = self.pending_contacts.get_mut(&self.current_slot) {
+        if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
+            let x = match self.tremor.as_mut() {
+                Some(tremor) => tremor.filter_x(self.current_slot, contact.id, x),
+                None => x,
+            };
             let old_y = contact.y;
-            contact.update_position(x, old_y);
+            contact.update_position(x, old_y, self.event_time);
         }
     }
 
     /// Update Y position for current slot
     fn update_contact_y(&mut self, y: i32) {
-        if let Some(contact) = self.pending_contacts.get_mut(&self.current_slot) {
+        if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
+            let y = match self.tremor.as_mut() {
+                Some(tremor) => tremor.filter_y(self.current_slot, contact.id, y),
+                None => y,
+            };
             let old_x = contact.x;
-            contact.update_position(old_x, y);
+            contact.update_position(old_x, y, self.event_time);
         }
     }
 
     /// Update touch major axis for current slot
     fn update_contact_touch_major(&mut self, major: i32) {
-        if let Some(contact) = self.pending_contacts.get_mut(&self.current_slot) {
+        if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
             let minor = contact.touch_minor;
-            contact.update_touch_area(major, minor);
+            contact.update_touch_area(major, minor, self.event_time);
         }
     }
 
     /// Update touch minor axis for current slot
     fn update_contact_touch_minor(&mut self, minor: i32) {
-        if let Some(contact) = self.pending_contacts.get_mut(&self.current_slot) {
+        if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
             let major = contact.touch_major;
-            contact.update_touch_area(major, minor);
+            contact.update_touch_area(major, minor, self.event_time);
         }
     }
 
     /// Update orientation for current slot
     fn update_contact_orientation(&mut self, orientation: i32) {
-        if let Some(contact) = self.pending_contacts.get_mut(&self.current_slot) {
-            contact.update_orientation(orientation);
+        if let Some(contact) = self.pending_contacts.get_mut(self.current_slot) {
+            contact.update_orientation(orientation, self.event_time);
         }
     }
 
+    /// How long a contact may go without updates, `None` with `stale_contact_ms`
+    /// set to 0
+    fn stale_timeout(&self) -> Option<Duration> {
+        let timeout = self.config.stale_contact_ms;
+        (timeout > 0).then(|| Duration::from_millis(timeout))
+    }
+
+    /// Forget contacts that weren't updated within `stale_contact_ms`
+    ///
+    /// Their release was missed (dropped events, a device reset), and left
+    /// alone they would count as a finger on the surface forever.
+    fn expire_stale_contacts(&mut self, now: Instant) {
+        let Some(timeout) = self.stale_timeout() else {
+            return;
+        };
+        let is_stale = |contact: &TouchContact| {
+            now.saturating_duration_since(contact.last_update_time) >= timeout
+        };
+        // Checked first, so a sync with live contacts allocates nothing
+        if !self.pending_contacts.values().any(is_stale) {
+            return;
+        }
+
+        let stale: Vec<i32> = self
+            .pending_contacts
+            .values()
+            .filter(|contact| is_stale(contact))
+            .map(|contact| contact.slot)
+            .collect();
+        for &slot in &stale {
+            let Some(contact) = self.pending_contacts.get(slot) else {
+                continue;
+            };
+            warn!(
+                "Contact {} in slot {} wasn't updated for {}ms, dropping it as a missed release",
+                contact.id,
+                slot,
+                now.saturating_duration_since(contact.last_update_time)
+                    .as_millis()
+            );
+        }
+        self.drop_lost_contacts(&stale, now);
+    }
+
+    /// Forget contacts whose release was missed
+    ///
+    /// The session they took part in is abandoned once no contact is left,
+    /// like after a resync, since its finger counts can't be trusted.
+    fn drop_lost_contacts(&mut self, slots: &[i32], now: Instant) {
+        for &slot in slots {
+            if let Some(contact) = self.pending_contacts.remove(slot) {
+                self.lost_contacts.insert(slot, contact);
+                self.active_contact_count = self.active_contact_count.saturating_sub(1);
+            }
+        }
+
+        if self.active_contact_count == 0 {
+            self.session = None;
+            self.completed_contacts.clear();
+            self.gesture_recognizer.reset();
+        } else if let Some(session) = self.session.as_mut() {
+            session.update_count(self.active_contact_count, now);
+        }
+    }
+
+    /// Fingers on the surface according to the slots, including contacts
+    /// ignored for starting in a dead zone
+    fn slot_finger_count(&self) -> usize {
+        self.active_contact_count + self.ignored_slots.values().count()
+    }
+
+    /// Cross-check the slot-derived finger count against the BTN_TOOL_* hint
+    ///
+    /// The kernel reports both in the same frame, so slots still holding more
+    /// contacts than the hint once `TOOL_HINT_DRIFT` has passed lost a release.
+    /// The least recently updated ones are dropped until the counts agree. Fewer
+    /// contacts than the hint are left alone: semi-MT touchpads report more
+    /// fingers than they have slots.
+    fn check_tool_hint(&mut self, now: Instant) {
+        let Some(hint) = self.tool_finger_count else {
+            return;
+        };
+        let slot_count = self.slot_finger_count();
+        // BTN_TOOL_QUINTTAP stands for five fingers or more
+        let excess = if hint < MAX_TOOL_FINGERS {
+            slot_count.saturating_sub(hint)
+        } else {
+            0
+        };
+        if excess == 0 {
+            if hint != slot_count {
+                trace!(
+                    "BTN_TOOL hint reports {} fingers, slots report {}",
+                    hint,
+                    slot_count
+                );
+            }
+            self.tool_drift_since = None;
+            return;
+        }
+
+        let since = *self.tool_drift_since.get_or_insert(now);
+        if now.saturating_duration_since(since) < TOOL_HINT_DRIFT {
+            return;
+        }
+        self.tool_drift_since = None;
+
+        let mut contacts: Vec<&TouchContact> = self.pending_contacts.values().collect();
+        contacts.sort_by_key(|contact| contact.last_update_time);
+        let lost: Vec<i32> = contacts
+            .iter()
+            .take(excess)
+            .map(|contact| contact.slot)
+            .collect();
+        for contact in contacts.iter().take(excess) {
+            warn!(
+                "BTN_TOOL hint reports {} fingers but slots report {}, dropping contact {} in slot {} as a missed release",
+                hint,
+                slot_count,
+                contact.id,
+                contact.slot
+            );
+        }
+        self.drop_lost_contacts(&lost, now);
+    }
+
+    /// Take back the dropped contact in the current slot, which reported again
+    ///
+    /// The finger was resting still rather than lifted. It keeps its position,
+    /// as the device only reports the axes that change.
+    fn restore_lost_contact(&mut self) {
+        let Some(contact) = self.lost_contacts.remove(self.current_slot) else {
+            return;
+        };
+        debug!(
+            "Contact {} in slot {} reported again, tracking it again",
+            contact.id, self.current_slot
+        );
+        self.pending_contacts.insert(self.current_slot, contact);
+        self.active_contact_count += 1;
+
+        let now = self.event_time;
+        let grace_ms = self.config.finger_count_grace_ms;
+        if self.session.is_none() {
+            self.session_clicked = !self.held_buttons.is_empty();
+            self.session_fast_pointer = false;
+            self.session_dragged = false;
+        }
+        self.session
+            .get_or_insert_with(|| GestureSession::new(0, now, grace_ms))
+            .update_count(self.active_contact_count, now);
+    }
+
+    /// Drop contacts that started inside a configured dead zone
+    ///
+    /// A contact's position is only known once the frame that started it is
+    /// synced, so new contacts are checked here. Contacts that land in a dead
+    /// zone are forgotten entirely and the rest of their slot's events ignored.
+    fn filter_dead_zone_contacts(&mut self, now: Instant) {
+        // Cleared rather than taken, so the buffer is reused across syncs
+        for index in 0..self.fresh_slots.len() {
+            let slot = self.fresh_slots[index];
+            let Some(contact) = self.pending_contacts.get(slot) else {
+                continue;
+            };
+
+            // Zones mark parts of the device, so they stay put however it is held
+            let (x, y) = self.orientation.device_position(contact.x, contact.y);
+            let (nx, ny) = self.orientation.device.normalize(x, y);
+            let in_dead_zone = self
+                .config
+                .dead_zones
+                .iter()
+                .any(|zone| zone.contains(nx, ny));
+            if !in_dead_zone {
+                continue;
+            }
+
+            debug!(
+                "Ignoring contact {} in slot {}: starts in dead zone at ({:.2}, {:.2})",
+                contact.id, slot, nx, ny
+            );
+            self.ignored_slots.insert(slot, contact.id);
+            self.pending_contacts.remove(slot);
+            self.active_contact_count = self.active_contact_count.saturating_sub(1);
+
+            if self.active_contact_count == 0 && self.completed_contacts.is_empty() {
+                self.session = None;
+            } else if let Some(session) = self.session.as_mut() {
+                session.update_count(self.active_contact_count, now);
+            }
+        }
+        self.fresh_slots.clear();
+    }
+
     /// Handle synchronization events (process accumulated changes)
     async fn handle_sync_event(&mut self, event: InputEvent) -> Option<Vec<MultiTouchEvent>> {
         if event.code() != Synchronization::SYN_REPORT.0 {
             return None;
         }
         // Note: here we logic justing is based on the Track ID and Slot.
-        let now = Instant::now();
-        self.last_sync_time = now;
+        let now = self.event_time;
 
-        None
+        self.filter_dead_zone_contacts(now);
+        self.expire_stale_contacts(now);
+        let pointer_moved = self.pointer_motion != (0, 0);
+        self.update_pointer_speed(now);
+        if pointer_moved
+            && self.held_buttons.contains(&Key::BTN_LEFT)
+            && self.session.is_some()
+            && !self.session_dragged
+        {
+            debug!("Dragging with the left button, suppressing swipes for this session");
+            self.session_dragged = true;
+        }
+
+        self.check_tool_hint(now);
+
+        if self.pending_contacts.is_empty() {
+            for rest in &mut self.rests {
+                rest.update(&[], false, now);
+            }
+            return None;
+        }
+
+        // Let continuous recognizers (scrolling) look at the in-progress session
+        // Borrowed, so a scroll frame copies no contacts
+        let contacts: Vec<&TouchContact> = self.pending_contacts.values().collect();
+
+        let mut events = self.gesture_recognizer.analyze_frame(&contacts);
+        events.retain(|event| !self.is_suppressed(event));
+        let moved = pointer_moved || !self.held_buttons.is_empty();
+        for rest in &mut self.rests {
+            rest.update(&contacts, moved, now);
+        }
+        events.extend(expire_rests(&mut self.rests, &contacts, now));
+        if events.is_empty() {
+            None
+        } else {
+            Some(events)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
 
     fn create_test_config() -> GestureConfig {
         GestureConfig {
-            scroll_threshold: 2.0,      // 2mm
-            swipe_threshold: 12.0,      // 12mm
+            scroll_threshold: Distance::Scaled(2.0),
+            swipe_threshold: Distance::Scaled(12.0),
             pinch_threshold: 0.1,
-            tap_timeout_ms: 300,
+            tap_timeout_ms: Some(300),
             debounce_ms: 10,
-            two_finger_tap_timeout_ms: 250,
-            two_finger_tap_distance_threshold: 30.0,  // 30mm
+            two_finger_tap_timeout_ms: Some(250),
+            two_finger_tap_distance_threshold: Distance::Scaled(30.0),
             contact_pressure_threshold: 0.5,
-            single_finger_tap_movement_threshold: 2.0,  // 2mm
+            single_finger_tap_movement_threshold: Distance::Scaled(2.0),
+            finger_count_grace_ms: 50,
+            scroll_axis_lock: true,
+            left_handed: false,
+            rotation: Rotation::Upright,
+            mirror: false,
+            dead_zones: Vec::new(),
+            tap_max_pointer_speed: 400.0,
+            smooth_scroll_speed: 30.0,
+            natural_scroll: false,
+            dwell_click: false,
+            dwell_ms: 800,
+            dwell_radius: Distance::Scaled(1.5),
+            sticky_drag: false,
+            long_press_ms: 600,
+            stale_contact_ms: 0,
+            contact_archive_ms: 0,
+            tremor_filter: false,
+            tremor_smoothing: 0.25,
+            tremor_deadband: Distance::Scaled(1.0),
         }
     }
 
@@ -464,4 +1852,883 @@ mod tests {
         assert!(processor.completed_contacts.is_empty());
         assert!(processor.pending_contacts.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_archive_keeps_every_axis_change() {
+        let clock = ManualClock::new();
+        let config = GestureConfig {
+            contact_archive_ms: 1_000,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, 0))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1234))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 5))
+            .await;
+        let early = processor.active_contacts()[0].clone();
+        // Jitter in place, more reports than the position history keeps
+        for _ in 0..=HISTORY_CAPACITY {
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 3))
+                .await;
+        }
+        // Past the archive window
+        clock.advance(Duration::from_millis(1_500));
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 9))
+            .await;
+
+        // A copy keeps seeing what was archived when it was made
+        assert_eq!(early.archive.to_vec().len(), 1);
+
+        // The archive holds every axis change in the window, untrimmed and raw
+        let finger = processor.active_contacts()[0];
+        assert_eq!(finger.position_history.len(), HISTORY_CAPACITY);
+        let archive = finger.archive.to_vec();
+        assert_eq!(archive.len(), HISTORY_CAPACITY + 2);
+        assert_eq!((archive[0].axis, archive[0].value), (ContactAxis::X, 5));
+        let last = archive.last().unwrap();
+        assert_eq!((last.axis, last.value), (ContactAxis::Y, 3));
+
+        // Archiving is off by default
+        let mut processor = MultiTouchProcessor::new(create_test_config());
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 5))
+            .await;
+        assert!(processor.active_contacts()[0].archive.to_vec().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tap_timeout_on_manual_clock() {
+        let clock = ManualClock::new();
+        let mut processor =
+            MultiTouchProcessor::new(create_test_config()).with_clock(Arc::new(clock.clone()));
+        let tracking_id = |id: i32| {
+            InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_MT_TRACKING_ID.0,
+                id,
+            )
+        };
+
+        // Held past the 300ms tap timeout
+        processor.process_event(tracking_id(1)).await;
+        clock.advance(Duration::from_millis(400));
+        assert!(processor.process_event(tracking_id(-1)).await.is_none());
+
+        clock.advance(Duration::from_secs(1));
+        processor.process_event(tracking_id(2)).await;
+        clock.advance(Duration::from_millis(120));
+        let events = processor.process_event(tracking_id(-1)).await.unwrap();
+        assert!(matches!(
+            events[..],
+            [MultiTouchEvent::SingleFingerTap {
+                duration_ms: 120,
+                ..
+            }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dwell_click_and_long_press_on_resting_finger() {
+        let clock = ManualClock::new();
+        let config = GestureConfig {
+            dwell_click: true,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let sync = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1))
+            .await;
+        processor.process_event(sync).await;
+        clock.advance(Duration::from_millis(500));
+        assert!(processor.poll().is_none());
+
+        // Due without any further events, and only once per rest
+        clock.advance(Duration::from_millis(300));
+        let events = processor.poll().unwrap();
+        assert!(matches!(
+            events[..],
+            [MultiTouchEvent::Dwell {
+                duration_ms: 800,
+                ..
+            }]
+        ));
+        assert!(processor.deadline().is_none());
+
+        // Moving on and resting again clicks again, lifting cancels
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 2000))
+            .await;
+        processor.process_event(sync).await;
+        assert!(processor.deadline().is_some());
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+            .await;
+        processor.process_event(sync).await;
+        assert!(processor.deadline().is_none());
+
+        // With sticky drag as well the shorter long press takes the rest
+        let config = GestureConfig {
+            dwell_click: true,
+            sticky_drag: true,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 2))
+            .await;
+        processor.process_event(sync).await;
+        clock.advance(Duration::from_millis(600));
+        let events = processor.poll().unwrap();
+        assert!(matches!(
+            events[..],
+            [MultiTouchEvent::LongPress {
+                duration_ms: 600,
+                ..
+            }]
+        ));
+        clock.advance(Duration::from_millis(200));
+        assert!(processor.poll().is_none());
+        assert!(processor.deadline().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_debounce_keeps_contact_lifecycle() {
+        async fn tap(
+            processor: &mut MultiTouchProcessor,
+            clock: &ManualClock,
+            id: i32,
+        ) -> Option<Vec<MultiTouchEvent>> {
+            let tracking_id = |id: i32| {
+                InputEvent::new(
+                    EventType::ABSOLUTE,
+                    AbsoluteAxisType::ABS_MT_TRACKING_ID.0,
+                    id,
+                )
+            };
+            processor.process_event(tracking_id(id)).await;
+            clock.advance(Duration::from_millis(50));
+            let events = processor.process_event(tracking_id(-1)).await;
+            assert_eq!(processor.active_contact_count, 0);
+            assert!(processor.pending_contacts.is_empty());
+            assert!(processor.completed_contacts.is_empty());
+            events
+        }
+
+        let clock = ManualClock::new();
+        let mut config = create_test_config();
+        config.debounce_ms = 200;
+        let mut processor = MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+
+        assert!(tap(&mut processor, &clock, 1).await.is_some());
+        // Within the debounce window only the gesture is dropped; the contact
+        // still ended
+        clock.advance(Duration::from_millis(20));
+        assert!(tap(&mut processor, &clock, 2).await.is_none());
+        clock.advance(Duration::from_millis(200));
+        assert!(tap(&mut processor, &clock, 3).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dead_zone_contact_is_ignored() {
+        let mut config = create_test_config();
+        config.dead_zones.push(crate::config::DeadZone {
+            x_min: 0.0,
+            y_min: 0.8,
+            x_max: 1.0,
+            y_max: 1.0,
+        });
+        let mut processor = MultiTouchProcessor::new(config);
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+
+        // Palm rests on the bottom edge of the surface
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, 0))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 7))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 0))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 1900))
+            .await;
+        processor.process_event(syn).await;
+
+        assert_eq!(processor.active_contact_count, 0);
+        assert!(processor.pending_contacts.is_empty());
+
+        // Lifting the palm must not produce a tap
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 1910))
+            .await;
+        let events = processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+            .await;
+        assert!(events.is_none());
+        assert!(processor.ignored_slots.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dead_zone_ignores_handedness() {
+        // A zone on the device's left edge, where a left hand's thumb rests
+        let config = GestureConfig {
+            left_handed: true,
+            dead_zones: vec![crate::config::DeadZone {
+                x_min: 0.0,
+                y_min: 0.0,
+                x_max: 0.2,
+                y_max: 1.0,
+            }],
+            ..create_test_config()
+        };
+        let surface = SurfaceBounds::default();
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+        let touch = |x: i32| {
+            [
+                abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+                abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1),
+                abs(AbsoluteAxisType::ABS_MT_POSITION_X, x),
+                abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 0),
+                syn,
+            ]
+        };
+
+        // Mirrored, the thumb is on the right of the held surface but still ignored
+        let mut processor = MultiTouchProcessor::new(config.clone());
+        for event in touch(surface.x.min) {
+            processor.process_event(event).await;
+        }
+        assert_eq!(processor.active_contact_count, 0);
+
+        // A finger on the device's right edge is tracked, though held on the left
+        let mut processor = MultiTouchProcessor::new(config);
+        for event in touch(surface.x.max) {
+            processor.process_event(event).await;
+        }
+        assert_eq!(processor.active_contact_count, 1);
+        assert_eq!(processor.active_contacts()[0].normalized_x, 0.0);
+    }
+
+    #[test]
+    fn test_orientation_device_position_round_trips() {
+        let device = SurfaceBounds::default();
+        for rotation in [
+            Rotation::Upright,
+            Rotation::Clockwise,
+            Rotation::UpsideDown,
+            Rotation::Counterclockwise,
+        ] {
+            for mirror in [false, true] {
+                let orientation = Orientation::new(rotation, mirror, device);
+                for (x, y) in [(device.x.min, device.y.max), (300, -200)] {
+                    let (hx, hy) = orientation.position(x, y);
+                    assert_eq!(orientation.device_position(hx, hy), (x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_clock_uses_kernel_spacing() {
+        let mut clock = EventClock::default();
+        let down = SystemTime::now();
+        let up = down + Duration::from_millis(400);
+
+        // Both events are processed back to back, but were recorded 400ms apart
+        let now = Instant::now();
+        let first = clock.instant_for(down, now);
+        let second = clock.instant_for(up, now);
+        assert_eq!(second.duration_since(first), Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_syn_dropped_resyncs_contacts() {
+        let mut processor = MultiTouchProcessor::new(create_test_config());
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let syn = |code: Synchronization| InputEvent::new(EventType::SYNCHRONIZATION, code.0, 0);
+
+        // Two fingers down
+        for (slot, id) in [(0, 10), (1, 11)] {
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                .await;
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, id))
+                .await;
+        }
+        processor
+            .process_event(syn(Synchronization::SYN_REPORT))
+            .await;
+        assert_eq!(processor.active_contact_count, 2);
+
+        // The buffer overflows while one finger lifts; the partial frame is discarded
+        processor
+            .process_event(syn(Synchronization::SYN_DROPPED))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+            .await;
+        processor
+            .process_event(syn(Synchronization::SYN_REPORT))
+            .await;
+        assert!(processor.needs_resync());
+        assert_eq!(processor.active_contact_count, 2);
+
+        // The kernel reports only slot 0 still in use
+        let mut slots = vec![MtSlotState::empty(0), MtSlotState::empty(1)];
+        slots[0].tracking_id = 10;
+        processor.resync(&slots, 1);
+
+        assert!(!processor.needs_resync());
+        assert_eq!(processor.active_contact_count, 1);
+        assert!(processor.pending_contacts.get(0).is_some());
+        assert!(processor.pending_contacts.get(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_physical_click_suppresses_tap() {
+        let mut processor = MultiTouchProcessor::new(create_test_config());
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let button = |value: i32| InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), value);
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+
+        // Finger rests on the shell and presses it down
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, 0))
+            .await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 21))
+            .await;
+        processor.process_event(syn).await;
+        processor.process_event(button(1)).await;
+        processor.process_event(syn).await;
+        processor.process_event(button(0)).await;
+        processor.process_event(syn).await;
+
+        // Lifting the finger looks like a tap, but it was a physical click
+        let events = processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+            .await;
+        assert!(events.is_none());
+        assert!(processor.modifiers().clicked);
+
+        // The next session starts without the click
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 22))
+            .await;
+        assert!(!processor.modifiers().clicked);
+    }
+
+    #[tokio::test]
+    async fn test_click_drag_suppresses_swipe() {
+        async fn swipe(drag: bool) -> (MultiTouchProcessor, Option<MultiTouchEvent>) {
+            let clock = ManualClock::new();
+            let mut processor =
+                MultiTouchProcessor::new(create_test_config()).with_clock(Arc::new(clock.clone()));
+            let abs = |axis: AbsoluteAxisType, value: i32| {
+                InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+            };
+            let button = |value: i32| InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), value);
+            let rel = InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, 3);
+            let syn = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+
+            processor.process_event(button(1)).await;
+            for x in [100, 300, 500, 700] {
+                for slot in 0..2 {
+                    processor
+                        .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                        .await;
+                    if x == 100 {
+                        processor
+                            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, slot + 1))
+                            .await;
+                    }
+                    processor
+                        .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, x))
+                        .await;
+                    processor
+                        .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 100 + slot * 300))
+                        .await;
+                }
+                if drag {
+                    processor.process_event(rel).await;
+                }
+                processor.process_event(syn).await;
+                // Slow enough not to be taken for a tap
+                clock.advance(Duration::from_millis(100));
+            }
+            processor.process_event(button(0)).await;
+            processor.process_event(syn).await;
+
+            let mut events = None;
+            for slot in 0..2 {
+                processor
+                    .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                    .await;
+                events = processor
+                    .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+                    .await;
+            }
+            let event = events.and_then(|events| events.into_iter().next());
+            (processor, event)
+        }
+
+        // Swiping while holding the button down still makes a click+ gesture
+        let (_, event) = swipe(false).await;
+        assert!(matches!(
+            event,
+            Some(MultiTouchEvent::TwoFingerSwipe { .. })
+        ));
+
+        // Moving the mouse at the same time is a drag
+        let (processor, event) = swipe(true).await;
+        assert!(event.is_none());
+        assert!(processor.modifiers().clicked);
+    }
+
+    #[tokio::test]
+    async fn test_left_handed_swipe_is_mirrored() {
+        async fn swipe(left_handed: bool) -> Option<MultiTouchEvent> {
+            let clock = ManualClock::new();
+            let config = GestureConfig {
+                left_handed,
+                ..create_test_config()
+            };
+            let mut processor =
+                MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+            let abs = |axis: AbsoluteAxisType, value: i32| {
+                InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+            };
+            let syn = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+
+            // Two fingers moving towards the device's right edge
+            for x in [-600, -200, 200, 600] {
+                for slot in 0..2 {
+                    processor
+                        .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                        .await;
+                    if x == -600 {
+                        processor
+                            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, slot + 1))
+                            .await;
+                    }
+                    processor
+                        .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, x))
+                        .await;
+                    processor
+                        .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 100 + slot * 300))
+                        .await;
+                }
+                processor.process_event(syn).await;
+                clock.advance(Duration::from_millis(100));
+            }
+
+            let mut events = None;
+            for slot in 0..2 {
+                processor
+                    .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                    .await;
+                events = processor
+                    .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+                    .await;
+            }
+            events.and_then(|events| events.into_iter().next())
+        }
+
+        let Some(MultiTouchEvent::TwoFingerSwipe { delta_x, .. }) = swipe(false).await else {
+            panic!("expected a two finger swipe");
+        };
+        assert!(delta_x > 0.0);
+
+        // Held in the left hand the same motion is a swipe to the left
+        let Some(MultiTouchEvent::TwoFingerSwipe { delta_x, .. }) = swipe(true).await else {
+            panic!("expected a two finger swipe");
+        };
+        assert!(delta_x < 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_slots_beyond_capacity_ignored() {
+        let mut processor = MultiTouchProcessor::new(create_test_config());
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let sync = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+        for (slot, id) in [(MAX_SLOTS as i32 - 1, 5), (MAX_SLOTS as i32, 6), (-1, 7)] {
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                .await;
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, id))
+                .await;
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 100))
+                .await;
+        }
+        processor.process_event(sync).await;
+
+        let contacts = processor.active_contacts();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(
+            (contacts[0].slot, contacts[0].id),
+            (MAX_SLOTS as i32 - 1, 5)
+        );
+        assert_eq!(processor.active_contact_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tracking_id_reuse_ends_previous_contact() {
+        let config = GestureConfig {
+            contact_archive_ms: 1_000,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config);
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let sync = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1))
+            .await;
+        processor.process_event(sync).await;
+
+        // The slot switches to a new finger with no -1 in between: the first
+        // finger's tap is recognized and the new one tracked on its own
+        let events = processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 2))
+            .await
+            .unwrap();
+        match &events[..] {
+            [MultiTouchEvent::SingleFingerTap { finger, .. }] => assert_eq!(finger.id, 1),
+            other => panic!("Expected the first finger's tap, got {:?}", other),
+        }
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 100))
+            .await;
+        processor.process_event(sync).await;
+
+        let contacts = processor.active_contacts();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!((contacts[0].id, contacts[0].x), (2, 100));
+        assert_eq!(contacts[0].archive.to_vec().len(), 1);
+        assert_eq!(processor.active_contact_count, 1);
+        assert!(processor.session.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_stale_contact_expires() {
+        let clock = ManualClock::new();
+        let config = GestureConfig {
+            stale_contact_ms: 10_000,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let sync = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+        for (slot, id) in [(0, 1), (1, 2)] {
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                .await;
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, id))
+                .await;
+        }
+        processor.process_event(sync).await;
+
+        // Only the finger in slot 1 keeps reporting; slot 0 missed its release
+        clock.advance(Duration::from_millis(6_000));
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 100))
+            .await;
+        processor.process_event(sync).await;
+        clock.advance(Duration::from_millis(4_000));
+        assert_eq!(processor.deadline(), Some(clock.now()));
+        assert!(processor.poll().is_none());
+        let contacts = processor.active_contacts();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].id, 2);
+        assert_eq!(processor.active_contact_count, 1);
+
+        // Once the last one goes too, the session is abandoned
+        clock.advance(Duration::from_millis(6_000));
+        processor.process_event(sync).await;
+        assert!(processor.active_contacts().is_empty());
+        assert_eq!(processor.active_contact_count, 0);
+        assert!(processor.session.is_none());
+        assert!(processor.deadline().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resting_finger_survives_stale_timeout() {
+        let clock = ManualClock::new();
+        let config = GestureConfig {
+            stale_contact_ms: 1_000,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config).with_clock(Arc::new(clock.clone()));
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let sync = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+        for event in [
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 5),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, 100),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 200),
+            sync,
+        ] {
+            processor.process_event(event).await;
+        }
+
+        // Resting perfectly still, the finger reports nothing and times out
+        clock.advance(Duration::from_millis(1_500));
+        assert!(processor.poll().is_none());
+        assert!(processor.active_contacts().is_empty());
+
+        // Once it moves, it is tracked again where it rested
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 150))
+            .await;
+        processor.process_event(sync).await;
+        let contacts = processor.active_contacts();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(
+            (contacts[0].id, contacts[0].x, contacts[0].y),
+            (5, 150, 200)
+        );
+        assert_eq!(processor.active_contact_count, 1);
+        assert!(processor.session.is_some());
+
+        // And its release ends it like any other contact
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1))
+            .await;
+        assert!(processor.active_contacts().is_empty());
+        assert_eq!(processor.active_contact_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_tool_hint_drops_missed_release() {
+        let clock = ManualClock::new();
+        let mut processor =
+            MultiTouchProcessor::new(create_test_config()).with_clock(Arc::new(clock.clone()));
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let key = |key: Key, value| InputEvent::new(EventType::KEY, key.code(), value);
+        let sync = InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+        for (slot, id) in [(0, 1), (1, 2)] {
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_SLOT, slot))
+                .await;
+            processor
+                .process_event(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, id))
+                .await;
+        }
+        processor
+            .process_event(key(Key::BTN_TOOL_DOUBLETAP, 1))
+            .await;
+        processor.process_event(sync).await;
+        assert!(processor.deadline().is_none());
+
+        // Slot 1 keeps reporting, but the release of slot 0 got lost
+        clock.advance(Duration::from_millis(20));
+        processor
+            .process_event(key(Key::BTN_TOOL_DOUBLETAP, 0))
+            .await;
+        processor.process_event(key(Key::BTN_TOOL_FINGER, 1)).await;
+        processor
+            .process_event(abs(AbsoluteAxisType::ABS_MT_POSITION_X, 100))
+            .await;
+        processor.process_event(sync).await;
+        assert_eq!(processor.active_contacts().len(), 2);
+
+        clock.advance(TOOL_HINT_DRIFT);
+        assert_eq!(processor.deadline(), Some(clock.now()));
+        assert!(processor.poll().is_none());
+        let contacts = processor.active_contacts();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].id, 2);
+        assert_eq!(processor.active_contact_count, 1);
+
+        // The last finger's release is lost too; the hint going to zero drops it
+        clock.advance(Duration::from_millis(20));
+        processor.process_event(key(Key::BTN_TOOL_FINGER, 0)).await;
+        processor.process_event(sync).await;
+        clock.advance(TOOL_HINT_DRIFT);
+        processor.process_event(sync).await;
+        assert!(processor.active_contacts().is_empty());
+        assert!(processor.session.is_none());
+    }
+
+    #[test]
+    fn test_position_history_bounds() {
+        let started = Instant::now();
+        let mut finger = TouchContact::new(1, 0, SurfaceBounds::default(), started);
+        finger.update_position(10, 0, started);
+        finger.update_position(10, 20, started);
+        let at = |ms| started + Duration::from_millis(ms);
+
+        // Capped at the capacity, with the origin kept in front
+        for i in 0..500 {
+            finger.update_position(10 + i, 20, at(i as u64));
+        }
+        assert_eq!(finger.position_history.len(), HISTORY_CAPACITY);
+        assert_eq!(finger.position_history[2].0, 10);
+        assert_eq!(finger.position_history.back().unwrap().0, 509);
+        assert_eq!(
+            finger.movement_delta().0,
+            SurfaceBounds::default().x.units_to_mm(499)
+        );
+
+        // Samples older than the maximum age go, the origin stays
+        finger.update_position(900, 20, at(10_000));
+        assert_eq!(finger.position_history.len(), HISTORY_ORIGIN + 1);
+        assert_eq!(finger.position_history[2], (10, 20, started));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_and_mirror() {
+        let surface = SurfaceBounds::default();
+        let config = GestureConfig {
+            rotation: Rotation::Clockwise,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config);
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+
+        // Turned clockwise, the device's top right corner is at the bottom right
+        for event in [
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, surface.x.max),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, surface.y.min),
+        ] {
+            processor.process_event(event).await;
+        }
+        let contact = processor.active_contacts()[0];
+        assert_eq!((contact.x, contact.y), (surface.y.max, surface.x.max));
+        assert_eq!((contact.normalized_x, contact.normalized_y), (1.0, 1.0));
+        assert_eq!(contact.surface, surface.rotated(Rotation::Clockwise));
+
+        // Mirrored as well, it ends up at the bottom left
+        let config = GestureConfig {
+            rotation: Rotation::Clockwise,
+            mirror: true,
+            ..create_test_config()
+        };
+        let mut processor = MultiTouchProcessor::new(config);
+        let slot = MtSlotState {
+            tracking_id: 1,
+            x: surface.x.max,
+            y: surface.y.min,
+            ..MtSlotState::empty(0)
+        };
+        processor.seed(&[slot], 0);
+        let contact = processor.active_contacts()[0];
+        assert_eq!((contact.normalized_x, contact.normalized_y), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_normalized_position() {
+        let device = DeviceInfo::new("Magic Trackpad", SurfaceBounds::default());
+        let surface = device.surface;
+        let processor = MultiTouchProcessor::new(create_test_config()).with_device(device);
+        assert_eq!(processor.device_info().name, "Magic Trackpad");
+
+        let mut finger = TouchContact::new(1, 0, surface, Instant::now());
+        finger.update_position(surface.x.min, surface.y.max, Instant::now());
+        assert_eq!((finger.normalized_x, finger.normalized_y), (0.0, 1.0));
+        let middle_x = (surface.x.min + surface.x.max) / 2;
+        finger.update_position(middle_x, surface.y.max + 100, Instant::now());
+        assert!((finger.normalized_x - 0.5).abs() < 0.001);
+        assert_eq!(finger.normalized_y, 1.0);
+    }
+
+    #[test]
+    fn test_motion_is_incremental() {
+        let started = Instant::now();
+        let at = |ms| started + Duration::from_millis(ms);
+        let mut finger = TouchContact::new(1, 0, SurfaceBounds::default(), started);
+        finger.update_position(0, 0, started);
+        assert_eq!(finger.origin_mm(), None);
+        finger.update_position(0, 700, started);
+        assert_eq!(finger.velocity_mm(), (0.0, 0.0));
+
+        // 26 units (1mm) every 10ms to the right
+        finger.update_position(26, 700, at(10));
+        finger.update_position(52, 700, at(20));
+        let (vx, vy) = finger.velocity_mm();
+        assert!((vx - 100.0).abs() < 1e-9, "{}", vx);
+        assert_eq!(vy, 0.0);
+        for i in 3..=50 {
+            finger.update_position(26 * i, 700, at(10 * i as u64));
+        }
+        let (vx, vy) = finger.velocity_mm();
+        assert!((vx - 100.0).abs() < 1e-9, "{}", vx);
+        assert_eq!(vy, 0.0);
+        assert_eq!(finger.origin_mm(), Some((0.0, 10.0)));
+        assert_eq!(finger.movement_delta(), (50.0, 0.0));
+    }
+
+    #[test]
+    fn test_event_serde_round_trip() {
+        let started = Instant::now() - Duration::from_millis(120);
+        let mut finger = TouchContact::new(7, 1, SurfaceBounds::default(), started);
+        finger.x = -210;
+        finger.y = 340;
+        finger
+            .position_history
+            .push_back((-210, 340, started + Duration::from_millis(80)));
+        let event = MultiTouchEvent::SingleFingerTap {
+            finger: Arc::new(finger),
+            duration_ms: 120,
+        };
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "single_finger_tap");
+        assert_eq!(json["type"], event.kind());
+        assert_eq!(json["finger"]["x"], -210);
+
+        let MultiTouchEvent::SingleFingerTap { finger, .. } = serde_json::from_value(json).unwrap()
+        else {
+            panic!("wrong variant");
+        };
+        assert_eq!((finger.id, finger.x, finger.y), (7, -210, 340));
+        assert_eq!(finger.surface, SurfaceBounds::default());
+        // Times come back relative to now, keeping their spacing
+        let age = finger.first_contact_time.elapsed().as_millis();
+        assert!((120..200).contains(&age), "age {}ms", age);
+        let (_, _, moved) = finger.position_history[1];
+        let spacing = moved.duration_since(finger.first_contact_time).as_millis();
+        assert!((79..=81).contains(&spacing), "spacing {}ms", spacing);
+    }
 }
@@ -0,0 +1,43 @@
+use log::warn;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use zbus::zvariant::Value;
+
+use crate::dbus::{self, MethodCall};
+
+/// Id of the last notification, so a new gesture replaces it instead of
+/// stacking up
+static LAST_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Show a desktop notification through `org.freedesktop.Notifications`
+///
+/// Runs in the background; failures are only logged.
+pub fn show(summary: String, body: String, timeout_ms: i32) {
+    tokio::spawn(async move {
+        let call = MethodCall::new(
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Notify",
+        )
+        .with_args(vec![
+            Value::from("mouse-gesture"),
+            Value::from(LAST_ID.load(Ordering::Relaxed)),
+            Value::from("input-mouse"),
+            Value::from(summary),
+            Value::from(body),
+            Value::from(Vec::<String>::new()),
+            Value::from(HashMap::<String, Value>::new()),
+            Value::from(timeout_ms),
+        ]);
+
+        match dbus::call(call).await {
+            Ok(reply) => {
+                if let Ok(id) = reply.body().deserialize::<u32>() {
+                    LAST_ID.store(id, Ordering::Relaxed);
+                }
+            }
+            Err(e) => warn!("Failed to show notification: {:#}", e),
+        }
+    });
+}
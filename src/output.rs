@@ -0,0 +1,790 @@
+use anyhow::{bail, Context, Result};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, EventType, InputEvent, Key, RelativeAxisType};
+use log::{debug, info, trace, warn};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::process::Command;
+use x11rb::protocol::xproto;
+
+use crate::config::OutputBackend;
+use crate::macros::{self, MacroStep};
+use crate::session::{self, SessionType};
+use crate::x11;
+
+/// Virtual keyboard/mouse shared by all event handlers, created on first use;
+/// `None` if uinput isn't available
+static UINPUT_DEVICE: OnceLock<Option<Mutex<VirtualDevice>>> = OnceLock::new();
+
+/// Mouse buttons held down with `set_button`, one bit per X11 button number;
+/// shared like the virtual device, since any handler may release them
+static HELD_BUTTONS: AtomicU8 = AtomicU8::new(0);
+
+/// High-resolution wheel units making up one wheel detent
+const HI_RES_PER_DETENT: i32 = 120;
+
+/// Mouse buttons as numbered by X11 (and xdotool)
+const BUTTONS: [(u8, Key); 3] = [
+    (1, Key::BTN_LEFT),
+    (2, Key::BTN_MIDDLE),
+    (3, Key::BTN_RIGHT),
+];
+
+/// xdotool-style key names and the keys they map to
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("ctrl", Key::KEY_LEFTCTRL),
+    ("control", Key::KEY_LEFTCTRL),
+    ("shift", Key::KEY_LEFTSHIFT),
+    ("alt", Key::KEY_LEFTALT),
+    ("super", Key::KEY_LEFTMETA),
+    ("meta", Key::KEY_LEFTMETA),
+    ("Left", Key::KEY_LEFT),
+    ("Right", Key::KEY_RIGHT),
+    ("Up", Key::KEY_UP),
+    ("Down", Key::KEY_DOWN),
+    ("Home", Key::KEY_HOME),
+    ("End", Key::KEY_END),
+    ("Page_Up", Key::KEY_PAGEUP),
+    ("Prior", Key::KEY_PAGEUP),
+    ("Page_Down", Key::KEY_PAGEDOWN),
+    ("Next", Key::KEY_PAGEDOWN),
+    ("Tab", Key::KEY_TAB),
+    ("Return", Key::KEY_ENTER),
+    ("Escape", Key::KEY_ESC),
+    ("space", Key::KEY_SPACE),
+    ("BackSpace", Key::KEY_BACKSPACE),
+    ("Delete", Key::KEY_DELETE),
+    ("Insert", Key::KEY_INSERT),
+    ("minus", Key::KEY_MINUS),
+    ("plus", Key::KEY_KPPLUS),
+    ("equal", Key::KEY_EQUAL),
+    ("comma", Key::KEY_COMMA),
+    ("period", Key::KEY_DOT),
+    ("slash", Key::KEY_SLASH),
+    ("XF86AudioMute", Key::KEY_MUTE),
+    ("XF86AudioLowerVolume", Key::KEY_VOLUMEDOWN),
+    ("XF86AudioRaiseVolume", Key::KEY_VOLUMEUP),
+    ("XF86AudioPlay", Key::KEY_PLAYPAUSE),
+    ("XF86AudioNext", Key::KEY_NEXTSONG),
+    ("XF86AudioPrev", Key::KEY_PREVIOUSSONG),
+];
+
+const LETTER_KEYS: [Key; 26] = [
+    Key::KEY_A,
+    Key::KEY_B,
+    Key::KEY_C,
+    Key::KEY_D,
+    Key::KEY_E,
+    Key::KEY_F,
+    Key::KEY_G,
+    Key::KEY_H,
+    Key::KEY_I,
+    Key::KEY_J,
+    Key::KEY_K,
+    Key::KEY_L,
+    Key::KEY_M,
+    Key::KEY_N,
+    Key::KEY_O,
+    Key::KEY_P,
+    Key::KEY_Q,
+    Key::KEY_R,
+    Key::KEY_S,
+    Key::KEY_T,
+    Key::KEY_U,
+    Key::KEY_V,
+    Key::KEY_W,
+    Key::KEY_X,
+    Key::KEY_Y,
+    Key::KEY_Z,
+];
+
+const DIGIT_KEYS: [Key; 10] = [
+    Key::KEY_0,
+    Key::KEY_1,
+    Key::KEY_2,
+    Key::KEY_3,
+    Key::KEY_4,
+    Key::KEY_5,
+    Key::KEY_6,
+    Key::KEY_7,
+    Key::KEY_8,
+    Key::KEY_9,
+];
+
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::KEY_F1,
+    Key::KEY_F2,
+    Key::KEY_F3,
+    Key::KEY_F4,
+    Key::KEY_F5,
+    Key::KEY_F6,
+    Key::KEY_F7,
+    Key::KEY_F8,
+    Key::KEY_F9,
+    Key::KEY_F10,
+    Key::KEY_F11,
+    Key::KEY_F12,
+];
+
+/// Buttons currently held down with `Output::set_button`
+pub fn held_buttons() -> Vec<u8> {
+    let held = HELD_BUTTONS.load(Ordering::Relaxed);
+    BUTTONS
+        .iter()
+        .map(|&(button, _)| button)
+        .filter(|button| held & button_bit(*button) != 0)
+        .collect()
+}
+
+fn button_bit(button: u8) -> u8 {
+    1 << button
+}
+
+/// The key for an X11 button number
+fn button_key(button: u8) -> Result<Key> {
+    BUTTONS
+        .iter()
+        .find(|(number, _)| *number == button)
+        .map(|&(_, key)| key)
+        .with_context(|| format!("Unsupported mouse button: {}", button))
+}
+
+/// Map a button name (`left`, `middle`, `right`) or X11 button number to the
+/// X11 number
+pub fn parse_button(name: &str) -> Option<u8> {
+    match name {
+        "left" => Some(1),
+        "middle" => Some(2),
+        "right" => Some(3),
+        number => number
+            .parse()
+            .ok()
+            .filter(|number| BUTTONS.iter().any(|(button, _)| button == number)),
+    }
+}
+
+/// Parse a `<direction>[:<steps>]` scroll spec such as `up` or `left:3` into
+/// (horizontal, vertical) wheel steps
+pub fn parse_scroll(spec: &str) -> Option<(i32, i32)> {
+    let (direction, steps) = match spec.split_once(':') {
+        Some((direction, steps)) => (direction, steps.parse::<i32>().ok()?),
+        None => (spec, 1),
+    };
+
+    match direction {
+        "up" => Some((0, steps)),
+        "down" => Some((0, -steps)),
+        "left" => Some((-steps, 0)),
+        "right" => Some((steps, 0)),
+        _ => None,
+    }
+}
+
+/// Map an xdotool-style key name (`ctrl`, `Right`, `t`, `F5`, ...) to a key
+pub fn parse_key(name: &str) -> Option<Key> {
+    if let Some((_, key)) = KEY_NAMES.iter().find(|(key_name, _)| *key_name == name) {
+        return Some(*key);
+    }
+
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphabetic() {
+            return Some(LETTER_KEYS[(c.to_ascii_lowercase() as u8 - b'a') as usize]);
+        }
+        if c.is_ascii_digit() {
+            return Some(DIGIT_KEYS[(c as u8 - b'0') as usize]);
+        }
+    }
+
+    let function = name.strip_prefix('F')?.parse::<usize>().ok()?;
+    FUNCTION_KEYS.get(function.checked_sub(1)?).copied()
+}
+
+/// Create the uinput output device now, while `/dev/uinput` can still be
+/// opened, if `backend` may use it
+pub fn open_ahead(backend: OutputBackend) {
+    if matches!(backend, OutputBackend::Auto | OutputBackend::Uinput) {
+        uinput_device();
+    }
+}
+
+fn uinput_device() -> Option<&'static Mutex<VirtualDevice>> {
+    UINPUT_DEVICE
+        .get_or_init(|| match create_uinput_device() {
+            Ok(device) => {
+                info!("Created uinput output device");
+                Some(Mutex::new(device))
+            }
+            Err(e) => {
+                warn!("uinput output unavailable: {:#}", e);
+                None
+            }
+        })
+        .as_ref()
+}
+
+fn create_uinput_device() -> Result<VirtualDevice> {
+    let mut keys = AttributeSet::<Key>::new();
+    let named_keys = BUTTONS.iter().map(|(_, key)| key);
+    let named_keys = named_keys.chain(KEY_NAMES.iter().map(|(_, key)| key));
+    for key in named_keys
+        .chain(&LETTER_KEYS)
+        .chain(&DIGIT_KEYS)
+        .chain(&FUNCTION_KEYS)
+    {
+        keys.insert(*key);
+    }
+
+    // Relative axes make the device a pointer, so button presses are accepted
+    let mut axes = AttributeSet::<RelativeAxisType>::new();
+    axes.insert(RelativeAxisType::REL_X);
+    axes.insert(RelativeAxisType::REL_Y);
+    axes.insert(RelativeAxisType::REL_WHEEL);
+    axes.insert(RelativeAxisType::REL_HWHEEL);
+    axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+    axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+
+    Ok(VirtualDeviceBuilder::new()
+        .context("Failed to open /dev/uinput")?
+        .name("Mouse Gesture Output")
+        .with_keys(&keys)?
+        .with_relative_axes(&axes)?
+        .build()?)
+}
+
+/// Whether an executable is on `PATH`
+pub fn command_exists(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(name).is_file()))
+}
+
+/// Concrete way of injecting input
+#[derive(Clone, Copy)]
+enum Injector {
+    Uinput(&'static Mutex<VirtualDevice>),
+    Xtest,
+    Xdotool,
+    Ydotool,
+    Wtype,
+    /// Injects nothing, so tests can run actions
+    #[cfg(test)]
+    Discard,
+}
+
+impl Injector {
+    fn name(&self) -> &'static str {
+        match self {
+            Injector::Uinput(_) => "uinput",
+            Injector::Xtest => "XTEST",
+            Injector::Xdotool => "xdotool",
+            Injector::Ydotool => "ydotool",
+            Injector::Wtype => "wtype",
+            #[cfg(test)]
+            Injector::Discard => "nothing",
+        }
+    }
+}
+
+/// Injector used by the `auto` backend and the session it was chosen for;
+/// chosen again when the session changes
+static AUTO_INJECTOR: Mutex<Option<(SessionType, Injector)>> = Mutex::new(None);
+
+fn auto_injector() -> Injector {
+    if let Some(device) = uinput_device() {
+        return Injector::Uinput(device);
+    }
+
+    let session = session::current();
+    let mut chosen = AUTO_INJECTOR.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((chosen_for, injector)) = *chosen {
+        if chosen_for == session {
+            return injector;
+        }
+    }
+
+    // X11 tools do nothing on Wayland or a console, so fall back to a tool
+    // that works there; on X11 inject in-process before spawning xdotool
+    let injector = match session {
+        SessionType::Wayland | SessionType::Tty if command_exists("ydotool") => Injector::Ydotool,
+        SessionType::Wayland if command_exists("wtype") => Injector::Wtype,
+        SessionType::Wayland | SessionType::Tty => Injector::Xdotool,
+        _ if x11::is_available() => Injector::Xtest,
+        _ => Injector::Xdotool,
+    };
+    match (session, injector) {
+        (SessionType::Wayland | SessionType::Tty, Injector::Xdotool) => warn!(
+            "Using xdotool for output, which can't reach a {} session: install ydotool \
+             or give the daemon write access to /dev/uinput",
+            session
+        ),
+        _ => info!("Using {} for output ({} session)", injector.name(), session),
+    }
+    *chosen = Some((session, injector));
+    injector
+}
+
+/// Injects clicks, key combos and scroll steps
+pub struct Output {
+    backend: OutputBackend,
+    smooth_scroll: Mutex<SmoothScroll>,
+    /// Input a discarding output would have injected
+    #[cfg(test)]
+    discarded: Option<Mutex<Vec<MacroStep>>>,
+}
+
+/// Smooth scroll travel not yet emitted, per axis (horizontal, vertical)
+#[derive(Default)]
+struct SmoothScroll {
+    /// Fractions of a high-resolution unit
+    fraction: (f64, f64),
+    /// High-resolution units short of a full detent
+    partial_detent: (i32, i32),
+}
+
+impl SmoothScroll {
+    /// Add travel in high-resolution units; returns the whole units and
+    /// detents that are now due
+    fn accumulate(&mut self, horizontal: f64, vertical: f64) -> ((i32, i32), (i32, i32)) {
+        let (hi_res_x, detents_x) =
+            accumulate_axis(&mut self.fraction.0, &mut self.partial_detent.0, horizontal);
+        let (hi_res_y, detents_y) =
+            accumulate_axis(&mut self.fraction.1, &mut self.partial_detent.1, vertical);
+        ((hi_res_x, hi_res_y), (detents_x, detents_y))
+    }
+}
+
+fn accumulate_axis(fraction: &mut f64, partial_detent: &mut i32, amount: f64) -> (i32, i32) {
+    let total = *fraction + amount;
+    let hi_res = total.trunc() as i32;
+    *fraction = total - hi_res as f64;
+
+    *partial_detent += hi_res;
+    let detents = *partial_detent / HI_RES_PER_DETENT;
+    *partial_detent %= HI_RES_PER_DETENT;
+    (hi_res, detents)
+}
+
+impl Output {
+    pub fn new(backend: OutputBackend) -> Self {
+        Self {
+            backend,
+            smooth_scroll: Mutex::new(SmoothScroll::default()),
+            #[cfg(test)]
+            discarded: None,
+        }
+    }
+
+    /// Output that injects nothing, only noting down the input; buttons are
+    /// still tracked as held
+    #[cfg(test)]
+    pub fn discarding() -> Self {
+        Self {
+            discarded: Some(Mutex::new(Vec::new())),
+            ..Self::new(OutputBackend::Auto)
+        }
+    }
+
+    /// Input a discarding output was asked to inject so far
+    #[cfg(test)]
+    pub fn discarded(&self) -> Vec<MacroStep> {
+        self.discarded
+            .as_ref()
+            .map(|steps| steps.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    fn discard(&self, step: MacroStep) -> Result<()> {
+        if let Some(steps) = &self.discarded {
+            steps.lock().unwrap().push(step);
+        }
+        Ok(())
+    }
+
+    fn injector(&self) -> Result<Injector> {
+        #[cfg(test)]
+        if self.discarded.is_some() {
+            return Ok(Injector::Discard);
+        }
+        Ok(match self.backend {
+            OutputBackend::Auto => auto_injector(),
+            OutputBackend::Uinput => {
+                Injector::Uinput(uinput_device().context("uinput output is unavailable")?)
+            }
+            OutputBackend::Xtest => Injector::Xtest,
+            OutputBackend::Xdotool => Injector::Xdotool,
+            OutputBackend::Ydotool => Injector::Ydotool,
+            OutputBackend::Wtype => Injector::Wtype,
+        })
+    }
+
+    /// Click a mouse button (1 left, 2 middle, 3 right)
+    pub async fn click(&self, button: u8) -> Result<()> {
+        debug!("Simulating mouse click: button {}", button);
+
+        let key = button_key(button)?;
+        macros::record(MacroStep::Click { button });
+        // A click ends with the button up, whatever held it before
+        HELD_BUTTONS.fetch_and(!button_bit(button), Ordering::Relaxed);
+        match self.injector()? {
+            Injector::Uinput(device) => {
+                let mut device = device.lock().unwrap_or_else(|e| e.into_inner());
+                device.emit(&[InputEvent::new(EventType::KEY, key.code(), 1)])?;
+                device.emit(&[InputEvent::new(EventType::KEY, key.code(), 0)])?;
+                Ok(())
+            }
+            Injector::Xtest => {
+                x11::with_connection_async(move |connection| {
+                    connection.fake_input(&[
+                        (xproto::BUTTON_PRESS_EVENT, button),
+                        (xproto::BUTTON_RELEASE_EVENT, button),
+                    ])
+                })
+                .await
+            }
+            Injector::Xdotool => run_tool("xdotool", &["click".into(), button.to_string()]).await,
+            Injector::Ydotool => {
+                // Low bits select the button, 0xC0 means press and release
+                let code = 0xc0 | (key.code() - Key::BTN_LEFT.code());
+                run_tool("ydotool", &["click".into(), format!("0x{:02x}", code)]).await
+            }
+            Injector::Wtype => Err(anyhow::anyhow!(
+                "wtype can't click; use the ydotool or uinput output backend"
+            )),
+            #[cfg(test)]
+            Injector::Discard => self.discard(MacroStep::Click { button }),
+        }
+    }
+
+    /// Press (`down`) or release a mouse button and leave it that way, e.g.
+    /// to drag without holding anything
+    pub async fn set_button(&self, button: u8, down: bool) -> Result<()> {
+        debug!(
+            "Simulating mouse button {}: button {}",
+            if down { "press" } else { "release" },
+            button
+        );
+
+        let key = button_key(button)?;
+        macros::record(MacroStep::Button { button, down });
+        match self.injector()? {
+            Injector::Uinput(device) => {
+                let mut device = device.lock().unwrap_or_else(|e| e.into_inner());
+                device.emit(&[InputEvent::new(EventType::KEY, key.code(), down as i32)])?;
+            }
+            Injector::Xtest => {
+                x11::with_connection_async(move |connection| {
+                    let kind = if down {
+                        xproto::BUTTON_PRESS_EVENT
+                    } else {
+                        xproto::BUTTON_RELEASE_EVENT
+                    };
+                    connection.fake_input(&[(kind, button)])
+                })
+                .await?
+            }
+            Injector::Xdotool => {
+                let command = if down { "mousedown" } else { "mouseup" };
+                run_tool("xdotool", &[command.into(), button.to_string()]).await?
+            }
+            Injector::Ydotool => {
+                // Low bits select the button, 0x40 means press and 0x80 release
+                let state = if down { 0x40 } else { 0x80 };
+                let code = state | (key.code() - Key::BTN_LEFT.code());
+                run_tool("ydotool", &["click".into(), format!("0x{:02x}", code)]).await?
+            }
+            Injector::Wtype => {
+                bail!("wtype can't press buttons; use the ydotool or uinput output backend")
+            }
+            #[cfg(test)]
+            Injector::Discard => self.discard(MacroStep::Button { button, down })?,
+        }
+
+        if down {
+            HELD_BUTTONS.fetch_or(button_bit(button), Ordering::Relaxed);
+        } else {
+            HELD_BUTTONS.fetch_and(!button_bit(button), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Press a `+`-separated key combination such as `ctrl+shift+t`
+    pub async fn key(&self, combo: &str) -> Result<()> {
+        debug!("Simulating key combo: {}", combo);
+        macros::record(MacroStep::Key {
+            combo: combo.to_string(),
+        });
+
+        let injector = self.injector()?;
+        match injector {
+            Injector::Xdotool => return run_tool("xdotool", &["key".into(), combo.into()]).await,
+            Injector::Wtype => return run_tool("wtype", &wtype_args(combo)).await,
+            Injector::Uinput(_) | Injector::Xtest | Injector::Ydotool => {}
+            #[cfg(test)]
+            Injector::Discard => {
+                return self.discard(MacroStep::Key {
+                    combo: combo.to_string(),
+                })
+            }
+        }
+
+        let keys = combo
+            .split('+')
+            .map(|name| parse_key(name).with_context(|| format!("Unknown key: {}", name)))
+            .collect::<Result<Vec<Key>>>()?;
+
+        if let Injector::Uinput(device) = injector {
+            let mut device = device.lock().unwrap_or_else(|e| e.into_inner());
+            for key in &keys {
+                device.emit(&[InputEvent::new(EventType::KEY, key.code(), 1)])?;
+            }
+            for key in keys.iter().rev() {
+                device.emit(&[InputEvent::new(EventType::KEY, key.code(), 0)])?;
+            }
+            return Ok(());
+        }
+
+        if let Injector::Xtest = injector {
+            return x11::with_connection_async(move |connection| {
+                let keycodes = keys
+                    .iter()
+                    .map(|key| connection.keycode(key.code()))
+                    .collect::<Result<Vec<u8>>>()?;
+                let events: Vec<(u8, u8)> = keycodes
+                    .iter()
+                    .map(|&keycode| (xproto::KEY_PRESS_EVENT, keycode))
+                    .chain(
+                        keycodes
+                            .iter()
+                            .rev()
+                            .map(|&keycode| (xproto::KEY_RELEASE_EVENT, keycode)),
+                    )
+                    .collect();
+                connection.fake_input(&events)
+            })
+            .await;
+        }
+
+        // ydotool takes raw key codes with 1 for press and 0 for release
+        let mut args = vec!["key".to_string()];
+        args.extend(keys.iter().map(|key| format!("{}:1", key.code())));
+        args.extend(keys.iter().rev().map(|key| format!("{}:0", key.code())));
+        run_tool("ydotool", &args).await
+    }
+
+    /// Scroll by high-resolution wheel units (120 per detent); positive is
+    /// up / right
+    ///
+    /// Only uinput can express partial detents; other backends get whole
+    /// wheel steps once enough travel has accumulated.
+    pub async fn smooth_scroll(&self, horizontal: f64, vertical: f64) -> Result<()> {
+        let (hi_res, detents) = self
+            .smooth_scroll
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .accumulate(horizontal, vertical);
+        trace!("Smooth scroll: hi-res {:?}, detents {:?}", hi_res, detents);
+
+        // Macros replay the whole detents
+        if detents != (0, 0) {
+            macros::record(MacroStep::Scroll {
+                horizontal: detents.0,
+                vertical: detents.1,
+            });
+        }
+        match self.injector()? {
+            Injector::Uinput(device) => emit_wheel(device, hi_res, detents),
+            _ if detents != (0, 0) => self.inject_scroll(detents.0, detents.1).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Scroll by wheel steps; positive is up / right
+    pub async fn scroll(&self, horizontal: i32, vertical: i32) -> Result<()> {
+        macros::record(MacroStep::Scroll {
+            horizontal,
+            vertical,
+        });
+        self.inject_scroll(horizontal, vertical).await
+    }
+
+    async fn inject_scroll(&self, horizontal: i32, vertical: i32) -> Result<()> {
+        debug!("Simulating scroll: ({}, {})", horizontal, vertical);
+
+        match self.injector()? {
+            Injector::Uinput(device) => emit_wheel(
+                device,
+                (horizontal * HI_RES_PER_DETENT, vertical * HI_RES_PER_DETENT),
+                (horizontal, vertical),
+            ),
+            Injector::Xtest => {
+                x11::with_connection_async(move |connection| {
+                    let events: Vec<(u8, u8)> = scroll_buttons(horizontal, vertical)
+                        .flat_map(|button| {
+                            [
+                                (xproto::BUTTON_PRESS_EVENT, button),
+                                (xproto::BUTTON_RELEASE_EVENT, button),
+                            ]
+                        })
+                        .collect();
+                    connection.fake_input(&events)
+                })
+                .await
+            }
+            Injector::Xdotool => {
+                for button in scroll_buttons(horizontal, vertical) {
+                    run_tool("xdotool", &["click".into(), button.to_string()]).await?;
+                }
+                Ok(())
+            }
+            Injector::Ydotool => {
+                let args = [
+                    "mousemove".to_string(),
+                    "--wheel".to_string(),
+                    "-x".to_string(),
+                    horizontal.to_string(),
+                    "-y".to_string(),
+                    vertical.to_string(),
+                ];
+                run_tool("ydotool", &args).await
+            }
+            Injector::Wtype => Err(anyhow::anyhow!(
+                "wtype can't scroll; use the ydotool or uinput output backend"
+            )),
+            #[cfg(test)]
+            Injector::Discard => self.discard(MacroStep::Scroll {
+                horizontal,
+                vertical,
+            }),
+        }
+    }
+}
+
+/// Emit a wheel frame; the device advertises high-resolution axes, so readers
+/// such as libinput only look at those and the detents are for legacy readers
+fn emit_wheel(
+    device: &Mutex<VirtualDevice>,
+    (hi_res_x, hi_res_y): (i32, i32),
+    (detents_x, detents_y): (i32, i32),
+) -> Result<()> {
+    let axes = [
+        (RelativeAxisType::REL_WHEEL_HI_RES, hi_res_y),
+        (RelativeAxisType::REL_HWHEEL_HI_RES, hi_res_x),
+        (RelativeAxisType::REL_WHEEL, detents_y),
+        (RelativeAxisType::REL_HWHEEL, detents_x),
+    ];
+    let events: Vec<InputEvent> = axes
+        .iter()
+        .filter(|(_, value)| *value != 0)
+        .map(|(axis, value)| InputEvent::new(EventType::RELATIVE, axis.0, *value))
+        .collect();
+
+    if !events.is_empty() {
+        let mut device = device.lock().unwrap_or_else(|e| e.into_inner());
+        device.emit(&events)?;
+    }
+    Ok(())
+}
+
+/// X11 button clicks making up a scroll; buttons 4-7 are the scroll wheel
+fn scroll_buttons(horizontal: i32, vertical: i32) -> impl Iterator<Item = u8> {
+    let vertical_button = if vertical > 0 { 4 } else { 5 };
+    let horizontal_button = if horizontal > 0 { 7 } else { 6 };
+    std::iter::repeat_n(vertical_button, vertical.unsigned_abs() as usize).chain(
+        std::iter::repeat_n(horizontal_button, horizontal.unsigned_abs() as usize),
+    )
+}
+
+/// wtype arguments for an xdotool-style combo: modifiers are held with `-M`
+/// around the other keys
+fn wtype_args(combo: &str) -> Vec<String> {
+    let mut modifiers = Vec::new();
+    let mut keys = Vec::new();
+    for name in combo.split('+') {
+        match name {
+            "ctrl" | "control" => modifiers.push("ctrl"),
+            "shift" => modifiers.push("shift"),
+            "alt" => modifiers.push("alt"),
+            "super" | "meta" => modifiers.push("logo"),
+            key => keys.push(key),
+        }
+    }
+
+    let mut args = Vec::new();
+    for modifier in &modifiers {
+        args.extend(["-M".to_string(), modifier.to_string()]);
+    }
+    for key in keys {
+        args.extend(["-k".to_string(), key.to_string()]);
+    }
+    for modifier in modifiers.iter().rev() {
+        args.extend(["-m".to_string(), modifier.to_string()]);
+    }
+    args
+}
+
+async fn run_tool(program: &str, args: &[String]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .kill_on_drop(true)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .with_context(|| format!("Failed to execute {}", program))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!("{} {} failed: {}", program, args.join(" "), stderr);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_xdotool_key_names() {
+        assert_eq!(parse_key("ctrl"), Some(Key::KEY_LEFTCTRL));
+        assert_eq!(parse_key("Right"), Some(Key::KEY_RIGHT));
+        assert_eq!(parse_key("T"), Some(Key::KEY_T));
+        assert_eq!(parse_key("7"), Some(Key::KEY_7));
+        assert_eq!(parse_key("F12"), Some(Key::KEY_F12));
+        assert_eq!(parse_key("F13"), None);
+        assert_eq!(parse_key("F0"), None);
+        assert_eq!(parse_key("Hyper_L"), None);
+    }
+
+    #[test]
+    fn test_parse_button_and_scroll_specs() {
+        assert_eq!(parse_button("middle"), Some(2));
+        assert_eq!(parse_button("3"), Some(3));
+        assert_eq!(parse_button("9"), None);
+        assert_eq!(parse_scroll("up"), Some((0, 1)));
+        assert_eq!(parse_scroll("left:3"), Some((-3, 0)));
+        assert_eq!(parse_scroll("down:x"), None);
+        assert_eq!(parse_scroll("sideways"), None);
+    }
+
+    #[test]
+    fn test_smooth_scroll_accumulates_detents() {
+        let mut scroll = SmoothScroll::default();
+        assert_eq!(scroll.accumulate(0.0, 50.5), ((0, 50), (0, 0)));
+        assert_eq!(scroll.accumulate(0.0, 80.0), ((0, 80), (0, 1)));
+        assert_eq!(scroll.accumulate(-0.7, -10.5), ((-0, -10), (0, 0)));
+        assert_eq!(scroll.partial_detent, (0, 0));
+        assert_eq!(scroll.accumulate(-0.5, 0.0), ((-1, 0), (0, 0)));
+    }
+
+    #[test]
+    fn test_wtype_holds_modifiers_around_keys() {
+        assert_eq!(
+            wtype_args("ctrl+shift+t").join(" "),
+            "-M ctrl -M shift -k t -m shift -m ctrl"
+        );
+        assert_eq!(wtype_args("super").join(" "), "-M logo -m logo");
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{
+    Char2b, ConnectionExt as _, CreateGCAux, CreateWindowAux, Window, WindowClass,
+};
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT};
+
+use crate::config::{Action, OverlayConfig, OverlayPosition};
+use crate::x11;
+
+/// Space around the text, in pixels
+const PADDING: u16 = 16;
+const BORDER_WIDTH: u16 = 2;
+/// Fallback every X server has
+const FALLBACK_FONT: &str = "fixed";
+
+/// `toggle_overlay`'s choice, which wins over `overlay.enabled` until the
+/// daemon restarts
+static TOGGLED: Mutex<Option<bool>> = Mutex::new(None);
+/// The overlay window on screen, if any
+static SHOWN: Mutex<Option<Window>> = Mutex::new(None);
+/// Bumped per flash, so only the latest one hides the window
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Whether the overlay already failed once; later failures are only debug
+/// logged
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+pub fn enabled(config: &OverlayConfig) -> bool {
+    TOGGLED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or(config.enabled)
+}
+
+/// Turn the overlay on or off at runtime, for the `toggle_overlay` action
+pub fn toggle(config: &OverlayConfig) -> bool {
+    let enabled = !enabled(config);
+    *TOGGLED.lock().unwrap_or_else(|e| e.into_inner()) = Some(enabled);
+    info!("Overlay {}", if enabled { "enabled" } else { "disabled" });
+    if !enabled {
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+        tokio::task::spawn_blocking(hide);
+    }
+    enabled
+}
+
+/// Flash `text` on screen for the configured duration, in the background
+pub fn show(config: &OverlayConfig, text: String) {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    let config = config.clone();
+    tokio::spawn(async move {
+        let duration = Duration::from_millis(config.duration_ms);
+        let shown = tokio::task::spawn_blocking(move || {
+            // A newer flash got here first
+            if GENERATION.load(Ordering::Relaxed) != generation {
+                return Ok(());
+            }
+            x11::with_connection(|connection| flash(connection, &config, &text))
+        })
+        .await;
+
+        match shown {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                // The window went away with the connection
+                *SHOWN.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                if FAILED.swap(true, Ordering::Relaxed) {
+                    debug!("Failed to show overlay: {:#}", e);
+                } else {
+                    warn!("Failed to show overlay (needs X11 or XWayland): {:#}", e);
+                }
+                return;
+            }
+            Err(e) => {
+                warn!("Overlay task failed: {}", e);
+                return;
+            }
+        }
+
+        tokio::time::sleep(duration).await;
+        if GENERATION.load(Ordering::Relaxed) == generation {
+            let _ = tokio::task::spawn_blocking(hide).await;
+        }
+    });
+}
+
+/// Gesture and the action it runs, e.g. `Swipe left · 2 fingers  ->  key:ctrl+w`
+pub fn text(action_name: &str, action: Option<&Action>) -> String {
+    let label = label(action_name);
+    match action {
+        Some(action) => format!("{}  ->  {}", label, action),
+        None => label,
+    }
+}
+
+/// Readable form of a binding name: `ctrl+swipe_left_2finger` becomes
+/// `Ctrl+  Swipe left · 2 fingers`
+fn label(action_name: &str) -> String {
+    let (modifiers, gesture) = match action_name.rsplit_once('+') {
+        Some((modifiers, gesture)) => (Some(modifiers), gesture),
+        None => (None, action_name),
+    };
+
+    let mut words = Vec::new();
+    let mut fingers = None;
+    for word in gesture.split('_') {
+        match word
+            .strip_suffix("finger")
+            .and_then(|count| count.parse::<u32>().ok())
+        {
+            Some(1) => fingers = Some("1 finger".to_string()),
+            Some(count) => fingers = Some(format!("{} fingers", count)),
+            None => words.push(word),
+        }
+    }
+    let mut label = capitalize(&words.join(" "));
+    if let Some(fingers) = fingers {
+        label = format!("{} · {}", label, fingers);
+    }
+
+    match modifiers {
+        Some(modifiers) => {
+            let modifiers: Vec<String> = modifiers.split('+').map(capitalize).collect();
+            format!("{}+  {}", modifiers.join("+"), label)
+        }
+        None => label,
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Core fonts draw Latin-1; anything else becomes `?`
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|ch| u8::try_from(u32::from(ch)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Show the text in a new window, replacing the one already up
+fn flash(connection: &x11::Connection, config: &OverlayConfig, text: &str) -> Result<()> {
+    let text = latin1(text);
+    // Core fonts draw 255 characters at most
+    let text = &text[..text.len().min(255)];
+    let font_exists = !connection
+        .list_fonts(1, config.font.as_bytes())?
+        .reply()?
+        .names
+        .is_empty();
+    let font_name = if font_exists {
+        config.font.as_str()
+    } else {
+        debug!("No font matches {:?}, using {}", config.font, FALLBACK_FONT);
+        FALLBACK_FONT
+    };
+    let font = connection.generate_id()?;
+    connection.open_font(font, font_name.as_bytes())?;
+    let characters: Vec<Char2b> = text
+        .iter()
+        .map(|&byte| Char2b {
+            byte1: 0,
+            byte2: byte,
+        })
+        .collect();
+    let extents = connection.query_text_extents(font, &characters)?.reply()?;
+    let (ascent, descent) = (
+        extents.font_ascent.max(0) as u16,
+        extents.font_descent.max(0) as u16,
+    );
+
+    let screen = connection.screen();
+    let (screen_width, screen_height) = (screen.width_in_pixels, screen.height_in_pixels);
+    let size = (
+        (extents.overall_width.max(0) as u16).saturating_add(2 * PADDING),
+        (ascent + descent).saturating_add(2 * PADDING),
+    );
+    let outer_height = size.1 + 2 * BORDER_WIDTH;
+    let x = screen_width.saturating_sub(size.0 + 2 * BORDER_WIDTH) / 2;
+    let y = match config.position {
+        OverlayPosition::Top => screen_height / 10,
+        OverlayPosition::Center => screen_height.saturating_sub(outer_height) / 2,
+        OverlayPosition::Bottom => screen_height
+            .saturating_sub(screen_height / 10)
+            .saturating_sub(outer_height),
+    };
+
+    // Override-redirect: no decorations, no focus, above the other windows
+    let window = connection.generate_id()?;
+    connection.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        screen.root,
+        x as i16,
+        y as i16,
+        size.0,
+        size.1,
+        BORDER_WIDTH,
+        WindowClass::INPUT_OUTPUT,
+        COPY_FROM_PARENT,
+        &CreateWindowAux::new()
+            .background_pixel(screen.black_pixel)
+            .border_pixel(screen.white_pixel)
+            .override_redirect(1),
+    )?;
+    connection.map_window(window)?;
+
+    // White on black, baseline below the top padding
+    let gc = connection.generate_id()?;
+    connection.create_gc(
+        gc,
+        window,
+        &CreateGCAux::new()
+            .foreground(screen.white_pixel)
+            .background(screen.black_pixel)
+            .font(font),
+    )?;
+    connection.image_text8(window, gc, PADDING as i16, (PADDING + ascent) as i16, text)?;
+    connection.free_gc(gc)?;
+    connection.close_font(font)?;
+    connection.sync()?;
+
+    let replaced = SHOWN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(window);
+    if let Some(replaced) = replaced {
+        connection.destroy_window(replaced)?;
+        connection.sync()?;
+    }
+    Ok(())
+}
+
+fn hide() {
+    if SHOWN.lock().unwrap_or_else(|e| e.into_inner()).is_none() {
+        return;
+    }
+    let _ = x11::with_connection(|connection| {
+        let shown = SHOWN.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(window) = shown {
+            connection.destroy_window(window)?;
+            connection.sync()?;
+        }
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label("swipe_left_2finger"), "Swipe left · 2 fingers");
+        assert_eq!(label("tap_1finger"), "Tap · 1 finger");
+        assert_eq!(label("pinch_in"), "Pinch in");
+        assert_eq!(
+            label("ctrl+click+swipe_up_2finger"),
+            "Ctrl+Click+  Swipe up · 2 fingers"
+        );
+        assert_eq!(
+            text(
+                "tap_2finger",
+                Some(&Action::Command("right_click".to_string()))
+            ),
+            "Tap · 2 fingers  ->  right_click"
+        );
+        assert_eq!(latin1("2 · é →"), b"2 \xb7 \xe9 ?");
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, Device, EventType, InputEvent, Key, RelativeAxisType, Synchronization};
+use std::io;
+
+/// Relative axes carrying wheel (scroll) motion
+const WHEEL_AXES: [RelativeAxisType; 4] = [
+    RelativeAxisType::REL_WHEEL,
+    RelativeAxisType::REL_HWHEEL,
+    RelativeAxisType::REL_WHEEL_HI_RES,
+    RelativeAxisType::REL_HWHEEL_HI_RES,
+];
+
+/// Re-injects pointer motion and physical clicks of a grabbed device
+///
+/// While the touch device is grabbed nothing else sees its events, so the
+/// non-gesture part (relative motion, mouse buttons and optionally the driver's
+/// wheel emulation) is forwarded through a uinput device.
+pub struct Passthrough {
+    device: VirtualDevice,
+    scroll: bool,
+    pending: Vec<InputEvent>,
+}
+
+impl Passthrough {
+    /// Create a virtual device mirroring the pointer capabilities of `source`
+    pub fn new(source: &Device, scroll: bool) -> Result<Self> {
+        let mut keys = AttributeSet::<Key>::new();
+        if let Some(supported) = source.supported_keys() {
+            for key in supported.iter().filter(|key| is_mouse_button(*key)) {
+                keys.insert(key);
+            }
+        }
+
+        let mut axes = AttributeSet::<RelativeAxisType>::new();
+        if let Some(supported) = source.supported_relative_axes() {
+            for axis in supported.iter() {
+                if scroll || !WHEEL_AXES.contains(&axis) {
+                    axes.insert(axis);
+                }
+            }
+        }
+
+        let device = VirtualDeviceBuilder::new()
+            .context("Failed to open /dev/uinput")?
+            .name("Mouse Gesture Passthrough")
+            .with_keys(&keys)?
+            .with_relative_axes(&axes)?
+            .build()
+            .context("Failed to create passthrough device")?;
+
+        Ok(Self {
+            device,
+            scroll,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Queue a raw event for re-injection; queued events are written as one
+    /// frame on SYN_REPORT
+    pub fn forward(&mut self, event: InputEvent) -> io::Result<()> {
+        match event.event_type() {
+            EventType::RELATIVE => {
+                let axis = RelativeAxisType(event.code());
+                if self.scroll || !WHEEL_AXES.contains(&axis) {
+                    self.pending.push(event);
+                }
+            }
+            EventType::KEY if is_mouse_button(Key::new(event.code())) => {
+                self.pending.push(event);
+            }
+            EventType::SYNCHRONIZATION
+                if event.code() == Synchronization::SYN_REPORT.0 && !self.pending.is_empty() =>
+            {
+                let result = self.device.emit(&self.pending);
+                self.pending.clear();
+                return result;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn is_mouse_button(key: Key) -> bool {
+    (Key::BTN_LEFT.code()..=Key::BTN_TASK.code()).contains(&key.code())
+}
@@ -0,0 +1,101 @@
+use log::info;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Subdirectory of the XDG base directories holding our files
+const APP_DIR: &str = "mouse-gesture";
+const CONFIG_FILE: &str = "config.json";
+
+/// `$XDG_CONFIG_HOME/mouse-gesture`, defaulting to `~/.config/mouse-gesture`
+pub fn config_dir() -> Option<PathBuf> {
+    Some(config_home()?.join(APP_DIR))
+}
+
+/// Where systemd looks for user units: `$XDG_CONFIG_HOME/systemd/user`
+pub fn systemd_user_dir() -> Option<PathBuf> {
+    Some(config_home()?.join("systemd").join("user"))
+}
+
+/// `name` in `$XDG_RUNTIME_DIR`, for sockets and PID files that must not
+/// outlive the session; the temporary directory without one
+pub fn runtime_file(name: &str) -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(std::env::temp_dir)
+        .join(name)
+}
+
+fn config_home() -> Option<PathBuf> {
+    base_dir(
+        std::env::var_os("XDG_CONFIG_HOME"),
+        std::env::var_os("HOME"),
+        ".config",
+    )
+}
+
+/// `$XDG_STATE_HOME/mouse-gesture`, defaulting to `~/.local/state/mouse-gesture`
+///
+/// Generated data that should survive restarts (but isn't worth backing up
+/// like the configuration) lives here.
+pub fn state_dir() -> Option<PathBuf> {
+    let state_home = base_dir(
+        std::env::var_os("XDG_STATE_HOME"),
+        std::env::var_os("HOME"),
+        ".local/state",
+    )?;
+    Some(state_home.join(APP_DIR))
+}
+
+/// Configuration file used when `--config` isn't given
+///
+/// A `config.json` in the working directory is still honoured when there's
+/// none in the XDG config directory, so existing setups keep working.
+pub fn default_config_path() -> PathBuf {
+    let legacy = PathBuf::from(CONFIG_FILE);
+    match config_dir().map(|dir| dir.join(CONFIG_FILE)) {
+        Some(path) if path.exists() => path,
+        _ if legacy.exists() => {
+            info!("Using ./{} from the working directory", CONFIG_FILE);
+            legacy
+        }
+        Some(path) => path,
+        None => legacy,
+    }
+}
+
+/// Per the XDG spec, unset, empty and relative values fall back to the
+/// default under `$HOME`
+fn base_dir(value: Option<OsString>, home: Option<OsString>, default: &str) -> Option<PathBuf> {
+    value
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            home.map(PathBuf::from)
+                .filter(|home| home.is_absolute())
+                .map(|home| home.join(default))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_dir() {
+        let home = Some(OsString::from("/home/me"));
+        assert_eq!(
+            base_dir(Some("/xdg".into()), home.clone(), ".config"),
+            Some(PathBuf::from("/xdg"))
+        );
+        assert_eq!(
+            base_dir(Some("relative".into()), home.clone(), ".local/state"),
+            Some(PathBuf::from("/home/me/.local/state"))
+        );
+        assert_eq!(
+            base_dir(Some("".into()), home, ".config"),
+            Some(PathBuf::from("/home/me/.config"))
+        );
+        assert_eq!(base_dir(None, None, ".config"), None);
+    }
+}
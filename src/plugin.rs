@@ -0,0 +1,313 @@
+use anyhow::{bail, Context, Result};
+use libloading::{Library, Symbol};
+use log::{debug, info, warn};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::gesture::Recognizer;
+use crate::multitouch::{MultiTouchEvent, TouchContact};
+
+/// Version of the plugin ABI described in `docs/plugins.md`
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// Symbol every plugin exports, returning its `PluginDescriptor`
+const ENTRY_POINT: &[u8] = b"mouse_gesture_plugin\0";
+
+type RecognizerNew = unsafe extern "C" fn() -> *mut c_void;
+type RecognizerFree = unsafe extern "C" fn(*mut c_void);
+type RecognizerFeed =
+    unsafe extern "C" fn(*mut c_void, *const PluginContact, usize) -> *const c_char;
+type RecognizerReset = unsafe extern "C" fn(*mut c_void);
+type ActionExecute = unsafe extern "C" fn(*const c_char) -> i32;
+
+/// Description a plugin hands out from `mouse_gesture_plugin()`
+///
+/// Every callback is optional, so a plugin can provide a recognizer, an
+/// action executor or both.
+#[repr(C)]
+pub struct PluginDescriptor {
+    pub api_version: u32,
+    pub name: *const c_char,
+    pub recognizer_new: Option<RecognizerNew>,
+    pub recognizer_free: Option<RecognizerFree>,
+    pub recognizer_feed: Option<RecognizerFeed>,
+    pub recognizer_feed_frame: Option<RecognizerFeed>,
+    pub recognizer_reset: Option<RecognizerReset>,
+    pub action_prefix: *const c_char,
+    pub action_execute: Option<ActionExecute>,
+}
+
+/// Touch contact as passed to plugin recognizers
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PluginContact {
+    pub slot: i32,
+    pub x: i32,
+    pub y: i32,
+    pub touch_major: i32,
+    pub touch_minor: i32,
+    pub orientation: i32,
+    pub pressure: i32,
+    /// 1 while the finger is on the surface
+    pub active: i32,
+    pub x_mm: f64,
+    pub y_mm: f64,
+    pub duration_ms: u64,
+}
+
+impl From<&TouchContact> for PluginContact {
+    fn from(contact: &TouchContact) -> Self {
+        let (x_mm, y_mm) = contact.position_mm();
+        Self {
+            slot: contact.slot,
+            x: contact.x,
+            y: contact.y,
+            touch_major: contact.touch_major,
+            touch_minor: contact.touch_minor,
+            orientation: contact.orientation,
+            pressure: contact.pressure,
+            active: contact.is_active as i32,
+            x_mm,
+            y_mm,
+            duration_ms: contact.contact_duration().as_millis() as u64,
+        }
+    }
+}
+
+/// A loaded plugin; the library stays loaded for the life of the process
+pub struct Plugin {
+    name: &'static str,
+    recognizer: Option<RecognizerFns>,
+    action: Option<(String, ActionExecute)>,
+    /// Keeps the callbacks above loaded
+    _library: Library,
+}
+
+#[derive(Clone, Copy)]
+struct RecognizerFns {
+    new: RecognizerNew,
+    free: Option<RecognizerFree>,
+    feed: Option<RecognizerFeed>,
+    feed_frame: Option<RecognizerFeed>,
+    reset: Option<RecognizerReset>,
+}
+
+static PLUGINS: OnceLock<Vec<Plugin>> = OnceLock::new();
+
+/// Load every `*.so` in `directory`; a missing directory means no plugins
+///
+/// Plugins that fail to load are skipped with a warning.
+pub fn load_all(directory: &Path) {
+    let mut plugins = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(directory) {
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "so"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            match Plugin::load(&path) {
+                Ok(plugin) => {
+                    info!("Loaded plugin {} from {:?}", plugin.name, path);
+                    plugins.push(plugin);
+                }
+                Err(e) => warn!("Failed to load plugin {:?}: {:#}", path, e),
+            }
+        }
+    } else {
+        debug!("No plugin directory at {:?}", directory);
+    }
+
+    if PLUGINS.set(plugins).is_err() {
+        warn!("Plugins were already loaded");
+    }
+}
+
+fn plugins() -> &'static [Plugin] {
+    PLUGINS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Fresh instances of every plugin recognizer, for one gesture pipeline
+pub fn recognizers() -> Vec<Box<dyn Recognizer>> {
+    plugins()
+        .iter()
+        .filter_map(|plugin| {
+            let fns = plugin.recognizer?;
+            // SAFETY: called as documented by the plugin ABI
+            let state = unsafe { (fns.new)() };
+            Some(Box::new(PluginRecognizer {
+                name: plugin.name,
+                fns,
+                state,
+            }) as Box<dyn Recognizer>)
+        })
+        .collect()
+}
+
+/// Run `<prefix>:<argument>` if a plugin handles the prefix; `None` if no
+/// plugin does
+pub async fn execute(prefix: &str, argument: &str) -> Option<Result<()>> {
+    let (name, execute) = plugins().iter().find_map(|plugin| {
+        let (action_prefix, execute) = plugin.action.as_ref()?;
+        (action_prefix == prefix).then_some((plugin.name, *execute))
+    })?;
+
+    let argument = match CString::new(argument) {
+        Ok(argument) => argument,
+        Err(e) => return Some(Err(e.into())),
+    };
+
+    // Plugins may block, so keep them off the async workers
+    let result = tokio::task::spawn_blocking(move || {
+        // SAFETY: called as documented by the plugin ABI
+        unsafe { execute(argument.as_ptr()) }
+    })
+    .await;
+
+    Some(match result {
+        Ok(0) => Ok(()),
+        Ok(code) => Err(anyhow::anyhow!("Plugin {} failed with {}", name, code)),
+        Err(e) => Err(e.into()),
+    })
+}
+
+impl Plugin {
+    fn load(path: &Path) -> Result<Self> {
+        // SAFETY: loading a library runs its initializers; plugins are trusted
+        // code installed by the user
+        let library = unsafe { Library::new(path) }?;
+
+        // SAFETY: the symbol is a function returning a descriptor per the ABI
+        let descriptor = unsafe {
+            let entry: Symbol<unsafe extern "C" fn() -> *const PluginDescriptor> = library
+                .get(ENTRY_POINT)
+                .context("No mouse_gesture_plugin symbol")?;
+            entry().as_ref().context("Plugin returned no descriptor")?
+        };
+
+        if descriptor.api_version != PLUGIN_API_VERSION {
+            bail!(
+                "Plugin API version {} is not supported (expected {})",
+                descriptor.api_version,
+                PLUGIN_API_VERSION
+            );
+        }
+
+        // SAFETY: strings in the descriptor are NUL-terminated and static
+        let name = unsafe { c_string(descriptor.name) }.unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into()
+        });
+        let action_prefix = unsafe { c_string(descriptor.action_prefix) };
+
+        let recognizer = descriptor.recognizer_new.map(|new| RecognizerFns {
+            new,
+            free: descriptor.recognizer_free,
+            feed: descriptor.recognizer_feed,
+            feed_frame: descriptor.recognizer_feed_frame,
+            reset: descriptor.recognizer_reset,
+        });
+        let action = action_prefix.zip(descriptor.action_execute);
+
+        Ok(Self {
+            // Plugins live until exit, so their names can too
+            name: Box::leak(name.into_boxed_str()),
+            recognizer,
+            action,
+            _library: library,
+        })
+    }
+}
+
+/// Copy a C string, `None` if it is NULL
+///
+/// # Safety
+/// `ptr` must be NULL or point to a NUL-terminated string.
+unsafe fn c_string(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// Recognizer backed by a plugin
+///
+/// The reported gesture name is looked up in the `actions` map like the
+/// built-in ones.
+struct PluginRecognizer {
+    name: &'static str,
+    fns: RecognizerFns,
+    state: *mut c_void,
+}
+
+// SAFETY: the state is only touched through `&mut self`; the ABI requires
+// recognizer state not to be tied to the creating thread
+unsafe impl Send for PluginRecognizer {}
+
+impl PluginRecognizer {
+    fn call(
+        &mut self,
+        feed: Option<RecognizerFeed>,
+        contacts: &[&TouchContact],
+    ) -> Vec<MultiTouchEvent> {
+        let Some(feed) = feed else {
+            return Vec::new();
+        };
+
+        let plugin_contacts: Vec<PluginContact> = contacts
+            .iter()
+            .map(|&contact| PluginContact::from(contact))
+            .collect();
+        // SAFETY: the contacts outlive the call and the returned string stays
+        // valid until the next call into the recognizer
+        let gesture = unsafe {
+            c_string(feed(
+                self.state,
+                plugin_contacts.as_ptr(),
+                plugin_contacts.len(),
+            ))
+        };
+
+        match gesture {
+            Some(name) if !name.is_empty() => vec![MultiTouchEvent::Custom {
+                name,
+                fingers: contacts
+                    .iter()
+                    .map(|&contact| Arc::new(contact.clone()))
+                    .collect(),
+            }],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Recognizer for PluginRecognizer {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn feed(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        self.call(self.fns.feed, contacts)
+    }
+
+    fn feed_frame(&mut self, contacts: &[&TouchContact]) -> Vec<MultiTouchEvent> {
+        self.call(self.fns.feed_frame, contacts)
+    }
+
+    fn reset(&mut self) {
+        if let Some(reset) = self.fns.reset {
+            // SAFETY: called as documented by the plugin ABI
+            unsafe { reset(self.state) }
+        }
+    }
+}
+
+impl Drop for PluginRecognizer {
+    fn drop(&mut self) {
+        if let Some(free) = self.fns.free {
+            // SAFETY: the state came from `recognizer_new` and isn't used again
+            unsafe { free(self.state) }
+        }
+    }
+}
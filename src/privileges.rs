@@ -0,0 +1,205 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// IDs of the `--user` account, which files written while still root go to
+static FILE_OWNER: OnceLock<(libc::uid_t, libc::gid_t)> = OnceLock::new();
+
+/// The unprivileged account `--user` switches to
+#[derive(Debug)]
+pub struct User {
+    pub name: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub home: PathBuf,
+}
+
+/// Look up `name` in the password database
+pub fn lookup(name: &str) -> Result<User> {
+    let c_name = CString::new(name).context("Invalid user name")?;
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    loop {
+        let error = unsafe {
+            libc::getpwnam_r(
+                c_name.as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        match error {
+            0 => break,
+            libc::ERANGE => buffer.resize(buffer.len() * 2, 0),
+            error => bail!(
+                "Failed to look up user {:?}: {}",
+                name,
+                std::io::Error::from_raw_os_error(error)
+            ),
+        }
+    }
+    if result.is_null() {
+        bail!("No such user: {:?}", name);
+    }
+
+    // SAFETY: getpwnam_r succeeded, so pw_dir points into `buffer`
+    let home = unsafe { CStr::from_ptr(entry.pw_dir) };
+    Ok(User {
+        name: name.to_string(),
+        uid: entry.pw_uid,
+        gid: entry.pw_gid,
+        home: PathBuf::from(home.to_string_lossy().into_owned()),
+    })
+}
+
+/// Point the environment at `user`'s session, so configuration, state and
+/// the control socket are theirs and actions reach their desktop
+///
+/// This has to happen before any other thread exists. `DISPLAY` and
+/// `WAYLAND_DISPLAY` are left as they are; a service sets them itself.
+pub fn set_environment(user: &User) {
+    let runtime_dir = PathBuf::from(format!("/run/user/{}", user.uid));
+    for (key, value) in environment(user, &runtime_dir) {
+        match value {
+            Some(value) => std::env::set_var(key, value),
+            None => std::env::remove_var(key),
+        }
+    }
+}
+
+/// Variables to set (or remove, for `None`) for `user`
+fn environment(user: &User, runtime_dir: &Path) -> Vec<(&'static str, Option<String>)> {
+    let mut variables = vec![
+        ("HOME", Some(user.home.to_string_lossy().into_owned())),
+        ("USER", Some(user.name.clone())),
+        ("LOGNAME", Some(user.name.clone())),
+        // These would still point at the privileged user's directories
+        ("XDG_CONFIG_HOME", None),
+        ("XDG_STATE_HOME", None),
+        ("XDG_DATA_HOME", None),
+        ("XDG_CACHE_HOME", None),
+    ];
+
+    if !runtime_dir.is_dir() {
+        // Without a session the socket and PID file go to the temporary directory
+        variables.push(("XDG_RUNTIME_DIR", None));
+        return variables;
+    }
+    let runtime_dir = runtime_dir.to_string_lossy().into_owned();
+    if Path::new(&runtime_dir).join("bus").exists() {
+        variables.push((
+            "DBUS_SESSION_BUS_ADDRESS",
+            Some(format!("unix:path={}/bus", runtime_dir)),
+        ));
+    }
+    variables.push(("XDG_RUNTIME_DIR", Some(runtime_dir)));
+    variables
+}
+
+/// Have `adopt` hand files to `user` from now on
+pub fn hand_files_to(user: &User) {
+    let _ = FILE_OWNER.set((user.uid, user.gid));
+}
+
+/// Give `path` to the `--user` account if it was created while still root,
+/// so the daemon can still update it after switching
+pub fn adopt(path: &Path) -> Result<()> {
+    let Some(&(uid, gid)) = FILE_OWNER.get() else {
+        return Ok(());
+    };
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(());
+    }
+    std::os::unix::fs::chown(path, Some(uid), Some(gid))
+        .with_context(|| format!("Failed to hand {:?} to uid {}", path, uid))
+}
+
+/// `create_dir_all`, adopting every directory it creates
+pub fn create_dir_all(dir: &Path) -> Result<()> {
+    let missing: Vec<_> = dir
+        .ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| !ancestor.exists())
+        .collect();
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory: {:?}", dir))?;
+    missing.into_iter().rev().try_for_each(adopt)
+}
+
+/// Give up root for `user` once the devices and uinput are open
+///
+/// The open file descriptors keep working. Changing the IDs of a process that
+/// was root also clears every capability, including ones granted by systemd,
+/// and the C library applies it to every thread.
+pub fn drop_to(user: &User) -> Result<()> {
+    let (uid, euid) = unsafe { (libc::getuid(), libc::geteuid()) };
+    if uid == user.uid && euid == user.uid {
+        return Ok(());
+    }
+    if euid != 0 {
+        bail!("Switching to user {:?} needs starting as root", user.name);
+    }
+
+    let name = CString::new(user.name.as_str())?;
+    // The group list first: it can't be changed anymore afterwards
+    if unsafe { libc::initgroups(name.as_ptr(), user.gid) } != 0 {
+        bail!(
+            "Failed to set groups of {:?}: {}",
+            user.name,
+            std::io::Error::last_os_error()
+        );
+    }
+    if unsafe { libc::setresgid(user.gid, user.gid, user.gid) } != 0 {
+        bail!("Failed to set group: {}", std::io::Error::last_os_error());
+    }
+    if unsafe { libc::setresuid(user.uid, user.uid, user.uid) } != 0 {
+        bail!("Failed to set user: {}", std::io::Error::last_os_error());
+    }
+
+    // A saved root ID left behind would let root be regained
+    if unsafe { libc::setuid(0) } == 0 {
+        bail!(
+            "Still able to regain root after switching to {:?}",
+            user.name
+        );
+    }
+    info!(
+        "Switched to user {} (uid {}, gid {})",
+        user.name, user.uid, user.gid
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment() {
+        let user = User {
+            name: "alice".to_string(),
+            uid: 1000,
+            gid: 1000,
+            home: PathBuf::from("/home/alice"),
+        };
+
+        let variables = environment(&user, Path::new("/nonexistent/run/user/1000"));
+        assert!(variables.contains(&("HOME", Some("/home/alice".to_string()))));
+        assert!(variables.contains(&("XDG_CONFIG_HOME", None)));
+        assert!(variables.contains(&("XDG_RUNTIME_DIR", None)));
+
+        let runtime_dir = std::env::temp_dir();
+        let variables = environment(&user, &runtime_dir);
+        assert!(variables.contains(&(
+            "XDG_RUNTIME_DIR",
+            Some(runtime_dir.to_string_lossy().into_owned())
+        )));
+
+        assert!(lookup("root").is_ok_and(|root| root.uid == 0));
+        assert!(lookup("no-such-user-here").is_err());
+    }
+}
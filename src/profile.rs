@@ -0,0 +1,181 @@
+use anyhow::{bail, Context, Result};
+use lazy_static::lazy_static;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+
+use crate::config::Config;
+use crate::paths;
+
+lazy_static! {
+    /// Profile in effect for every device; `None` is the global configuration
+    static ref ACTIVE: watch::Sender<Option<String>> = watch::channel(None).0;
+}
+
+/// Follow profile switches
+pub fn subscribe() -> watch::Receiver<Option<String>> {
+    ACTIVE.subscribe()
+}
+
+/// Name of the active profile
+pub fn active() -> Option<String> {
+    ACTIVE.borrow().clone()
+}
+
+/// Make `name` the active profile; devices pick it up with their next event
+pub fn activate(name: Option<String>) {
+    match &name {
+        Some(name) => info!("Switched to profile {}", name),
+        None => info!("Switched to the default profile"),
+    }
+    ACTIVE.send_replace(name);
+}
+
+/// Run a `profile:` action: a profile name, `next` or `default`
+pub fn execute(config: &Config, argument: &str) -> Result<()> {
+    let current = active();
+    let target = match argument {
+        "default" => None,
+        "next" => next_profile(config, current.as_deref()),
+        name if config.profiles.contains_key(name) => Some(name.to_string()),
+        name => bail!("Unknown profile: {}", name),
+    };
+
+    if target != current {
+        activate(target);
+    }
+    Ok(())
+}
+
+/// Profile state kept across restarts
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct SavedState {
+    /// Profile active when the daemon stopped
+    profile: Option<String>,
+    /// `profile` in the configuration at the time, so editing it still
+    /// takes effect on the next start
+    configured: Option<String>,
+}
+
+fn state_file() -> Option<PathBuf> {
+    Some(paths::state_dir()?.join("state.json"))
+}
+
+/// Remember the active profile for the next start
+pub fn save(config: &Config) -> Result<()> {
+    save_to(&state_file().context("HOME is not set")?, config)
+}
+
+fn save_to(path: &Path, config: &Config) -> Result<()> {
+    let state = SavedState {
+        profile: active(),
+        configured: config.profile.clone(),
+    };
+    std::fs::create_dir_all(path.parent().unwrap_or(path))?;
+    std::fs::write(path, serde_json::to_string_pretty(&state)? + "\n")
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    debug!("Saved profile state to {:?}", path);
+    Ok(())
+}
+
+/// The profile to start with: the one active when the daemon last stopped,
+/// unless the configured one changed since or the profile is gone
+pub fn restore(config: &Config) -> Option<String> {
+    match state_file() {
+        Some(path) => restore_from(&path, config),
+        None => config.profile.clone(),
+    }
+}
+
+fn restore_from(path: &Path, config: &Config) -> Option<String> {
+    let state = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    match state {
+        Some(state) => restored_profile(config, state),
+        None => config.profile.clone(),
+    }
+}
+
+fn restored_profile(config: &Config, state: SavedState) -> Option<String> {
+    if state.configured != config.profile {
+        return config.profile.clone();
+    }
+    match state.profile {
+        Some(name) if !config.profiles.contains_key(&name) => config.profile.clone(),
+        profile => profile,
+    }
+}
+
+/// Profiles in name order, then back to the default
+fn next_profile(config: &Config, current: Option<&str>) -> Option<String> {
+    let mut names = config.profiles.keys();
+    match current {
+        None => names.next(),
+        Some(current) => names.skip_while(|name| *name != current).nth(1),
+    }
+    .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+
+    #[test]
+    fn test_next_profile() {
+        let mut config = Config::default();
+        for name in ["media", "work"] {
+            config.profiles.insert(name.to_string(), Profile::default());
+        }
+
+        assert_eq!(next_profile(&config, None).as_deref(), Some("media"));
+        assert_eq!(
+            next_profile(&config, Some("media")).as_deref(),
+            Some("work")
+        );
+        assert_eq!(next_profile(&config, Some("work")), None);
+
+        // Restarting keeps the profile switched to, unless the configured one
+        // was edited in the meantime
+        let saved = |profile: &str, configured: Option<&str>| SavedState {
+            profile: Some(profile.to_string()),
+            configured: configured.map(str::to_string),
+        };
+        assert_eq!(
+            restored_profile(&config, saved("work", None)).as_deref(),
+            Some("work")
+        );
+        config.profile = Some("media".to_string());
+        assert_eq!(
+            restored_profile(&config, saved("work", None)).as_deref(),
+            Some("media")
+        );
+        assert_eq!(
+            restored_profile(&config, saved("gone", Some("media"))).as_deref(),
+            Some("media")
+        );
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let mut config = Config::default();
+        config
+            .profiles
+            .insert("work".to_string(), Profile::default());
+        let path = std::env::temp_dir()
+            .join(format!("mouse-gesture-state-{}", std::process::id()))
+            .join("state.json");
+
+        // Nothing saved yet: the configured profile
+        assert_eq!(restore_from(&path, &config), None);
+
+        activate(Some("work".to_string()));
+        save_to(&path, &config).unwrap();
+        activate(None);
+        let restored = restore_from(&path, &config);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(restored.as_deref(), Some("work"));
+    }
+}
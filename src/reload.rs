@@ -0,0 +1,221 @@
+use log::{debug, info, warn};
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+use crate::config::{Config, DeviceEntry};
+use crate::profile;
+
+/// Editors write in bursts (truncate, write, chmod); wait for them to settle
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// The configuration in effect, updated whenever the file changes
+pub struct ConfigSource {
+    path: PathBuf,
+    sender: watch::Sender<Arc<Config>>,
+    /// Re-applies command line overrides to each reloaded version
+    prepare: Box<dyn Fn(&mut Config) + Send + Sync>,
+}
+
+impl ConfigSource {
+    /// The configuration file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Follow configuration updates
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.sender.subscribe()
+    }
+
+    /// The latest valid configuration
+    pub fn current(&self) -> Arc<Config> {
+        Arc::clone(&self.sender.borrow())
+    }
+
+    /// Re-read the file and publish it; a file that fails to parse is
+    /// reported and the previous configuration kept
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let mut config = Config::load(&self.path)?;
+        (self.prepare)(&mut config);
+        if config.plugins.directory != self.current().plugins.directory {
+            warn!(
+                "Plugins are only loaded at startup; restart to load them from the new directory"
+            );
+        }
+        info!("Configuration reloaded from {:?}", self.path);
+        self.sender.send_replace(Arc::new(config));
+        Ok(())
+    }
+}
+
+/// Watch the configuration file and publish every valid new version
+///
+/// The file's directory is watched rather than the file itself, so editors
+/// that save by renaming a temporary file over it are picked up too.
+pub fn watch<F>(path: PathBuf, initial: Config, prepare: F) -> Arc<ConfigSource>
+where
+    F: Fn(&mut Config) + Send + Sync + 'static,
+{
+    let source = Arc::new(ConfigSource {
+        path,
+        sender: watch::channel(Arc::new(initial)).0,
+        prepare: Box::new(prepare),
+    });
+
+    if let Err(e) = watch_file(Arc::clone(&source)) {
+        warn!("Not watching {:?} for changes: {}", source.path, e);
+    }
+    source
+}
+
+fn watch_file(source: Arc<ConfigSource>) -> notify::Result<()> {
+    let directory = match source.path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // Fails only once the task below is gone
+        let _ = sender.send(event);
+    })?;
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    debug!("Watching {:?} for configuration changes", source.path);
+    // Read before returning, so a save right after watching isn't mistaken
+    // for the content it replaced
+    let mut last_content = std::fs::read(&source.path).ok();
+
+    tokio::spawn(async move {
+        // Watching stops when the watcher is dropped
+        let _watcher = watcher;
+        let path = source.path.as_path();
+        let file_name = path.file_name().unwrap_or_default();
+        while let Some(event) = events.recv().await {
+            match event {
+                Ok(event) if saves(&event, file_name) => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("Failed to watch {:?}: {}", path, e);
+                    continue;
+                }
+            }
+            tokio::time::sleep(SETTLE_DELAY).await;
+            while events.try_recv().is_ok() {}
+
+            // A rename or a chmod can touch the file without changing it
+            let content = tokio::fs::read(path).await.ok();
+            if content.is_none() || content == last_content {
+                continue;
+            }
+            last_content = content;
+
+            if let Err(e) = source.reload() {
+                warn!("Keeping previous configuration: {:#}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Whether `event` finished writing `file_name` or moved it into place;
+/// reading the file, as reloading does, doesn't count
+fn saves(event: &Event, file_name: &OsStr) -> bool {
+    let saved = matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Both))
+    );
+    saved
+        && event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == Some(file_name))
+}
+
+/// Configuration of one device that follows reloads of the file and
+/// profile switches
+pub struct LiveConfig {
+    receiver: watch::Receiver<Arc<Config>>,
+    profile: watch::Receiver<Option<String>>,
+    entry: DeviceEntry,
+}
+
+impl LiveConfig {
+    pub fn new(receiver: watch::Receiver<Arc<Config>>, entry: DeviceEntry) -> Self {
+        Self {
+            receiver,
+            profile: profile::subscribe(),
+            entry,
+        }
+    }
+
+    /// This device's configuration as of now
+    pub fn current(&mut self) -> anyhow::Result<Config> {
+        let profile = self.profile.borrow_and_update().clone();
+        self.receiver
+            .borrow_and_update()
+            .with_profile(profile.as_deref())?
+            .for_device(&self.entry)
+    }
+
+    /// This device's new configuration, if the file was reloaded or another
+    /// profile activated since the last call
+    pub fn changed(&mut self) -> Option<Config> {
+        let reloaded = self.receiver.has_changed().unwrap_or(false);
+        if !reloaded && !self.profile.has_changed().unwrap_or(false) {
+            return None;
+        }
+        match self.current() {
+            Ok(config) => Some(config),
+            Err(e) => {
+                warn!("Keeping previous device configuration: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saves() {
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+        let name = OsStr::new("config.json");
+
+        let written = EventKind::Access(AccessKind::Close(AccessMode::Write));
+        assert!(saves(&event(written, "/etc/mg/config.json"), name));
+        let renamed = EventKind::Modify(ModifyKind::Name(RenameMode::To));
+        assert!(saves(&event(renamed, "/etc/mg/config.json"), name));
+        assert!(!saves(&event(written, "/etc/mg/other.json"), name));
+        // Reading the file back must not trigger another reload
+        let read = EventKind::Access(AccessKind::Close(AccessMode::Read));
+        assert!(!saves(&event(read, "/etc/mg/config.json"), name));
+    }
+
+    #[tokio::test]
+    async fn test_watch_reloads_saved_file() {
+        let dir = std::env::temp_dir().join(format!("mouse-gesture-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let config = Config::load_or_create(&path).unwrap();
+        let source = watch(path.clone(), config, |_| {});
+        let mut updates = source.subscribe();
+
+        let mut document: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        document["gesture"]["debounce_ms"] = 42.into();
+        std::fs::write(&path, document.to_string()).unwrap();
+
+        let reloaded = tokio::time::timeout(Duration::from_secs(5), updates.changed()).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(reloaded.is_ok_and(|changed| changed.is_ok()));
+        assert_eq!(source.current().gesture.debounce_ms, 42);
+    }
+}
@@ -0,0 +1,414 @@
+use anyhow::{bail, Context, Result};
+use evdev::{EventType, InputEvent};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::action_queue::ActionQueue;
+use crate::clock::{Clock, ManualClock};
+use crate::config::{Config, DeviceType, GestureConfig};
+use crate::device::{self, describe_gesture, MagicMouseModel};
+use crate::event_handler::{swipe_direction, EventHandler};
+use crate::multitouch::{
+    AxisInfo, DeviceInfo, MultiTouchEvent, MultiTouchProcessor, SurfaceBounds,
+};
+
+const ABS_MT_POSITION_X: u16 = 0x35;
+const ABS_MT_POSITION_Y: u16 = 0x36;
+
+/// A device and its events as recorded by `evemu-record`
+#[derive(Debug)]
+pub struct Recording {
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    /// Multi-touch position axes, if the recording describes them
+    pub axes: Option<SurfaceBounds>,
+    pub events: Vec<RecordedEvent>,
+    /// Outcomes listed in `# expect:` comments, for `verify`; `nothing`
+    /// expects no gesture at all
+    pub expected: Option<Vec<String>>,
+}
+
+/// One `E:` line, timed from the first event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedEvent {
+    pub time: Duration,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl RecordedEvent {
+    /// Process the event at its recorded time on `clock`, which stood at
+    /// `start` at the first event
+    async fn replay(
+        &self,
+        processor: &mut MultiTouchProcessor,
+        clock: &ManualClock,
+        start: Instant,
+    ) -> Vec<MultiTouchEvent> {
+        clock.advance((start + self.time).saturating_duration_since(clock.now()));
+        // Without a kernel timestamp the processor takes the time from the clock
+        let event = InputEvent::new(EventType(self.event_type), self.code, self.value);
+        let gestures = processor.process_event(event).await;
+        if processor.needs_resync() {
+            // There's no kernel slot state to read back
+            processor.resync(&[], 0);
+        }
+        gestures.unwrap_or_default()
+    }
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recording {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("Invalid recording {:?}", path))
+    }
+
+    /// Parse the evemu text format: `N:` name, `I:` bus, vendor, product and
+    /// version, `A:` axis code, min, max, fuzz, flat and resolution, and
+    /// `E:` seconds, type, code and value; everything after `#` is a comment
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut recording = Recording {
+            name: String::new(),
+            vendor: 0,
+            product: 0,
+            axes: None,
+            events: Vec::new(),
+            expected: None,
+        };
+        let (mut x, mut y) = (None, None);
+        let mut first_time = None;
+
+        for (number, line) in text.lines().enumerate() {
+            if let Some(expected) = line.strip_prefix("# expect:") {
+                let outcomes = expected
+                    .split([',', ' '])
+                    .filter(|name| !name.is_empty() && *name != "nothing");
+                let listed = recording.expected.get_or_insert_with(Vec::new);
+                listed.extend(outcomes.map(str::to_string));
+                continue;
+            }
+            let Some((kind, fields)) = line.split_once(':') else {
+                continue;
+            };
+            // Names may contain '#', so they're taken before stripping comments
+            if kind == "N" {
+                recording.name = fields.trim().to_string();
+                continue;
+            }
+            let fields: Vec<&str> = fields
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            let parsed = match kind {
+                "I" => parse_id(&fields).map(|(vendor, product)| {
+                    recording.vendor = vendor;
+                    recording.product = product;
+                }),
+                "A" => parse_axis(&fields).map(|(code, axis)| match code {
+                    ABS_MT_POSITION_X => x = Some(axis),
+                    ABS_MT_POSITION_Y => y = Some(axis),
+                    _ => {}
+                }),
+                "E" => parse_event(&fields).map(|(time, mut event)| {
+                    let first = *first_time.get_or_insert(time);
+                    event.time = time.saturating_sub(first);
+                    recording.events.push(event);
+                }),
+                _ => Ok(()),
+            };
+            parsed.with_context(|| format!("line {}: {:?}", number + 1, line))?;
+        }
+
+        if recording.events.is_empty() {
+            bail!("No events recorded");
+        }
+        if let (Some(x), Some(y)) = (x, y) {
+            recording.axes = Some(SurfaceBounds { x, y });
+        }
+        Ok(recording)
+    }
+
+    /// Multi-touch processor for the recorded device, its resolved type and
+    /// the clock it runs on
+    fn processor(
+        &self,
+        config: &GestureConfig,
+        device_type: DeviceType,
+    ) -> (DeviceType, MultiTouchProcessor, ManualClock) {
+        let device_type = device_type.resolve(&self.name);
+        let model = MagicMouseModel::from_ids(self.vendor, self.product);
+        let surface = device::surface_bounds(self.axes, device_type, model);
+        let clock = ManualClock::new();
+        let info = DeviceInfo::new(&self.name, surface);
+        let processor = device::gesture_processor(config, device_type, model, info)
+            .with_clock(Arc::new(clock.clone()));
+        (device_type, processor, clock)
+    }
+
+    /// What the recording is recognized as, without pausing between events;
+    /// see `outcome`
+    pub async fn outcomes(&self, config: &GestureConfig) -> Vec<String> {
+        let (_, mut processor, clock) = self.processor(config, DeviceType::Auto);
+        let start = clock.now();
+        let mut outcomes: Vec<String> = Vec::new();
+        for event in &self.events {
+            for gesture in event.replay(&mut processor, &clock, start).await {
+                let outcome = outcome(&gesture);
+                // Each frame of a scroll is an event of its own
+                if outcome == "scroll" && outcomes.last().is_some_and(|last| last == "scroll") {
+                    continue;
+                }
+                outcomes.push(outcome);
+            }
+        }
+        outcomes
+    }
+}
+
+fn describe_outcomes(outcomes: &[String]) -> String {
+    if outcomes.is_empty() {
+        "nothing".to_string()
+    } else {
+        outcomes.join(" ")
+    }
+}
+
+/// Name of a recognized gesture as written in `# expect:` annotations: the
+/// action it triggers (`tap_2finger`, `swipe_left_2finger`, `pinch_in`, a
+/// plugin gesture's name) or `scroll` for a run of scroll frames
+pub fn outcome(event: &MultiTouchEvent) -> String {
+    match event {
+        MultiTouchEvent::SingleFingerTap { .. } => "tap_1finger".to_string(),
+        MultiTouchEvent::TwoFingerTap { .. } => "tap_2finger".to_string(),
+        MultiTouchEvent::TwoFingerSwipe {
+            delta_x, delta_y, ..
+        } => format!("swipe_{}_2finger", swipe_direction(*delta_x, *delta_y)),
+        MultiTouchEvent::Scroll { .. } => "scroll".to_string(),
+        MultiTouchEvent::Pinch { scale_factor, .. } if *scale_factor > 1.0 => {
+            "pinch_out".to_string()
+        }
+        MultiTouchEvent::Pinch { .. } => "pinch_in".to_string(),
+        MultiTouchEvent::Dwell { .. } => "dwell_1finger".to_string(),
+        MultiTouchEvent::LongPress { .. } => "long_press_1finger".to_string(),
+        MultiTouchEvent::Custom { name, .. } => name.clone(),
+    }
+}
+
+fn parse_id(fields: &[&str]) -> Result<(u16, u16)> {
+    let [_, vendor, product, ..] = fields else {
+        bail!("Expected bus, vendor, product and version");
+    };
+    Ok((
+        u16::from_str_radix(vendor, 16)?,
+        u16::from_str_radix(product, 16)?,
+    ))
+}
+
+fn parse_axis(fields: &[&str]) -> Result<(u16, AxisInfo)> {
+    let [code, min, max, _fuzz, _flat, rest @ ..] = fields else {
+        bail!("Expected code, min, max, fuzz and flat");
+    };
+    Ok((
+        u16::from_str_radix(code, 16)?,
+        AxisInfo {
+            min: min.parse()?,
+            max: max.parse()?,
+            // Recordings made before evemu 1.1 have no resolution
+            resolution: rest
+                .first()
+                .map(|res| res.parse())
+                .transpose()?
+                .unwrap_or(0),
+        },
+    ))
+}
+
+fn parse_event(fields: &[&str]) -> Result<(Duration, RecordedEvent)> {
+    let [time, event_type, code, value] = fields else {
+        bail!("Expected time, type, code and value");
+    };
+    let (secs, micros) = time.split_once('.').unwrap_or((time, "0"));
+    let micros = format!("{:0<6}", micros);
+    let time = Duration::from_secs(secs.parse()?) + Duration::from_micros(micros[..6].parse()?);
+    Ok((
+        time,
+        RecordedEvent {
+            time: Duration::ZERO,
+            event_type: u16::from_str_radix(event_type, 16)?,
+            code: u16::from_str_radix(code, 16)?,
+            value: value.parse()?,
+        },
+    ))
+}
+
+/// Feed a recording through gesture recognition and print what's recognized
+///
+/// `speed` scales the recorded pace (2 replays twice as fast, 0 as fast as
+/// possible); recognition keeps the recorded timing either way. With
+/// `execute` the configured actions run as well.
+pub async fn run(
+    path: &Path,
+    config: &Config,
+    device_type: DeviceType,
+    speed: f64,
+    execute: bool,
+) -> Result<()> {
+    let recording = Recording::load(path)?;
+    let (device_type, mut processor, clock) = recording.processor(&config.gesture, device_type);
+
+    let event_handler = Arc::new(EventHandler::new(config.clone()));
+    let queue = execute.then(|| ActionQueue::new(config.execution.queue_size));
+
+    let length = recording.events.last().map(|event| event.time);
+    println!(
+        "Replaying {} events ({:.1}s) recorded from {} as {:?}",
+        recording.events.len(),
+        length.unwrap_or_default().as_secs_f64(),
+        recording.name,
+        device_type
+    );
+
+    let started = Instant::now();
+    let start = clock.now();
+    let mut recognized = 0;
+    for event in &recording.events {
+        if speed > 0.0 {
+            tokio::time::sleep_until((started + event.time.div_f64(speed)).into()).await;
+        }
+
+        for gesture in event.replay(&mut processor, &clock, start).await {
+            recognized += 1;
+            println!(
+                "{:>9.3}s  {}",
+                event.time.as_secs_f64(),
+                describe_gesture(&gesture)
+            );
+            if let Some(queue) = &queue {
+                queue.push(
+                    &event_handler,
+                    gesture,
+                    processor.modifiers(),
+                    processor.input_time(),
+                )?;
+            }
+        }
+    }
+
+    if let Some(queue) = queue {
+        queue.flush().await;
+    }
+    println!("{} gestures recognized", recognized);
+    Ok(())
+}
+
+/// Replay every `.evemu` recording in `dir` with the built-in gesture
+/// settings and compare what's recognized with its `# expect:` annotations
+///
+/// A recording without annotations counts as failed, so a new recording
+/// can't silently go unchecked.
+pub async fn verify(dir: &Path) -> Result<()> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {:?}", dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "evemu"))
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        bail!("No .evemu recordings in {:?}", dir);
+    }
+
+    let config = Config::default().gesture;
+    let mut failed = 0;
+    for path in &paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (recording, expected) = match Recording::load(path) {
+            Ok(Recording { expected: None, .. }) => {
+                println!("FAIL {}: no `# expect:` annotation", name);
+                failed += 1;
+                continue;
+            }
+            Ok(mut recording) => {
+                let expected = recording.expected.take().unwrap_or_default();
+                (recording, expected)
+            }
+            Err(e) => {
+                println!("FAIL {}: {:#}", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let outcomes = recording.outcomes(&config).await;
+        if outcomes == expected {
+            println!("ok   {}", name);
+        } else {
+            println!(
+                "FAIL {}: expected {}, recognized {}",
+                name,
+                describe_outcomes(&expected),
+                describe_outcomes(&outcomes)
+            );
+            failed += 1;
+        }
+    }
+
+    println!("{} passed, {} failed", paths.len() - failed, failed);
+    if failed > 0 {
+        bail!("{} of {} recordings changed", failed, paths.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recording() {
+        let recording = Recording::parse(
+            "# EVEMU 1.3\n\
+             # expect: scroll, swipe_left_2finger\n\
+             N: Apple Inc. Magic Mouse\n\
+             I: 0005 004c 0269 0001\n\
+             A: 2f 0 15 0 0 0\n\
+             A: 35 -1100 1258 4 0 26\n\
+             A: 36 -1589 2047 4 0 70\n\
+             ################################\n\
+             E: 12.500000 0003 0039 0042\t# EV_ABS / ABS_MT_TRACKING_ID 42\n\
+             E: 12.500000 0003 0035 -120\t# EV_ABS / ABS_MT_POSITION_X -120\n\
+             E: 12.5125 0000 0000 0000\t# ------------ SYN_REPORT (0) ----------\n",
+        )
+        .unwrap();
+
+        assert_eq!(recording.name, "Apple Inc. Magic Mouse");
+        assert_eq!((recording.vendor, recording.product), (0x004c, 0x0269));
+        assert_eq!(
+            recording.axes.map(|axes| (axes.x.min, axes.y.resolution)),
+            Some((-1100, 70))
+        );
+        assert_eq!(recording.events.len(), 3);
+        assert_eq!(
+            recording.events[1],
+            RecordedEvent {
+                time: Duration::ZERO,
+                event_type: 3,
+                code: 0x35,
+                value: -120,
+            }
+        );
+        assert_eq!(recording.events[2].time, Duration::from_micros(12_500));
+        assert_eq!(
+            recording.expected,
+            Some(vec!["scroll".to_string(), "swipe_left_2finger".to_string()])
+        );
+
+        assert!(Recording::parse("N: nothing recorded\n").is_err());
+        assert!(Recording::parse("E: 0.1 0003 zz 1\n").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::future::Future;
+use std::io;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Handle};
+use tokio::task::JoinHandle;
+
+use crate::config::SchedulingConfig;
+
+/// Runtime of the thread reading input events, when `init` gave it its own
+static READER_RUNTIME: OnceLock<Handle> = OnceLock::new();
+
+/// Start a thread for device readers with the priority `config` asks for
+///
+/// Without a request, readers share the main runtime's threads. Failing to
+/// raise the priority only warns: the thread then reads at normal priority.
+pub fn init(config: &SchedulingConfig) {
+    if config.realtime_priority.is_none() && config.nice.is_none() {
+        return;
+    }
+
+    let config = config.clone();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let spawned = std::thread::Builder::new()
+        .name("input-reader".to_string())
+        .spawn(move || {
+            raise_priority(&config);
+            let runtime = match Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    warn!("Failed to start the input reader runtime: {}", e);
+                    return;
+                }
+            };
+            let _ = sender.send(runtime.handle().clone());
+            // Tasks spawned through the handle run while this blocks
+            runtime.block_on(std::future::pending::<()>());
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start the input reader thread: {}", e);
+        return;
+    }
+    if let Ok(handle) = receiver.recv() {
+        let _ = READER_RUNTIME.set(handle);
+    }
+}
+
+/// Run a device reader task, on the thread started by `init` if there is one
+pub fn spawn_reader<F>(reader: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match READER_RUNTIME.get() {
+        Some(handle) => handle.spawn(reader),
+        None => tokio::spawn(reader),
+    }
+}
+
+/// Apply `config` to the calling thread
+fn raise_priority(config: &SchedulingConfig) {
+    if let Some(priority) = config.realtime_priority {
+        match set_realtime(priority) {
+            Ok(()) => {
+                info!("Reading input with SCHED_FIFO priority {}", priority);
+                return;
+            }
+            Err(e) => warn!(
+                "Failed to set SCHED_FIFO priority {} for reading input: {} \
+                 (needs CAP_SYS_NICE or an RLIMIT_RTPRIO, e.g. LimitRTPRIO= in the service)",
+                priority, e
+            ),
+        }
+    }
+    if let Some(nice) = config.nice {
+        match set_nice(nice) {
+            Ok(()) => info!("Reading input with niceness {}", nice),
+            Err(e) => warn!(
+                "Failed to set niceness {} for reading input: {} \
+                 (negative values need CAP_SYS_NICE or an RLIMIT_NICE)",
+                nice, e
+            ),
+        }
+    }
+}
+
+fn set_realtime(priority: i32) -> Result<()> {
+    let (min, max) = unsafe {
+        (
+            libc::sched_get_priority_min(libc::SCHED_FIFO),
+            libc::sched_get_priority_max(libc::SCHED_FIFO),
+        )
+    };
+    if !(min..=max).contains(&priority) {
+        bail!("priority must be between {} and {}", min, max);
+    }
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // Only this thread: actions and recognition keep their normal priority
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result).into());
+    }
+    Ok(())
+}
+
+fn set_nice(nice: i32) -> Result<()> {
+    // On Linux the niceness belongs to the thread, identified by its ID
+    let thread = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, thread, nice) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_realtime_priority_range() {
+        let error = set_realtime(0).unwrap_err().to_string();
+        assert!(
+            error.starts_with("priority must be between 1 and"),
+            "{}",
+            error
+        );
+    }
+}
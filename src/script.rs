@@ -0,0 +1,347 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use serde_json::{json, Value};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::config::Action;
+use crate::multitouch::{MultiTouchEvent, TouchContact};
+use crate::paths;
+
+/// Operations a script may run per gesture before it is stopped, so a
+/// runaway loop can't stall gesture handling
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Runs `script:` actions with an embedded Rhai interpreter
+///
+/// Each script file is compiled once. On every invocation it sees the action
+/// name as `action`, the gesture as `gesture` (`()` for actions without one)
+/// and a `state` map that is kept between invocations, and evaluates to the
+/// action to run: a command, an array for a chain, or `()` to do nothing.
+pub struct ScriptRunner {
+    engine: Engine,
+    scripts: Mutex<HashMap<String, Script>>,
+}
+
+struct Script {
+    ast: AST,
+    /// What the script stored in `state` so far
+    state: Map,
+}
+
+impl ScriptRunner {
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!("Script: {}", text));
+        engine.on_debug(|text, _, position| debug!("Script at {}: {}", position, text));
+        Self {
+            engine,
+            scripts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Ask a script what to do for `action_name`
+    pub fn run(
+        &self,
+        script: &str,
+        action_name: &str,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<Option<Action>> {
+        let mut scripts = self.scripts.lock().unwrap_or_else(|e| e.into_inner());
+        let loaded = match scripts.entry(script.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Script::load(&self.engine, script)?),
+        };
+        loaded.run(&self.engine, action_name, gesture)
+    }
+}
+
+impl Script {
+    fn load(engine: &Engine, script: &str) -> Result<Self> {
+        let path = script_path(script);
+        info!("Loading action script: {:?}", path);
+        let ast = engine
+            .compile_file(path.clone())
+            .map_err(|e| anyhow!("Failed to load script {:?}: {}", path, e))?;
+        Ok(Self {
+            ast,
+            state: Map::new(),
+        })
+    }
+
+    fn run(
+        &mut self,
+        engine: &Engine,
+        action_name: &str,
+        gesture: Option<&MultiTouchEvent>,
+    ) -> Result<Option<Action>> {
+        let gesture = match gesture {
+            Some(gesture) => rhai::serde::to_dynamic(gesture_json_with_archive(gesture))
+                .map_err(|e| anyhow!("Failed to pass the gesture to the script: {}", e))?,
+            None => Dynamic::UNIT,
+        };
+        let mut scope = Scope::new();
+        scope.push_constant("action", action_name.to_string());
+        scope.push_constant("gesture", gesture);
+        scope.push("state", std::mem::take(&mut self.state));
+
+        let result = engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        // Keep what the script stored, even when it failed afterwards
+        if let Some(state) = scope.get_value::<Map>("state") {
+            self.state = state;
+        }
+        let reply = result.map_err(|e| anyhow!("Script failed: {}", e))?;
+
+        debug!("Script returned: {}", reply);
+        let reply: Value = rhai::serde::from_dynamic(&reply)
+            .map_err(|e| anyhow!("Invalid action from script: {}", e))?;
+        parse_reply(reply)
+    }
+}
+
+/// Scripts given by a relative path are looked up in the config directory
+fn script_path(script: &str) -> PathBuf {
+    let path = PathBuf::from(script.trim());
+    match paths::config_dir() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    }
+}
+
+/// Nothing, a command, or an action object such as an array of chain steps
+fn parse_reply(reply: Value) -> Result<Option<Action>> {
+    match reply {
+        Value::Null => Ok(None),
+        Value::String(command) if command.trim().is_empty() => Ok(None),
+        Value::String(command) if command.starts_with("script:") => {
+            warn!("Scripts can't answer with another script: {}", command);
+            Ok(None)
+        }
+        Value::String(command) => Ok(Some(Action::from(command.as_str()))),
+        Value::Array(_) | Value::Object(_) => {
+            let action = serde_json::from_value(reply.clone())
+                .with_context(|| format!("Invalid action from script: {}", reply))?;
+            Ok(Some(action))
+        }
+        _ => Err(anyhow!(
+            "Scripts must return a command, an action or (): {}",
+            reply
+        )),
+    }
+}
+
+/// JSON description of a gesture for subscribers, D-Bus signals and `MG_*`
+/// variables
+pub fn gesture_json(event: &MultiTouchEvent) -> Value {
+    describe(event, false)
+}
+
+/// `gesture_json` with each finger's archived axis changes as `events`, for
+/// scripts and contact subscribers
+pub fn gesture_json_with_archive(event: &MultiTouchEvent) -> Value {
+    describe(event, true)
+}
+
+fn describe(event: &MultiTouchEvent, archive: bool) -> Value {
+    match event {
+        MultiTouchEvent::SingleFingerTap {
+            finger,
+            duration_ms,
+        } => json!({
+            "type": "single_finger_tap",
+            "fingers": [contact_json(finger, archive)],
+            "duration_ms": duration_ms,
+        }),
+        MultiTouchEvent::TwoFingerTap {
+            finger1,
+            finger2,
+            duration_ms,
+        } => json!({
+            "type": "two_finger_tap",
+            "fingers": [contact_json(finger1, archive), contact_json(finger2, archive)],
+            "duration_ms": duration_ms,
+        }),
+        MultiTouchEvent::TwoFingerSwipe {
+            finger1,
+            finger2,
+            delta_x,
+            delta_y,
+        } => json!({
+            "type": "two_finger_swipe",
+            "fingers": [contact_json(finger1, archive), contact_json(finger2, archive)],
+            "delta_x": delta_x,
+            "delta_y": delta_y,
+        }),
+        MultiTouchEvent::Scroll { delta_x, delta_y } => json!({
+            "type": "scroll",
+            "delta_x": delta_x,
+            "delta_y": delta_y,
+        }),
+        MultiTouchEvent::Pinch {
+            center_x,
+            center_y,
+            scale_factor,
+        } => json!({
+            "type": "pinch",
+            "center_x": center_x,
+            "center_y": center_y,
+            "scale_factor": scale_factor,
+        }),
+        MultiTouchEvent::Dwell {
+            finger,
+            duration_ms,
+        } => json!({
+            "type": "dwell",
+            "fingers": [contact_json(finger, archive)],
+            "duration_ms": duration_ms,
+        }),
+        MultiTouchEvent::LongPress {
+            finger,
+            duration_ms,
+        } => json!({
+            "type": "long_press",
+            "fingers": [contact_json(finger, archive)],
+            "duration_ms": duration_ms,
+        }),
+        MultiTouchEvent::Custom { name, fingers } => json!({
+            "type": "custom",
+            "name": name,
+            "fingers": fingers.iter().map(|finger| contact_json(finger, archive)).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+/// Action name and the gesture's scalar details as `MG_*` environment
+/// variables for spawned commands, e.g. `MG_DELTA_X`; `MG_FINGERS` is the
+/// finger count
+pub fn gesture_env(action_name: &str, gesture: Option<&MultiTouchEvent>) -> Vec<(String, String)> {
+    let mut env = vec![("MG_ACTION".to_string(), action_name.to_string())];
+    let Some(Value::Object(fields)) = gesture.map(gesture_json) else {
+        return env;
+    };
+
+    for (key, value) in fields {
+        let value = match value {
+            Value::String(text) => text,
+            Value::Number(number) => number.to_string(),
+            Value::Array(items) if key == "fingers" => items.len().to_string(),
+            _ => continue,
+        };
+        env.push((format!("MG_{}", key.to_uppercase()), value));
+    }
+    env
+}
+
+fn contact_json(contact: &TouchContact, archive: bool) -> Value {
+    let (x_mm, y_mm) = contact.position_mm();
+    let (velocity_x, velocity_y) = contact.velocity_mm();
+    let mut json = json!({
+        "slot": contact.slot,
+        "x": contact.x,
+        "y": contact.y,
+        "x_mm": x_mm,
+        "y_mm": y_mm,
+        "normalized_x": contact.normalized_x,
+        "normalized_y": contact.normalized_y,
+        "touch_major": contact.touch_major,
+        "touch_minor": contact.touch_minor,
+        "orientation": contact.orientation,
+        "pressure": contact.pressure,
+        "duration_ms": contact.contact_duration().as_millis() as u64,
+        "velocity_x": velocity_x,
+        "velocity_y": velocity_y,
+    });
+    if archive {
+        json["events"] = json!(contact.archive);
+    }
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ActionStep;
+
+    #[test]
+    fn test_parse_script_reply() {
+        assert_eq!(parse_reply(Value::Null).unwrap(), None);
+        assert_eq!(parse_reply(json!("")).unwrap(), None);
+        assert_eq!(
+            parse_reply(json!("key:ctrl+t")).unwrap(),
+            Some(Action::from("key:ctrl+t"))
+        );
+        assert_eq!(
+            parse_reply(json!(["click", {"delay_ms": 10}])).unwrap(),
+            Some(Action::Chain(vec![
+                ActionStep::Command("click".to_string()),
+                ActionStep::Delay { delay_ms: 10 },
+            ]))
+        );
+        assert_eq!(parse_reply(json!("script:other.rhai")).unwrap(), None);
+        assert!(parse_reply(json!(42)).is_err());
+    }
+
+    #[test]
+    fn test_script_keeps_state() {
+        let path = std::env::temp_dir().join(format!("mouse-gesture-{}.rhai", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            state.scrolls = (state.scrolls ?? 0) + 1;
+            if gesture.delta_x < 0.0 && state.scrolls > 1 {
+                [action, "key:alt+Left"]
+            } else if gesture.type == "scroll" {
+                "key:alt+Right"
+            }
+            "#,
+        )
+        .unwrap();
+
+        let runner = ScriptRunner::new();
+        let script = path.to_str().unwrap();
+        let scroll = MultiTouchEvent::Scroll {
+            delta_x: -5.0,
+            delta_y: 0.0,
+        };
+        let first = runner.run(script, "scroll_horizontal", Some(&scroll));
+        let second = runner.run(script, "scroll_horizontal", Some(&scroll));
+        let missing = runner.run(script, "scroll_horizontal", None);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.unwrap(), Some(Action::from("key:alt+Right")));
+        assert_eq!(
+            second.unwrap(),
+            Some(Action::Chain(vec![
+                ActionStep::Command("scroll_horizontal".to_string()),
+                ActionStep::Command("key:alt+Left".to_string()),
+            ]))
+        );
+        // No gesture to look at: the script fails
+        assert!(missing.is_err());
+        assert!(runner
+            .run("/nonexistent.rhai", "scroll_horizontal", None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_gesture_env() {
+        let scroll = MultiTouchEvent::Scroll {
+            delta_x: 1.5,
+            delta_y: 0.0,
+        };
+        let env = gesture_env("scroll_horizontal", Some(&scroll));
+        let var = |name: &str| {
+            env.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(var("MG_ACTION"), Some("scroll_horizontal"));
+        assert_eq!(var("MG_TYPE"), Some("scroll"));
+        assert_eq!(var("MG_DELTA_X"), Some("1.5"));
+        assert_eq!(var("MG_FINGERS"), None);
+    }
+}
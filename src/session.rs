@@ -0,0 +1,278 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedObjectPath;
+
+use crate::config::{Config, OutputBackend};
+use crate::reload::ConfigSource;
+
+/// How often logind is asked whether the session changed
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_user(&self, uid: u32) -> zbus::Result<OwnedObjectPath>;
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.User",
+    default_service = "org.freedesktop.login1"
+)]
+trait User {
+    /// The user's graphical session as (id, path), path `/` if there is none
+    #[zbus(property)]
+    fn display(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+trait Session {
+    #[zbus(property, name = "Type")]
+    fn kind(&self) -> zbus::Result<String>;
+}
+
+lazy_static! {
+    /// Session the daemon's user is in, kept up to date by `monitor`
+    static ref CURRENT: watch::Sender<SessionType> = watch::channel(SessionType::Unknown).0;
+}
+
+/// Kind of session the daemon's user is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionType {
+    Wayland,
+    X11,
+    /// Text console without a graphical session
+    Tty,
+    Unknown,
+}
+
+impl fmt::Display for SessionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionType::Wayland => write!(f, "Wayland"),
+            SessionType::X11 => write!(f, "X11"),
+            SessionType::Tty => write!(f, "TTY"),
+            SessionType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl SessionType {
+    /// Parse a logind session type or an `XDG_SESSION_TYPE` value
+    fn parse(value: &str) -> Self {
+        match value {
+            "wayland" | "mir" => SessionType::Wayland,
+            "x11" => SessionType::X11,
+            "tty" => SessionType::Tty,
+            _ => SessionType::Unknown,
+        }
+    }
+}
+
+/// The session as last detected
+pub fn current() -> SessionType {
+    *CURRENT.borrow()
+}
+
+/// Detect the session: the user's graphical session according to logind,
+/// otherwise the environment the daemon was started in
+pub async fn detect() -> SessionType {
+    let detected = async { from_logind(&zbus::Connection::system().await?).await };
+    match detected.await {
+        Ok(SessionType::Unknown) => from_environment(),
+        Ok(session) => session,
+        Err(e) => {
+            debug!("Session type unavailable from logind: {:#}", e);
+            from_environment()
+        }
+    }
+}
+
+/// Session type from `XDG_SESSION_TYPE`, `WAYLAND_DISPLAY` or `DISPLAY`
+pub fn from_environment() -> SessionType {
+    let session = std::env::var("XDG_SESSION_TYPE")
+        .map(|value| SessionType::parse(&value))
+        .unwrap_or(SessionType::Unknown);
+    if session != SessionType::Unknown {
+        return session;
+    }
+
+    let is_set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    if is_set("WAYLAND_DISPLAY") {
+        SessionType::Wayland
+    } else if is_set("DISPLAY") {
+        SessionType::X11
+    } else {
+        SessionType::Unknown
+    }
+}
+
+/// Type of the user's graphical session (logind's `Display` session), or of
+/// the session the daemon was started in if the user has none
+async fn from_logind(bus: &zbus::Connection) -> Result<SessionType> {
+    // Properties are read once per check, so don't follow their changes
+    let manager = ManagerProxy::builder(bus)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    // SAFETY: getuid can't fail
+    let uid = unsafe { libc::getuid() };
+    let user = UserProxy::builder(bus)
+        .path(manager.get_user(uid).await?)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+
+    let (_, display) = user.display().await?;
+    let mut session = Some(display).filter(|path| path.as_str() != "/");
+    if session.is_none() {
+        if let Ok(id) = std::env::var("XDG_SESSION_ID") {
+            session = Some(manager.get_session(&id).await?);
+        }
+    }
+    // Logged in without any session of its own, e.g. a lingering service
+    let Some(session) = session else {
+        return Ok(SessionType::Tty);
+    };
+
+    let session = SessionProxy::builder(bus)
+        .path(session)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    Ok(SessionType::parse(&session.kind().await?))
+}
+
+/// Detect the session before devices start and warn about actions it can't run
+pub async fn init(config: &Config) {
+    let session = detect().await;
+    info!("Running in a {} session", session);
+    CURRENT.send_replace(session);
+    for problem in problems(session, config) {
+        warn!("{}", problem);
+    }
+}
+
+/// Follow session changes through logind, warning about actions that stop
+/// working in the new session
+pub async fn monitor(configs: Arc<ConfigSource>) {
+    let mut bus = None;
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        if bus.is_none() {
+            bus = zbus::Connection::system().await.ok();
+        }
+        // Without logind the session can't change under the daemon
+        let Some(connection) = &bus else {
+            continue;
+        };
+        let session = match from_logind(connection).await {
+            Ok(session) => session,
+            Err(e) => {
+                debug!("Session type unavailable from logind: {:#}", e);
+                bus = None;
+                continue;
+            }
+        };
+        if session == SessionType::Unknown || session == current() {
+            continue;
+        }
+        info!("Session changed from {} to {}", current(), session);
+        CURRENT.send_replace(session);
+        for problem in problems(session, &configs.current()) {
+            warn!("{}", problem);
+        }
+    }
+}
+
+/// Settings and actions of `config` that can't work in `session`
+fn problems(session: SessionType, config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let backend = config.output.backend;
+    match (backend, session) {
+        (
+            OutputBackend::Xtest | OutputBackend::Xdotool,
+            SessionType::Wayland | SessionType::Tty,
+        ) => {
+            problems.push(format!(
+                "The {} output backend needs an X11 session, but this is a {} session: \
+                 clicks and key: actions won't arrive; use the auto, uinput or ydotool backend",
+                format!("{:?}", backend).to_lowercase(),
+                session
+            ));
+        }
+        (OutputBackend::Wtype, SessionType::X11 | SessionType::Tty) => {
+            problems.push(format!(
+                "The wtype output backend needs a Wayland session, but this is a {} session; \
+                 use the auto, uinput or ydotool backend",
+                session
+            ));
+        }
+        _ => {}
+    }
+
+    if matches!(session, SessionType::Wayland | SessionType::Tty) {
+        let profiles = config.profiles.values().map(|profile| &profile.actions);
+        let xdotool: BTreeSet<&str> = std::iter::once(&config.actions)
+            .chain(profiles)
+            .flatten()
+            .filter(|(_, action)| action.to_string().contains("xdotool "))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !xdotool.is_empty() {
+            let reach = match session {
+                SessionType::Wayland => "only reaches XWayland windows",
+                _ => "does nothing without an X11 session",
+            };
+            problems.push(format!(
+                "xdotool in {} {} in a {} session; use key: or click actions instead",
+                xdotool.into_iter().collect::<Vec<_>>().join(", "),
+                reach,
+                session
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Action;
+
+    #[test]
+    fn test_problems_per_session() {
+        let mut config = Config::default();
+        assert!(problems(SessionType::Wayland, &config).is_empty());
+
+        config.actions.insert(
+            "swipe_up_2finger".to_string(),
+            Action::from("xdotool key super"),
+        );
+        config.output.backend = OutputBackend::Xdotool;
+        assert!(problems(SessionType::X11, &config).is_empty());
+
+        let wayland = problems(SessionType::Wayland, &config);
+        assert_eq!(wayland.len(), 2, "{:?}", wayland);
+        assert!(wayland[0].starts_with("The xdotool output backend needs an X11 session"));
+        assert!(wayland[1].starts_with("xdotool in swipe_up_2finger only reaches XWayland"));
+
+        config.output.backend = OutputBackend::Wtype;
+        assert_eq!(problems(SessionType::Tty, &config).len(), 2);
+        assert_eq!(SessionType::parse("mir"), SessionType::Wayland);
+    }
+}
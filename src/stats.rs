@@ -0,0 +1,285 @@
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::multitouch::MultiTouchEvent;
+use crate::paths;
+
+/// How often changed statistics are written while running
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref STATS: Mutex<Stats> = Mutex::new(Stats::default());
+}
+/// Whether anything was recorded since the last save
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// How gestures were used, accumulated across restarts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// When collection started, in seconds since the epoch
+    #[serde(default)]
+    pub since: u64,
+    /// By action name, e.g. `swipe_left_2finger`
+    #[serde(default)]
+    pub gestures: BTreeMap<String, GestureStats>,
+    /// Touch sessions that ended without any gesture, by the most fingers
+    /// that were down, e.g. `2finger`
+    #[serde(default)]
+    pub false_starts: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GestureStats {
+    pub recognized: u64,
+    /// Actions that failed or timed out
+    #[serde(default)]
+    pub failures: u64,
+    /// Sum of the gestures' magnitudes, in `unit`
+    #[serde(default)]
+    pub magnitude_sum: f64,
+    /// `ms` of tap duration, `mm` of swipe travel or `%` of pinch scale
+    /// change; empty for gestures without a magnitude
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub unit: String,
+}
+
+impl GestureStats {
+    pub fn average_magnitude(&self) -> Option<f64> {
+        (!self.unit.is_empty() && self.recognized > 0)
+            .then(|| self.magnitude_sum / self.recognized as f64)
+    }
+}
+
+impl Stats {
+    fn record(&mut self, action_name: &str, event: &MultiTouchEvent) {
+        let entry = self.gestures.entry(action_name.to_string()).or_default();
+        entry.recognized += 1;
+        if let Some((magnitude, unit)) = magnitude(event) {
+            entry.magnitude_sum += magnitude;
+            entry.unit = unit.to_string();
+        }
+    }
+}
+
+/// Size of a gesture in the unit that matters for its thresholds
+fn magnitude(event: &MultiTouchEvent) -> Option<(f64, &'static str)> {
+    match event {
+        MultiTouchEvent::SingleFingerTap { duration_ms, .. }
+        | MultiTouchEvent::TwoFingerTap { duration_ms, .. }
+        | MultiTouchEvent::Dwell { duration_ms, .. }
+        | MultiTouchEvent::LongPress { duration_ms, .. } => Some((*duration_ms as f64, "ms")),
+        MultiTouchEvent::TwoFingerSwipe {
+            delta_x, delta_y, ..
+        } => Some((delta_x.hypot(*delta_y), "mm")),
+        MultiTouchEvent::Pinch { scale_factor, .. } => {
+            Some(((scale_factor - 1.0).abs() * 100.0, "%"))
+        }
+        MultiTouchEvent::Scroll { .. } | MultiTouchEvent::Custom { .. } => None,
+    }
+}
+
+fn update(f: impl FnOnce(&mut Stats)) {
+    let mut stats = STATS.lock().unwrap_or_else(|e| e.into_inner());
+    if stats.since == 0 {
+        stats.since = unix_time();
+    }
+    f(&mut stats);
+    CHANGED.store(true, Ordering::Relaxed);
+}
+
+/// `action_name` was recognized, as `event`
+pub fn recognized(action_name: &str, event: &MultiTouchEvent) {
+    update(|stats| stats.record(action_name, event));
+}
+
+pub fn action_failed(action_name: &str) {
+    update(|stats| {
+        stats
+            .gestures
+            .entry(action_name.to_string())
+            .or_default()
+            .failures += 1
+    });
+}
+
+/// Fingers touched and lifted without any gesture being recognized
+pub fn false_start(fingers: usize) {
+    update(|stats| {
+        *stats
+            .false_starts
+            .entry(format!("{}finger", fingers))
+            .or_default() += 1
+    });
+}
+
+/// Follows one device's touch sessions to spot false starts
+#[derive(Debug, Default)]
+pub struct SessionTracker {
+    touching: bool,
+    /// Most fingers down at once in this session
+    fingers: usize,
+    recognized: bool,
+}
+
+impl SessionTracker {
+    /// After each event, with the fingers now on the surface
+    pub fn update(&mut self, fingers: usize) {
+        if fingers > 0 {
+            if !self.touching {
+                *self = Self {
+                    touching: true,
+                    ..Self::default()
+                };
+            }
+            self.fingers = self.fingers.max(fingers);
+        } else if std::mem::take(&mut self.touching) && !self.recognized {
+            false_start(self.fingers);
+        }
+    }
+
+    /// A gesture (scrolling included) came out of the current session
+    pub fn recognized(&mut self) {
+        self.recognized = true;
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `$XDG_STATE_HOME/mouse-gesture/stats.json`
+pub fn stats_file() -> Option<PathBuf> {
+    Some(paths::state_dir()?.join("stats.json"))
+}
+
+/// Statistics saved by earlier runs
+pub fn read() -> Result<Stats> {
+    let path = stats_file().context("HOME is not set")?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => {
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Stats::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    }
+}
+
+/// Continue from the saved statistics and keep saving them while running
+pub fn start() {
+    match read() {
+        Ok(saved) => *STATS.lock().unwrap_or_else(|e| e.into_inner()) = saved,
+        Err(e) => warn!("Starting gesture statistics over: {:#}", e),
+    }
+
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(SAVE_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = save() {
+                warn!("Failed to save gesture statistics: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Write the statistics if anything changed since the last save
+pub fn save() -> Result<()> {
+    if !CHANGED.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let path = stats_file().context("HOME is not set")?;
+    let content = serde_json::to_string_pretty(&*STATS.lock().unwrap_or_else(|e| e.into_inner()))?;
+    std::fs::create_dir_all(path.parent().unwrap_or(&path))?;
+    std::fs::write(&path, content + "\n").with_context(|| format!("Failed to write {:?}", path))?;
+    debug!("Saved gesture statistics to {:?}", path);
+    Ok(())
+}
+
+/// Print the saved statistics, for the `stats` subcommand
+pub fn show() -> Result<()> {
+    let stats = read()?;
+    if stats.gestures.is_empty() && stats.false_starts.is_empty() {
+        println!("No gestures recorded yet");
+        return Ok(());
+    }
+
+    let days = unix_time().saturating_sub(stats.since) as f64 / 86400.0;
+    println!("Over the last {:.1} days:", days);
+    println!();
+    println!(
+        "{:<32} {:>8} {:>8} {:>12}",
+        "Gesture", "Count", "Failed", "Average"
+    );
+    for (name, gesture) in &stats.gestures {
+        let average = match gesture.average_magnitude() {
+            Some(average) => format!("{:.1} {}", average, gesture.unit),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<32} {:>8} {:>8} {:>12}",
+            name, gesture.recognized, gesture.failures, average
+        );
+    }
+
+    if !stats.false_starts.is_empty() {
+        println!();
+        println!("Touches without a gesture:");
+        for (fingers, count) in &stats.false_starts {
+            println!("  {:<30} {:>8}", fingers, count);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_false_starts() {
+        let pinch = |scale_factor| MultiTouchEvent::Pinch {
+            center_x: 0.0,
+            center_y: 0.0,
+            scale_factor,
+        };
+        let mut stats = Stats::default();
+        stats.record("pinch_out", &pinch(1.5));
+        stats.record("pinch_out", &pinch(1.1));
+        let pinches = &stats.gestures["pinch_out"];
+        assert_eq!(pinches.recognized, 2);
+        assert!((pinches.average_magnitude().unwrap() - 30.0).abs() < 1e-9);
+        assert_eq!(pinches.unit, "%");
+
+        let false_starts = || {
+            STATS
+                .lock()
+                .unwrap()
+                .false_starts
+                .get("3finger")
+                .copied()
+                .unwrap_or(0)
+        };
+        let before = false_starts();
+        let mut session = SessionTracker::default();
+        for fingers in [1, 3, 2, 0] {
+            session.update(fingers);
+        }
+        for fingers in [2, 0] {
+            session.update(fingers);
+            session.recognized();
+        }
+        session.update(0);
+        assert_eq!(false_starts(), before + 1);
+    }
+}
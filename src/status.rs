@@ -0,0 +1,288 @@
+use lazy_static::lazy_static;
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+
+use crate::multitouch::TouchContact;
+
+/// Whether recognized gestures are dropped instead of run
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Paused for the focused window rather than by hand
+static AUTO_PAUSED: AtomicBool = AtomicBool::new(false);
+/// Subscribers following contact updates, who also get contact archives
+static CONTACT_SUBSCRIBERS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref STARTED: Instant = Instant::now();
+    static ref DEVICES: Mutex<BTreeMap<PathBuf, DeviceStatus>> = Mutex::new(BTreeMap::new());
+    /// How often each gesture was recognized, by action name
+    static ref GESTURES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+    static ref EVENTS: broadcast::Sender<StreamEvent> = broadcast::channel(256).0;
+    static ref SHUTDOWN: watch::Sender<bool> = watch::channel(false).0;
+    /// Whether grabbed devices should let go while auto-paused
+    static ref GRAB_RELEASED: watch::Sender<bool> = watch::channel(false).0;
+}
+
+/// A device gestures are being recognized on
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceStatus {
+    pub path: PathBuf,
+    pub name: String,
+    /// Fingers currently on the surface
+    pub contacts: Vec<ContactStatus>,
+}
+
+/// Snapshot of a touch contact
+#[derive(Debug, Clone, Serialize)]
+pub struct ContactStatus {
+    pub id: i32,
+    pub slot: i32,
+    pub x: i32,
+    pub y: i32,
+    /// Position from the top left corner in millimeters
+    pub x_mm: f64,
+    pub y_mm: f64,
+    /// Position on the surface from 0 to 1, for any device size
+    pub normalized_x: f64,
+    pub normalized_y: f64,
+    pub pressure: i32,
+    pub duration_ms: u64,
+    /// Current speed in millimeters per second
+    pub speed_mm_s: f64,
+}
+
+impl From<&TouchContact> for ContactStatus {
+    fn from(contact: &TouchContact) -> Self {
+        let (x_mm, y_mm) = contact.position_mm();
+        let (vx, vy) = contact.velocity_mm();
+        Self {
+            id: contact.id,
+            slot: contact.slot,
+            x: contact.x,
+            y: contact.y,
+            x_mm,
+            y_mm,
+            normalized_x: contact.normalized_x,
+            normalized_y: contact.normalized_y,
+            pressure: contact.pressure,
+            duration_ms: contact.contact_duration().as_millis() as u64,
+            speed_mm_s: vx.hypot(vy),
+        }
+    }
+}
+
+/// What subscribers of the control socket are told about as it happens
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A gesture was recognized; `action` is the binding it looks up
+    Gesture {
+        action: String,
+        gesture: Value,
+        /// `gesture` with the fingers' archived axis changes, sent instead to
+        /// contact subscribers
+        #[serde(skip)]
+        archived: Option<Value>,
+    },
+    /// The fingers on a device moved, landed or lifted
+    Contacts {
+        device: PathBuf,
+        contacts: Vec<ContactStatus>,
+    },
+    /// A device's recognition failed or panicked; it's restarted unless
+    /// it failed too often
+    TaskFailed {
+        task: String,
+        error: String,
+        restarting: bool,
+    },
+}
+
+/// Follow gestures and contact updates
+pub fn subscribe() -> broadcast::Receiver<StreamEvent> {
+    EVENTS.subscribe()
+}
+
+/// Whether anyone follows the event stream
+pub fn subscribed() -> bool {
+    EVENTS.receiver_count() > 0
+}
+
+/// Counts as a contact subscriber until dropped
+pub struct ContactSubscription(());
+
+impl Drop for ContactSubscription {
+    fn drop(&mut self) {
+        CONTACT_SUBSCRIBERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Register a subscriber that follows contact updates
+pub fn follow_contacts() -> ContactSubscription {
+    CONTACT_SUBSCRIBERS.fetch_add(1, Ordering::Relaxed);
+    ContactSubscription(())
+}
+
+/// Whether a subscriber follows contact updates, so gestures should come
+/// with their contact archives
+pub fn contacts_followed() -> bool {
+    CONTACT_SUBSCRIBERS.load(Ordering::Relaxed) > 0
+}
+
+fn publish(event: StreamEvent) {
+    // Fails only when nobody is listening
+    let _ = EVENTS.send(event);
+}
+
+/// Start counting uptime; called once at startup
+pub fn init() {
+    lazy_static::initialize(&STARTED);
+}
+
+pub fn uptime() -> Duration {
+    STARTED.elapsed()
+}
+
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed) || auto_paused()
+}
+
+pub fn auto_paused() -> bool {
+    AUTO_PAUSED.load(Ordering::Relaxed)
+}
+
+/// Stop or resume running gestures; touches are still tracked while paused.
+/// Resuming also lifts a pause for the focused window.
+pub fn set_paused(paused: bool) {
+    let was_paused = self::paused();
+    PAUSED.store(paused, Ordering::Relaxed);
+    if !paused {
+        AUTO_PAUSED.store(false, Ordering::Relaxed);
+        GRAB_RELEASED.send_if_modified(|released| std::mem::replace(released, false));
+    }
+    if was_paused != paused {
+        info!("Gestures {}", if paused { "paused" } else { "resumed" });
+    }
+}
+
+/// Pause or resume gestures for the focused window, letting go of device
+/// grabs meanwhile with `release_grab`
+pub fn set_auto_paused(paused: bool, release_grab: bool) {
+    let was_paused = self::paused();
+    AUTO_PAUSED.store(paused, Ordering::Relaxed);
+    let release = paused && release_grab;
+    GRAB_RELEASED.send_if_modified(|released| std::mem::replace(released, release) != release);
+    if was_paused != self::paused() {
+        info!(
+            "Gestures {} for the focused window",
+            if paused { "paused" } else { "resumed" }
+        );
+    }
+}
+
+/// Follow whether grabbed devices should let go of their grab
+pub fn subscribe_grab_released() -> watch::Receiver<bool> {
+    GRAB_RELEASED.subscribe()
+}
+
+/// Tell subscribers about a recognized gesture
+pub fn publish_gesture(action_name: &str, gesture: Value, archived: Option<Value>) {
+    publish(StreamEvent::Gesture {
+        action: action_name.to_string(),
+        gesture,
+        archived,
+    });
+}
+
+/// Count a recognized gesture
+pub fn count_gesture(action_name: &str) {
+    *GESTURES
+        .lock()
+        .unwrap()
+        .entry(action_name.to_string())
+        .or_default() += 1;
+}
+
+/// Tell subscribers that `task` failed with `error`
+pub fn task_failed(task: &str, error: &str, restarting: bool) {
+    publish(StreamEvent::TaskFailed {
+        task: task.to_string(),
+        error: error.to_string(),
+        restarting,
+    });
+}
+
+/// Recognized gestures since startup, by action name
+pub fn gesture_counts() -> BTreeMap<String, u64> {
+    GESTURES.lock().unwrap().clone()
+}
+
+/// Ask devices to finish their queued actions and let go of the hardware
+pub fn request_shutdown() {
+    SHUTDOWN.send_replace(true);
+}
+
+pub fn shutting_down() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// Wait until shutdown is requested
+pub async fn shutdown_requested() {
+    let _ = SHUTDOWN.subscribe().wait_for(|&requested| requested).await;
+}
+
+/// The devices being listened on, in path order
+pub fn devices() -> Vec<DeviceStatus> {
+    DEVICES.lock().unwrap().values().cloned().collect()
+}
+
+/// Listed among the active devices until dropped
+pub struct Registration {
+    path: PathBuf,
+}
+
+pub fn register(path: &Path, name: &str) -> Registration {
+    DEVICES.lock().unwrap().insert(
+        path.to_path_buf(),
+        DeviceStatus {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            contacts: Vec::new(),
+        },
+    );
+    Registration {
+        path: path.to_path_buf(),
+    }
+}
+
+impl Registration {
+    pub fn update_contacts(&self, contacts: &[&TouchContact]) {
+        let contacts: Vec<ContactStatus> = contacts.iter().map(|&contact| contact.into()).collect();
+        let mut devices = DEVICES.lock().unwrap();
+        let Some(device) = devices.get_mut(&self.path) else {
+            return;
+        };
+
+        // Nothing to tell while the surface stays untouched
+        let untouched = contacts.is_empty() && device.contacts.is_empty();
+        if !untouched && EVENTS.receiver_count() > 0 {
+            publish(StreamEvent::Contacts {
+                device: self.path.clone(),
+                contacts: contacts.clone(),
+            });
+        }
+        device.contacts = contacts;
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        DEVICES.lock().unwrap().remove(&self.path);
+    }
+}
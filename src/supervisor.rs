@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use log::error;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::metrics;
+use crate::status;
+
+/// How often a failing task is restarted before giving up on it
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Restarts allowed within `window`
+    pub max_restarts: usize,
+    pub window: Duration,
+    /// Wait before the first restart, doubled for each restart in a row
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window: Duration::from_secs(600),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Run the task `start` creates until it finishes cleanly, starting a new one
+/// whenever it returns an error or panics
+///
+/// Each failure is logged, counted and published to control socket
+/// subscribers. Once the policy's restarts are used up the last error is
+/// returned.
+pub async fn supervise<F, Fut>(name: &str, policy: RestartPolicy, mut start: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut restarts: VecDeque<Instant> = VecDeque::new();
+    let mut delay = policy.initial_delay;
+    loop {
+        // Running the attempt as its own task turns a panic into an error
+        let error = match tokio::spawn(start()).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => format!("{:#}", e),
+            Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+            Err(e) => return Err(e.into()),
+        };
+        if status::shutting_down() {
+            return Err(anyhow!(error));
+        }
+
+        let now = Instant::now();
+        while restarts
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > policy.window)
+        {
+            restarts.pop_front();
+        }
+        if restarts.len() >= policy.max_restarts {
+            status::task_failed(name, &error, false);
+            return Err(anyhow!(
+                "{} (gave up after {} restarts within {:?})",
+                error,
+                restarts.len(),
+                policy.window
+            ));
+        }
+        // A task that ran cleanly for a whole window starts over with a short delay
+        if restarts.is_empty() {
+            delay = policy.initial_delay;
+        }
+        restarts.push_back(now);
+
+        error!("{} failed, restarting in {:?}: {}", name, delay, error);
+        status::task_failed(name, &error, true);
+        metrics::restarted();
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = status::shutdown_requested() => return Ok(()),
+        }
+        delay = (delay * 2).min(policy.max_delay);
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_supervise_restarts_then_gives_up() {
+        let policy = RestartPolicy {
+            max_restarts: 2,
+            window: Duration::from_secs(60),
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        };
+
+        // Recovers after a panic and an error
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        let result = supervise("test", policy, move || {
+            let attempt = counted.fetch_add(1, Ordering::Relaxed);
+            async move {
+                match attempt {
+                    0 => panic!("first attempt"),
+                    1 => Err(anyhow!("second attempt")),
+                    _ => Ok(()),
+                }
+            }
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+
+        // Gives up once the restarts are used up
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        let result = supervise("test", policy, move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+            async { Err(anyhow!("always")) }
+        })
+        .await;
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.starts_with("always (gave up after 2 restarts"),
+            "{}",
+            error
+        );
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+}
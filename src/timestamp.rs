@@ -0,0 +1,46 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Serde representation of an `Instant`, for `#[serde(with = "timestamp")]`
+///
+/// An `Instant` means nothing outside the process, so it's written as how long
+/// ago it was in microseconds and read back relative to the time of reading.
+/// Timestamps serialized together keep their spacing.
+pub fn serialize<S: Serializer>(instant: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+    age_micros(*instant).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+    Ok(from_age_micros(u64::deserialize(deserializer)?))
+}
+
+fn age_micros(instant: Instant) -> u64 {
+    instant.elapsed().as_micros() as u64
+}
+
+fn from_age_micros(age: u64) -> Instant {
+    let now = Instant::now();
+    now.checked_sub(Duration::from_micros(age)).unwrap_or(now)
+}
+
+/// The same for a contact's `(x, y, time)` position history
+pub mod history {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        history: &VecDeque<(i32, i32, Instant)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(history.iter().map(|&(x, y, time)| (x, y, age_micros(time))))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<VecDeque<(i32, i32, Instant)>, D::Error> {
+        Ok(Vec::<(i32, i32, u64)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(x, y, age)| (x, y, from_age_micros(age)))
+            .collect())
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, InputEvent, InputId, Key,
+    PropType, UinputAbsSetup,
+};
+use log::{info, warn};
+use std::io;
+
+use crate::config::TouchpadConfig;
+use crate::multitouch::{MultiTouchEvent, SurfaceBounds, TouchContact, MAX_SLOTS};
+
+/// BTN_TOOL_* keys announcing one to five fingers, in that order
+const TOOL_KEYS: [Key; 5] = [
+    Key::BTN_TOOL_FINGER,
+    Key::BTN_TOOL_DOUBLETAP,
+    Key::BTN_TOOL_TRIPLETAP,
+    Key::BTN_TOOL_QUADTAP,
+    Key::BTN_TOOL_QUINTTAP,
+];
+
+/// Re-emits the Magic Mouse's touches through a uinput touchpad
+///
+/// libinput and the desktop then treat the touches like any touchpad's and
+/// apply their own scrolling and gestures. Only touches with at least
+/// `min_fingers` fingers are forwarded: a single finger resting on the mouse
+/// while it moves would otherwise drag the pointer along.
+pub struct VirtualTouchpad {
+    device: VirtualDevice,
+    frames: Frames,
+    suppress_actions: bool,
+}
+
+impl VirtualTouchpad {
+    /// Create the touchpad with the geometry of `surface`
+    pub fn new(surface: &SurfaceBounds, config: &TouchpadConfig) -> Result<Self> {
+        let mut keys = AttributeSet::<Key>::new();
+        // libinput expects a touchpad to have a button, even if it never clicks
+        keys.insert(Key::BTN_LEFT);
+        keys.insert(Key::BTN_TOUCH);
+        for key in TOOL_KEYS {
+            keys.insert(key);
+        }
+        let mut properties = AttributeSet::<PropType>::new();
+        properties.insert(PropType::POINTER);
+
+        let axis = |code, min, max, resolution| {
+            UinputAbsSetup::new(code, AbsInfo::new(0, min, max, 0, 0, resolution))
+        };
+        let (x, y) = (surface.x, surface.y);
+        let device = VirtualDeviceBuilder::new()
+            .context("Failed to open /dev/uinput")?
+            .name("Mouse Gesture Touchpad")
+            .input_id(InputId::new(BusType::BUS_VIRTUAL, 0, 0, 1))
+            .with_keys(&keys)?
+            .with_properties(&properties)?
+            .with_absolute_axis(&axis(AbsoluteAxisType::ABS_X, x.min, x.max, x.resolution))?
+            .with_absolute_axis(&axis(AbsoluteAxisType::ABS_Y, y.min, y.max, y.resolution))?
+            .with_absolute_axis(&axis(
+                AbsoluteAxisType::ABS_MT_SLOT,
+                0,
+                MAX_SLOTS as i32 - 1,
+                0,
+            ))?
+            .with_absolute_axis(&axis(
+                AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                0,
+                u16::MAX as i32,
+                0,
+            ))?
+            .with_absolute_axis(&axis(
+                AbsoluteAxisType::ABS_MT_POSITION_X,
+                x.min,
+                x.max,
+                x.resolution,
+            ))?
+            .with_absolute_axis(&axis(
+                AbsoluteAxisType::ABS_MT_POSITION_Y,
+                y.min,
+                y.max,
+                y.resolution,
+            ))?
+            .build()
+            .context("Failed to create virtual touchpad")?;
+
+        Ok(Self {
+            device,
+            frames: Frames::new(config.min_fingers),
+            suppress_actions: config.suppress_actions,
+        })
+    }
+
+    /// Create the touchpad if `config` enables it, warning if that fails
+    pub fn open(surface: &SurfaceBounds, config: &TouchpadConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        match Self::new(surface, config) {
+            Ok(touchpad) => {
+                info!(
+                    "Forwarding touches of {} or more fingers to a virtual touchpad",
+                    config.min_fingers
+                );
+                Some(touchpad)
+            }
+            Err(e) => {
+                warn!("Virtual touchpad unavailable: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Mirror the contacts of a completed input frame
+    pub fn update(&mut self, contacts: &[&TouchContact]) -> io::Result<()> {
+        let events = self.frames.next(contacts);
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.device.emit(&events)
+    }
+
+    /// Whether the desktop handles `event` instead of its configured action
+    pub fn handles(&self, event: &MultiTouchEvent) -> bool {
+        self.suppress_actions && event.fingers() >= self.frames.min_fingers
+    }
+}
+
+/// A contact as last emitted on the touchpad
+#[derive(Debug, Clone, Copy)]
+struct Emitted {
+    /// The contact's tracking ID on the Magic Mouse
+    source_id: i32,
+    x: i32,
+    y: i32,
+}
+
+/// Turns the contacts of each input frame into the touchpad events that
+/// change what was emitted before into them
+#[derive(Debug)]
+struct Frames {
+    min_fingers: usize,
+    emitted: [Option<Emitted>; MAX_SLOTS],
+    next_tracking_id: i32,
+}
+
+impl Frames {
+    fn new(min_fingers: usize) -> Self {
+        Self {
+            min_fingers: min_fingers.max(1),
+            emitted: [None; MAX_SLOTS],
+            next_tracking_id: 0,
+        }
+    }
+
+    /// Events of the next frame, without the closing SYN_REPORT
+    fn next(&mut self, contacts: &[&TouchContact]) -> Vec<InputEvent> {
+        let mut target = [None; MAX_SLOTS];
+        if contacts.len() >= self.min_fingers {
+            for contact in contacts {
+                if let Some(slot) = target.get_mut(contact.slot as usize) {
+                    *slot = Some(Emitted {
+                        source_id: contact.id,
+                        x: contact.x,
+                        y: contact.y,
+                    });
+                }
+            }
+        }
+
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let before = self.fingers();
+        let mut events = Vec::new();
+        for (slot, (emitted, target)) in self.emitted.iter_mut().zip(target).enumerate() {
+            let slot_event = abs(AbsoluteAxisType::ABS_MT_SLOT, slot as i32);
+            match (*emitted, target) {
+                (None, None) => continue,
+                (Some(_), None) => {
+                    events.push(slot_event);
+                    events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1));
+                }
+                (Some(old), Some(new)) if old.source_id == new.source_id => {
+                    if (old.x, old.y) == (new.x, new.y) {
+                        continue;
+                    }
+                    events.push(slot_event);
+                    if old.x != new.x {
+                        events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, new.x));
+                    }
+                    if old.y != new.y {
+                        events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, new.y));
+                    }
+                }
+                // A new tracking ID also ends the slot's previous touch
+                (_, Some(new)) => {
+                    events.push(slot_event);
+                    events.push(abs(
+                        AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                        self.next_tracking_id,
+                    ));
+                    self.next_tracking_id = (self.next_tracking_id + 1) % (u16::MAX as i32 + 1);
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, new.x));
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, new.y));
+                }
+            }
+            *emitted = target;
+        }
+        if events.is_empty() {
+            return events;
+        }
+
+        // Single-touch emulation follows the first finger
+        if let Some(first) = self.emitted.iter().flatten().next() {
+            events.push(abs(AbsoluteAxisType::ABS_X, first.x));
+            events.push(abs(AbsoluteAxisType::ABS_Y, first.y));
+        }
+        let fingers = self.fingers();
+        if fingers != before {
+            let key = |key: Key, pressed: bool| {
+                InputEvent::new(EventType::KEY, key.code(), pressed as i32)
+            };
+            events.push(key(Key::BTN_TOUCH, fingers > 0));
+            for (index, tool) in TOOL_KEYS.into_iter().enumerate() {
+                let count = index + 1;
+                let pressed = fingers == count || (count == TOOL_KEYS.len() && fingers > count);
+                events.push(key(tool, pressed));
+            }
+        }
+        events
+    }
+
+    fn fingers(&self) -> usize {
+        self.emitted.iter().flatten().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn contact(id: i32, slot: i32, x: i32, y: i32) -> TouchContact {
+        TouchContact::at(id, slot, x, y, Instant::now())
+    }
+
+    fn codes(events: &[InputEvent]) -> Vec<(u16, i32)> {
+        of_type(events, EventType::ABSOLUTE)
+    }
+
+    fn of_type(events: &[InputEvent], event_type: EventType) -> Vec<(u16, i32)> {
+        events
+            .iter()
+            .filter(|event| event.event_type() == event_type)
+            .map(|event| (event.code(), event.value()))
+            .collect()
+    }
+
+    #[test]
+    fn test_forwards_multi_finger_touches() {
+        let mut frames = Frames::new(2);
+        let (first, second) = (contact(7, 0, 10, 20), contact(8, 1, 30, 40));
+
+        // A single finger stays with the mouse
+        assert!(frames.next(&[&first]).is_empty());
+
+        let events = frames.next(&[&first, &second]);
+        let slot = AbsoluteAxisType::ABS_MT_SLOT.0;
+        let tracking_id = AbsoluteAxisType::ABS_MT_TRACKING_ID.0;
+        let x = AbsoluteAxisType::ABS_MT_POSITION_X.0;
+        let y = AbsoluteAxisType::ABS_MT_POSITION_Y.0;
+        assert_eq!(
+            codes(&events)[..8],
+            [
+                (slot, 0),
+                (tracking_id, 0),
+                (x, 10),
+                (y, 20),
+                (slot, 1),
+                (tracking_id, 1),
+                (x, 30),
+                (y, 40)
+            ]
+        );
+        assert!(of_type(&events, EventType::KEY).contains(&(Key::BTN_TOOL_DOUBLETAP.code(), 1)));
+
+        // Only what changed is sent
+        let moved = contact(8, 1, 30, 45);
+        let events = frames.next(&[&first, &moved]);
+        assert_eq!(codes(&events)[..2], [(slot, 1), (y, 45)]);
+        assert!(frames.next(&[&first, &moved]).is_empty());
+
+        // Dropping below the minimum lifts every forwarded finger
+        let events = frames.next(&[&first]);
+        assert_eq!(
+            codes(&events),
+            [(slot, 0), (tracking_id, -1), (slot, 1), (tracking_id, -1)]
+        );
+        assert!(of_type(&events, EventType::KEY).contains(&(Key::BTN_TOUCH.code(), 0)));
+    }
+}
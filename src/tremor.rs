@@ -0,0 +1,150 @@
+//! Position filtering for users with hand tremor
+//!
+//! Each contact's position is smoothed per axis with a low-pass filter and then
+//! held inside a deadband, so small oscillations never reach tap and swipe
+//! classification. Jumps well beyond the deadband pass with little or no
+//! smoothing, keeping deliberate movements responsive.
+
+use crate::multitouch::{SurfaceBounds, MAX_SLOTS};
+
+/// Movements this many deadbands away from the smoothed position bypass the
+/// low-pass filter entirely
+const RESPONSIVE_MULTIPLE: f64 = 4.0;
+/// Lowest accepted smoothing weight; zero would freeze the contact
+const MIN_SMOOTHING: f64 = 0.01;
+
+/// Filter state of one axis of one contact
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    /// Low-pass filtered position in device units
+    smoothed: f64,
+    /// Position passed on, trailing `smoothed` by at most the deadband
+    shown: f64,
+}
+
+impl Axis {
+    fn new(raw: i32) -> Self {
+        Self {
+            smoothed: raw as f64,
+            shown: raw as f64,
+        }
+    }
+}
+
+/// Filter state of the contact in a slot, with its tracking ID so a new
+/// contact reusing the slot starts afresh
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    id: i32,
+    x: Option<Axis>,
+    y: Option<Axis>,
+}
+
+pub struct TremorFilter {
+    /// Weight of a new report in the low-pass filter, 0-1
+    smoothing: f64,
+    /// Deadband per axis in device units
+    deadband: (f64, f64),
+    slots: [Option<Slot>; MAX_SLOTS],
+}
+
+impl TremorFilter {
+    /// `deadband_mm` is converted to device units on `surface`
+    pub fn new(smoothing: f64, deadband_mm: f64, surface: &SurfaceBounds) -> Self {
+        let deadband_mm = deadband_mm.max(0.0);
+        Self {
+            smoothing: smoothing.clamp(MIN_SMOOTHING, 1.0),
+            deadband: (
+                deadband_mm * surface.x.resolution.max(1) as f64,
+                deadband_mm * surface.y.resolution.max(1) as f64,
+            ),
+            slots: [None; MAX_SLOTS],
+        }
+    }
+
+    /// Filter an X position report of contact `id` in `slot`
+    pub fn filter_x(&mut self, slot: i32, id: i32, raw: i32) -> i32 {
+        let (smoothing, deadband) = (self.smoothing, self.deadband.0);
+        match self.slot(slot, id) {
+            Some(state) => step(&mut state.x, raw, smoothing, deadband),
+            None => raw,
+        }
+    }
+
+    /// Filter a Y position report of contact `id` in `slot`
+    pub fn filter_y(&mut self, slot: i32, id: i32, raw: i32) -> i32 {
+        let (smoothing, deadband) = (self.smoothing, self.deadband.1);
+        match self.slot(slot, id) {
+            Some(state) => step(&mut state.y, raw, smoothing, deadband),
+            None => raw,
+        }
+    }
+
+    fn slot(&mut self, slot: i32, id: i32) -> Option<&mut Slot> {
+        let entry = self.slots.get_mut(usize::try_from(slot).ok()?)?;
+        if entry.is_none_or(|state| state.id != id) {
+            *entry = Some(Slot {
+                id,
+                x: None,
+                y: None,
+            });
+        }
+        entry.as_mut()
+    }
+}
+
+/// Advance one axis by a raw report and return the filtered position
+fn step(axis: &mut Option<Axis>, raw: i32, smoothing: f64, deadband: f64) -> i32 {
+    let Some(state) = axis else {
+        // The first report is where the finger landed; nothing to smooth yet
+        *axis = Some(Axis::new(raw));
+        return raw;
+    };
+
+    let delta = raw as f64 - state.smoothed;
+    let fast = deadband * RESPONSIVE_MULTIPLE;
+    let weight = if fast > 0.0 {
+        (delta.abs() / fast).clamp(smoothing, 1.0)
+    } else {
+        smoothing
+    };
+    state.smoothed += weight * delta;
+
+    // The shown position is dragged along once the smoothed one leaves the
+    // deadband around it, so reversing direction costs a full deadband
+    let offset = state.smoothed - state.shown;
+    if offset.abs() > deadband {
+        state.shown = state.smoothed - deadband.copysign(offset);
+    }
+    state.shown.round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tremor_suppressed_and_large_moves_pass() {
+        // 10 units/mm on both axes, 1mm deadband
+        let mut surface = SurfaceBounds::default();
+        surface.x.resolution = 10;
+        surface.y.resolution = 10;
+        let mut filter = TremorFilter::new(0.25, 1.0, &surface);
+
+        assert_eq!(filter.filter_x(0, 1, 500), 500);
+        // A 1.5mm oscillation around the landing point never moves the contact
+        for i in 0..40 {
+            let raw = if i % 2 == 0 { 515 } else { 485 };
+            assert_eq!(filter.filter_x(0, 1, raw), 500);
+        }
+
+        // A 20mm move arrives at once, short only by the deadband
+        assert_eq!(filter.filter_x(0, 1, 700), 690);
+
+        // The other axis and a new contact in the slot start afresh
+        assert_eq!(filter.filter_y(0, 1, 300), 300);
+        assert_eq!(filter.filter_x(0, 2, 100), 100);
+        // Slots beyond the tracked range pass through
+        assert_eq!(filter.filter_x(99, 1, 42), 42);
+    }
+}
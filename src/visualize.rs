@@ -0,0 +1,382 @@
+use anyhow::{bail, Context, Result};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table, Widget};
+use ratatui::DefaultTerminal;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use crate::device::describe_gesture;
+use crate::multitouch::{DeviceInfo, MultiTouchEvent, TouchContact};
+
+/// How long a finger's path stays visible, also after it lifted
+const TRAIL_LENGTH: Duration = Duration::from_millis(1200);
+/// How long the surface border lights up after a gesture fired
+const FLASH_DURATION: Duration = Duration::from_millis(600);
+/// Recognized gestures listed under the surface
+const GESTURE_HISTORY: usize = 6;
+/// Terminal cells are about twice as tall as they are wide
+const CELL_ASPECT: f64 = 2.0;
+
+/// Lines below the surface: contact table header and rows, then gestures
+const FOOTER_LINES: u16 = 4 + GESTURE_HISTORY as u16;
+
+/// Colors picked per slot so fingers keep their color
+const SLOT_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+];
+
+/// What `visualize` draws: contacts, their recent paths and fired gestures
+pub struct Visualizer {
+    device: DeviceInfo,
+    contacts: Vec<TouchContact>,
+    /// Recent normalized positions by tracking ID
+    trails: BTreeMap<i32, Trail>,
+    gestures: VecDeque<(Instant, String)>,
+}
+
+struct Trail {
+    slot: i32,
+    points: VecDeque<(f64, f64, Instant)>,
+}
+
+impl Visualizer {
+    pub fn new(device: &DeviceInfo) -> Self {
+        Self {
+            device: device.clone(),
+            contacts: Vec::new(),
+            trails: BTreeMap::new(),
+            gestures: VecDeque::new(),
+        }
+    }
+
+    /// Take the fingers on the surface after a report
+    pub fn update(&mut self, contacts: &[&TouchContact], now: Instant) {
+        for contact in contacts {
+            let (x, y) = (contact.normalized_x, contact.normalized_y);
+            let trail = self.trails.entry(contact.id).or_insert_with(|| Trail {
+                slot: contact.slot,
+                points: VecDeque::new(),
+            });
+            trail.points.push_back((x, y, now));
+        }
+        self.contacts = contacts.iter().map(|&contact| contact.clone()).collect();
+    }
+
+    pub fn gesture(&mut self, event: &MultiTouchEvent, now: Instant) {
+        let description = describe_gesture(event);
+        // A scroll is reported every frame; keep one line for the latest
+        let scrolling = matches!(event, MultiTouchEvent::Scroll { .. })
+            && self
+                .gestures
+                .back()
+                .is_some_and(|(_, last)| last.starts_with("scroll "));
+        if scrolling {
+            self.gestures.pop_back();
+        }
+        self.gestures.push_back((now, description));
+        while self.gestures.len() > GESTURE_HISTORY {
+            self.gestures.pop_front();
+        }
+    }
+
+    /// Forget trail points that aged out; true while anything still fades
+    pub fn expire(&mut self, now: Instant) -> bool {
+        for trail in self.trails.values_mut() {
+            while trail
+                .points
+                .front()
+                .is_some_and(|&(_, _, at)| now.duration_since(at) > TRAIL_LENGTH)
+            {
+                trail.points.pop_front();
+            }
+        }
+        self.trails.retain(|_, trail| !trail.points.is_empty());
+        !self.trails.is_empty() || self.flashing(now)
+    }
+
+    fn flashing(&self, now: Instant) -> bool {
+        self.gestures
+            .back()
+            .is_some_and(|&(at, _)| now.duration_since(at) < FLASH_DURATION)
+    }
+
+    /// Draw the screen into `area` of `buf`
+    pub fn render(&self, area: Rect, buf: &mut Buffer, now: Instant) {
+        let (columns, rows) = self.surface_size(area.width, area.height);
+        let [header, surface, contacts, gestures] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(rows + 2),
+            Constraint::Length(4),
+            Constraint::Fill(1),
+        ])
+        .areas(area);
+
+        let (width_mm, height_mm) = self.device.size_mm();
+        Paragraph::new(format!(
+            "{}  ({:.0}x{:.0}mm)  q to quit",
+            self.device.name, width_mm, height_mm
+        ))
+        .render(header, buf);
+
+        let surface = Rect {
+            width: columns + 2,
+            ..surface
+        }
+        .intersection(area);
+        let border = if self.flashing(now) {
+            Style::new().fg(Color::LightYellow)
+        } else {
+            Style::new()
+        };
+        let block = Block::bordered().border_style(border);
+        let inner = block.inner(surface);
+        block.render(surface, buf);
+        self.draw_surface(inner, buf);
+
+        self.render_contacts(contacts, buf);
+        self.render_gestures(gestures, buf, now);
+    }
+
+    /// Trails and contacts inside the surface's border
+    fn draw_surface(&self, inner: Rect, buf: &mut Buffer) {
+        let (columns, rows) = (inner.width.max(1), inner.height.max(1));
+        let mut put = |column: i32, row: i32, symbol: char, color: Color| {
+            let (Ok(column), Ok(row)) = (u16::try_from(column), u16::try_from(row)) else {
+                return;
+            };
+            if column < columns && row < rows {
+                if let Some(cell) = buf.cell_mut((inner.x + column, inner.y + row)) {
+                    cell.set_char(symbol).set_fg(color);
+                }
+            }
+        };
+
+        // Trails first, so the contacts are drawn over them
+        let position = |x: f64, y: f64| {
+            (
+                (x * (columns - 1) as f64).round() as i32,
+                (y * (rows - 1) as f64).round() as i32,
+            )
+        };
+        for trail in self.trails.values() {
+            let color = slot_color(trail.slot);
+            for &(x, y, _) in &trail.points {
+                let (column, row) = position(x, y);
+                put(column, row, '·', color);
+            }
+        }
+
+        let (width_mm, _) = self.device.size_mm();
+        let columns_per_mm = (columns - 1) as f64 / width_mm.max(1.0);
+        for contact in &self.contacts {
+            let color = slot_color(contact.slot);
+            let (column, row) = position(contact.normalized_x, contact.normalized_y);
+
+            // The touch ellipse, sized from the contact's axes
+            let surface = &self.device.surface;
+            let radius_x = surface.x.units_to_mm(contact.touch_major) / 2.0 * columns_per_mm;
+            let radius_y = surface
+                .y
+                .units_to_mm(contact.touch_minor.max(contact.touch_major / 2))
+                / 2.0
+                * columns_per_mm
+                / CELL_ASPECT;
+            let (reach_x, reach_y) = (radius_x.ceil() as i32, radius_y.ceil() as i32);
+            for dy in -reach_y..=reach_y {
+                for dx in -reach_x..=reach_x {
+                    let distance = (dx as f64 / radius_x.max(0.5)).powi(2)
+                        + (dy as f64 / radius_y.max(0.5)).powi(2);
+                    if distance <= 1.0 {
+                        put(column + dx, row + dy, 'o', color);
+                    }
+                }
+            }
+            let label = char::from_digit(contact.slot.unsigned_abs() % 36, 36).unwrap_or('#');
+            put(column, row, label, color);
+        }
+    }
+
+    /// Largest surface that fits the terminal with its border, header and
+    /// footer, keeping the surface's proportions
+    fn surface_size(&self, width: u16, height: u16) -> (u16, u16) {
+        let available_columns = width.saturating_sub(2).max(8) as f64;
+        let available_rows = height.saturating_sub(3 + FOOTER_LINES).max(4) as f64;
+        let (width_mm, height_mm) = self.device.size_mm();
+        let (width_mm, height_mm) = (width_mm.max(1.0), height_mm.max(1.0));
+
+        let columns_per_mm =
+            (available_columns / width_mm).min(available_rows * CELL_ASPECT / height_mm);
+        (
+            ((width_mm * columns_per_mm) as u16).max(2),
+            ((height_mm * columns_per_mm / CELL_ASPECT) as u16).max(2),
+        )
+    }
+
+    /// Up to two contacts with their axes, then how many more there are
+    fn render_contacts(&self, area: Rect, buf: &mut Buffer) {
+        let [table, more] =
+            Layout::vertical([Constraint::Length(3), Constraint::Length(1)]).areas(area);
+        let widths = [4, 6, 7, 7, 6, 6, 8, 7].map(Constraint::Length);
+        let header = [
+            "slot", "id", "x_mm", "y_mm", "major", "minor", "pressure", "age_ms",
+        ]
+        .map(|title| Line::from(title).right_aligned());
+        let contacts = self.contacts.iter().take(2).map(|contact| {
+            let (x_mm, y_mm) = contact.position_mm();
+            Row::new(
+                [
+                    contact.slot.to_string(),
+                    contact.id.to_string(),
+                    format!("{:.1}", x_mm),
+                    format!("{:.1}", y_mm),
+                    contact.touch_major.to_string(),
+                    contact.touch_minor.to_string(),
+                    contact.pressure.to_string(),
+                    contact.contact_duration().as_millis().to_string(),
+                ]
+                .map(|value| Line::from(value).right_aligned()),
+            )
+        });
+        Table::new(contacts, widths)
+            .header(Row::new(header))
+            .render(table, buf);
+
+        if self.contacts.len() > 2 {
+            Paragraph::new(format!(" ... {} more", self.contacts.len() - 2)).render(more, buf);
+        }
+    }
+
+    /// Recognized gestures, newest first and lit up while the flash lasts
+    fn render_gestures(&self, area: Rect, buf: &mut Buffer, now: Instant) {
+        let lines: Vec<Line> = self
+            .gestures
+            .iter()
+            .rev()
+            .map(|(at, description)| {
+                let age = now.duration_since(*at);
+                let line = format!(" => {:<40} {:>6.1}s ago", description, age.as_secs_f64());
+                if age < FLASH_DURATION {
+                    Line::styled(
+                        line,
+                        Style::new()
+                            .fg(Color::LightYellow)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                } else {
+                    Line::raw(line)
+                }
+            })
+            .collect();
+        Paragraph::new(lines).render(area, buf);
+    }
+}
+
+/// Slots keep their color, so fingers can be told apart
+fn slot_color(slot: i32) -> Color {
+    SLOT_COLORS[slot.unsigned_abs() as usize % SLOT_COLORS.len()]
+}
+
+/// The terminal in full-screen raw mode, restored when dropped
+pub struct Screen {
+    terminal: DefaultTerminal,
+}
+
+impl Screen {
+    pub fn open() -> Result<Self> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            bail!("visualize needs a terminal");
+        }
+        let terminal = ratatui::try_init()
+            .inspect_err(|_| ratatui::restore())
+            .context("Failed to set up terminal")?;
+        Ok(Self { terminal })
+    }
+
+    /// Replace the screen's contents with the current view
+    pub fn draw(&mut self, view: &Visualizer, now: Instant) -> Result<()> {
+        self.terminal
+            .draw(|frame| view.render(frame.area(), frame.buffer_mut(), now))?;
+        Ok(())
+    }
+
+    /// Whether `q`, Escape or Ctrl-C was pressed since the last call
+    pub fn quit_pressed(&self) -> Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            // Raw mode turns Ctrl-C into a key press instead of SIGINT
+            let interrupt =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press
+                && (interrupt || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::multitouch::{MtSlotState, MultiTouchProcessor, SurfaceBounds};
+
+    #[test]
+    fn test_render_contact_and_trail() {
+        let device = DeviceInfo::new("Magic Mouse", SurfaceBounds::default());
+        let surface = device.surface;
+        let mut processor =
+            MultiTouchProcessor::new(Config::default().gesture).with_device(device.clone());
+        let slot = |x| MtSlotState {
+            slot: 3,
+            tracking_id: 7,
+            x,
+            y: (surface.y.min + surface.y.max) / 2,
+            ..MtSlotState::empty(3)
+        };
+
+        let mut visualizer = Visualizer::new(&device);
+        let now = Instant::now();
+        processor.seed(&[slot(surface.x.min)], 3);
+        visualizer.update(&processor.active_contacts(), now);
+        processor.seed(&[slot(surface.x.max)], 3);
+        visualizer.update(&processor.active_contacts(), now);
+
+        let area = Rect::new(0, 0, 80, 40);
+        let mut buf = Buffer::empty(area);
+        visualizer.render(area, &mut buf, now);
+        let lines: Vec<String> = area
+            .rows()
+            .map(|row| row.columns().map(|cell| buf[cell].symbol()).collect())
+            .collect();
+        let row = lines
+            .iter()
+            .find(|line| line.starts_with('│') && line.contains('3'))
+            .expect("contact drawn on the surface");
+        // Starting point of the trail at the left edge, finger at the right
+        assert_eq!(row.chars().nth(1), Some('·'));
+        assert!(row.trim_end().ends_with("3│"));
+
+        assert!(visualizer.expire(now));
+        assert!(!visualizer.expire(now + TRAIL_LENGTH * 2));
+    }
+}
@@ -0,0 +1,660 @@
+use anyhow::{bail, Context, Result};
+use lazy_static::lazy_static;
+use log::{debug, info, log, warn, Level};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window,
+};
+use x11rb::protocol::Event;
+
+use crate::compositor::{self, I3_IPC_MAGIC};
+use crate::config::{Config, WindowRule};
+use crate::profile;
+use crate::reload::ConfigSource;
+use crate::session::{self, SessionType};
+use crate::status;
+use crate::x11;
+
+/// Wait before reconnecting after the provider failed or when there is none
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+const IPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// sway IPC message types
+const SUBSCRIBE: u32 = 2;
+const GET_TREE: u32 = 4;
+/// Bit set in the type of sway IPC events
+const EVENT_BIT: u32 = 1 << 31;
+
+/// Hyprland events that may change the focused window or its state
+const HYPRLAND_EVENTS: [&str; 6] = [
+    "activewindow",
+    "activewindowv2",
+    "windowtitle",
+    "windowtitlev2",
+    "fullscreen",
+    "closewindow",
+];
+
+lazy_static! {
+    /// Focused window, `None` if nothing is focused or it's unknown
+    static ref CURRENT: watch::Sender<Option<ActiveWindow>> = watch::channel(None).0;
+}
+
+/// The window with keyboard focus
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ActiveWindow {
+    /// X11 `WM_CLASS` class or Wayland app ID, e.g. `firefox`
+    pub class: String,
+    pub title: String,
+    pub fullscreen: bool,
+}
+
+/// Source of the focused window for one kind of session
+///
+/// Implementations block in `wait` until the window server reports a change,
+/// so focus is followed as it happens rather than polled.
+pub trait WindowContext: Send {
+    /// Name for the log
+    fn name(&self) -> &'static str;
+
+    /// The focused window right now
+    fn active(&mut self) -> Result<Option<ActiveWindow>>;
+
+    /// Block until the focused window or its title or state may have changed
+    fn wait(&mut self) -> Result<()>;
+}
+
+/// The focused window as last reported
+pub fn current() -> Option<ActiveWindow> {
+    CURRENT.borrow().clone()
+}
+
+/// The provider for `session`, `None` if the session has none
+fn open(session: SessionType) -> Option<Result<Box<dyn WindowContext>>> {
+    let is_set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    match session {
+        SessionType::X11 => Some(X11Context::open().map(|context| Box::new(context) as _)),
+        SessionType::Wayland if is_set("SWAYSOCK") => {
+            Some(SwayContext::open().map(|context| Box::new(context) as _))
+        }
+        SessionType::Wayland if is_set("HYPRLAND_INSTANCE_SIGNATURE") => {
+            Some(HyprlandContext::open().map(|context| Box::new(context) as _))
+        }
+        _ => None,
+    }
+}
+
+/// Follow the focused window on a thread of its own, reconnecting when the
+/// window server goes away or the session changes
+pub fn start() {
+    let spawned = std::thread::Builder::new()
+        .name("window-context".to_string())
+        .spawn(|| {
+            let mut reported = None;
+            loop {
+                let session = session::current();
+                let report = match open(session) {
+                    None => (
+                        Level::Info,
+                        format!(
+                            "No active-window provider for this {} session, \
+                             per-application profiles are inactive",
+                            session
+                        ),
+                    ),
+                    Some(Ok(context)) => {
+                        info!("Following the focused window through {}", context.name());
+                        reported = None;
+                        match watch_windows(context) {
+                            Ok(()) => continue,
+                            Err(e) => (Level::Warn, format!("Lost the focused window: {:#}", e)),
+                        }
+                    }
+                    Some(Err(e)) => (
+                        Level::Warn,
+                        format!("Can't follow the focused window: {:#}", e),
+                    ),
+                };
+                CURRENT.send_replace(None);
+
+                // Say so once, not on every retry
+                if reported.as_ref() != Some(&report) {
+                    log!(report.0, "{}", report.1);
+                    reported = Some(report);
+                }
+                std::thread::sleep(RETRY_DELAY);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start following the focused window: {}", e);
+    }
+}
+
+fn watch_windows(mut context: Box<dyn WindowContext>) -> Result<()> {
+    loop {
+        let window = context.active()?;
+        if *CURRENT.borrow() != window {
+            debug!("Focused window: {:?}", window);
+            CURRENT.send_replace(window);
+        }
+        context.wait()?;
+    }
+}
+
+/// Switch to the profile listing the focused window's class in `windows`, and
+/// back once another window is focused; pause gestures for the windows
+/// `auto_pause` names
+pub async fn follow(configs: Arc<ConfigSource>) {
+    let mut windows = CURRENT.subscribe();
+    let mut config_changes = configs.subscribe();
+    let mut switcher = Switcher::default();
+    let mut paused = false;
+    loop {
+        tokio::select! {
+            changed = windows.changed() => if changed.is_err() { return },
+            changed = config_changes.changed() => if changed.is_err() { return },
+        }
+        let window = windows.borrow_and_update().clone();
+        let config = config_changes.borrow_and_update().clone();
+
+        if let Some(target) = switcher.update(&config, window.as_ref(), profile::active()) {
+            profile::activate(target);
+        }
+
+        // Only on a change, so resuming by hand lasts until the next one
+        let pause = pauses(&config, window.as_ref());
+        if pause != paused {
+            status::set_auto_paused(pause, config.auto_pause.release_grab);
+            paused = pause;
+        }
+    }
+}
+
+/// Whether gestures pause while `window` has focus
+fn pauses(config: &Config, window: Option<&ActiveWindow>) -> bool {
+    let Some(window) = window else {
+        return false;
+    };
+    let listed = config
+        .auto_pause
+        .windows
+        .iter()
+        .any(|rule| matches(rule, window));
+    // A fullscreen window with a profile of its own, like a presentation,
+    // still wants its gestures
+    listed
+        || (config.auto_pause.fullscreen
+            && window.fullscreen
+            && profile_for(config, window).is_none())
+}
+
+/// Profile with a rule in `windows` matching `window`; the rule with the
+/// most conditions wins, then the first profile by name
+fn profile_for(config: &Config, window: &ActiveWindow) -> Option<String> {
+    config
+        .profiles
+        .iter()
+        .filter_map(|(name, profile)| {
+            let conditions = profile
+                .windows
+                .iter()
+                .filter(|rule| matches(rule, window))
+                .map(WindowRule::conditions)
+                .max()?;
+            Some((conditions, name))
+        })
+        .min_by_key(|&(conditions, _)| std::cmp::Reverse(conditions))
+        .map(|(_, name)| name.clone())
+}
+
+fn matches(rule: &WindowRule, window: &ActiveWindow) -> bool {
+    let class = |class: &str| !class.is_empty() && class.eq_ignore_ascii_case(&window.class);
+    match rule {
+        WindowRule::Class(name) => class(name),
+        WindowRule::Match(rule) => {
+            rule.class.as_deref().is_none_or(class)
+                && rule
+                    .title
+                    .as_deref()
+                    .is_none_or(|title| window.title.to_lowercase().contains(&title.to_lowercase()))
+                && rule
+                    .fullscreen
+                    .is_none_or(|fullscreen| fullscreen == window.fullscreen)
+        }
+    }
+}
+
+/// Profile switches made for focused windows
+#[derive(Debug, Default)]
+struct Switcher {
+    /// Profile activated for the focused window
+    switched: Option<String>,
+    /// Profile active before, restored when no profile matches anymore
+    previous: Option<String>,
+}
+
+impl Switcher {
+    /// Profile to activate now that `window` is focused, `None` to keep the
+    /// active one
+    fn update(
+        &mut self,
+        config: &Config,
+        window: Option<&ActiveWindow>,
+        active: Option<String>,
+    ) -> Option<Option<String>> {
+        // Switched by hand in the meantime, which stays
+        if self.switched.is_some() && active != self.switched {
+            self.switched = None;
+            self.previous = None;
+        }
+
+        match window.and_then(|window| profile_for(config, window)) {
+            Some(name) if active.as_ref() == Some(&name) => None,
+            Some(name) => {
+                if self.switched.is_none() {
+                    self.previous = active;
+                }
+                self.switched = Some(name.clone());
+                Some(Some(name))
+            }
+            None => self.switched.take().map(|_| self.previous.take()),
+        }
+    }
+}
+
+/// EWMH: `_NET_ACTIVE_WINDOW` on the root window, then the class, title and
+/// state of that window, with PropertyNotify events for changes
+struct X11Context {
+    connection: x11::Connection,
+    /// Window whose properties are followed, `NONE` for none
+    window: Window,
+}
+
+impl X11Context {
+    fn open() -> Result<Self> {
+        let connection = x11::Connection::open()?;
+        select_property_changes(&connection, connection.root())?;
+        Ok(Self {
+            connection,
+            window: x11rb::NONE,
+        })
+    }
+
+    fn string_property(
+        &self,
+        property: impl Into<Atom>,
+        property_type: impl Into<Atom>,
+    ) -> Result<Option<String>> {
+        let reply = self
+            .connection
+            .get_property(false, self.window, property, property_type, 0, 1024)?
+            .reply()?;
+        Ok(
+            (reply.type_ != x11rb::NONE)
+                .then(|| String::from_utf8_lossy(&reply.value).into_owned()),
+        )
+    }
+}
+
+/// Receive PropertyNotify events for `window`
+fn select_property_changes(connection: &x11::Connection, window: Window) -> Result<()> {
+    let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    connection.change_window_attributes(window, &attributes)?;
+    connection.sync()
+}
+
+impl WindowContext for X11Context {
+    fn name(&self) -> &'static str {
+        "X11"
+    }
+
+    fn active(&mut self) -> Result<Option<ActiveWindow>> {
+        let atoms = self.connection.atoms;
+        let window = self
+            .connection
+            .property32(
+                self.connection.root(),
+                atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+            )?
+            .first()
+            .copied()
+            .unwrap_or(x11rb::NONE);
+        if window != self.window {
+            if window != x11rb::NONE {
+                select_property_changes(&self.connection, window)?;
+            }
+            self.window = window;
+        }
+        if window == x11rb::NONE {
+            return Ok(None);
+        }
+
+        // WM_CLASS is the instance and then the class, each NUL-terminated
+        let wm_class = self
+            .string_property(AtomEnum::WM_CLASS, AtomEnum::STRING)?
+            .unwrap_or_default();
+        let mut names = wm_class.split('\0');
+        let instance = names.next().unwrap_or_default();
+        let class = names
+            .next()
+            .filter(|class| !class.is_empty())
+            .unwrap_or(instance);
+
+        let title = match self.string_property(atoms._NET_WM_NAME, atoms.UTF8_STRING)? {
+            Some(title) => title,
+            None => self
+                .string_property(AtomEnum::WM_NAME, AtomEnum::ANY)?
+                .unwrap_or_default(),
+        };
+
+        let fullscreen = self
+            .connection
+            .property32(window, atoms._NET_WM_STATE, AtomEnum::ATOM)?
+            .contains(&atoms._NET_WM_STATE_FULLSCREEN);
+
+        Ok(Some(ActiveWindow {
+            class: class.to_string(),
+            title,
+            fullscreen,
+        }))
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        let root = self.connection.root();
+        let atoms = self.connection.atoms;
+        let followed = [
+            AtomEnum::WM_CLASS.into(),
+            AtomEnum::WM_NAME.into(),
+            atoms._NET_WM_NAME,
+            atoms._NET_WM_STATE,
+        ];
+        loop {
+            let Event::PropertyNotify(event) = self.connection.wait_for_event()? else {
+                continue;
+            };
+            if (event.window == root && event.atom == atoms._NET_ACTIVE_WINDOW)
+                || (event.window == self.window && followed.contains(&event.atom))
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// sway IPC: window and workspace events, the focused container from the tree
+struct SwayContext {
+    socket: PathBuf,
+    events: UnixStream,
+}
+
+impl SwayContext {
+    fn open() -> Result<Self> {
+        let socket = PathBuf::from(std::env::var_os("SWAYSOCK").context("SWAYSOCK is not set")?);
+        let mut events = UnixStream::connect(&socket).context("Failed to connect to sway")?;
+        sway_send(&mut events, SUBSCRIBE, br#"["window", "workspace"]"#)?;
+        let (_, reply) = sway_receive(&mut events)?;
+        let reply: Value = serde_json::from_slice(&reply).context("Invalid reply from sway")?;
+        if reply["success"] != true {
+            bail!("sway refused the event subscription");
+        }
+        Ok(Self { socket, events })
+    }
+}
+
+impl WindowContext for SwayContext {
+    fn name(&self) -> &'static str {
+        "sway IPC"
+    }
+
+    fn active(&mut self) -> Result<Option<ActiveWindow>> {
+        let mut stream = UnixStream::connect(&self.socket).context("Failed to connect to sway")?;
+        stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+        sway_send(&mut stream, GET_TREE, b"")?;
+        let (_, tree) = sway_receive(&mut stream)?;
+        let tree: Value = serde_json::from_slice(&tree).context("Invalid tree from sway")?;
+        Ok(sway_focused(&tree))
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        loop {
+            let (message_type, _) = sway_receive(&mut self.events)?;
+            if message_type & EVENT_BIT != 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn sway_send(stream: &mut UnixStream, message_type: u32, payload: &[u8]) -> Result<()> {
+    let mut message = I3_IPC_MAGIC.to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&message_type.to_ne_bytes());
+    message.extend_from_slice(payload);
+    stream
+        .write_all(&message)
+        .context("Failed to write to sway")
+}
+
+/// Next message as `(type, payload)`
+fn sway_receive(stream: &mut UnixStream) -> Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; 14];
+    stream
+        .read_exact(&mut header)
+        .context("Failed to read from sway")?;
+    if &header[..6] != I3_IPC_MAGIC {
+        bail!("Unexpected message from sway");
+    }
+    let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+    let message_type = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+    let mut payload = vec![0u8; length as usize];
+    stream
+        .read_exact(&mut payload)
+        .context("Failed to read from sway")?;
+    Ok((message_type, payload))
+}
+
+/// The focused window in a sway tree; `None` when a workspace or output has
+/// focus itself
+fn sway_focused(node: &Value) -> Option<ActiveWindow> {
+    if node["focused"] == true {
+        if node["type"] != "con" && node["type"] != "floating_con" {
+            return None;
+        }
+        // Native Wayland windows have an app ID, Xwayland ones a class
+        let class = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .unwrap_or_default();
+        return Some(ActiveWindow {
+            class: class.to_string(),
+            title: node["name"].as_str().unwrap_or_default().to_string(),
+            fullscreen: node["fullscreen_mode"].as_u64().unwrap_or(0) != 0,
+        });
+    }
+
+    ["nodes", "floating_nodes"]
+        .iter()
+        .flat_map(|key| node[*key].as_array().into_iter().flatten())
+        .find_map(sway_focused)
+}
+
+/// Hyprland: the event socket, then `j/activewindow` on the request socket
+struct HyprlandContext {
+    events: BufReader<UnixStream>,
+}
+
+impl HyprlandContext {
+    fn open() -> Result<Self> {
+        let events = UnixStream::connect(compositor::hyprland_socket(".socket2.sock")?)
+            .context("Failed to connect to Hyprland's event socket")?;
+        Ok(Self {
+            events: BufReader::new(events),
+        })
+    }
+}
+
+impl WindowContext for HyprlandContext {
+    fn name(&self) -> &'static str {
+        "Hyprland IPC"
+    }
+
+    fn active(&mut self) -> Result<Option<ActiveWindow>> {
+        let mut stream = UnixStream::connect(compositor::hyprland_socket(".socket.sock")?)
+            .context("Failed to connect to Hyprland")?;
+        stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+        stream.write_all(b"j/activewindow")?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)?;
+        let window: Value = serde_json::from_str(&reply).context("Invalid reply from Hyprland")?;
+        Ok(hyprland_window(&window))
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        loop {
+            let mut line = String::new();
+            if self.events.read_line(&mut line)? == 0 {
+                bail!("Hyprland closed its event socket");
+            }
+            let event = line
+                .split_once(">>")
+                .map_or(line.as_str(), |(event, _)| event);
+            if HYPRLAND_EVENTS.contains(&event) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Hyprland's `activewindow` reply, `{}` when nothing has focus
+fn hyprland_window(window: &Value) -> Option<ActiveWindow> {
+    let class = window["class"].as_str()?;
+    // A boolean before 0.42, the fullscreen mode since
+    let fullscreen = &window["fullscreen"];
+    Some(ActiveWindow {
+        class: class.to_string(),
+        title: window["title"].as_str().unwrap_or_default().to_string(),
+        fullscreen: fullscreen == true || fullscreen.as_u64().is_some_and(|mode| mode != 0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+    use serde_json::json;
+
+    #[test]
+    fn test_follows_focused_window() {
+        let mut config = Config::default();
+        let browser = Profile {
+            windows: vec![WindowRule::Class("firefox".to_string())],
+            ..Profile::default()
+        };
+        config.profiles.insert("browser".to_string(), browser);
+        config
+            .profiles
+            .insert("media".to_string(), Profile::default());
+        let window = |class: &str| ActiveWindow {
+            class: class.to_string(),
+            ..ActiveWindow::default()
+        };
+
+        let mut switcher = Switcher::default();
+        let media = Some("media".to_string());
+        let browser = Some("browser".to_string());
+        assert_eq!(
+            switcher.update(&config, Some(&window("Firefox")), media.clone()),
+            Some(browser.clone())
+        );
+        // Back to the profile from before once focus moves on
+        assert_eq!(
+            switcher.update(&config, Some(&window("foot")), browser.clone()),
+            Some(media.clone())
+        );
+        assert_eq!(switcher.update(&config, None, media.clone()), None);
+
+        // A switch by hand while the window has focus is kept
+        switcher.update(&config, Some(&window("firefox")), None);
+        assert_eq!(switcher.update(&config, Some(&window("foot")), media), None);
+
+        // Fullscreen windows pause gestures, unless they have a profile
+        config.auto_pause.fullscreen = true;
+        config
+            .auto_pause
+            .windows
+            .push(WindowRule::Class("steam".to_string()));
+        let fullscreen = |class: &str| ActiveWindow {
+            fullscreen: true,
+            ..window(class)
+        };
+        assert!(pauses(&config, Some(&fullscreen("mpv"))));
+        assert!(!pauses(&config, Some(&fullscreen("firefox"))));
+        assert!(!pauses(&config, Some(&window("mpv"))));
+        assert!(pauses(&config, Some(&window("Steam"))));
+        assert!(!pauses(&config, None));
+
+        // The most specific rule wins, and fullscreen windows with a profile
+        // keep their gestures
+        let mut value = serde_json::to_value(Config::default()).unwrap();
+        value["profiles"] = json!({
+            "office": { "windows": ["libreoffice-impress"] },
+            "slides": { "windows": [
+                { "class": "libreoffice-impress", "fullscreen": true },
+                { "title": "presentation mode", "fullscreen": true },
+            ] },
+        });
+        value["auto_pause"] = json!({ "fullscreen": true });
+        let config = Config::from_value(value.clone()).unwrap();
+        let impress = ActiveWindow {
+            class: "libreoffice-impress".to_string(),
+            title: "Talk.odp".to_string(),
+            fullscreen: false,
+        };
+        assert_eq!(profile_for(&config, &impress).as_deref(), Some("office"));
+        let impress = ActiveWindow {
+            fullscreen: true,
+            ..impress
+        };
+        assert_eq!(profile_for(&config, &impress).as_deref(), Some("slides"));
+        assert!(!pauses(&config, Some(&impress)));
+        let viewer = ActiveWindow {
+            class: "okular".to_string(),
+            title: "talk.pdf — Presentation Mode".to_string(),
+            fullscreen: true,
+        };
+        assert_eq!(profile_for(&config, &viewer).as_deref(), Some("slides"));
+        value["profiles"] = json!({ "all": { "windows": [{}] } });
+        assert!(Config::from_value(value).is_err());
+
+        // Window trees and replies of the compositors
+        let tree = json!({
+            "type": "root",
+            "nodes": [{"type": "workspace", "nodes": [], "floating_nodes": [{
+                "type": "floating_con", "focused": true, "app_id": null,
+                "name": "Slides", "fullscreen_mode": 1,
+                "window_properties": {"class": "libreoffice-impress"},
+            }]}],
+        });
+        assert_eq!(
+            sway_focused(&tree),
+            Some(ActiveWindow {
+                class: "libreoffice-impress".to_string(),
+                title: "Slides".to_string(),
+                fullscreen: true,
+            })
+        );
+        assert_eq!(hyprland_window(&json!({})), None);
+        let hyprland = hyprland_window(&json!({"class": "mpv", "title": "x", "fullscreen": 2}));
+        assert!(hyprland.is_some_and(|window| window.fullscreen));
+    }
+}
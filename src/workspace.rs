@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use x11rb::protocol::xproto::{AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask};
+
+use crate::x11;
+
+/// Target of a `workspace:` action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Next,
+    Previous,
+    /// Zero-based desktop index
+    Index(u32),
+}
+
+/// Run a `workspace:` action: `next`, `prev` or a 1-based desktop number
+///
+/// Desktops are switched with EWMH `_NET_CURRENT_DESKTOP` requests, which
+/// every EWMH window manager honours without needing a keybinding.
+pub async fn execute(argument: &str) -> Result<()> {
+    let target = parse_target(argument)?;
+    x11::with_connection_async(move |connection| switch(connection, target)).await
+}
+
+fn parse_target(argument: &str) -> Result<Target> {
+    Ok(match argument {
+        "next" => Target::Next,
+        "prev" | "previous" => Target::Previous,
+        number => match number.parse::<u32>() {
+            Ok(number) if number >= 1 => Target::Index(number - 1),
+            _ => bail!("Unknown workspace action: {}", argument),
+        },
+    })
+}
+
+fn switch(connection: &x11::Connection, target: Target) -> Result<()> {
+    let root = connection.root();
+    let atoms = connection.atoms;
+    let count = connection
+        .property32(root, atoms._NET_NUMBER_OF_DESKTOPS, AtomEnum::CARDINAL)?
+        .first()
+        .copied()
+        .context("Window manager doesn't report desktops (no EWMH support)")?;
+    let current = connection
+        .property32(root, atoms._NET_CURRENT_DESKTOP, AtomEnum::CARDINAL)?
+        .first()
+        .copied()
+        .unwrap_or(0);
+
+    let desktop = desktop_index(target, current, count)?;
+    debug!(
+        "Switching from desktop {} to {} of {}",
+        current, desktop, count
+    );
+    // Sent to the root window the way EWMH requests are made; data: new
+    // desktop, timestamp (0 = now)
+    let message =
+        ClientMessageEvent::new(32, root, atoms._NET_CURRENT_DESKTOP, [desktop, 0, 0, 0, 0]);
+    connection.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        message,
+    )?;
+    connection.sync()
+}
+
+/// Desktop to switch to; next and previous wrap around
+fn desktop_index(target: Target, current: u32, count: u32) -> Result<u32> {
+    if count == 0 {
+        bail!("Window manager reports no desktops");
+    }
+    Ok(match target {
+        Target::Next => (current + 1) % count,
+        Target::Previous => (current + count - 1) % count,
+        Target::Index(index) if index < count => index,
+        Target::Index(index) => bail!("Workspace {} doesn't exist ({} desktops)", index + 1, count),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_targets() {
+        assert_eq!(parse_target("3").unwrap(), Target::Index(2));
+        assert!(parse_target("0").is_err());
+        assert_eq!(desktop_index(Target::Next, 3, 4).unwrap(), 0);
+        assert_eq!(desktop_index(Target::Previous, 0, 4).unwrap(), 3);
+        assert!(desktop_index(Target::Index(4), 0, 4).is_err());
+    }
+}
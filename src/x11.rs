@@ -0,0 +1,184 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use std::ops::Deref;
+use std::sync::Mutex;
+use x11rb::connection::{Connection as _, RequestConnection as _};
+use x11rb::protocol::xproto::{self, ConnectionExt as _, Screen, Window};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// X servers driven by evdev/libinput number keys as the kernel code plus 8
+const EVDEV_KEYCODE_OFFSET: u16 = 8;
+
+x11rb::atom_manager! {
+    /// EWMH atoms, interned once per connection
+    pub Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CURRENT_DESKTOP,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        UTF8_STRING,
+    }
+}
+
+/// Shared connection, opened on first use and dropped after an error so the
+/// next call reconnects (e.g. after an X server restart)
+static CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// Connection to the display named by `DISPLAY`, local or over TCP, with the
+/// EWMH atoms interned
+///
+/// Dereferences to the x11rb connection for making requests.
+pub struct Connection {
+    inner: RustConnection,
+    screen: usize,
+    pub atoms: Atoms,
+}
+
+/// Pointer location on the root window
+#[derive(Debug, Clone, Copy)]
+pub struct Pointer {
+    pub x: i16,
+    pub y: i16,
+    /// Innermost window under the pointer, 0 over the bare root window
+    pub window: Window,
+}
+
+impl Deref for Connection {
+    type Target = RustConnection;
+
+    fn deref(&self) -> &RustConnection {
+        &self.inner
+    }
+}
+
+impl Connection {
+    pub fn open() -> Result<Self> {
+        let (inner, screen) = x11rb::connect(None).context("Failed to connect to X server")?;
+        let atoms = Atoms::new(&inner)?.reply()?;
+        Ok(Self {
+            inner,
+            screen,
+            atoms,
+        })
+    }
+
+    /// The default screen
+    pub fn screen(&self) -> &Screen {
+        &self.setup().roots[self.screen]
+    }
+
+    pub fn root(&self) -> Window {
+        self.screen().root
+    }
+
+    /// Send synthetic input events through XTEST, each `(event type,
+    /// keycode or button)` with the types from `xproto`, e.g.
+    /// `xproto::KEY_PRESS_EVENT`
+    pub fn fake_input(&self, events: &[(u8, u8)]) -> Result<()> {
+        self.require_xtest()?;
+        let root = self.root();
+        for &(event_type, detail) in events {
+            self.xtest_fake_input(event_type, detail, x11rb::CURRENT_TIME, root, 0, 0, 0)?;
+        }
+        // Round trip so X errors surface here instead of being lost
+        self.sync()
+    }
+
+    fn require_xtest(&self) -> Result<()> {
+        if self
+            .extension_information(xtest::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            bail!("X server has no XTEST extension");
+        }
+        Ok(())
+    }
+
+    /// X keycode for a kernel key code
+    pub fn keycode(&self, code: u16) -> Result<u8> {
+        let keycode = code + EVDEV_KEYCODE_OFFSET;
+        let setup = self.setup();
+        if keycode < setup.min_keycode as u16 || keycode > setup.max_keycode as u16 {
+            bail!("Key code {} is outside the X server's keycode range", code);
+        }
+        Ok(keycode as u8)
+    }
+
+    /// Current pointer location and the window under it
+    pub fn query_pointer(&self) -> Result<Pointer> {
+        let mut reply = self.inner.query_pointer(self.root())?.reply()?;
+        let (x, y) = (reply.root_x, reply.root_y);
+
+        // The child is the top-level (frame) window, so descend to the
+        // innermost one, which is what tools like xdotool act on
+        let mut window = x11rb::NONE;
+        while reply.child != x11rb::NONE {
+            window = reply.child;
+            reply = self.inner.query_pointer(window)?.reply()?;
+        }
+
+        Ok(Pointer { x, y, window })
+    }
+
+    /// Property of `window` as 32-bit values, empty if it isn't set
+    pub fn property32(
+        &self,
+        window: Window,
+        property: xproto::Atom,
+        property_type: impl Into<xproto::Atom>,
+    ) -> Result<Vec<u32>> {
+        let reply = self
+            .get_property(false, window, property, property_type, 0, 1024)?
+            .reply()?;
+        Ok(reply.value32().map(Iterator::collect).unwrap_or_default())
+    }
+
+    /// Wait for the server to process the requests sent so far, surfacing
+    /// their errors
+    pub fn sync(&self) -> Result<()> {
+        self.get_input_focus()?.reply()?;
+        Ok(())
+    }
+}
+
+/// Run `f` on the shared connection, connecting first if needed
+pub fn with_connection<T>(f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+    let mut connection = CONNECTION.lock().unwrap_or_else(|e| e.into_inner());
+    if connection.is_none() {
+        *connection = Some(Connection::open()?);
+        info!("Connected to X server");
+    }
+
+    let result = f(connection.as_ref().expect("connection was just opened"));
+    if let Err(e) = &result {
+        warn!("X11 request failed, reconnecting next time: {:#}", e);
+        *connection = None;
+    }
+    result
+}
+
+/// `with_connection` for async code, run on a blocking thread: replies are
+/// waited for, and other callers wait for the connection
+pub async fn with_connection_async<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || with_connection(f))
+        .await
+        .context("X11 request panicked")?
+}
+
+/// Whether XTEST injection works on the current display
+pub fn is_available() -> bool {
+    match with_connection(|connection| connection.require_xtest()) {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("XTEST unavailable: {:#}", e);
+            false
+        }
+    }
+}
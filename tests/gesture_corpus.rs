@@ -0,0 +1,23 @@
+//! Runs the recordings in `tests/gestures` through recognition, so a threshold
+//! change that alters how any of them is recognized fails the build
+
+use std::process::Command;
+
+#[test]
+fn recorded_gestures_are_recognized_as_expected() {
+    let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/gestures");
+    let output = Command::new(env!("CARGO_BIN_EXE_mouse-gesture-recognition"))
+        .args(["verify", corpus])
+        .env("RUST_LOG", "warn")
+        .output()
+        .expect("failed to run mouse-gesture-recognition");
+
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "recognition changed:\n{}{}",
+        report,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(report.contains(" passed, 0 failed"), "{}", report);
+}